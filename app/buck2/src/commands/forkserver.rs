@@ -29,6 +29,10 @@ pub(crate) struct ForkserverCommand {
 
     #[clap(long)]
     state_dir: PathBuf,
+
+    /// Run each command in a cgroup of its own.
+    #[clap(long)]
+    cgroups: bool,
 }
 
 impl ForkserverCommand {
@@ -56,6 +60,7 @@ impl ForkserverCommand {
                 self.fd,
                 log_reload_handle,
                 state_dir,
+                self.cgroups,
             ))
         }
 
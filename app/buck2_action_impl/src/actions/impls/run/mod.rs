@@ -169,6 +169,7 @@ pub(crate) struct UnregisteredRunAction {
     pub(crate) allow_dep_file_cache_upload: bool,
     pub(crate) force_full_hybrid_if_capable: bool,
     pub(crate) unique_input_inodes: bool,
+    pub(crate) memory_limit_mb: Option<u64>,
 }
 
 impl UnregisteredAction for UnregisteredRunAction {
@@ -608,6 +609,10 @@ impl Action for RunAction {
             "no_outputs_cleanup".to_owned() => self.inner.no_outputs_cleanup.to_string(),
             "allow_cache_upload".to_owned() => self.inner.allow_cache_upload.to_string(),
             "allow_dep_file_cache_upload".to_owned() => self.inner.allow_dep_file_cache_upload.to_string(),
+            "memory_limit_mb".to_owned() => match self.inner.memory_limit_mb {
+                None => "None".to_owned(),
+                Some(x) => x.to_string(),
+            },
         }
     }
 
@@ -648,7 +653,8 @@ impl IncrementalActionExecutable for RunAction {
             .with_outputs_cleanup(!self.inner.no_outputs_cleanup)
             .with_local_environment_inheritance(EnvironmentInheritance::local_command_exclusions())
             .with_force_full_hybrid_if_capable(self.inner.force_full_hybrid_if_capable)
-            .with_unique_input_inodes(self.inner.unique_input_inodes);
+            .with_unique_input_inodes(self.inner.unique_input_inodes)
+            .with_memory_limit(self.inner.memory_limit_mb.map(|mb| mb * 1024 * 1024));
 
         let (mut dep_file_bundle, req) = if let Some(visitor) = dep_file_visitor {
             let bundle = make_dep_file_bundle(ctx, visitor, cmdline_digest, req.paths())?;
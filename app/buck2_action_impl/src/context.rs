@@ -116,6 +116,8 @@ enum RunActionError {
     NoOutputsSpecified,
     #[error("`weight` must be a positive integer, got `{0}`")]
     InvalidWeight(i32),
+    #[error("`memory_limit_mb` must be a positive integer, got `{0}`")]
+    InvalidMemoryLimit(i32),
     #[error("`weight` and `weight_percentage` cannot both be passed")]
     DuplicateWeightsSpecified,
     #[error("`dep_files` value with key `{}` has an invalid count of associated outputs. Expected 1, got {}.", .key, .count)]
@@ -605,6 +607,11 @@ fn analysis_actions_methods_actions(builder: &mut MethodsBuilder) {
     ///   event stream, and must be unique for a given target
     /// * `weight`: used to note how heavy the command is and will typically be set to a higher
     ///   value to indicate that less such commands should be run in parallel (if running locally)
+    /// * `memory_limit_mb`: the most memory the command may use when it runs locally. Local
+    ///   commands with a memory limit only start once their limit fits in the memory budget
+    ///   (`build.local_memory_budget_mb`, the machine's memory by default), and if the forkserver
+    ///   runs commands in cgroups (`buck2.local_action_cgroups`), they are killed if they go over
+    ///   it. Otherwise the limit isn't enforced, and Buck2 warns about it once
     /// * `no_outputs_cleanup`: if this flag is set then Buck2 won't clean the outputs of a previous
     ///   build that might be present on a disk; in which case, command from arguments should be
     ///   responsible for the cleanup (that is useful, for example, when an action is supporting
@@ -656,6 +663,7 @@ fn analysis_actions_methods_actions(builder: &mut MethodsBuilder) {
         #[starlark(require = named, default = false)] always_print_stderr: bool,
         #[starlark(require = named)] weight: Option<i32>,
         #[starlark(require = named)] weight_percentage: Option<i32>,
+        #[starlark(require = named)] memory_limit_mb: Option<i32>,
         #[starlark(require = named)] dep_files: Option<SmallMap<&'v str, &'v ArtifactTag>>,
         #[starlark(require = named)] metadata_env_var: Option<String>,
         #[starlark(require = named)] metadata_path: Option<String>,
@@ -760,6 +768,12 @@ fn analysis_actions_methods_actions(builder: &mut MethodsBuilder) {
             }
         };
 
+        let memory_limit_mb = match memory_limit_mb {
+            None => None,
+            Some(v) if v < 1 => return Err(RunActionError::InvalidMemoryLimit(v).into()),
+            Some(v) => Some(v as u64),
+        };
+
         let starlark_env = match env {
             None => Value::new_none(),
             Some(env) => {
@@ -848,6 +862,7 @@ fn analysis_actions_methods_actions(builder: &mut MethodsBuilder) {
             allow_dep_file_cache_upload,
             force_full_hybrid_if_capable,
             unique_input_inodes,
+            memory_limit_mb,
         };
         this.state().register_action(
             artifacts.inputs,
@@ -411,6 +411,7 @@ impl ActionExecutionCtx for BuckActionExecutionContext<'_> {
                     request,
                     prepared_action,
                     digest_config: self.digest_config(),
+                    scratch_path_suffix: None,
                 },
                 self.cancellations,
             )
@@ -487,6 +488,7 @@ impl ActionExecutionCtx for BuckActionExecutionContext<'_> {
                     request,
                     prepared_action,
                     digest_config: self.digest_config(),
+                    scratch_path_suffix: None,
                 },
                 self.cancellations,
             )
@@ -153,6 +153,7 @@ fn get_action_digest(command_details: Option<&CommandExecutionDetails>) -> Optio
                 Some(Command::OmittedLocalCommand(c)) => Some(c.action_digest.clone()),
                 Some(Command::WorkerCommand(c)) => Some(c.action_digest.clone()),
                 Some(Command::WorkerInitCommand(_)) => None,
+                Some(Command::LocalActionCacheCommand(c)) => Some(c.action_digest.clone()),
                 Some(Command::RemoteCommand(c)) => Some(c.action_digest.clone()),
                 None => None,
             }
@@ -10,15 +10,20 @@
 use std::sync::Arc;
 
 use buck2_common::dice::cells::SetCellResolver;
+use buck2_common::dice::cells::SetStartupCellResolver;
 use buck2_common::dice::data::SetIoProvider;
+use buck2_common::dice::file_ops::register_persistent_keys;
 use buck2_common::io::IoProvider;
 use buck2_common::legacy_configs::dice::SetLegacyConfigs;
 use buck2_common::legacy_configs::LegacyBuckConfig;
+use buck2_core::cells::CellResolver;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::digest_config::SetDigestConfig;
 use dice::DetectCycles;
 use dice::Dice;
+use dice::EvictionPolicy;
 use dice::WhichDice;
+use dupe::Dupe;
 
 /// Utility to configure the dice globals.
 /// One place to not forget to initialize something in all places.
@@ -26,8 +31,10 @@ pub async fn configure_dice_for_buck(
     io: Arc<dyn IoProvider>,
     digest_config: DigestConfig,
     root_config: Option<&LegacyBuckConfig>,
+    cells: Option<&CellResolver>,
     detect_cycles: Option<DetectCycles>,
     which_dice: Option<WhichDice>,
+    saved_graph: Option<&[u8]>,
 ) -> anyhow::Result<Arc<Dice>> {
     let detect_cycles = detect_cycles.map_or_else(
         || {
@@ -56,10 +63,33 @@ pub async fn configure_dice_for_buck(
     };
     dice.set_io_provider(io);
     dice.set_digest_config(digest_config);
+    if let Some(cells) = cells {
+        dice.set_startup_cell_resolver(cells.dupe());
+    }
+    register_persistent_keys(&mut dice);
+
+    if let Some(root_config) = root_config {
+        dice.set_eviction_policy(EvictionPolicy {
+            max_unused_versions: root_config.parse("buck2", "dice_max_unused_versions")?,
+            memory_budget: root_config.parse("buck2", "dice_memory_budget_bytes")?,
+        });
+    }
 
     let dice = dice.build(detect_cycles);
+    if let Some(mut saved_graph) = saved_graph {
+        // A fresh graph is always correct, so this is not an error.
+        match dice.load_graph(&mut saved_graph) {
+            Ok(loaded) => tracing::info!("Loaded {} DICE nodes saved by a previous daemon", loaded),
+            Err(e) => tracing::warn!("Error loading saved DICE graph: {:#}", e),
+        }
+    }
     let mut dice_ctx = dice.updater();
-    dice_ctx.set_none_cell_resolver()?;
+    match cells {
+        // Same as what the loaded graph was computed with, if it was loaded, so that it stays
+        // valid until the first command sets the cells it uses.
+        Some(cells) => dice_ctx.set_cell_resolver(cells.dupe())?,
+        None => dice_ctx.set_none_cell_resolver()?,
+    }
     dice_ctx.set_none_legacy_configs()?;
     dice_ctx.commit().await;
 
@@ -98,6 +98,14 @@ pub struct ExternalRunnerTestInfoGen<V> {
     /// should be ignored when executing tests even if those are passed as required from test runner.
     #[provider(field_type = DictType<String, Option<StarlarkConfiguredProvidersLabel>>)]
     local_resources: V,
+
+    /// Whether passing results of this test may be cached and replayed, when test result caching
+    /// is enabled. Tests that depend on anything that isn't one of their inputs (e.g. integration
+    /// tests talking to external services) should set this to `False`.
+    ///
+    /// Defaults to `True`.
+    #[provider(field_type = bool)]
+    allow_result_caching: V,
 }
 
 // NOTE: All the methods here unwrap because we validate at freeze time.
@@ -136,10 +144,23 @@ impl FrozenExternalRunnerTestInfo {
             .unwrap_or(true)
     }
 
+    pub fn allow_result_caching(&self) -> bool {
+        NoneOr::<bool>::unpack_value(self.allow_result_caching.to_value())
+            .unwrap()
+            .into_option()
+            .unwrap_or(true)
+    }
+
     pub fn default_executor(&self) -> Option<&StarlarkCommandExecutorConfig> {
         unpack_opt_executor(self.default_executor.to_value()).unwrap()
     }
 
+    pub fn executor_overrides(
+        &self,
+    ) -> impl Iterator<Item = (&str, &StarlarkCommandExecutorConfig)> {
+        unwrap_all(iter_executor_overrides(self.executor_overrides.to_value()))
+    }
+
     /// Access a specific executor override.
     pub fn executor_override(&self, key: &str) -> Option<&StarlarkCommandExecutorConfig> {
         let executor_overrides = DictRef::from_value(self.executor_overrides.to_value()).unwrap();
@@ -430,6 +451,8 @@ where
         .context("`use_project_relative_paths` must be a bool if provided")?;
     NoneOr::<bool>::unpack_value(info.run_from_project_root.to_value())
         .context("`run_from_project_root` must be a bool if provided")?;
+    NoneOr::<bool>::unpack_value(info.allow_result_caching.to_value())
+        .context("`allow_result_caching` must be a bool if provided")?;
     unpack_opt_executor(info.default_executor.to_value()).context("Invalid `default_executor`")?;
     info.test_type
         .to_value()
@@ -452,6 +475,7 @@ fn external_runner_test_info_creator(globals: &mut GlobalsBuilder) {
         #[starlark(default = NoneType)] default_executor: Value<'v>,
         #[starlark(default = NoneType)] executor_overrides: Value<'v>,
         #[starlark(default = NoneType)] local_resources: Value<'v>,
+        #[starlark(default = NoneType)] allow_result_caching: Value<'v>,
     ) -> anyhow::Result<ExternalRunnerTestInfo<'v>> {
         let res = ExternalRunnerTestInfo {
             test_type: r#type,
@@ -464,6 +488,7 @@ fn external_runner_test_info_creator(globals: &mut GlobalsBuilder) {
             default_executor,
             executor_overrides,
             local_resources,
+            allow_result_caching,
         };
         validate_external_runner_test_info(&res)?;
         Ok(res)
@@ -13,6 +13,7 @@ use buck2_core::cells::name::CellName;
 use buck2_test_api::data::ConfiguredTarget;
 use buck2_test_api::data::ExternalRunnerSpec;
 use buck2_test_api::data::ExternalRunnerSpecValue;
+use buck2_test_api::data::TestCaseShard;
 use buck2_test_api::protocol::TestExecutor;
 use futures::future::BoxFuture;
 use futures::future::FutureExt;
@@ -33,6 +34,7 @@ pub trait TestProvider {
         target: ConfiguredTarget,
         executor: Arc<dyn TestExecutor + 'exec>,
         working_dir_cell: CellName,
+        case_shard: Option<TestCaseShard>,
     ) -> BoxFuture<'exec, anyhow::Result<()>>;
 }
 
@@ -50,6 +52,7 @@ impl TestProvider for FrozenExternalRunnerTestInfo {
         target: ConfiguredTarget,
         executor: Arc<dyn TestExecutor + 'exec>,
         working_dir_cell: CellName,
+        case_shard: Option<TestCaseShard>,
     ) -> BoxFuture<'exec, anyhow::Result<()>> {
         let mut handle_index = 0;
 
@@ -87,6 +90,11 @@ impl TestProvider for FrozenExternalRunnerTestInfo {
             contacts: self.contacts().map(|l| l.to_owned()).collect(),
             oncall: self.contacts().exactly_one().ok().map(str::to_owned),
             working_dir_cell,
+            executor_overrides: self
+                .executor_overrides()
+                .map(|(name, _)| name.to_owned())
+                .collect(),
+            case_shard,
         };
 
         async move { executor.external_runner_spec(spec).await }.boxed()
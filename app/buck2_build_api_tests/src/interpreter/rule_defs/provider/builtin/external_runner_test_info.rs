@@ -34,6 +34,7 @@ fn test_construction() -> anyhow::Result<()> {
             ExternalRunnerTestInfo(type = "foo", labels = ("foo",))
             ExternalRunnerTestInfo(type = "foo", use_project_relative_paths = True)
             ExternalRunnerTestInfo(type = "foo", run_from_project_root = True)
+            ExternalRunnerTestInfo(type = "foo", allow_result_caching = False)
         "#
     );
     let mut tester = tester();
@@ -164,6 +165,16 @@ fn test_validation() -> anyhow::Result<()> {
         "`run_from_project_root`",
     );
 
+    tester.run_starlark_bzl_test_expecting_error(
+        indoc!(
+            r#"
+        def test():
+            ExternalRunnerTestInfo(type = "foo", allow_result_caching = "foo")
+        "#
+        ),
+        "`allow_result_caching`",
+    );
+
     tester.run_starlark_bzl_test_expecting_error(
         indoc!(
             r#"
@@ -136,6 +136,11 @@ pub struct BuildCommand {
         help = "Experimental: Path to a file where the Buck2 daemon should write a list of produced artifacts in json format"
     )]
     output_hashes_file: Option<PathArg>,
+
+    /// Run every action that executes twice, without using caches, and fail the actions whose
+    /// outputs differ between the two runs. Use with `--keep-going` to find all of them.
+    #[clap(long)]
+    check_determinism: bool,
 }
 
 impl BuildCommand {
@@ -226,7 +231,10 @@ impl StreamingCommand for BuildCommand {
                             || self.output_path.is_some(),
                         return_default_other_outputs: show_default_other_outputs,
                     }),
-                    build_opts: Some(self.build_opts.to_proto()),
+                    build_opts: Some(buck2_cli_proto::CommonBuildOptions {
+                        check_determinism: self.check_determinism,
+                        ..self.build_opts.to_proto()
+                    }),
                     final_artifact_materializations: self.materializations.to_proto() as i32,
                     target_universe: self.target_universe,
                     output_hashes_file: self
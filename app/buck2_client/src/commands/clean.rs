@@ -70,16 +70,27 @@ the specified duration, without killing the daemon",
     ///  - Writing to `buck-out` without being expected by Buck
     #[clap(long = "tracked-only", requires = "stale")]
     tracked_only: bool,
+
+    /// Keep `buck-out` under this size (e.g. `50GB`), without killing the daemon.
+    ///
+    /// Once artifacts older than `--stale` are removed, the least recently accessed
+    /// artifacts are evicted until the remaining tracked artifacts fit in the budget.
+    /// Can be used on its own or together with `--stale`.
+    #[clap(long = "max-size", value_name = "SIZE")]
+    max_size: Option<bytesize::ByteSize>,
 }
 
 impl CleanCommand {
     pub fn exec(self, matches: &clap::ArgMatches, ctx: ClientCommandContext<'_>) -> ExitResult {
-        if let Some(keep_since_arg) = parse_clean_stale_args(self.stale, self.keep_since_time)? {
+        if let Some(keep_since_arg) =
+            parse_clean_stale_args(self.stale, self.keep_since_time, self.max_size)?
+        {
             let cmd = CleanStaleCommand {
                 common_opts: self.common_opts,
                 keep_since_arg,
                 dry_run: self.dry_run,
                 tracked_only: self.tracked_only,
+                max_bytes: self.max_size.map(|s| s.as_u64()),
             };
             return cmd.exec(matches, ctx);
         }
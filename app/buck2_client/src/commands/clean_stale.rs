@@ -36,6 +36,7 @@ pub struct CleanStaleCommand {
     pub keep_since_arg: KeepSinceArg,
     pub dry_run: bool,
     pub tracked_only: bool,
+    pub max_bytes: Option<u64>,
 }
 
 /// Specifies the maximum age of artifacts to keep
@@ -47,6 +48,7 @@ pub enum KeepSinceArg {
 pub fn parse_clean_stale_args(
     stale: Option<Option<humantime::Duration>>,
     keep_since_time: Option<i64>,
+    max_size: Option<bytesize::ByteSize>,
 ) -> anyhow::Result<Option<KeepSinceArg>> {
     let arg = match (stale, keep_since_time) {
         (Some(Some(human_duration)), None) => {
@@ -56,6 +58,8 @@ pub fn parse_clean_stale_args(
         (Some(None), None) => Some(KeepSinceArg::Duration(chrono::Duration::weeks(1))),
         (None, Some(time)) => Some(KeepSinceArg::Time(time)),
         (Some(_), Some(_)) => unreachable!("keep-since-time conflicts_with stale"),
+        // Only a size budget was given: no artifact is old enough to be stale by age alone.
+        (None, None) if max_size.is_some() => Some(KeepSinceArg::Time(0)),
         (None, None) => None,
     };
     Ok(arg)
@@ -132,6 +136,7 @@ impl StreamingCommand for CleanStaleCommand {
                     keep_since_time: keep_since_time.timestamp(),
                     dry_run: self.dry_run,
                     tracked_only: self.tracked_only,
+                    max_bytes: self.max_bytes,
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use async_trait::async_trait;
+use buck2_cli_proto::UnstableDiceEvictRequest;
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::CommonBuildConfigurationOptions;
+use buck2_client_ctx::common::CommonConsoleOptions;
+use buck2_client_ctx::common::CommonDaemonCommandOptions;
+use buck2_client_ctx::daemon::client::BuckdClientConnector;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::streaming::StreamingCommand;
+
+#[derive(Debug, clap::Parser)]
+#[clap(group = clap::ArgGroup::new("policy").required(true).multiple(true))]
+pub struct DiceEvictCommand {
+    /// Evict nodes that have not been used in this many versions of the graph. A new version is
+    /// created whenever files or configs change.
+    #[clap(long, group = "policy", value_name = "VERSIONS")]
+    max_unused_versions: Option<u64>,
+    /// Evict the least recently used nodes while the estimated size of the computed values in the
+    /// graph exceeds this many bytes.
+    #[clap(long, group = "policy", value_name = "BYTES")]
+    memory_budget: Option<u64>,
+}
+
+#[async_trait]
+impl StreamingCommand for DiceEvictCommand {
+    const COMMAND_NAME: &'static str = "dice_evict";
+
+    fn existing_only() -> bool {
+        true
+    }
+
+    async fn exec_impl(
+        self,
+        buckd: &mut BuckdClientConnector,
+        _matches: &clap::ArgMatches,
+        _ctx: &mut ClientCommandContext<'_>,
+    ) -> ExitResult {
+        let res = buckd
+            .with_flushing()
+            .unstable_dice_evict(UnstableDiceEvictRequest {
+                max_unused_versions: self.max_unused_versions,
+                memory_budget_bytes: self.memory_budget,
+            })
+            .await?;
+
+        buck2_client_ctx::println!(
+            "Evicted {} keys, {} keys remaining",
+            res.evicted_key_count,
+            res.remaining_key_count
+        )?;
+        if let Some(size) = res.remaining_size_bytes {
+            buck2_client_ctx::println!("Estimated size of the remaining values: {} bytes", size)?;
+        }
+
+        ExitResult::success()
+    }
+
+    fn console_opts(&self) -> &CommonConsoleOptions {
+        CommonConsoleOptions::none_ref()
+    }
+
+    fn event_log_opts(&self) -> &CommonDaemonCommandOptions {
+        CommonDaemonCommandOptions::default_ref()
+    }
+
+    fn common_opts(&self) -> &CommonBuildConfigurationOptions {
+        CommonBuildConfigurationOptions::default_ref()
+    }
+}
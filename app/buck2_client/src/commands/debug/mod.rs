@@ -16,6 +16,7 @@ use buck2_common::argv::SanitizedArgv;
 use chrome_trace::ChromeTraceCommand;
 use crash::CrashCommand;
 use dice_dump::DiceDumpCommand;
+use dice_evict::DiceEvictCommand;
 use file_status::FileStatusCommand;
 use flush_dep_files::FlushDepFilesCommand;
 use heap_dump::HeapDumpCommand;
@@ -42,6 +43,7 @@ mod chrome_trace;
 mod crash;
 mod daemon_dir;
 mod dice_dump;
+mod dice_evict;
 mod eval;
 mod exe;
 mod file_status;
@@ -71,6 +73,8 @@ pub enum DebugCommand {
     AllocatorStats(AllocatorStatsCommand),
     /// Dump the DICE graph to a file and saves it to disk.
     DiceDump(DiceDumpCommand),
+    /// Evicts unused nodes from the DICE graph. Requires `buck2.dice = modern`.
+    DiceEvict(DiceEvictCommand),
     #[clap(setting(clap::AppSettings::Hidden))]
     Replay(DebugReplayCommand),
     /// Prints the hash of the buck2 binary
@@ -110,6 +114,7 @@ impl DebugCommand {
         let matches = matches.subcommand().expect("subcommand not found").1;
         match self {
             DebugCommand::DiceDump(cmd) => cmd.exec(matches, ctx),
+            DebugCommand::DiceEvict(cmd) => cmd.exec(matches, ctx),
             DebugCommand::Crash(cmd) => cmd.exec(matches, ctx),
             DebugCommand::HeapDump(cmd) => cmd.exec(matches, ctx),
             DebugCommand::AllocatorStats(cmd) => cmd.exec(matches, ctx),
@@ -25,6 +25,9 @@ enum QueryOutputFormatArg {
     Dot,
     Json,
     DotCompact,
+    Graphml,
+    Mermaid,
+    Proto,
 }
 
 /// Args common to all the query commands
@@ -53,9 +56,12 @@ pub(crate) struct CommonQueryOptions {
         long_help = "Output format (default: list). \n
            dot -  dot graph format. \n
            dot_compact - compact alternative to dot format. \n
-           json - JSON format.
+           json - JSON format. \n
+           graphml - GraphML format. \n
+           mermaid - Mermaid flowchart format. \n
+           proto - stream of length-delimited `QueryGraphNode` protobuf messages (see `daemon.proto`).
          ",
-        value_name = "dot|dot_compact|json",
+        value_name = "dot|dot_compact|json|graphml|mermaid|proto",
         arg_enum
     )]
     output_format: Option<QueryOutputFormatArg>,
@@ -87,6 +93,9 @@ impl CommonQueryOptions {
             Some(QueryOutputFormatArg::Json) => QueryOutputFormat::Json,
             Some(QueryOutputFormatArg::Dot) => QueryOutputFormat::Dot,
             Some(QueryOutputFormatArg::DotCompact) => QueryOutputFormat::DotCompact,
+            Some(QueryOutputFormatArg::Graphml) => QueryOutputFormat::Graphml,
+            Some(QueryOutputFormatArg::Mermaid) => QueryOutputFormat::Mermaid,
+            Some(QueryOutputFormatArg::Proto) => QueryOutputFormat::Proto,
             None => {
                 if self.json {
                     QueryOutputFormat::Json
@@ -7,11 +7,15 @@
  * of this source tree.
  */
 
+use std::collections::HashMap;
+use std::str::FromStr;
+
 use anyhow::Context;
 use async_trait::async_trait;
 use buck2_cli_proto::CounterWithExamples;
 use buck2_cli_proto::TestRequest;
 use buck2_cli_proto::TestSessionOptions;
+use buck2_cli_proto::TestShard;
 use buck2_client_ctx::client_ctx::ClientCommandContext;
 use buck2_client_ctx::common::CommonBuildConfigurationOptions;
 use buck2_client_ctx::common::CommonBuildOptions;
@@ -26,8 +30,11 @@ use buck2_client_ctx::output_destination_arg::OutputDestinationArg;
 use buck2_client_ctx::path_arg::PathArg;
 use buck2_client_ctx::stdio::eprint_line;
 use buck2_client_ctx::streaming::StreamingCommand;
+use buck2_client_ctx::subscribers::subscriber::EventSubscriber;
 use buck2_client_ctx::subscribers::superconsole::test::span_from_build_failure_count;
 use buck2_client_ctx::subscribers::superconsole::test::TestCounterColumn;
+use buck2_client_ctx::subscribers::test_report::target_durations_from_json_report;
+use buck2_client_ctx::subscribers::test_report::TestReportWriter;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::working_dir::WorkingDir;
 use gazebo::prelude::*;
@@ -45,6 +52,32 @@ fn forward_output_to_path(
         .context("Failed to write test executor output to path")
 }
 
+/// A shard of the test targets, as `N/M` on the command line, where `N` is between 1 and `M`.
+#[derive(Debug, Clone, Copy)]
+struct ShardArg {
+    /// Zero-based.
+    index: u32,
+    count: u32,
+}
+
+impl FromStr for ShardArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("expected a shard as `N/M` with 1 <= N <= M, got `{}`", s);
+        let (n, m) = s.split_once('/').ok_or_else(err)?;
+        let n: u32 = n.trim().parse().map_err(|_| err())?;
+        let m: u32 = m.trim().parse().map_err(|_| err())?;
+        if n == 0 || n > m {
+            return Err(err());
+        }
+        Ok(Self {
+            index: n - 1,
+            count: m,
+        })
+    }
+}
+
 fn print_error_counter(
     console: &FinalConsole,
     counter: &CounterWithExamples,
@@ -123,6 +156,24 @@ If include patterns are present, regardless of whether exclude patterns are pres
     #[clap(long, group = "re_options", alias = "unstable-force-tests-on-re")]
     unstable_allow_all_tests_on_re: bool,
 
+    /// Cache passing test results in the action cache, and replay them instead of running tests
+    /// whose command and inputs did not change. Tests can opt out by setting
+    /// `allow_result_caching = False` in their `ExternalRunnerTestInfo`.
+    #[clap(long)]
+    cache_test_results: bool,
+
+    /// Only build and run a share of the test targets: the test targets are partitioned into `M`
+    /// shards, and shard `N` (between 1 and `M`) runs. Every CI worker can run one shard of the same
+    /// command to split a test suite between them. A target that takes longer than a shard should
+    /// (per `--shard-durations`) runs in every shard, each running a share of its test cases.
+    #[clap(long, value_name = "N/M")]
+    shard: Option<ShardArg>,
+
+    /// A JSON report written by `--test-report-json` in a previous run, used to balance the shards
+    /// by how long each target took to test, instead of by number of targets.
+    #[clap(long, value_name = "PATH", requires = "shard")]
+    shard_durations: Option<PathArg>,
+
     // NOTE: the field below is given a different name from the test runner's `timeout` to avoid
     // confusion between the two parameters.
     /// How long to execute tests for. If the timeout is exceeded, Buck2 will exit
@@ -167,6 +218,16 @@ If include patterns are present, regardless of whether exclude patterns are pres
     #[clap(long)]
     test_executor_stderr: Option<OutputDestinationArg>,
 
+    /// Writes a JUnit XML report of the test results to the provided path, for CI systems to
+    /// ingest. Test cases are grouped in one test suite per target.
+    #[clap(long, value_name = "PATH")]
+    junit_xml: Option<PathArg>,
+
+    /// Writes a JSON report of the test results to the provided path: a summary of the number of
+    /// tests per status, and the status, duration and output of every test case.
+    #[clap(long, value_name = "PATH")]
+    test_report_json: Option<PathArg>,
+
     /// Additional arguments passed to the test executor.
     ///
     /// Test executor is expected to have `--env` flag to pass environment variables.
@@ -188,6 +249,26 @@ impl StreamingCommand for TestCommand {
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
         let context = ctx.client_context(matches, &self)?;
+
+        let shard = match self.shard {
+            Some(ShardArg { index, count }) => {
+                let target_durations = match &self.shard_durations {
+                    Some(path) => {
+                        let report = fs_util::read_to_string(path.resolve(&ctx.working_dir))
+                            .context("Error reading `--shard-durations`")?;
+                        target_durations_from_json_report(&report)?
+                    }
+                    None => HashMap::new(),
+                };
+                Some(TestShard {
+                    index,
+                    count,
+                    target_durations,
+                })
+            }
+            None => None,
+        };
+
         let response = buckd
             .with_flushing()
             .test(
@@ -209,6 +290,7 @@ impl StreamingCommand for TestCommand {
                             || self.unstable_allow_all_tests_on_re,
                         force_use_project_relative_paths: self.unstable_allow_all_tests_on_re,
                         force_run_from_project_root: self.unstable_allow_all_tests_on_re,
+                        cache_test_results: self.cache_test_results,
                     }),
                     timeout: self
                         .timeout
@@ -218,6 +300,7 @@ impl StreamingCommand for TestCommand {
                         })
                         .transpose()
                         .context("Invalid `timeout`")?,
+                    shard,
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -336,4 +419,14 @@ impl StreamingCommand for TestCommand {
     fn common_opts(&self) -> &CommonBuildConfigurationOptions {
         &self.common_opts.config_opts
     }
+
+    fn extra_subscribers(&self) -> Vec<Box<dyn EventSubscriber>> {
+        if self.junit_xml.is_none() && self.test_report_json.is_none() {
+            return vec![];
+        }
+        vec![Box::new(TestReportWriter::new(
+            self.junit_xml.as_ref().map(|p| p.path().to_owned()),
+            self.test_report_json.as_ref().map(|p| p.path().to_owned()),
+        ))]
+    }
 }
@@ -532,6 +532,7 @@ impl CommonBuildOptions {
             skip_missing_targets: self.skip_missing_targets,
             skip_incompatible_targets: self.skip_incompatible_targets,
             materialize_failed_inputs: self.materialize_failed_inputs,
+            check_determinism: false,
             unstable_include_failures_build_report,
         }
     }
@@ -574,6 +574,11 @@ impl<'a, 'b> FlushingBuckdClient<'a, 'b> {
         UnstableDiceDumpRequest,
         UnstableDiceDumpResponse
     );
+    debug_method!(
+        unstable_dice_evict,
+        UnstableDiceEvictRequest,
+        UnstableDiceEvictResponse
+    );
 
     wrap_method!(status(snapshot: bool), StatusResponse);
     wrap_method!(set_log_filter(log_filter: SetLogFilterRequest), ());
@@ -21,3 +21,4 @@ pub mod stdout_stderr_forwarder;
 pub mod subscriber;
 pub mod subscriber_unpack;
 pub mod superconsole;
+pub mod test_report;
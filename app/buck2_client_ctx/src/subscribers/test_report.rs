@@ -0,0 +1,432 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Machine readable reports of the test results of `buck2 test`, for CI systems to ingest.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::working_dir::WorkingDir;
+use buck2_data::TestStatus;
+use buck2_event_observer::display::display_configured_target_label;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_events::BuckEvent;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::subscribers::subscriber::EventSubscriber;
+
+/// Max number of bytes of stdout and stderr included in reports, per test case. The end of the
+/// output is kept, since that's usually where failures are.
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// The markers the built-in test runner uses to separate stdout and stderr in the details of a
+/// test result.
+const STDOUT_MARKER: &str = "---- STDOUT ----\n";
+const STDERR_MARKER: &str = "---- STDERR ----\n";
+
+/// Collects the `TestResult` events of a test command, and writes them out as JUnit XML and/or
+/// JSON when the command ends.
+pub struct TestReportWriter {
+    junit_xml: Option<PathBuf>,
+    json: Option<PathBuf>,
+    results: Vec<TestCaseReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct TestCaseReport {
+    target: String,
+    name: String,
+    #[serde(serialize_with = "serialize_status")]
+    status: TestStatus,
+    duration_secs: Option<f64>,
+    message: Option<String>,
+    stdout: String,
+    stderr: String,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    summary: BTreeMap<&'static str, usize>,
+    results: &'a [TestCaseReport],
+}
+
+/// The parts of a JSON report that are needed to read durations back.
+#[derive(Deserialize)]
+struct JsonReportDurations {
+    results: Vec<TestCaseDuration>,
+}
+
+#[derive(Deserialize)]
+struct TestCaseDuration {
+    target: String,
+    duration_secs: Option<f64>,
+}
+
+/// Read the total duration of the tests of every target from a JSON report, keyed by unconfigured
+/// target label, so that the next runs can be balanced across shards.
+pub fn target_durations_from_json_report(report: &str) -> anyhow::Result<HashMap<String, f64>> {
+    let report: JsonReportDurations =
+        serde_json::from_str(report).context("Error parsing JSON test report")?;
+
+    let mut durations = HashMap::new();
+    for result in report.results {
+        // Targets are reported with their configuration, e.g. `root//foo:bar (cfg)`.
+        let target = match result.target.split_once(" (") {
+            Some((target, _)) => target,
+            None => &result.target,
+        };
+        *durations.entry(target.to_owned()).or_default() += result.duration_secs.unwrap_or(0.0);
+    }
+
+    Ok(durations)
+}
+
+impl TestReportWriter {
+    /// Relative paths are resolved against the current directory when the reports are written.
+    pub fn new(junit_xml: Option<PathBuf>, json: Option<PathBuf>) -> Self {
+        Self {
+            junit_xml,
+            json,
+            results: Vec::new(),
+        }
+    }
+
+    fn handle_test_result(&mut self, result: &buck2_data::TestResult) -> anyhow::Result<()> {
+        let status = TestStatus::from_i32(result.status).unwrap_or(TestStatus::Unknown);
+        // Listing successes are not test cases, they only tell us that test cases were found.
+        if status == TestStatus::ListingSuccess {
+            return Ok(());
+        }
+
+        let target = match &result.target_label {
+            Some(label) => display_configured_target_label(label, TargetDisplayOptions::for_log())?,
+            None => String::new(),
+        };
+        let (stdout, stderr) = split_details(&result.details);
+
+        self.results.push(TestCaseReport {
+            target,
+            name: result.name.clone(),
+            status,
+            duration_secs: result
+                .duration
+                .clone()
+                .and_then(|d| Duration::try_from(d).ok())
+                .map(|d| d.as_secs_f64()),
+            message: result.msg.as_ref().map(|m| m.msg.clone()),
+            stdout: excerpt(stdout),
+            stderr: excerpt(stderr),
+        });
+
+        Ok(())
+    }
+
+    fn write_reports(&self) -> anyhow::Result<()> {
+        if self.junit_xml.is_none() && self.json.is_none() {
+            return Ok(());
+        }
+
+        let working_dir = WorkingDir::current_dir()?;
+
+        if let Some(path) = &self.junit_xml {
+            fs_util::write(working_dir.resolve(path), junit_xml(&self.results))
+                .context("Error writing JUnit XML test report")?;
+        }
+
+        if let Some(path) = &self.json {
+            let mut summary = BTreeMap::new();
+            for result in &self.results {
+                *summary.entry(result.status.as_str_name()).or_default() += 1;
+            }
+            let report = JsonReport {
+                summary,
+                results: &self.results,
+            };
+            fs_util::write(
+                working_dir.resolve(path),
+                serde_json::to_string_pretty(&report)?,
+            )
+            .context("Error writing JSON test report")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for TestReportWriter {
+    async fn handle_events(&mut self, events: &[Arc<BuckEvent>]) -> anyhow::Result<()> {
+        for event in events {
+            if let buck2_data::buck_event::Data::Instant(instant) = event.data() {
+                if let Some(buck2_data::instant_event::Data::TestResult(result)) = &instant.data {
+                    self.handle_test_result(result)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn exit(&mut self) -> anyhow::Result<()> {
+        self.write_reports()
+    }
+}
+
+fn serialize_status<S: serde::Serializer>(
+    status: &TestStatus,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(status.as_str_name())
+}
+
+/// Split the details of a test result into stdout and stderr, if they were produced by the
+/// built-in test runner. Otherwise, the details are treated as stdout.
+fn split_details(details: &str) -> (&str, &str) {
+    match details.strip_prefix(STDOUT_MARKER) {
+        Some(rest) => match rest.split_once(STDERR_MARKER) {
+            Some((stdout, stderr)) => (stdout.trim_end(), stderr.trim_end()),
+            None => (rest.trim_end(), ""),
+        },
+        None => (details, ""),
+    }
+}
+
+fn excerpt(output: &str) -> String {
+    if output.len() <= MAX_OUTPUT_BYTES {
+        return output.to_owned();
+    }
+
+    let mut start = output.len() - MAX_OUTPUT_BYTES;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("[... {} bytes truncated ...]\n{}", start, &output[start..])
+}
+
+/// JUnit XML, as understood by most CI systems: one `<testsuite>` per target. Reruns are
+/// attempts that were superseded by a later result, so they are left out.
+fn junit_xml(results: &[TestCaseReport]) -> String {
+    let mut suites: BTreeMap<&str, Vec<&TestCaseReport>> = BTreeMap::new();
+    for result in results {
+        if result.status != TestStatus::Rerun {
+            suites.entry(&result.target).or_default().push(result);
+        }
+    }
+
+    let mut out = String::new();
+    let all = suites.values().flatten().copied();
+    let (tests, failures, errors, skipped, time) = junit_counts(all);
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        out,
+        r#"<testsuites name="buck2" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+        tests, failures, errors, skipped, time
+    )
+    .unwrap();
+
+    for (target, cases) in &suites {
+        let (tests, failures, errors, skipped, time) = junit_counts(cases.iter().copied());
+        writeln!(
+            out,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+            xml_escape(target),
+            tests,
+            failures,
+            errors,
+            skipped,
+            time
+        )
+        .unwrap();
+
+        for case in cases {
+            write!(
+                out,
+                r#"    <testcase name="{}" classname="{}""#,
+                xml_escape(&case.name),
+                xml_escape(target)
+            )
+            .unwrap();
+            if let Some(duration) = case.duration_secs {
+                write!(out, r#" time="{:.3}""#, duration).unwrap();
+            }
+            writeln!(out, ">").unwrap();
+
+            let message = xml_escape(
+                case.message
+                    .as_deref()
+                    .unwrap_or_else(|| case.status.as_str_name()),
+            );
+            match junit_outcome(case.status) {
+                JunitOutcome::Pass => {}
+                JunitOutcome::Failure => {
+                    writeln!(out, r#"      <failure message="{}"/>"#, message).unwrap()
+                }
+                JunitOutcome::Error => {
+                    writeln!(out, r#"      <error message="{}"/>"#, message).unwrap()
+                }
+                JunitOutcome::Skipped => {
+                    writeln!(out, r#"      <skipped message="{}"/>"#, message).unwrap()
+                }
+            }
+            if !case.stdout.is_empty() {
+                writeln!(
+                    out,
+                    "      <system-out>{}</system-out>",
+                    xml_escape(&case.stdout)
+                )
+                .unwrap();
+            }
+            if !case.stderr.is_empty() {
+                writeln!(
+                    out,
+                    "      <system-err>{}</system-err>",
+                    xml_escape(&case.stderr)
+                )
+                .unwrap();
+            }
+            writeln!(out, "    </testcase>").unwrap();
+        }
+
+        writeln!(out, "  </testsuite>").unwrap();
+    }
+
+    writeln!(out, "</testsuites>").unwrap();
+    out
+}
+
+enum JunitOutcome {
+    Pass,
+    Failure,
+    Error,
+    Skipped,
+}
+
+fn junit_outcome(status: TestStatus) -> JunitOutcome {
+    match status {
+        TestStatus::Pass => JunitOutcome::Pass,
+        TestStatus::Fail => JunitOutcome::Failure,
+        TestStatus::Skip | TestStatus::Omitted => JunitOutcome::Skipped,
+        _ => JunitOutcome::Error,
+    }
+}
+
+/// Number of tests, failures, errors, skipped tests, and total time in seconds.
+fn junit_counts<'a>(
+    cases: impl Iterator<Item = &'a TestCaseReport>,
+) -> (usize, usize, usize, usize, f64) {
+    let (mut tests, mut failures, mut errors, mut skipped, mut time) = (0, 0, 0, 0, 0.0);
+    for case in cases {
+        tests += 1;
+        match junit_outcome(case.status) {
+            JunitOutcome::Pass => {}
+            JunitOutcome::Failure => failures += 1,
+            JunitOutcome::Error => errors += 1,
+            JunitOutcome::Skipped => skipped += 1,
+        }
+        time += case.duration_secs.unwrap_or_default();
+    }
+    (tests, failures, errors, skipped, time)
+}
+
+/// Escape text for use in XML attributes and text nodes. Control characters are not allowed in
+/// XML 1.0 at all, so they are dropped.
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' | '\r' | '\t' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(name: &str, status: TestStatus, stdout: &str) -> TestCaseReport {
+        TestCaseReport {
+            target: "root//foo:bar (cfg)".to_owned(),
+            name: name.to_owned(),
+            status,
+            duration_secs: Some(1.5),
+            message: None,
+            stdout: stdout.to_owned(),
+            stderr: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_split_details() {
+        assert_eq!(
+            split_details("---- STDOUT ----\nout\n---- STDERR ----\nerr\n"),
+            ("out", "err")
+        );
+        assert_eq!(split_details("something else"), ("something else", ""));
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let output = "é".repeat(MAX_OUTPUT_BYTES);
+        let excerpt = excerpt(&output);
+        assert!(excerpt.starts_with("[... "));
+        assert!(excerpt.len() < output.len());
+        assert_eq!(super::excerpt("short"), "short");
+    }
+
+    #[test]
+    fn test_target_durations_from_json_report() {
+        let results = [
+            case("a", TestStatus::Pass, ""),
+            case("b", TestStatus::Rerun, ""),
+        ];
+        let report = serde_json::to_string(&JsonReport {
+            summary: BTreeMap::new(),
+            results: &results,
+        })
+        .unwrap();
+
+        assert_eq!(
+            target_durations_from_json_report(&report).unwrap(),
+            HashMap::from([("root//foo:bar".to_owned(), 3.0)])
+        );
+    }
+
+    #[test]
+    fn test_junit_xml() {
+        let results = [
+            case("a", TestStatus::Pass, ""),
+            case("b", TestStatus::Rerun, "flake"),
+            case("b", TestStatus::Fail, "x < y"),
+            case("c", TestStatus::Skip, ""),
+        ];
+
+        let xml = junit_xml(&results);
+        assert!(xml.contains(
+            r#"<testsuite name="root//foo:bar (cfg)" tests="3" failures="1" errors="0" skipped="1" time="4.500">"#
+        ));
+        assert!(xml.contains(r#"<failure message="FAIL"/>"#));
+        assert!(xml.contains("<system-out>x &lt; y</system-out>"));
+        assert!(!xml.contains("flake"));
+    }
+}
@@ -9,14 +9,26 @@
 
 //! Core dice computations relating to cells
 
+use std::fmt::Write;
+
 use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_core::cells::CellResolver;
 use derive_more::Display;
 use dice::DiceComputations;
+use dice::DiceData;
+use dice::DiceDataBuilder;
 use dice::DiceTransactionUpdater;
 use dice::InjectedKey;
+use dice::PersistentKey;
 use dupe::Dupe;
+use itertools::Itertools;
+
+#[derive(Debug, buck2_error::Error)]
+enum SavedCellResolverError {
+    #[error("Cells have changed since the DICE graph was saved")]
+    CellsChanged,
+}
 
 #[async_trait]
 pub trait HasCellResolver {
@@ -31,6 +43,14 @@ pub trait SetCellResolver {
     fn set_none_cell_resolver(&mut self) -> anyhow::Result<()>;
 }
 
+pub trait SetStartupCellResolver {
+    /// The cells the daemon was started with. A saved DICE graph only keeps the values computed
+    /// from its cells if they are the same.
+    fn set_startup_cell_resolver(&mut self, cell_resolver: CellResolver);
+}
+
+struct StartupCellResolver(CellResolver);
+
 #[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
 #[display(fmt = "{:?}", self)]
 struct CellResolverKey;
@@ -47,6 +67,65 @@ impl InjectedKey for CellResolverKey {
     }
 }
 
+/// The cells aren't saved: the saved value only records them, and loads as the startup cells if
+/// they match.
+impl PersistentKey for CellResolverKey {
+    const NAME: &'static str = "CellResolverKey";
+
+    fn key_to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn key_from_bytes(_bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(CellResolverKey)
+    }
+
+    fn value_to_bytes(value: &Self::Value) -> Option<Vec<u8>> {
+        value.as_ref().map(cell_resolver_fingerprint)
+    }
+
+    fn value_from_bytes(bytes: &[u8], data: &DiceData) -> anyhow::Result<Self::Value> {
+        let StartupCellResolver(cells) = data.get::<StartupCellResolver>()?;
+        if bytes != cell_resolver_fingerprint(cells) {
+            return Err(SavedCellResolverError::CellsChanged.into());
+        }
+        Ok(Some(cells.dupe()))
+    }
+}
+
+/// Everything that identifies the cells, in a deterministic order.
+fn cell_resolver_fingerprint(cells: &CellResolver) -> Vec<u8> {
+    let mut fingerprint = String::new();
+    for (name, cell) in cells.cells().sorted_by_key(|(name, _)| *name) {
+        writeln!(
+            fingerprint,
+            "{} {:?} {:?}",
+            name,
+            cell.path().as_str(),
+            cell.buildfiles(),
+        )
+        .unwrap();
+        for (alias, target) in cell
+            .cell_alias_resolver()
+            .mappings()
+            .sorted_by_key(|(alias, _)| *alias)
+        {
+            writeln!(fingerprint, "  {} = {}", alias, target).unwrap();
+        }
+    }
+    fingerprint.into_bytes()
+}
+
+pub(crate) fn register_persistent_cell_resolver_key(builder: &mut DiceDataBuilder) {
+    builder.register_persistent_key::<CellResolverKey>();
+}
+
+impl SetStartupCellResolver for DiceDataBuilder {
+    fn set_startup_cell_resolver(&mut self, cell_resolver: CellResolver) {
+        self.set(StartupCellResolver(cell_resolver))
+    }
+}
+
 #[async_trait]
 impl HasCellResolver for DiceComputations {
     async fn get_cell_resolver(&self) -> anyhow::Result<CellResolver> {
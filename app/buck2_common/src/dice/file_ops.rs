@@ -18,6 +18,7 @@ use buck2_core::cells::cell_path::CellPath;
 use buck2_core::cells::cell_path::CellPathRef;
 use buck2_core::cells::cell_root_path::CellRootPathBuf;
 use buck2_core::cells::name::CellName;
+use buck2_core::cells::paths::CellRelativePathBuf;
 use buck2_core::cells::unchecked_cell_rel_path::UncheckedCellRelativePath;
 use buck2_core::cells::CellResolver;
 use buck2_core::fs::paths::file_name::FileNameBuf;
@@ -30,19 +31,28 @@ use cmp_any::PartialEqAny;
 use derivative::Derivative;
 use derive_more::Display;
 use dice::DiceComputations;
+use dice::DiceData;
+use dice::DiceDataBuilder;
 use dice::DiceTransactionUpdater;
 use dice::Key;
+use dice::PersistentKey;
 use dupe::Dupe;
 
+use crate::cas_digest::CasDigestConfig;
+use crate::cas_digest::DigestAlgorithmKind;
+use crate::dice::cells::register_persistent_cell_resolver_key;
 use crate::dice::cells::HasCellResolver;
 use crate::dice::data::HasIoProvider;
 use crate::dice::file_ops::keys::FileOpsKey;
 use crate::dice::file_ops::keys::FileOpsValue;
+use crate::file_ops::FileDigest;
+use crate::file_ops::FileMetadata;
 use crate::file_ops::FileOps;
 use crate::file_ops::RawDirEntry;
 use crate::file_ops::RawPathMetadata;
 use crate::file_ops::ReadDirOutput;
 use crate::file_ops::SimpleDirEntry;
+use crate::file_ops::TrackedFileDigest;
 use crate::ignores::all_cells::AllCellIgnores;
 use crate::ignores::all_cells::HasAllCellIgnores;
 use crate::io::IoProvider;
@@ -366,6 +376,58 @@ impl Key for ReadFileKey {
     }
 }
 
+impl PersistentKey for ReadFileKey {
+    const NAME: &'static str = "ReadFileKey";
+
+    fn key_to_bytes(&self) -> Vec<u8> {
+        cell_path_to_bytes(&self.0)
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(ReadFileKey(Arc::new(cell_path_from_bytes(bytes)?)))
+    }
+
+    fn value_to_bytes(value: &Self::Value) -> Option<Vec<u8>> {
+        Some(cell_path_to_bytes(&value.0))
+    }
+
+    fn value_from_bytes(bytes: &[u8], _data: &DiceData) -> anyhow::Result<Self::Value> {
+        Ok(FileToken(Arc::new(cell_path_from_bytes(bytes)?)))
+    }
+}
+
+fn cell_path_to_bytes(path: &CellPath) -> Vec<u8> {
+    format!("{}//{}", path.cell(), path.path()).into_bytes()
+}
+
+fn cell_path_from_bytes(bytes: &[u8]) -> anyhow::Result<CellPath> {
+    let (cell, path) = std::str::from_utf8(bytes)?
+        .split_once("//")
+        .ok_or_else(|| anyhow::anyhow!("Invalid saved cell path"))?;
+    Ok(CellPath::new(
+        CellName::unchecked_new(cell)?,
+        CellRelativePathBuf::try_from(path.to_owned())?,
+    ))
+}
+
+#[derive(Debug, buck2_error::Error)]
+enum SavedPathMetadataError {
+    #[error("Invalid saved path metadata")]
+    Invalid,
+    #[error("Saved file digest uses {0}, but source files are now hashed with {1}")]
+    DigestAlgorithmChanged(DigestAlgorithmKind, DigestAlgorithmKind),
+    #[error("Saved file digests can't be checked against a keyed digest algorithm")]
+    KeyedDigest,
+}
+
+/// Register the keys whose values DICE can save across daemon restarts: file metadata, so that
+/// files aren't hashed again, along with everything it is computed from.
+pub fn register_persistent_keys(builder: &mut DiceDataBuilder) {
+    register_persistent_cell_resolver_key(builder);
+    builder.register_persistent_key::<ReadFileKey>();
+    builder.register_persistent_key::<PathMetadataKey>();
+}
+
 #[derive(Clone, Display, Debug, Eq, Hash, PartialEq, Allocative)]
 struct ReadDirKey(CellPath);
 
@@ -407,10 +469,17 @@ impl Key for PathMetadataKey {
         ctx: &mut DiceComputations,
         _cancellations: &CancellationContext,
     ) -> Self::Value {
-        let res = get_default_file_ops(ctx)
-            .await?
-            .read_path_metadata_if_exists(self.0.as_ref())
-            .await?;
+        // Only computed from the cells and the files, rather than from `FileOpsKey`, which is not
+        // persistent, so that this can be saved across daemon restarts.
+        let cells = ctx.get_cell_resolver().await?;
+        let io = ctx.global_data().get_io_provider();
+        let res = io
+            .read_path_metadata_if_exists(cells.resolve_path(self.0.as_ref())?)
+            .await
+            .with_context(|| format!("Error accessing metadata for path `{}`", self.0))?;
+        let res = res
+            .map(|meta| meta.try_map(|path| anyhow::Ok(Arc::new(cells.get_cell_path(&path)?))))
+            .transpose()?;
 
         match res {
             Some(RawPathMetadata::Symlink {
@@ -437,6 +506,70 @@ impl Key for PathMetadataKey {
     }
 }
 
+/// Only files, directories and missing paths are saved: symlinks are cheap to read again.
+impl PersistentKey for PathMetadataKey {
+    const NAME: &'static str = "PathMetadataKey";
+
+    fn key_to_bytes(&self) -> Vec<u8> {
+        cell_path_to_bytes(&self.0)
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(PathMetadataKey(cell_path_from_bytes(bytes)?))
+    }
+
+    fn value_to_bytes(value: &Self::Value) -> Option<Vec<u8>> {
+        match value {
+            Ok(None) => Some(b"none".to_vec()),
+            Ok(Some(RawPathMetadata::Directory)) => Some(b"dir".to_vec()),
+            Ok(Some(RawPathMetadata::File(meta))) => Some(
+                format!(
+                    "file {} {} {} {}",
+                    meta.digest.raw_digest().algorithm(),
+                    meta.digest.raw_digest(),
+                    meta.digest.size(),
+                    meta.is_executable,
+                )
+                .into_bytes(),
+            ),
+            Ok(Some(RawPathMetadata::Symlink { .. })) | Err(_) => None,
+        }
+    }
+
+    fn value_from_bytes(bytes: &[u8], data: &DiceData) -> anyhow::Result<Self::Value> {
+        let value = std::str::from_utf8(bytes)?;
+        let meta = match value.split(' ').collect::<Vec<_>>().as_slice() {
+            ["none"] => None,
+            ["dir"] => Some(RawPathMetadata::Directory),
+            ["file", algorithm, digest, size, is_executable] => {
+                let config = data.get::<CasDigestConfig>()?.source_files_config();
+                let algorithm: DigestAlgorithmKind = algorithm.parse()?;
+                // Digests are not checked again, so they must be what hashing the file now
+                // would give.
+                let source_algorithm = config.preferred_algorithm().kind();
+                if source_algorithm == DigestAlgorithmKind::Blake3Keyed {
+                    return Err(SavedPathMetadataError::KeyedDigest.into());
+                }
+                if algorithm != source_algorithm {
+                    return Err(SavedPathMetadataError::DigestAlgorithmChanged(
+                        algorithm,
+                        source_algorithm,
+                    )
+                    .into());
+                }
+                let digest =
+                    FileDigest::from_digest_bytes(algorithm, &hex::decode(digest)?, size.parse()?)?;
+                Some(RawPathMetadata::File(FileMetadata {
+                    digest: TrackedFileDigest::new(digest, config),
+                    is_executable: is_executable.parse()?,
+                }))
+            }
+            _ => return Err(SavedPathMetadataError::Invalid.into()),
+        };
+        Ok(Ok(meta))
+    }
+}
+
 #[async_trait]
 impl<'c> FileOps for DiceFileOps<'c> {
     async fn read_file_if_exists(
@@ -485,3 +618,157 @@ impl<'c> FileOps for DiceFileOps<'c> {
 pub mod testing {
     pub use super::keys::FileOpsKey;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::fs::project::ProjectRoot;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use dice::DetectCycles;
+    use dice::Dice;
+
+    use super::*;
+    use crate::dice::cells::SetCellResolver;
+    use crate::dice::cells::SetStartupCellResolver;
+    use crate::dice::data::SetIoProvider;
+    use crate::io::fs::FsIoProvider;
+
+    /// Counts how many paths had their metadata read, i.e. how many files were hashed.
+    #[derive(Allocative)]
+    struct CountingIoProvider {
+        inner: FsIoProvider,
+        #[allocative(skip)]
+        metadata_reads: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl IoProvider for CountingIoProvider {
+        async fn read_file_if_exists(
+            &self,
+            path: ProjectRelativePathBuf,
+        ) -> anyhow::Result<Option<String>> {
+            self.inner.read_file_if_exists(path).await
+        }
+
+        async fn read_dir(&self, path: ProjectRelativePathBuf) -> anyhow::Result<Vec<RawDirEntry>> {
+            self.inner.read_dir(path).await
+        }
+
+        async fn read_path_metadata_if_exists(
+            &self,
+            path: ProjectRelativePathBuf,
+        ) -> anyhow::Result<Option<RawPathMetadata<ProjectRelativePathBuf>>> {
+            self.metadata_reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.read_path_metadata_if_exists(path).await
+        }
+
+        async fn settle(&self) -> anyhow::Result<()> {
+            self.inner.settle().await
+        }
+
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn eden_version(&self) -> anyhow::Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn project_root(&self) -> &ProjectRoot {
+            self.inner.project_root()
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    fn dice(fs: &ProjectRootTemp, cells: &CellResolver) -> (Arc<Dice>, Arc<CountingIoProvider>) {
+        let config = CasDigestConfig::testing_default();
+        let io = Arc::new(CountingIoProvider {
+            inner: FsIoProvider::new(fs.path().dupe(), config),
+            metadata_reads: AtomicUsize::new(0),
+        });
+        let mut builder = Dice::modern();
+        builder.set_io_provider(io.dupe());
+        builder.set(config);
+        builder.set_startup_cell_resolver(cells.dupe());
+        register_persistent_keys(&mut builder);
+        (builder.build(DetectCycles::Enabled), io)
+    }
+
+    async fn read_path_metadata(
+        dice: &Arc<Dice>,
+        cells: &CellResolver,
+        path: &CellPath,
+    ) -> anyhow::Result<Option<RawPathMetadata>> {
+        let mut updater = dice.updater();
+        updater.set_cell_resolver(cells.dupe())?;
+        let ctx = updater.commit().await;
+        ctx.file_ops()
+            .read_path_metadata_if_exists(path.as_ref())
+            .await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_saved_path_metadata_is_reused() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        fs.write_file("foo/bar.txt", "hello");
+        let cells = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let path = CellPath::testing_new("root//foo/bar.txt");
+
+        let (dice, _) = self::dice(&fs, &cells);
+        let metadata = read_path_metadata(&dice, &cells, &path).await?;
+        assert!(matches!(metadata, Some(RawPathMetadata::File(_))));
+        let mut saved = Vec::new();
+        // The cells and the path metadata.
+        assert_eq!(dice.save_graph(&mut saved)?, 2);
+
+        let (dice, io) = self::dice(&fs, &cells);
+        assert_eq!(dice.load_graph(&mut saved.as_slice())?, 2);
+        assert_eq!(read_path_metadata(&dice, &cells, &path).await?, metadata);
+        assert_eq!(io.metadata_reads.load(Ordering::SeqCst), 0);
+
+        // Changed files are read again.
+        let mut updater = dice.updater();
+        let mut tracker = FileChangeTracker::new();
+        tracker.file_changed(path.clone());
+        tracker.write_to_dice(&mut updater)?;
+        updater.commit().await;
+        assert_eq!(read_path_metadata(&dice, &cells, &path).await?, metadata);
+        assert_eq!(io.metadata_reads.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_saved_path_metadata_is_dropped_when_cells_change() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        fs.write_file("foo/bar.txt", "hello");
+        let cells = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let path = CellPath::testing_new("root//foo/bar.txt");
+
+        let (dice, _) = self::dice(&fs, &cells);
+        read_path_metadata(&dice, &cells, &path).await?;
+        let mut saved = Vec::new();
+        assert_eq!(dice.save_graph(&mut saved)?, 2);
+
+        let other_cells = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("other"),
+            CellRootPathBuf::testing_new("other"),
+        );
+        let (dice, _) = self::dice(&fs, &other_cells);
+        assert_eq!(dice.load_graph(&mut saved.as_slice())?, 0);
+
+        Ok(())
+    }
+}
@@ -358,6 +358,7 @@ fn display_file_watcher(provider: i32) -> &'static str {
         Some(buck2_data::FileWatcherProvider::Watchman) => "Watchman",
         Some(buck2_data::FileWatcherProvider::RustNotify) => "notify",
         Some(buck2_data::FileWatcherProvider::FsHashCrawler) => "fs_hash_crawler",
+        Some(buck2_data::FileWatcherProvider::Inotify) => "inotify",
         None => "unknown mechanism",
     }
 }
@@ -440,6 +441,7 @@ pub fn display_executor_stage(
             match buck2_data::CacheType::from_i32(cache_query.cache_type).unwrap() {
                 buck2_data::CacheType::ActionCache => "re_action_cache",
                 buck2_data::CacheType::RemoteDepFileCache => "re_dep_file_cache",
+                buck2_data::CacheType::LocalActionCache => "local_action_cache",
             }
         }
         Stage::CacheHit(..) => "re_download",
@@ -635,6 +637,12 @@ impl<'a> ActionErrorDisplay<'a> {
                         remote_command.action_digest
                     );
                 }
+                Some(Command::LocalActionCacheCommand(local_action_cache_command)) => {
+                    append!(
+                        "Local action cache hit: {}",
+                        local_action_cache_command.action_digest
+                    );
+                }
                 Some(Command::OmittedLocalCommand(..)) | None => {
                     // Nothing to show in this case.
                 }
@@ -722,6 +730,7 @@ fn failure_reason_for_command_execution(
             Some(Command::LocalCommand(..)) | Some(Command::OmittedLocalCommand(..)) => "Local ",
             Some(Command::WorkerInitCommand(..)) => "Local Worker Initialization ",
             Some(Command::WorkerCommand(..)) => "Local Worker ",
+            Some(Command::LocalActionCacheCommand(..)) => "Local Action Cache ",
             None => "",
         }
     } else {
@@ -761,6 +770,11 @@ fn failure_reason_for_command_execution(
 
             format!("Command timed out after {:.3}s", duration.as_secs_f64(),)
         }
+        // Not an internal error: the command ran fine, twice, but with different results
+        // (see `buck2 build --check-determinism`).
+        Status::Error(Error { stage, error }) if stage == "check_determinism" => {
+            format!("{}command is not deterministic: {}", locality, error)
+        }
         Status::Error(Error { stage, error }) => {
             format!("Internal error (stage: {}): {}", stage, error)
         }
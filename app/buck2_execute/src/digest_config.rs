@@ -116,6 +116,8 @@ impl HasDigestConfig for DiceData {
 
 impl SetDigestConfig for DiceDataBuilder {
     fn set_digest_config(&mut self, digest_config: DigestConfig) {
+        // Also set on its own, for crates that don't know about `DigestConfig`.
+        self.set(digest_config.cas_digest_config());
         self.set(digest_config)
     }
 }
@@ -67,6 +67,9 @@ pub enum CommandExecutionKind {
         env: SortedVectorMap<String, String>,
         fallback_exe: Vec<String>,
     },
+    /// This action was served by the local on-disk action cache and not executed.
+    #[display(fmt = "local_action_cache")]
+    LocalActionCache { digest: ActionDigest },
 }
 
 impl CommandExecutionKind {
@@ -79,6 +82,7 @@ impl CommandExecutionKind {
             Self::Remote { .. } => buck2_data::ActionExecutionKind::Remote,
             Self::ActionCache { .. } => buck2_data::ActionExecutionKind::ActionCache,
             Self::RemoteDepFileCache { .. } => buck2_data::ActionExecutionKind::RemoteDepFileCache,
+            Self::LocalActionCache { .. } => buck2_data::ActionExecutionKind::LocalActionCache,
         }
     }
 
@@ -181,6 +185,12 @@ impl CommandExecutionKind {
                     .collect(),
                 fallback_exe: fallback_exe.to_owned(),
             }),
+
+            Self::LocalActionCache { digest } => {
+                Command::LocalActionCacheCommand(buck2_data::LocalActionCacheCommand {
+                    action_digest: digest.to_string(),
+                })
+            }
         });
 
         buck2_data::CommandExecutionKind { command }
@@ -40,6 +40,9 @@ pub struct PreparedCommand<'a, 'b> {
     pub target: &'b dyn CommandExecutionTarget,
     pub prepared_action: &'a PreparedAction,
     pub digest_config: DigestConfig,
+    /// When set, local execution uses a scratch directory with this suffix instead of the
+    /// action's own, so that a command running a second time doesn't reuse the first one's.
+    pub scratch_path_suffix: Option<&'static str>,
 }
 
 #[async_trait]
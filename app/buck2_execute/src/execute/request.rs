@@ -318,6 +318,8 @@ pub struct CommandExecutionRequest {
     /// Whether the executor should guarantee that the inodes for all inputs are unique (i.e. avoid
     /// hardlinking identical input files, for example)
     unique_input_inodes: bool,
+    /// If set, the most memory, in bytes, this command may use when it runs locally.
+    memory_limit: Option<u64>,
     /// Remote dep file key, if the action has a dep file.
     /// If this key is set and remote dep file caching is enabled, it will be used to query the cache.
     pub remote_dep_file_key: Option<DepFileDigest>,
@@ -348,6 +350,7 @@ impl CommandExecutionRequest {
             required_local_resources: SortedSet::new(),
             worker: None,
             unique_input_inodes: false,
+            memory_limit: None,
             remote_dep_file_key: None,
         }
     }
@@ -528,6 +531,15 @@ impl CommandExecutionRequest {
     pub fn unique_input_inodes(&self) -> bool {
         self.unique_input_inodes
     }
+
+    pub fn with_memory_limit(mut self, memory_limit: Option<u64>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn memory_limit(&self) -> Option<u64> {
+        self.memory_limit
+    }
 }
 
 /// Is an output a file or a directory
@@ -367,6 +367,7 @@ mod tests {
                     time_enabled: 50,
                     time_running: 100,
                 }),
+                ..Default::default()
             }),
             input_materialization_duration: Duration::from_secs(6),
             hashing_duration: Duration::from_secs(7),
@@ -417,6 +418,7 @@ mod tests {
                 time_enabled: 50,
                 time_running: 100,
             }),
+            ..Default::default()
         };
         let command_execution_metadata = buck2_data::CommandExecutionMetadata {
             wall_time: Some(Duration {
@@ -62,6 +62,7 @@ impl PreparedCommandExecutor for DryRunExecutor {
             target: _target,
             prepared_action: _prepared_action,
             digest_config,
+            scratch_path_suffix: _,
         } = command;
 
         let manager = manager.claim().await;
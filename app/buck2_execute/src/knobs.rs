@@ -7,8 +7,17 @@
  * of this source tree.
  */
 
+use std::sync::Arc;
+
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use dupe::Dupe;
 
+/// Host paths visible in the hermetic sandbox unless `build.hermetic_sandbox_host_paths` is set.
+/// Those that don't exist on the host are left out.
+pub const DEFAULT_HERMETIC_SANDBOX_HOST_PATHS: &[&str] = &[
+    "/bin", "/etc", "/lib", "/lib64", "/nix", "/opt", "/sbin", "/usr",
+];
+
 /// Command-level config that can tweak how the executors work.
 #[derive(Clone, Dupe, Default)]
 pub struct ExecutorGlobalKnobs {
@@ -17,4 +26,12 @@ pub struct ExecutorGlobalKnobs {
     /// Whether to emit action keys to execution logs (thos are pretty verbose and omitted by
     /// default).
     pub log_action_keys: bool,
+
+    /// If set, local actions run in a hermetic sandbox, where only their declared inputs and
+    /// outputs, plus these host paths (e.g. for the system toolchain), are visible.
+    pub hermetic_sandbox_host_paths: Option<Arc<Vec<AbsNormPathBuf>>>,
+
+    /// Whether the forkserver runs local actions in cgroups (`buck2.local_action_cgroups`),
+    /// which is what enforces their memory limits.
+    pub local_action_cgroups: bool,
 }
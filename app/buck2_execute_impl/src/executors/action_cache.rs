@@ -37,6 +37,7 @@ use buck2_futures::cancellation::CancellationContext;
 use dupe::Dupe;
 use prost::Message;
 
+use crate::executors::action_cache_verification::ActionCacheVerifier;
 use crate::re::download::download_action_results;
 use crate::re::download::DownloadResult;
 use crate::re::paranoid_download::ParanoidDownloader;
@@ -51,6 +52,7 @@ pub struct ActionCacheChecker {
     pub knobs: ExecutorGlobalKnobs,
     pub paranoid: Option<ParanoidDownloader>,
     pub remote_dep_file_checker: Arc<dyn PreparedCommandOptionalExecutor>,
+    pub verifier: Option<Arc<ActionCacheVerifier>>,
 }
 
 enum CacheType {
@@ -83,6 +85,7 @@ async fn query_action_cache_and_download_result(
     upload_all_actions: bool,
     log_action_keys: bool,
     details: RemoteCommandExecutionDetails,
+    verifier: Option<&ActionCacheVerifier>,
 ) -> ControlFlow<CommandExecutionResult, CommandExecutionManager> {
     let request = command.request;
     let action_blobs = &command.prepared_action.action_and_blobs.blobs;
@@ -130,6 +133,12 @@ async fn query_action_cache_and_download_result(
         Ok(None) => return ControlFlow::Continue(manager),
     };
 
+    if let (CacheType::ActionCache, Some(verifier)) = (&cache_type, verifier) {
+        if verifier.skip_cache_hit(&digest, &response.action_result) {
+            return ControlFlow::Continue(manager);
+        }
+    }
+
     let action_exit_code = response.action_result.exit_code;
 
     // Select the RemoteActionResult type so that we set the CommandExecutionKind properly.
@@ -254,6 +263,7 @@ impl PreparedCommandOptionalExecutor for ActionCacheChecker {
             self.upload_all_actions,
             self.knobs.log_action_keys,
             details,
+            self.verifier.as_deref(),
         )
         .await;
 
@@ -325,6 +335,7 @@ impl PreparedCommandOptionalExecutor for RemoteDepFileCacheChecker {
             self.upload_all_actions,
             self.knobs.log_action_keys,
             details,
+            None,
         )
         .await
     }
@@ -0,0 +1,332 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Verification of action cache hits. A sample of hits is not served from the cache: the action
+//! is executed again instead, and its outputs are compared against the cached result. Mismatches
+//! point at nondeterministic rules, or at a corrupted or compromised cache.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::rollout_percentage::RolloutPercentage;
+use buck2_events::dispatch::get_dispatcher_opt;
+use buck2_execute::digest::CasDigestToReExt;
+use buck2_execute::digest_config::DigestConfig;
+use buck2_execute::directory::directory_to_re_tree;
+use buck2_execute::directory::ActionDirectoryEntry;
+use buck2_execute::directory::ActionDirectoryMember;
+use buck2_execute::directory::ActionSharedDirectory;
+use buck2_execute::execute::action_digest::ActionDigest;
+use buck2_execute::execute::blobs::ActionBlobs;
+use buck2_execute::execute::manager::CommandExecutionManager;
+use buck2_execute::execute::prepared::PreparedCommand;
+use buck2_execute::execute::prepared::PreparedCommandExecutor;
+use buck2_execute::execute::request::ExecutorPreference;
+use buck2_execute::execute::result::CommandExecutionResult;
+use buck2_execute::execute::result::CommandExecutionStatus;
+use buck2_futures::cancellation::CancellationContext;
+use dashmap::DashMap;
+use dashmap::DashSet;
+use dupe::Dupe;
+use remote_execution::TActionResult2;
+
+/// The outputs of a cached action result, keyed by path.
+struct CachedOutputs {
+    exit_code: i32,
+    digests: BTreeMap<String, String>,
+}
+
+impl CachedOutputs {
+    fn new(action_result: &TActionResult2) -> Self {
+        let files = action_result
+            .output_files
+            .iter()
+            .map(|f| (f.name.clone(), f.digest.digest.to_string()));
+        let directories = action_result
+            .output_directories
+            .iter()
+            .map(|d| (d.path.clone(), d.tree_digest.to_string()));
+
+        Self {
+            exit_code: action_result.exit_code,
+            digests: files.chain(directories).collect(),
+        }
+    }
+}
+
+/// Shared by every executor for the lifetime of the daemon, so that quarantined results stay
+/// quarantined across commands.
+pub struct ActionCacheVerifier {
+    sample_rate: RolloutPercentage,
+    quarantine: bool,
+    /// Actions currently being re-executed for verification.
+    pending: DashMap<ActionDigest, CachedOutputs>,
+    /// Actions whose cached result did not match re-execution.
+    quarantined: DashSet<ActionDigest>,
+}
+
+impl ActionCacheVerifier {
+    pub fn new(sample_rate: RolloutPercentage, quarantine: bool) -> Self {
+        Self {
+            sample_rate,
+            quarantine,
+            pending: DashMap::new(),
+            quarantined: DashSet::new(),
+        }
+    }
+
+    /// Called on an action cache hit. Returns whether the hit should be ignored: either because
+    /// the result is quarantined, or because it was sampled for verification, in which case the
+    /// caller should fall through to execution.
+    pub fn skip_cache_hit(&self, digest: &ActionDigest, action_result: &TActionResult2) -> bool {
+        if self.is_quarantined(digest) {
+            tracing::debug!("Ignoring quarantined action cache result for `{}`", digest);
+            return true;
+        }
+
+        if !self.sample_rate.roll() {
+            return false;
+        }
+
+        tracing::debug!(
+            "Re-executing `{}` to verify its action cache result",
+            digest
+        );
+        self.pending
+            .insert(digest.dupe(), CachedOutputs::new(action_result));
+        true
+    }
+
+    /// Whether this action is being re-executed for verification. The remote executor skips its
+    /// own cache lookup for those and for quarantined actions, since it would otherwise return the
+    /// very result we're checking, or the one we've rejected.
+    pub fn is_pending(&self, digest: &ActionDigest) -> bool {
+        self.pending.contains_key(digest)
+    }
+
+    pub fn is_quarantined(&self, digest: &ActionDigest) -> bool {
+        self.quarantined.contains(digest)
+    }
+
+    fn verify(
+        &self,
+        artifact_fs: &ArtifactFs,
+        command: &PreparedCommand<'_, '_>,
+        result: &CommandExecutionResult,
+    ) {
+        let digest = &command.prepared_action.action_and_blobs.action;
+        let cached = match self.pending.remove(digest) {
+            Some((_, cached)) => cached,
+            None => return,
+        };
+
+        if matches!(result.report.status, CommandExecutionStatus::Cancelled) {
+            return;
+        }
+
+        let actual = match actual_outputs(artifact_fs, result, command.digest_config) {
+            Some(actual) => actual,
+            None => {
+                tracing::debug!(
+                    "Not verifying action cache result for `{}`: it has symlink outputs",
+                    digest
+                );
+                return;
+            }
+        };
+        let paths: BTreeSet<&String> = cached.digests.keys().chain(actual.keys()).collect();
+        let outputs: Vec<_> = paths
+            .into_iter()
+            .filter_map(|path| {
+                let cached_digest = cached.digests.get(path).cloned().unwrap_or_default();
+                let actual_digest = actual.get(path).cloned().unwrap_or_default();
+                if cached_digest == actual_digest {
+                    None
+                } else {
+                    Some(buck2_data::ActionCacheVerificationOutput {
+                        path: path.clone(),
+                        cached_digest,
+                        actual_digest,
+                    })
+                }
+            })
+            .collect();
+
+        let actual_exit_code = result.report.exit_code;
+        if outputs.is_empty() && actual_exit_code == Some(cached.exit_code) {
+            tracing::debug!("Action cache result for `{}` verified", digest);
+            return;
+        }
+
+        if self.quarantine {
+            self.quarantined.insert(digest.dupe());
+        }
+
+        tracing::warn!(
+            "Action cache result for `{}` (`{}`) does not match re-execution: {} output(s) differ{}",
+            command.target.re_action_key(),
+            digest,
+            outputs.len(),
+            if self.quarantine {
+                ", quarantining it"
+            } else {
+                ""
+            },
+        );
+
+        if let Some(dispatcher) = get_dispatcher_opt() {
+            dispatcher.instant_event(buck2_data::ActionCacheVerificationMismatch {
+                key: Some(command.target.as_proto_action_key()),
+                name: Some(command.target.as_proto_action_name()),
+                action_digest: digest.to_string(),
+                outputs,
+                cached_exit_code: cached.exit_code,
+                actual_exit_code,
+                quarantined: self.quarantine,
+            });
+        }
+    }
+}
+
+/// Digests of the outputs of an execution, in the same format as `CachedOutputs`. Returns `None`
+/// if any output can't be compared.
+fn actual_outputs(
+    artifact_fs: &ArtifactFs,
+    result: &CommandExecutionResult,
+    digest_config: DigestConfig,
+) -> Option<BTreeMap<String, String>> {
+    result
+        .resolve_outputs(artifact_fs)
+        .map(|(output, value)| {
+            let digest = output_digest(value.entry(), digest_config)?;
+            Some((output.path().to_string(), digest))
+        })
+        .collect()
+}
+
+/// The digest of an output, as found in a cached action result. Symlink outputs aren't
+/// represented in `CachedOutputs`, so they have none.
+fn output_digest(
+    entry: &ActionDirectoryEntry<ActionSharedDirectory>,
+    digest_config: DigestConfig,
+) -> Option<String> {
+    match entry {
+        DirectoryEntry::Leaf(ActionDirectoryMember::File(f)) => Some(f.digest.to_re().to_string()),
+        // Use the same tree encoding as cache uploads, so that results written by Buck2 compare
+        // equal.
+        DirectoryEntry::Dir(d) => {
+            let tree = directory_to_re_tree(d);
+            let mut blobs = ActionBlobs::new(digest_config);
+            Some(
+                blobs
+                    .add_protobuf_message(&tree, digest_config)
+                    .to_re()
+                    .to_string(),
+            )
+        }
+        DirectoryEntry::Leaf(
+            ActionDirectoryMember::Symlink(..) | ActionDirectoryMember::ExternalSymlink(..),
+        ) => None,
+    }
+}
+
+/// Wraps the executor to compare the results of actions re-executed for verification against
+/// their cached result.
+pub struct ActionCacheVerifyingExecutor {
+    pub artifact_fs: ArtifactFs,
+    pub verifier: Arc<ActionCacheVerifier>,
+    pub inner: Arc<dyn PreparedCommandExecutor>,
+}
+
+#[async_trait]
+impl PreparedCommandExecutor for ActionCacheVerifyingExecutor {
+    async fn exec_cmd(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> CommandExecutionResult {
+        let result = self.inner.exec_cmd(command, manager, cancellations).await;
+        self.verifier.verify(&self.artifact_fs, command, &result);
+        result
+    }
+
+    fn is_local_execution_possible(&self, executor_preference: ExecutorPreference) -> bool {
+        self.inner.is_local_execution_possible(executor_preference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use remote_execution::TDigest;
+    use remote_execution::TFile;
+
+    use super::*;
+
+    #[test]
+    fn test_cached_outputs() {
+        let action_result = TActionResult2 {
+            output_files: vec![TFile {
+                name: "buck-out/v2/gen/foo".to_owned(),
+                digest: remote_execution::DigestWithStatus {
+                    digest: TDigest {
+                        hash: "aa".to_owned(),
+                        size_in_bytes: 3,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            exit_code: 0,
+            ..Default::default()
+        };
+
+        let cached = CachedOutputs::new(&action_result);
+        assert_eq!(cached.exit_code, 0);
+        assert_eq!(
+            cached.digests.into_iter().collect::<Vec<_>>(),
+            vec![("buck-out/v2/gen/foo".to_owned(), "aa:3".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_never_sampled() {
+        let verifier = ActionCacheVerifier::new(RolloutPercentage::never(), true);
+        let digest = ActionDigest::empty(DigestConfig::testing_default().cas_digest_config());
+        assert!(!verifier.skip_cache_hit(&digest, &TActionResult2::default()));
+        assert!(!verifier.is_pending(&digest));
+    }
+
+    #[test]
+    fn test_always_sampled() {
+        let verifier = ActionCacheVerifier::new(RolloutPercentage::always(), true);
+        let digest = ActionDigest::empty(DigestConfig::testing_default().cas_digest_config());
+        assert!(verifier.skip_cache_hit(&digest, &TActionResult2::default()));
+        assert!(verifier.is_pending(&digest));
+        assert!(!verifier.is_quarantined(&digest));
+    }
+
+    #[test]
+    fn test_symlink_outputs_have_no_digest() -> anyhow::Result<()> {
+        let digest_config = DigestConfig::testing_default();
+        let symlink = DirectoryEntry::Leaf(buck2_execute::directory::new_symlink("target")?);
+        assert_eq!(output_digest(&symlink, digest_config), None);
+
+        let file = DirectoryEntry::Leaf(ActionDirectoryMember::File(
+            buck2_common::file_ops::FileMetadata::empty(digest_config.cas_digest_config()),
+        ));
+        assert!(output_digest(&file, digest_config).is_some());
+        Ok(())
+    }
+}
@@ -56,6 +56,7 @@ use remote_execution::TStatus;
 use remote_execution::TTimestamp;
 
 use crate::executors::action_cache_upload_permission_checker::ActionCacheUploadPermissionChecker;
+use crate::executors::action_cache_verification::ActionCacheVerifier;
 use crate::executors::to_re_platform::RePlatformFieldsToRePlatform;
 
 // Whether to throw errors when cache uploads fail (primarily for tests).
@@ -78,6 +79,7 @@ pub struct CacheUploader {
     platform: RePlatformFields,
     max_bytes: Option<u64>,
     cache_upload_permission_checker: Arc<ActionCacheUploadPermissionChecker>,
+    verifier: Option<Arc<ActionCacheVerifier>>,
 }
 
 impl CacheUploader {
@@ -89,6 +91,7 @@ impl CacheUploader {
         platform: RePlatformFields,
         max_bytes: Option<u64>,
         cache_upload_permission_checker: Arc<ActionCacheUploadPermissionChecker>,
+        verifier: Option<Arc<ActionCacheVerifier>>,
     ) -> CacheUploader {
         CacheUploader {
             artifact_fs,
@@ -98,6 +101,7 @@ impl CacheUploader {
             platform,
             max_bytes,
             cache_upload_permission_checker,
+            verifier,
         }
     }
 
@@ -212,6 +216,14 @@ impl CacheUploader {
                 let mut tree_digests = Vec::new();
 
                 let res: std::result::Result<CacheUploadOutcome, anyhow::Error> = async {
+                    if let Some(verifier) = &self.verifier {
+                        if verifier.is_quarantined(&digest) {
+                            return Ok(CacheUploadOutcome::Rejected(
+                                CacheUploadRejectionReason::Quarantined,
+                            ));
+                        }
+                    }
+
                     if let Some(max_bytes) = self.max_bytes {
                         if output_bytes > max_bytes {
                             return Ok(CacheUploadOutcome::Rejected(
@@ -281,7 +293,8 @@ impl CacheUploader {
                         tracing::info!("Cache upload for `{}` rejected: {:#}", digest_str, reason);
                         let re_error_code = match reason {
                             CacheUploadRejectionReason::SymlinkOutput
-                            | CacheUploadRejectionReason::OutputExceedsLimit { .. } => None,
+                            | CacheUploadRejectionReason::OutputExceedsLimit { .. }
+                            | CacheUploadRejectionReason::Quarantined => None,
                             CacheUploadRejectionReason::PermissionDenied(_) => {
                                 Some(TCode::PERMISSION_DENIED.to_string())
                             }
@@ -499,6 +512,10 @@ enum CacheUploadRejectionReason {
     OutputExceedsLimit { max_bytes: u64 },
     #[display(fmt = "PermissionDenied (permission check error: {})", _0)]
     PermissionDenied(String),
+    /// The cached result for this action did not match re-execution, so we don't overwrite it
+    /// with a result that might be equally suspect.
+    #[display(fmt = "Quarantined")]
+    Quarantined,
 }
 
 #[async_trait]
@@ -0,0 +1,445 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Support for `buck2 build --check-determinism`: every command runs twice, and fails if its
+//! outputs differ between the two runs. Nondeterministic actions produce a different output for
+//! the same action digest every time they run, which defeats caching of everything downstream.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use buck2_core::directory::Directory;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::directory::DirectoryIterator;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_execute::directory::ActionDirectoryMember;
+use buck2_execute::execute::claim::MutexClaimManager;
+use buck2_execute::execute::manager::CommandExecutionManager;
+use buck2_execute::execute::prepared::PreparedCommand;
+use buck2_execute::execute::prepared::PreparedCommandExecutor;
+use buck2_execute::execute::request::ExecutorPreference;
+use buck2_execute::execute::result::CommandExecutionResult;
+use buck2_execute::execute::result::CommandExecutionStatus;
+use buck2_futures::cancellation::CancellationContext;
+use dupe::Dupe;
+use itertools::Itertools;
+
+const SECOND_RUN_SCRATCH_PATH_SUFFIX: &str = "determinism-check";
+
+#[derive(Debug, buck2_error::Error)]
+enum DeterminismCheckError {
+    #[error(
+        "{} output file(s) differ between two runs:\n{}",
+        .0.len(),
+        .0.iter().map(|d| format!("  {}", d)).join("\n")
+    )]
+    OutputsDiffer(Vec<String>),
+}
+
+/// Runs each command twice and compares the outputs of both runs file by file.
+pub struct DeterminismCheckingExecutor {
+    pub artifact_fs: ArtifactFs,
+    pub inner: Arc<dyn PreparedCommandExecutor>,
+}
+
+#[async_trait]
+impl PreparedCommandExecutor for DeterminismCheckingExecutor {
+    async fn exec_cmd(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> CommandExecutionResult {
+        // Actions that don't clean their outputs are incremental by design: their second run
+        // starts from the outputs of the first one, so there is nothing meaningful to compare.
+        if !command.request.outputs_cleanup {
+            return self.inner.exec_cmd(command, manager, cancellations).await;
+        }
+
+        let second_manager = CommandExecutionManager::new(
+            Box::new(MutexClaimManager::new()),
+            manager.events.dupe(),
+            manager.liveliness_observer.dupe(),
+        );
+
+        let first = self.inner.exec_cmd(command, manager, cancellations).await;
+        if !is_success(&first) {
+            return first;
+        }
+        let first_outputs = output_files(&self.artifact_fs, &first);
+
+        // Executors clean up outputs before running, so the second run starts from the same state
+        // as the first. It gets a scratch directory of its own, so that it doesn't see what the
+        // first run left there, and outputs embedding that path show up as differences.
+        let second_command = PreparedCommand {
+            request: command.request,
+            target: command.target,
+            prepared_action: command.prepared_action,
+            digest_config: command.digest_config,
+            scratch_path_suffix: Some(SECOND_RUN_SCRATCH_PATH_SUFFIX),
+        };
+        let mut second = self
+            .inner
+            .exec_cmd(&second_command, second_manager, cancellations)
+            .await;
+        if !is_success(&second) {
+            return second;
+        }
+        let second_outputs = output_files(&self.artifact_fs, &second);
+
+        let differences = diff_output_files(&first_outputs, &second_outputs);
+        if differences.is_empty() {
+            return second;
+        }
+
+        tracing::debug!(
+            "`{}` is not deterministic: {} output file(s) differ",
+            command.target.re_action_key(),
+            differences.len()
+        );
+
+        second.report.status = CommandExecutionStatus::Error {
+            stage: "check_determinism",
+            error: DeterminismCheckError::OutputsDiffer(differences).into(),
+            execution_kind: second.report.status.execution_kind().cloned(),
+        };
+        second.outputs.clear();
+        second
+    }
+
+    fn is_local_execution_possible(&self, executor_preference: ExecutorPreference) -> bool {
+        self.inner.is_local_execution_possible(executor_preference)
+    }
+}
+
+fn is_success(result: &CommandExecutionResult) -> bool {
+    matches!(result.report.status, CommandExecutionStatus::Success { .. })
+}
+
+/// Every file (or symlink) in the outputs of a command, keyed by path, described by whatever
+/// identifies its contents.
+fn output_files(
+    artifact_fs: &ArtifactFs,
+    result: &CommandExecutionResult,
+) -> BTreeMap<String, String> {
+    let mut files = BTreeMap::new();
+
+    for (output, value) in result.resolve_outputs(artifact_fs) {
+        match value.entry().as_ref() {
+            DirectoryEntry::Dir(d) => {
+                for (path, entry) in d.ordered_walk().with_paths() {
+                    if let DirectoryEntry::Leaf(member) = entry {
+                        files.insert(
+                            output.path().join(&path).to_string(),
+                            describe_member(member),
+                        );
+                    }
+                }
+            }
+            DirectoryEntry::Leaf(member) => {
+                files.insert(output.path().to_string(), describe_member(member));
+            }
+        }
+    }
+
+    files
+}
+
+fn describe_member(member: &ActionDirectoryMember) -> String {
+    match member {
+        ActionDirectoryMember::File(f) if f.is_executable => format!("{} (executable)", f.digest),
+        ActionDirectoryMember::File(f) => f.digest.to_string(),
+        ActionDirectoryMember::Symlink(s) => format!("symlink to {}", s.target()),
+        ActionDirectoryMember::ExternalSymlink(s) => format!("symlink to {}", s),
+    }
+}
+
+/// Describe, for each path whose contents differ between the two runs, what each run produced.
+fn diff_output_files(
+    first: &BTreeMap<String, String>,
+    second: &BTreeMap<String, String>,
+) -> Vec<String> {
+    let paths: BTreeSet<&String> = first.keys().chain(second.keys()).collect();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let first = first.get(path).map_or("missing", |d| d.as_str());
+            let second = second.get(path).map_or("missing", |d| d.as_str());
+            if first == second {
+                None
+            } else {
+                Some(format!("{}: {} != {}", path, first, second))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use buck2_common::file_ops::FileMetadata;
+    use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_common::liveliness_observer::NoopLivelinessObserver;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
+    use buck2_core::fs::buck_out_path::BuckOutPathResolver;
+    use buck2_core::fs::buck_out_path::BuckOutTestPath;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+    use buck2_events::dispatch::EventDispatcher;
+    use buck2_execute::artifact_value::ArtifactValue;
+    use buck2_execute::digest_config::DigestConfig;
+    use buck2_execute::execute::action_digest_and_blobs::ActionDigestAndBlobsBuilder;
+    use buck2_execute::execute::kind::CommandExecutionKind;
+    use buck2_execute::execute::manager::CommandExecutionManagerExt;
+    use buck2_execute::execute::prepared::PreparedAction;
+    use buck2_execute::execute::request::CommandExecutionOutput;
+    use buck2_execute::execute::request::CommandExecutionPaths;
+    use buck2_execute::execute::request::CommandExecutionRequest;
+    use buck2_execute::execute::request::OutputCreationBehavior;
+    use buck2_execute::execute::target::CommandExecutionTarget;
+    use indexmap::IndexMap;
+    use indexmap::IndexSet;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct FakeTarget;
+
+    impl CommandExecutionTarget for FakeTarget {
+        fn re_action_key(&self) -> String {
+            "fake".to_owned()
+        }
+
+        fn re_affinity_key(&self) -> String {
+            "fake".to_owned()
+        }
+
+        fn as_proto_action_key(&self) -> buck2_data::ActionKey {
+            Default::default()
+        }
+
+        fn as_proto_action_name(&self) -> buck2_data::ActionName {
+            Default::default()
+        }
+    }
+
+    fn output() -> CommandExecutionOutput {
+        CommandExecutionOutput::TestPath {
+            path: BuckOutTestPath::new(
+                ForwardRelativePathBuf::unchecked_new("test".to_owned()),
+                ForwardRelativePathBuf::unchecked_new("out".to_owned()),
+            ),
+            create: OutputCreationBehavior::Parent,
+        }
+    }
+
+    /// Produces `out` with the given contents on each run, or fails the runs that have none.
+    struct FakeExecutor {
+        runs: Vec<Option<&'static str>>,
+        /// The scratch path suffix of every run so far.
+        calls: Mutex<Vec<Option<&'static str>>>,
+    }
+
+    impl FakeExecutor {
+        fn new(runs: Vec<Option<&'static str>>) -> Arc<Self> {
+            Arc::new(Self {
+                runs,
+                calls: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn calls(&self) -> Vec<Option<&'static str>> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl PreparedCommandExecutor for FakeExecutor {
+        async fn exec_cmd(
+            &self,
+            command: &PreparedCommand<'_, '_>,
+            manager: CommandExecutionManager,
+            _cancellations: &CancellationContext,
+        ) -> CommandExecutionResult {
+            let run = {
+                let mut calls = self.calls.lock().unwrap();
+                calls.push(command.scratch_path_suffix);
+                self.runs[calls.len() - 1]
+            };
+
+            let contents = match run {
+                Some(contents) => contents,
+                None => return manager.error("fake", anyhow::anyhow!("Run failed")),
+            };
+            let value = ArtifactValue::file(FileMetadata {
+                digest: TrackedFileDigest::from_content(
+                    contents.as_bytes(),
+                    command.digest_config.cas_digest_config(),
+                ),
+                is_executable: false,
+            });
+            manager.claim().await.success(
+                CommandExecutionKind::Local {
+                    digest: command.prepared_action.digest(),
+                    command: Vec::new(),
+                    env: Default::default(),
+                },
+                IndexMap::from([(output(), value)]),
+                Default::default(),
+                Default::default(),
+            )
+        }
+
+        fn is_local_execution_possible(&self, _executor_preference: ExecutorPreference) -> bool {
+            true
+        }
+    }
+
+    fn artifact_fs(temp: &ProjectRootTemp) -> ArtifactFs {
+        ArtifactFs::new(
+            CellResolver::testing_with_name_and_path(
+                CellName::testing_new("cell"),
+                CellRootPathBuf::new(ProjectRelativePathBuf::unchecked_new("cell_path".into())),
+            ),
+            BuckOutPathResolver::new(ProjectRelativePathBuf::unchecked_new("buck_out/v2".into())),
+            temp.path().dupe(),
+        )
+    }
+
+    /// Run a command producing `output()` through a `DeterminismCheckingExecutor` wrapping
+    /// `inner`.
+    async fn check(
+        inner: Arc<FakeExecutor>,
+        outputs_cleanup: bool,
+    ) -> anyhow::Result<CommandExecutionResult> {
+        let temp = ProjectRootTemp::new()?;
+        let artifact_fs = artifact_fs(&temp);
+        let digest_config = DigestConfig::testing_default();
+
+        let request = CommandExecutionRequest::new(
+            Vec::new(),
+            vec!["true".to_owned()],
+            CommandExecutionPaths::new(
+                Vec::new(),
+                IndexSet::from([output()]),
+                &artifact_fs,
+                digest_config,
+            )?,
+            Default::default(),
+        )
+        .with_outputs_cleanup(outputs_cleanup);
+        let prepared_action = PreparedAction {
+            action_and_blobs: ActionDigestAndBlobsBuilder::new(digest_config)
+                .build(&remote_execution::Action::default()),
+            platform: Default::default(),
+        };
+        let command = PreparedCommand {
+            request: &request,
+            target: &FakeTarget,
+            prepared_action: &prepared_action,
+            digest_config,
+            scratch_path_suffix: None,
+        };
+
+        let executor = DeterminismCheckingExecutor { artifact_fs, inner };
+        let manager = CommandExecutionManager::new(
+            Box::new(MutexClaimManager::new()),
+            EventDispatcher::null(),
+            NoopLivelinessObserver::create(),
+        );
+        Ok(executor
+            .exec_cmd(&command, manager, CancellationContext::testing())
+            .await)
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_command_succeeds() -> anyhow::Result<()> {
+        let inner = FakeExecutor::new(vec![Some("foo"), Some("foo")]);
+        let result = check(inner.dupe(), true).await?;
+        assert!(is_success(&result));
+        assert_eq!(result.outputs.len(), 1);
+        // The second run has a scratch directory of its own.
+        assert_eq!(
+            inner.calls(),
+            vec![None, Some(SECOND_RUN_SCRATCH_PATH_SUFFIX)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_nondeterministic_command_fails() -> anyhow::Result<()> {
+        let inner = FakeExecutor::new(vec![Some("foo"), Some("bar")]);
+        let result = check(inner, true).await?;
+        match &result.report.status {
+            CommandExecutionStatus::Error { stage, error, .. } => {
+                assert_eq!(*stage, "check_determinism");
+                assert!(
+                    error.to_string().contains("1 output file(s) differ"),
+                    "{:#}",
+                    error
+                );
+            }
+            _ => panic!("Expected an error, got: {:?}", result.report.status),
+        }
+        assert!(result.outputs.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failing_second_run_is_returned() -> anyhow::Result<()> {
+        let inner = FakeExecutor::new(vec![Some("foo"), None]);
+        let result = check(inner.dupe(), true).await?;
+        match &result.report.status {
+            CommandExecutionStatus::Error { stage, .. } => assert_eq!(*stage, "fake"),
+            _ => panic!("Expected an error, got: {:?}", result.report.status),
+        }
+        assert_eq!(inner.calls().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_incremental_command_runs_once() -> anyhow::Result<()> {
+        let inner = FakeExecutor::new(vec![Some("foo")]);
+        let result = check(inner.dupe(), false).await?;
+        assert!(is_success(&result));
+        assert_eq!(inner.calls(), vec![None]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_output_files() {
+        let first = BTreeMap::from([
+            ("out/a".to_owned(), "aa:1".to_owned()),
+            ("out/b".to_owned(), "bb:1".to_owned()),
+            ("out/c".to_owned(), "cc:1".to_owned()),
+        ]);
+        let second = BTreeMap::from([
+            ("out/a".to_owned(), "aa:1".to_owned()),
+            ("out/b".to_owned(), "b2:1".to_owned()),
+            ("out/d".to_owned(), "dd:1".to_owned()),
+        ]);
+
+        assert_eq!(
+            diff_output_files(&first, &second),
+            vec![
+                "out/b: bb:1 != b2:1".to_owned(),
+                "out/c: cc:1 != missing".to_owned(),
+                "out/d: missing != dd:1".to_owned(),
+            ]
+        );
+        assert!(diff_output_files(&first, &first).is_empty());
+    }
+}
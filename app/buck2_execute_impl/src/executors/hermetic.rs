@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Running local actions in a hermetic sandbox (`build.hermetic_local_execution`), so that an
+//! action which reads an input it did not declare fails locally, like it would on RE, instead of
+//! silently succeeding.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+use buck2_execute::execute::request::CommandExecutionRequest;
+
+use crate::executors::local::MaterializedInputPaths;
+
+/// The most paths listed when explaining a failure.
+const MAX_UNDECLARED_PATHS: usize = 10;
+
+static NEXT_SANDBOX: AtomicU64 = AtomicU64::new(0);
+
+/// The paths visible to a local action running in the hermetic sandbox. The sandbox root
+/// directory is removed when this is dropped.
+pub(crate) struct HermeticSandbox {
+    root: AbsNormPathBuf,
+    read_only: Vec<AbsNormPathBuf>,
+    writable: Vec<AbsNormPathBuf>,
+}
+
+impl HermeticSandbox {
+    pub(crate) fn new(
+        artifact_fs: &ArtifactFs,
+        request: &CommandExecutionRequest,
+        inputs: &MaterializedInputPaths,
+        host_paths: &[AbsNormPathBuf],
+    ) -> anyhow::Result<Self> {
+        let fs = artifact_fs.fs();
+
+        let root = fs.resolve(&artifact_fs.buck_out_path_resolver().root().join(
+            ForwardRelativePathBuf::unchecked_new(format!(
+                "sandbox/{}",
+                NEXT_SANDBOX.fetch_add(1, Ordering::Relaxed)
+            )),
+        ));
+        // This may be left over from a previous daemon, but the mounts went away with it.
+        fs_util::remove_all(&root)?;
+        fs_util::create_dir_all(&root)?;
+
+        let read_only = host_paths
+            .iter()
+            .cloned()
+            .chain(inputs.paths.iter().map(|p| fs.resolve(p)))
+            .chain(inputs.other_paths.iter().map(|p| fs.resolve(p)))
+            .collect();
+
+        // Outputs don't exist yet, so make the directories they are written to visible.
+        let writable = inputs
+            .scratch
+            .path()
+            .map(|p| fs.resolve(p))
+            .into_iter()
+            .chain(request.outputs().filter_map(|output| {
+                let path = output.resolve(artifact_fs).into_path();
+                Some(fs.resolve(path.parent()?))
+            }))
+            .collect();
+
+        Ok(Self {
+            root,
+            read_only,
+            writable,
+        })
+    }
+
+    /// Make `cmd` enter the sandbox when it is spawned.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub(crate) fn apply(&self, cmd: &mut Command, cwd: &AbsNormPath) -> anyhow::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            self.spec().apply(cmd, cwd.as_path())
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(anyhow::anyhow!(
+                "`build.hermetic_local_execution` is only supported on Linux"
+            ))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn spec(&self) -> buck2_forkserver::run::sandbox::SandboxSpec {
+        buck2_forkserver::run::sandbox::SandboxSpec {
+            root: self.root.as_path().to_owned(),
+            read_only: self
+                .read_only
+                .iter()
+                .map(|p| p.as_path().to_owned())
+                .collect(),
+            writable: self
+                .writable
+                .iter()
+                .map(|p| p.as_path().to_owned())
+                .collect(),
+        }
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn to_proto(&self) -> buck2_forkserver_proto::Sandbox {
+        use std::os::unix::ffi::OsStrExt;
+
+        let bytes = |p: &AbsNormPathBuf| p.as_os_str().as_bytes().to_vec();
+        buck2_forkserver_proto::Sandbox {
+            root: bytes(&self.root),
+            read_only: self.read_only.iter().map(bytes).collect(),
+            writable: self.writable.iter().map(bytes).collect(),
+        }
+    }
+
+    fn is_visible(&self, path: &Path) -> bool {
+        self.read_only
+            .iter()
+            .chain(&self.writable)
+            // Either the path is visible, or it is a directory containing visible paths.
+            .any(|p| path.starts_with(p) || p.as_path().starts_with(path))
+    }
+
+    /// A note to add to the error output of an action which failed in the sandbox. It lists the
+    /// paths mentioned in `stderr` which exist outside the sandbox but not inside it, as those are
+    /// likely undeclared inputs.
+    pub(crate) fn explain_failure(&self, cwd: &AbsNormPath, stderr: &[u8]) -> String {
+        let stderr = String::from_utf8_lossy(stderr);
+        let undeclared: BTreeSet<&str> = stderr
+            .split(|c: char| c.is_whitespace() || "'\"`:,;()[]{}<>".contains(c))
+            .map(|word| word.trim_end_matches('.'))
+            .filter(|word| !word.is_empty())
+            .filter(|word| {
+                let path: PathBuf = cwd.as_path().join(word);
+                !self.is_visible(&path) && path.symlink_metadata().is_ok()
+            })
+            .take(MAX_UNDECLARED_PATHS)
+            .collect();
+
+        let mut note =
+            "\nThe action ran in a hermetic sandbox (`build.hermetic_local_execution`), \
+            where only its declared inputs are visible.\n"
+                .to_owned();
+        if !undeclared.is_empty() {
+            note.push_str("These paths mentioned above exist, but are not inputs of the action:\n");
+            for path in undeclared {
+                note.push_str("  ");
+                note.push_str(path);
+                note.push('\n');
+            }
+        }
+        note
+    }
+}
+
+impl Drop for HermeticSandbox {
+    fn drop(&mut self) {
+        // The mounts only exist in the namespace of the action, so this is a plain directory.
+        let _ignored = fs_util::remove_all(&self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+
+    use super::*;
+
+    #[test]
+    fn test_explain_failure_lists_undeclared_paths() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let cwd = AbsNormPathBuf::new(tempdir.path().canonicalize()?)?;
+        for dir in ["declared", "undeclared"] {
+            std::fs::create_dir(cwd.as_path().join(dir))?;
+            std::fs::write(cwd.as_path().join(dir).join("a.h"), "")?;
+        }
+
+        let sandbox = HermeticSandbox {
+            root: cwd.join(ForwardRelativePath::new("sandbox")?),
+            read_only: vec![cwd.join(ForwardRelativePath::new("declared/a.h")?)],
+            writable: vec![],
+        };
+        let note = sandbox.explain_failure(
+            &cwd,
+            b"declared/a.h:1:10: fatal error: 'undeclared/a.h' file not found\n\
+              missing/a.h: No such file or directory.",
+        );
+        assert!(note.contains("hermetic sandbox"));
+        assert!(note.contains("  undeclared/a.h\n"));
+        assert!(!note.contains("  declared/a.h"));
+        assert!(!note.contains("missing"));
+        Ok(())
+    }
+}
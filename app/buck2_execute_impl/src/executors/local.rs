@@ -13,6 +13,7 @@ use std::ffi::OsString;
 use std::ops::ControlFlow;
 use std::process::Command;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::time::Duration;
 use std::time::Instant;
 use std::time::SystemTime;
@@ -25,6 +26,7 @@ use buck2_common::liveliness_observer::LivelinessObserverExt;
 use buck2_common::local_resource_state::LocalResourceHolder;
 use buck2_core::fs::artifact_path_resolver::ArtifactFs;
 use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::abs_path::AbsPath;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
@@ -83,6 +85,7 @@ use host_sharing::HostSharingRequirements;
 use indexmap::IndexMap;
 use tracing::info;
 
+use crate::executors::hermetic::HermeticSandbox;
 use crate::executors::worker::WorkerHandle;
 use crate::executors::worker::WorkerPool;
 
@@ -145,6 +148,8 @@ impl LocalExecutor {
         env_inheritance: Option<&'a EnvironmentInheritance>,
         liveliness_observer: impl LivelinessObserver + 'static,
         disable_miniperf: bool,
+        sandbox: Option<&'a HermeticSandbox>,
+        memory_limit: Option<u64>,
     ) -> impl futures::future::Future<
         Output = anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>,
     > + Send
@@ -155,6 +160,18 @@ impl LocalExecutor {
                 None => Cow::Borrowed(&self.root),
             };
 
+            if memory_limit.is_some()
+                && !(self.forkserver.is_some() && self.knobs.local_action_cgroups)
+            {
+                // The limit still counts against the memory budget, so the action can run.
+                static WARN: OnceLock<()> = OnceLock::new();
+                WARN.get_or_init(|| {
+                    tracing::warn!(
+                        "Local actions run over their `memory_limit_mb`, as it is only enforced when the forkserver runs them in cgroups (`buck2.local_action_cgroups = true`)"
+                    )
+                });
+            }
+
             match &self.forkserver {
                 Some(forkserver) => {
                     #[cfg(unix)]
@@ -168,14 +185,17 @@ impl LocalExecutor {
                             timeout,
                             env_inheritance,
                             liveliness_observer,
-                            self.knobs.enable_miniperf && !disable_miniperf,
+                            // The miniperf binary is not visible inside the sandbox.
+                            self.knobs.enable_miniperf && !disable_miniperf && sandbox.is_none(),
+                            sandbox.map(|s| s.to_proto()),
+                            memory_limit,
                         )
                         .await
                     }
 
                     #[cfg(not(unix))]
                     {
-                        let _unused = (forkserver, disable_miniperf);
+                        let _unused = (forkserver, disable_miniperf, sandbox, memory_limit);
                         Err(anyhow::anyhow!("Forkserver is not supported off-UNIX"))
                     }
                 }
@@ -191,6 +211,11 @@ impl LocalExecutor {
                         env,
                         env_inheritance,
                     );
+                    if let Some(sandbox) = sandbox {
+                        sandbox.apply(&mut cmd, &working_directory)?;
+                    }
+                    // Memory limits are enforced by the forkserver, using cgroups, as warned above.
+                    let _unused = memory_limit;
                     let timeout = timeout_into_cancellation(timeout);
 
                     let alive = liveliness_observer
@@ -216,13 +241,14 @@ impl LocalExecutor {
         cancellations: &CancellationContext<'_>,
         digest_config: DigestConfig,
         local_resource_holders: &[LocalResourceHolder],
+        scratch_path_suffix: Option<&str>,
     ) -> CommandExecutionResult {
         let args = &request.all_args_vec();
         if args.is_empty() {
             return manager.error("no_args", LocalExecutionError::NoArgs);
         }
 
-        let (scratch_path, input_materialization_duration) = match executor_stage_async(
+        let (inputs, input_materialization_duration) = match executor_stage_async(
             buck2_data::LocalStage {
                 stage: Some(buck2_data::LocalMaterializeInputs {}.into()),
             },
@@ -231,8 +257,13 @@ impl LocalExecutor {
 
                 let (r1, r2) = future::join(
                     async {
-                        materialize_inputs(&self.artifact_fs, self.materializer.as_ref(), request)
-                            .await
+                        materialize_inputs(
+                            &self.artifact_fs,
+                            self.materializer.as_ref(),
+                            request,
+                            scratch_path_suffix,
+                        )
+                        .await
                     },
                     async {
                         // When user requests to not perform a cleanup for a specific action
@@ -252,16 +283,16 @@ impl LocalExecutor {
                 )
                 .await;
 
-                let scratch_path = r1?.scratch;
+                let inputs = r1?;
                 r2?;
 
-                anyhow::Ok((scratch_path, start.elapsed()))
+                anyhow::Ok((inputs, start.elapsed()))
             },
         )
         .await
         {
-            Ok((scratch_path, input_materialization_duration)) => {
-                (scratch_path, input_materialization_duration)
+            Ok((inputs, input_materialization_duration)) => {
+                (inputs, input_materialization_duration)
             }
             Err(e) => return manager.error("materialize_inputs_failed", e),
         };
@@ -269,7 +300,7 @@ impl LocalExecutor {
         // TODO: Release here.
         let manager = manager.claim().await;
 
-        let scratch_path = &scratch_path.0;
+        let scratch_path = &inputs.scratch.0;
 
         if let Err(e) = executor_stage_async(
             buck2_data::LocalStage {
@@ -294,6 +325,16 @@ impl LocalExecutor {
             return manager.error("prepare_output_dirs_failed", e);
         };
 
+        let sandbox = match &self.knobs.hermetic_sandbox_host_paths {
+            Some(host_paths) if request.worker().is_none() => {
+                match HermeticSandbox::new(&self.artifact_fs, request, &inputs, host_paths) {
+                    Ok(sandbox) => Some(sandbox),
+                    Err(e) => return manager.error("prepare_sandbox_failed", e),
+                }
+            }
+            _ => None,
+        };
+
         info!(
             "Local execution command line:\n```\n$ {}\n```",
             args.join(" "),
@@ -369,6 +410,7 @@ impl LocalExecutor {
                 )))
         };
         let liveliness_observer = manager.liveliness_observer.dupe().and(cancellation);
+        let sandbox_ref = sandbox.as_ref();
 
         let (worker, manager) = self.initialize_worker(request, manager, dispatcher).await?;
 
@@ -436,6 +478,8 @@ impl LocalExecutor {
                         request.local_environment_inheritance(),
                         liveliness_observer,
                         request.disable_miniperf(),
+                        sandbox_ref,
+                        request.memory_limit(),
                     )
                     .await
                 };
@@ -458,13 +502,47 @@ impl LocalExecutor {
         )
         .await;
 
-        let (status, stdout, stderr) = match res {
+        let (status, stdout, mut stderr) = match res {
             Ok(res) => res,
             Err(e) => {
                 return manager.error("exec_failed", e);
             }
         };
 
+        let sandbox_note = |stderr: &[u8]| match &sandbox {
+            Some(sandbox) => {
+                let cwd = match request.working_directory() {
+                    Some(d) => self.root.join(d),
+                    None => self.root.clone(),
+                };
+                sandbox.explain_failure(&cwd, stderr)
+            }
+            None => String::new(),
+        };
+        if let GatherOutputStatus::Finished {
+            exit_code,
+            execution_stats,
+        } = &status
+        {
+            if *exit_code != 0 {
+                let note = sandbox_note(&stderr);
+                stderr.extend(note.into_bytes());
+
+                if let (Some(limit), true) = (
+                    request.memory_limit(),
+                    execution_stats.map_or(false, |s| s.memory_limit_exceeded),
+                ) {
+                    stderr.extend(
+                        format!(
+                            "\nThe action was killed for using more than its memory limit of {} MiB (`memory_limit_mb`).\n",
+                            limit / (1024 * 1024)
+                        )
+                        .into_bytes(),
+                    );
+                }
+            }
+        }
+
         let std_streams = CommandStdStreams::Local { stdout, stderr };
 
         match status {
@@ -523,8 +601,13 @@ impl LocalExecutor {
                     Default::default(),
                     CommandStdStreams::Local {
                         stdout: Default::default(),
-                        stderr: format!("Spawning executable `{}` failed: {}", args[0], reason)
-                            .into_bytes(),
+                        stderr: format!(
+                            "Spawning executable `{}` failed: {}{}",
+                            args[0],
+                            reason,
+                            sandbox_note(reason.as_bytes())
+                        )
+                        .into_bytes(),
                     },
                     None,
                     timing,
@@ -542,61 +625,15 @@ impl LocalExecutor {
         request: &CommandExecutionRequest,
         digest_config: DigestConfig,
     ) -> anyhow::Result<(IndexMap<CommandExecutionOutput, ArtifactValue>, HashingInfo)> {
-        let mut builder = inputs_directory(request.inputs(), &self.artifact_fs)?;
-
-        // Read outputs from disk and add them to the builder
-        let mut entries = Vec::new();
-        let mut total_hashing_time = Duration::ZERO;
-        let mut total_hashed_outputs = 0;
-        for output in request.outputs() {
-            let path = output.resolve(&self.artifact_fs).into_path();
-            let abspath = self.root.join(&path);
-            let (entry, hashing_info) = build_entry_from_disk(
-                abspath,
-                FileDigestConfig::build(digest_config.cas_digest_config()),
-                self.blocking_executor.as_ref(),
-            )
-            .await
-            .with_context(|| format!("collecting output {:?}", path))?;
-            total_hashing_time += hashing_info.hashing_duration;
-            total_hashed_outputs += hashing_info.hashed_artifacts_count;
-            if let Some(entry) = entry {
-                insert_entry(&mut builder, &path, entry)?;
-                entries.push((output.cloned(), path));
-            }
-        }
-
-        let mut to_declare = vec![];
-        let mut mapped_outputs = IndexMap::with_capacity(entries.len());
-
-        for (output, path) in entries {
-            let value = extract_artifact_value(&builder, &path, digest_config)?;
-            if let Some(value) = value {
-                match output {
-                    CommandExecutionOutput::BuildArtifact { .. } => {
-                        to_declare.push((path, value.dupe()));
-                    }
-                    CommandExecutionOutput::TestPath { .. } => {
-                        // Don't declare those as we don't currently have any form of GC so this
-                        // would take up space for nothing, and most importantly, we will never
-                        // need them to be in materializer state for e.g. matching as nothing
-                        // should depend on them.
-                    }
-                }
-
-                mapped_outputs.insert(output, value);
-            }
-        }
-
-        self.materializer.declare_existing(to_declare).await?;
-
-        Ok((
-            mapped_outputs,
-            HashingInfo {
-                hashing_duration: total_hashing_time,
-                hashed_artifacts_count: total_hashed_outputs,
-            },
-        ))
+        calculate_and_declare_output_values(
+            &self.artifact_fs,
+            self.materializer.as_ref(),
+            self.blocking_executor.as_ref(),
+            &self.root,
+            request,
+            digest_config,
+        )
+        .await
     }
 
     async fn acquire_worker_permit(
@@ -726,8 +763,25 @@ impl PreparedCommandExecutor for LocalExecutor {
             target: _,
             prepared_action,
             digest_config,
+            scratch_path_suffix,
         } = command;
 
+        // Wait for memory before acquiring local resources or taking any permits, so none are held
+        // while waiting.
+        let _memory_permit = match request.memory_limit() {
+            Some(limit) => Some(
+                executor_stage_async(
+                    buck2_data::LocalStage {
+                        stage: Some(buck2_data::LocalQueued {}.into()),
+                    },
+                    self.host_sharing_broker
+                        .acquire_memory((limit / (1024 * 1024)) as usize),
+                )
+                .await,
+            ),
+            None => None,
+        };
+
         let local_resource_holders = executor_stage_async(
             {
                 let a = buck2_data::AcquireLocalResource {};
@@ -774,6 +828,7 @@ impl PreparedCommandExecutor for LocalExecutor {
                     cancellations,
                     *digest_config,
                     &local_resource_holders,
+                    *scratch_path_suffix,
                 )
             })
             .await
@@ -811,25 +866,31 @@ impl<'a> StrOrOsStr<'a> {
 pub struct MaterializedInputPaths {
     pub scratch: ScratchPath,
     pub paths: Vec<ProjectRelativePathBuf>,
+    /// Inputs which are not materialized by the materializer: sources and action metadata.
+    pub other_paths: Vec<ProjectRelativePathBuf>,
 }
 
 /// Materialize all inputs artifact for CommandExecutionRequest so the command can be executed locally.
 ///
 /// This also discovers the scratch directory if any was passed (if multiple are passed, one of
-/// them is returned).
+/// them is returned). With `scratch_path_suffix`, a sibling of that directory is used instead.
 pub async fn materialize_inputs(
     artifact_fs: &ArtifactFs,
     materializer: &dyn Materializer,
     request: &CommandExecutionRequest,
+    scratch_path_suffix: Option<&str>,
 ) -> anyhow::Result<MaterializedInputPaths> {
     let mut paths = vec![];
+    let mut other_paths = vec![];
     let mut scratch = ScratchPath(None);
 
     for input in request.inputs() {
         match input {
             CommandExecutionInput::Artifact(group) => {
                 for (artifact, _) in group.iter() {
-                    if !artifact.is_source() {
+                    if artifact.is_source() {
+                        other_paths.push(artifact.resolve_path(artifact_fs)?);
+                    } else {
                         paths.push(artifact.resolve_path(artifact_fs)?);
                     }
                 }
@@ -842,9 +903,13 @@ pub async fn materialize_inputs(
                 artifact_fs
                     .fs()
                     .write_file(&path, &metadata.data.0.0, false)?;
+                other_paths.push(path);
             }
             CommandExecutionInput::ScratchPath(path) => {
-                let path = artifact_fs.buck_out_path_resolver().resolve_scratch(path);
+                let mut path = artifact_fs.buck_out_path_resolver().resolve_scratch(path);
+                if let Some(suffix) = scratch_path_suffix {
+                    path = ProjectRelativePathBuf::try_from(format!("{}-{}", path, suffix))?;
+                }
 
                 // Clean and produce it.
                 CleanOutputPaths::clean(std::iter::once(path.as_ref()), artifact_fs.fs())?;
@@ -877,12 +942,22 @@ pub async fn materialize_inputs(
         }
     }
 
-    Ok(MaterializedInputPaths { scratch, paths })
+    Ok(MaterializedInputPaths {
+        scratch,
+        paths,
+        other_paths,
+    })
 }
 
 /// A scratch path discovered during `materialize_inputs`.
 pub struct ScratchPath(Option<ProjectRelativePathBuf>);
 
+impl ScratchPath {
+    pub fn path(&self) -> Option<&ProjectRelativePath> {
+        self.0.as_deref()
+    }
+}
+
 async fn check_inputs(
     manager: CommandExecutionManagerWithClaim,
     artifact_fs: &ArtifactFs,
@@ -957,6 +1032,72 @@ pub async fn materialize_build_outputs_from_previous_run(
     materializer.ensure_materialized(paths).await
 }
 
+/// Hash the outputs the command produced on disk and declare them to the materializer.
+pub(crate) async fn calculate_and_declare_output_values(
+    artifact_fs: &ArtifactFs,
+    materializer: &dyn Materializer,
+    blocking_executor: &dyn BlockingExecutor,
+    root: &AbsNormPath,
+    request: &CommandExecutionRequest,
+    digest_config: DigestConfig,
+) -> anyhow::Result<(IndexMap<CommandExecutionOutput, ArtifactValue>, HashingInfo)> {
+    let mut builder = inputs_directory(request.inputs(), artifact_fs)?;
+
+    // Read outputs from disk and add them to the builder
+    let mut entries = Vec::new();
+    let mut total_hashing_time = Duration::ZERO;
+    let mut total_hashed_outputs = 0;
+    for output in request.outputs() {
+        let path = output.resolve(artifact_fs).into_path();
+        let abspath = root.join(&path);
+        let (entry, hashing_info) = build_entry_from_disk(
+            abspath,
+            FileDigestConfig::build(digest_config.cas_digest_config()),
+            blocking_executor,
+        )
+        .await
+        .with_context(|| format!("collecting output {:?}", path))?;
+        total_hashing_time += hashing_info.hashing_duration;
+        total_hashed_outputs += hashing_info.hashed_artifacts_count;
+        if let Some(entry) = entry {
+            insert_entry(&mut builder, &path, entry)?;
+            entries.push((output.cloned(), path));
+        }
+    }
+
+    let mut to_declare = vec![];
+    let mut mapped_outputs = IndexMap::with_capacity(entries.len());
+
+    for (output, path) in entries {
+        let value = extract_artifact_value(&builder, &path, digest_config)?;
+        if let Some(value) = value {
+            match output {
+                CommandExecutionOutput::BuildArtifact { .. } => {
+                    to_declare.push((path, value.dupe()));
+                }
+                CommandExecutionOutput::TestPath { .. } => {
+                    // Don't declare those as we don't currently have any form of GC so this
+                    // would take up space for nothing, and most importantly, we will never
+                    // need them to be in materializer state for e.g. matching as nothing
+                    // should depend on them.
+                }
+            }
+
+            mapped_outputs.insert(output, value);
+        }
+    }
+
+    materializer.declare_existing(to_declare).await?;
+
+    Ok((
+        mapped_outputs,
+        HashingInfo {
+            hashing_duration: total_hashing_time,
+            hashed_artifacts_count: total_hashed_outputs,
+        },
+    ))
+}
+
 /// Create any output dirs requested by the command. Note that this makes no effort to delete
 /// the output paths first. Eventually it should, but right now this happens earlier. This
 /// would be a separate refactor.
@@ -1088,6 +1229,8 @@ mod unix {
         env_inheritance: Option<&EnvironmentInheritance>,
         liveliness_observer: impl LivelinessObserver + 'static,
         enable_miniperf: bool,
+        sandbox: Option<buck2_forkserver_proto::Sandbox>,
+        memory_limit_bytes: Option<u64>,
     ) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)> {
         let exe = exe.as_ref();
 
@@ -1105,6 +1248,8 @@ mod unix {
             enable_miniperf,
             std_redirects: None,
             graceful_shutdown_timeout_s: None,
+            sandbox,
+            memory_limit_bytes,
         };
         apply_local_execution_environment(&mut req, working_directory, env, env_inheritance);
         forkserver
@@ -1309,6 +1454,8 @@ mod tests {
                 None,
                 NoopLivelinessObserver::create(),
                 false,
+                None,
+                None,
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
@@ -1345,6 +1492,8 @@ mod tests {
                 Some(&EnvironmentInheritance::empty()),
                 NoopLivelinessObserver::create(),
                 false,
+                None,
+                None,
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
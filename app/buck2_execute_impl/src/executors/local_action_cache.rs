@@ -0,0 +1,360 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Executors backed by the `LocalActionCache`: a checker that serves actions out of it, and an
+//! executor wrapper that fills it with the results of actions that ran locally.
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::directory::DirectoryIterator;
+use buck2_core::directory::FingerprintedDirectory;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_execute::artifact_value::ArtifactValue;
+use buck2_execute::directory::ActionDirectoryMember;
+use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::executor_stage_async;
+use buck2_execute::execute::kind::CommandExecutionKind;
+use buck2_execute::execute::manager::CommandExecutionManager;
+use buck2_execute::execute::manager::CommandExecutionManagerExt;
+use buck2_execute::execute::output::CommandStdStreams;
+use buck2_execute::execute::prepared::PreparedCommand;
+use buck2_execute::execute::prepared::PreparedCommandExecutor;
+use buck2_execute::execute::prepared::PreparedCommandOptionalExecutor;
+use buck2_execute::execute::request::ExecutorPreference;
+use buck2_execute::execute::result::CommandExecutionMetadata;
+use buck2_execute::execute::result::CommandExecutionResult;
+use buck2_execute::materialize::materializer::Materializer;
+use buck2_futures::cancellation::CancellationContext;
+use dupe::Dupe;
+
+use crate::executors::local::calculate_and_declare_output_values;
+use crate::executors::local::create_output_dirs;
+use crate::local_action_cache::blob_name;
+use crate::local_action_cache::CachedActionResult;
+use crate::local_action_cache::CachedOutputEntry;
+use crate::local_action_cache::CachedOutputKind;
+use crate::local_action_cache::LocalActionCache;
+
+/// Serves actions out of the `LocalActionCache`, deferring to `next_checker` on a miss.
+pub struct LocalActionCacheChecker {
+    pub artifact_fs: ArtifactFs,
+    pub materializer: Arc<dyn Materializer>,
+    pub blocking_executor: Arc<dyn BlockingExecutor>,
+    pub local_action_cache: Arc<LocalActionCache>,
+    pub next_checker: Arc<dyn PreparedCommandOptionalExecutor>,
+}
+
+impl LocalActionCacheChecker {
+    /// Write the cached outputs in place of whatever the action produced before.
+    async fn restore_outputs(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        cancellations: &CancellationContext<'_>,
+        cached: &CachedActionResult,
+    ) -> anyhow::Result<()> {
+        create_output_dirs(
+            &self.artifact_fs,
+            command.request,
+            self.materializer.dupe(),
+            self.blocking_executor.dupe(),
+            cancellations,
+        )
+        .await?;
+
+        let fs = self.artifact_fs.fs();
+        self.blocking_executor
+            .execute_io_inline(|| self.local_action_cache.restore(fs, cached))
+            .await
+    }
+
+    async fn restore(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext<'_>,
+        cached: CachedActionResult,
+    ) -> ControlFlow<CommandExecutionResult, CommandExecutionManager> {
+        let request = command.request;
+        let action_digest = &command.prepared_action.action_and_blobs.action;
+        let start_time = SystemTime::now();
+        let start = Instant::now();
+
+        // Only claim once the outputs are in place: blobs can be evicted after the lookup, in
+        // which case this is a miss and the action still needs to run. Nothing else is writing
+        // to the outputs yet, since checkers run before the action is executed.
+        if let Err(e) = self.restore_outputs(command, cancellations, &cached).await {
+            tracing::warn!(
+                "Error restoring `{}` from the local action cache: {:#}",
+                action_digest,
+                e
+            );
+            // Don't leave partially restored outputs behind.
+            if let Err(e) = create_output_dirs(
+                &self.artifact_fs,
+                request,
+                self.materializer.dupe(),
+                self.blocking_executor.dupe(),
+                cancellations,
+            )
+            .await
+            {
+                return ControlFlow::Break(manager.error("local_action_cache", e));
+            }
+            return ControlFlow::Continue(manager);
+        }
+
+        let execution_kind = CommandExecutionKind::LocalActionCache {
+            digest: action_digest.dupe(),
+        };
+        let manager = manager
+            .with_execution_kind(execution_kind.clone())
+            .claim()
+            .await;
+
+        let fs = self.artifact_fs.fs();
+        let (outputs, hashing_info) = match calculate_and_declare_output_values(
+            &self.artifact_fs,
+            self.materializer.as_ref(),
+            self.blocking_executor.as_ref(),
+            fs.root(),
+            request,
+            command.digest_config,
+        )
+        .await
+        {
+            Ok(res) => res,
+            Err(e) => return ControlFlow::Break(manager.error("local_action_cache", e)),
+        };
+
+        let wall_time = start.elapsed();
+        let timing = CommandExecutionMetadata {
+            wall_time,
+            execution_time: wall_time,
+            start_time,
+            execution_stats: None,
+            input_materialization_duration: Default::default(),
+            hashing_duration: hashing_info.hashing_duration,
+            hashed_artifacts_count: hashing_info.hashed_artifacts_count,
+            queue_duration: None,
+        };
+
+        tracing::info!(
+            "Action result is in the local action cache, skipping execution of:\n```\n$ {}\n```\n for action `{}`",
+            request.all_args_str(),
+            action_digest,
+        );
+
+        ControlFlow::Break(manager.success(
+            execution_kind,
+            outputs,
+            CommandStdStreams::Local {
+                stdout: cached.stdout,
+                stderr: cached.stderr,
+            },
+            timing,
+        ))
+    }
+}
+
+#[async_trait]
+impl PreparedCommandOptionalExecutor for LocalActionCacheChecker {
+    async fn maybe_execute(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> ControlFlow<CommandExecutionResult, CommandExecutionManager> {
+        // We can only restore outputs into a clean slate, since we don't know what the action
+        // would have done with whatever is already there.
+        if !command.request.outputs_cleanup {
+            return self
+                .next_checker
+                .maybe_execute(command, manager, cancellations)
+                .await;
+        }
+
+        let action_digest = &command.prepared_action.action_and_blobs.action;
+        let digest = action_digest.to_string();
+        let lookup = executor_stage_async(
+            buck2_data::CacheQuery {
+                action_digest: digest.clone(),
+                cache_type: buck2_data::CacheType::LocalActionCache.into(),
+            },
+            self.blocking_executor
+                .execute_io_inline(|| self.local_action_cache.lookup(&digest)),
+        )
+        .await;
+
+        match lookup {
+            Ok(Some(cached)) => match self.restore(command, manager, cancellations, cached).await {
+                ControlFlow::Break(result) => ControlFlow::Break(result),
+                ControlFlow::Continue(manager) => {
+                    self.next_checker
+                        .maybe_execute(command, manager, cancellations)
+                        .await
+                }
+            },
+            Ok(None) => {
+                self.next_checker
+                    .maybe_execute(command, manager, cancellations)
+                    .await
+            }
+            Err(e) => {
+                // The local action cache is only ever an optimization, so don't fail the build
+                // over it.
+                tracing::warn!(
+                    "Error querying the local action cache for `{}`: {:#}",
+                    digest,
+                    e
+                );
+                self.next_checker
+                    .maybe_execute(command, manager, cancellations)
+                    .await
+            }
+        }
+    }
+}
+
+/// Runs commands using `inner`, and stores the results of those that ran locally in the
+/// `LocalActionCache`.
+///
+/// Results from Remote Execution and from the remote action cache are not stored: their outputs
+/// are usually not materialized (they are only downloaded when something needs them), and
+/// downloading every output to fill the cache would defeat deferred materialization. Those
+/// actions can be served by the remote action cache instead.
+pub struct LocalActionCacheWriter {
+    pub artifact_fs: ArtifactFs,
+    pub blocking_executor: Arc<dyn BlockingExecutor>,
+    pub local_action_cache: Arc<LocalActionCache>,
+    pub inner: Arc<dyn PreparedCommandExecutor>,
+}
+
+impl LocalActionCacheWriter {
+    async fn store(
+        &self,
+        action_digest: &str,
+        result: &CommandExecutionResult,
+    ) -> anyhow::Result<()> {
+        let fs = self.artifact_fs.fs();
+
+        let mut entries = Vec::new();
+        for (output, value) in result.resolve_outputs(&self.artifact_fs) {
+            collect_cached_outputs(fs, output.path(), value, &mut entries)?;
+        }
+
+        let streams = result
+            .report
+            .std_streams
+            .clone()
+            .into_bytes()
+            .await
+            .context("Error reading std streams")?;
+
+        let cached = CachedActionResult {
+            entries,
+            stdout: streams.stdout,
+            stderr: streams.stderr,
+        };
+
+        self.blocking_executor
+            .execute_io_inline(|| self.local_action_cache.store(fs, action_digest, &cached))
+            .await
+    }
+}
+
+#[async_trait]
+impl PreparedCommandExecutor for LocalActionCacheWriter {
+    async fn exec_cmd(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> CommandExecutionResult {
+        let result = self.inner.exec_cmd(command, manager, cancellations).await;
+
+        if result.was_locally_executed() && command.request.outputs_cleanup {
+            let action_digest = command.prepared_action.action_and_blobs.action.to_string();
+            if let Err(e) = self.store(&action_digest, &result).await {
+                tracing::warn!(
+                    "Error storing `{}` in the local action cache: {:#}",
+                    action_digest,
+                    e
+                );
+            }
+        }
+
+        result
+    }
+
+    fn is_local_execution_possible(&self, executor_preference: ExecutorPreference) -> bool {
+        self.inner.is_local_execution_possible(executor_preference)
+    }
+}
+
+fn collect_cached_outputs(
+    fs: &ProjectRoot,
+    path: &ProjectRelativePath,
+    value: &ArtifactValue,
+    entries: &mut Vec<CachedOutputEntry>,
+) -> anyhow::Result<()> {
+    let member_kind = |path: &ProjectRelativePath, member: &ActionDirectoryMember| {
+        anyhow::Ok(match member {
+            ActionDirectoryMember::File(f) => CachedOutputKind::File {
+                blob: blob_name(f.digest.data()),
+                size: f.digest.size(),
+                is_executable: f.is_executable,
+            },
+            ActionDirectoryMember::Symlink(_) | ActionDirectoryMember::ExternalSymlink(_) => {
+                let target = fs_util::read_link(fs.resolve(path))?;
+                CachedOutputKind::Symlink {
+                    target: target
+                        .to_str()
+                        .with_context(|| format!("Symlink `{}` is not valid UTF-8", path))?
+                        .to_owned(),
+                }
+            }
+        })
+    };
+
+    match value.entry() {
+        DirectoryEntry::Dir(d) => {
+            entries.push(CachedOutputEntry {
+                path: path.to_buf(),
+                kind: CachedOutputKind::Directory,
+            });
+            let mut walk = d.fingerprinted_unordered_walk();
+            while let Some((rel, entry)) = walk.next() {
+                let entry_path = path.join(rel.get());
+                let kind = match entry {
+                    DirectoryEntry::Dir(_) => CachedOutputKind::Directory,
+                    DirectoryEntry::Leaf(member) => member_kind(&entry_path, member)?,
+                };
+                entries.push(CachedOutputEntry {
+                    path: entry_path,
+                    kind,
+                });
+            }
+        }
+        DirectoryEntry::Leaf(member) => entries.push(CachedOutputEntry {
+            path: path.to_buf(),
+            kind: member_kind(path, member)?,
+        }),
+    }
+
+    Ok(())
+}
@@ -9,10 +9,14 @@
 
 pub mod action_cache;
 pub mod action_cache_upload_permission_checker;
+pub mod action_cache_verification;
 pub mod caching;
+pub mod determinism_check;
 pub(crate) mod empty_action_result;
+pub(crate) mod hermetic;
 pub mod hybrid;
 pub mod local;
+pub mod local_action_cache;
 pub mod re;
 pub mod stacked;
 pub mod to_re_platform;
@@ -50,6 +50,7 @@ use remote_execution::ExecuteResponse;
 use remote_execution::TCode;
 use tracing::info;
 
+use crate::executors::action_cache_verification::ActionCacheVerifier;
 use crate::re::download::download_action_results;
 use crate::re::download::DownloadResult;
 use crate::re::paranoid_download::ParanoidDownloader;
@@ -74,6 +75,7 @@ pub struct ReExecutor {
     pub paranoid: Option<ParanoidDownloader>,
     pub materialize_failed_inputs: bool,
     pub dependencies: Vec<RemoteExecutorDependency>,
+    pub verifier: Option<Arc<ActionCacheVerifier>>,
 }
 
 impl ReExecutor {
@@ -147,7 +149,10 @@ impl ReExecutor {
                 self.re_use_case,
                 &identity,
                 &mut manager,
-                self.skip_cache_read,
+                self.skip_cache_read
+                    || self.verifier.as_ref().map_or(false, |v| {
+                        v.is_pending(action_digest) || v.is_quarantined(action_digest)
+                    }),
                 self.skip_cache_write,
                 self.re_max_queue_time_ms.map(Duration::from_millis),
                 &self.knobs,
@@ -255,6 +260,7 @@ impl PreparedCommandExecutor for ReExecutor {
                     platform,
                 },
             digest_config,
+            scratch_path_suffix: _,
         } = command;
 
         let details = RemoteCommandExecutionDetails {
@@ -15,6 +15,7 @@
 #![feature(used_with_arg)]
 
 pub mod executors;
+pub mod local_action_cache;
 pub mod low_pass_filter;
 pub mod materializers;
 pub mod re;
@@ -0,0 +1,854 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An on-disk action cache, keyed by action digest, that lives in `buck-out`.
+//!
+//! Output file contents are stored once per CAS digest under `blobs/`, and a sqlite index maps
+//! action digests to the outputs (and std streams) they produced. Blobs are evicted in LRU order
+//! when the cache exceeds its size budget, dropping any action that references them.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::Context;
+use buck2_common::file_ops::FileDigest;
+use buck2_common::sqlite::KeyValueSqliteTable;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_execute::execute::blocking::BlockingExecutor;
+use chrono::Utc;
+use dupe::Dupe;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+
+/// Hand-maintained schema version for the local action cache sqlite db. Bump this if you make
+/// a breaking change to the schema: the cache will be wiped on the next daemon startup.
+pub const DB_SCHEMA_VERSION: u64 = 1;
+
+const ACTION_RESULTS_TABLE_NAME: &str = "action_results";
+const ACTION_OUTPUTS_TABLE_NAME: &str = "action_outputs";
+const BLOBS_TABLE_NAME: &str = "blobs";
+
+/// When evicting, free up space until the cache is this fraction of its budget, so that we don't
+/// evict on every single insertion once the cache is full.
+const EVICTION_LOW_WATER_MARK: f64 = 0.9;
+
+#[derive(buck2_error::Error, Debug, PartialEq, Eq)]
+enum LocalActionCacheError {
+    #[error("Path {} does not exist", .0)]
+    PathDoesNotExist(AbsNormPathBuf),
+
+    #[error("Expected versions {:?}. Found versions {:?} in sqlite db at {}", .expected, .found, .path)]
+    VersionMismatch {
+        expected: HashMap<String, String>,
+        found: HashMap<String, String>,
+        path: AbsNormPathBuf,
+    },
+
+    #[error("Internal error: found unknown value '{}' for enum `entry_type`", .0)]
+    UnknownEntryType(String),
+
+    #[error("Internal error: expected field `{}` to be not null for entry type '{}'", .field, .entry_type)]
+    ExpectedNotNull {
+        field: &'static str,
+        entry_type: String,
+    },
+}
+
+/// The name of the file holding the contents of a blob in the local action cache.
+pub fn blob_name(digest: &FileDigest) -> String {
+    format!("{}_{}", digest.raw_digest(), digest.size())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CachedOutputKind {
+    Directory,
+    File {
+        /// See `blob_name`.
+        blob: String,
+        size: u64,
+        is_executable: bool,
+    },
+    Symlink {
+        target: String,
+    },
+}
+
+/// A single filesystem entry produced by a cached action.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedOutputEntry {
+    pub path: ProjectRelativePathBuf,
+    pub kind: CachedOutputKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedActionResult {
+    /// Entries are ordered such that a directory always precedes its contents.
+    pub entries: Vec<CachedOutputEntry>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Sqlite index of the local action cache.
+struct LocalActionCacheSqliteTables {
+    connection: Arc<Mutex<Connection>>,
+    /// Holds the versions this db was created with. If they don't match what this buck2 expects
+    /// on startup, the whole cache is thrown away.
+    versions_table: KeyValueSqliteTable,
+}
+
+impl LocalActionCacheSqliteTables {
+    fn open(path: &AbsNormPath) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)?;
+        // TODO: make this work on Windows too
+        if cfg!(unix) {
+            connection.pragma_update(None, "journal_mode", "WAL")?;
+        }
+        // Like the materializer state, losing the index on power loss is acceptable: we will
+        // just start over with an empty cache.
+        connection.pragma_update(None, "synchronous", "OFF")?;
+
+        let connection = Arc::new(Mutex::new(connection));
+        let versions_table = KeyValueSqliteTable::new("versions".to_owned(), connection.dupe());
+
+        Ok(Self {
+            connection,
+            versions_table,
+        })
+    }
+
+    fn create_all_tables(&self) -> anyhow::Result<()> {
+        let statements = [
+            format!(
+                "CREATE TABLE {} (
+                    action_digest           TEXT NOT NULL PRIMARY KEY,
+                    stdout                  BLOB NOT NULL,
+                    stderr                  BLOB NOT NULL,
+                    last_access_time        INTEGER NOT NULL
+                )",
+                ACTION_RESULTS_TABLE_NAME,
+            ),
+            format!(
+                "CREATE TABLE {} (
+                    action_digest           TEXT NOT NULL,
+                    path                    TEXT NOT NULL,
+                    entry_type              TEXT CHECK(entry_type IN ('directory','file','symlink')) NOT NULL,
+                    blob                    TEXT NULL DEFAULT NULL,
+                    file_is_executable      INTEGER NULL DEFAULT NULL,
+                    symlink_target          TEXT NULL DEFAULT NULL
+                )",
+                ACTION_OUTPUTS_TABLE_NAME,
+            ),
+            format!(
+                "CREATE INDEX {0}_action_digest ON {0} (action_digest)",
+                ACTION_OUTPUTS_TABLE_NAME,
+            ),
+            format!(
+                "CREATE INDEX {0}_blob ON {0} (blob)",
+                ACTION_OUTPUTS_TABLE_NAME
+            ),
+            format!(
+                "CREATE TABLE {} (
+                    blob                    TEXT NOT NULL PRIMARY KEY,
+                    size                    INTEGER NOT NULL,
+                    last_access_time        INTEGER NOT NULL
+                )",
+                BLOBS_TABLE_NAME,
+            ),
+        ];
+
+        {
+            let connection = self.connection.lock();
+            for sql in &statements {
+                tracing::trace!(sql = %sql, "creating table");
+                connection
+                    .execute(sql, [])
+                    .with_context(|| format!("creating local action cache table: {}", sql))?;
+            }
+        }
+
+        self.versions_table.create_table()?;
+        Ok(())
+    }
+}
+
+/// A size-bounded on-disk action cache. All methods do blocking I/O.
+pub struct LocalActionCache {
+    /// Directory holding blob contents, named by `blob_name`.
+    blobs_dir: AbsNormPathBuf,
+    /// Directory for blobs that are being written, before they get renamed into `blobs_dir`.
+    tmp_dir: AbsNormPathBuf,
+    max_bytes: u64,
+    /// Total size of the blobs recorded in the index.
+    total_bytes: AtomicU64,
+    tmp_counter: AtomicU64,
+    tables: LocalActionCacheSqliteTables,
+}
+
+impl LocalActionCache {
+    const DB_FILENAME: &'static str = "db.sqlite";
+
+    /// Open the cache in `cache_dir`, throwing away whatever is there if it can't be loaded (e.g.
+    /// it doesn't exist, or it was created by a buck2 with a different schema).
+    pub async fn initialize(
+        cache_dir: AbsNormPathBuf,
+        max_bytes: u64,
+        io_executor: Arc<dyn BlockingExecutor>,
+    ) -> anyhow::Result<Self> {
+        io_executor
+            .execute_io_inline(|| Self::initialize_impl(cache_dir, max_bytes))
+            .await
+    }
+
+    fn initialize_impl(cache_dir: AbsNormPathBuf, max_bytes: u64) -> anyhow::Result<Self> {
+        let versions =
+            HashMap::from([("schema_version".to_owned(), DB_SCHEMA_VERSION.to_string())]);
+        let db_path = cache_dir.join(FileName::unchecked_new(Self::DB_FILENAME));
+
+        let existing: anyhow::Result<LocalActionCacheSqliteTables> = try {
+            if !db_path.exists() {
+                Err(LocalActionCacheError::PathDoesNotExist(db_path.clone()))?
+            }
+            let tables = LocalActionCacheSqliteTables::open(&db_path)?;
+            let read_versions = tables.versions_table.read_all()?;
+            if read_versions != versions {
+                Err(LocalActionCacheError::VersionMismatch {
+                    expected: versions.clone(),
+                    found: read_versions,
+                    path: db_path.clone(),
+                })?;
+            }
+            tables
+        };
+
+        let tables = match existing {
+            Ok(tables) => tables,
+            Err(e) => {
+                tracing::debug!("Creating a new local action cache: {:#}", e);
+                // Delete the whole directory, since sqlite may leave other files behind and the
+                // blobs are meaningless without the index.
+                if cache_dir.exists() {
+                    fs_util::remove_dir_all(&cache_dir)?;
+                }
+                fs_util::create_dir_all(&cache_dir)?;
+                let tables = LocalActionCacheSqliteTables::open(&db_path)?;
+                tables.create_all_tables()?;
+                tables.versions_table.insert_all(versions)?;
+                tables
+            }
+        };
+
+        let blobs_dir = cache_dir.join(FileName::unchecked_new("blobs"));
+        let tmp_dir = cache_dir.join(FileName::unchecked_new("tmp"));
+        fs_util::create_dir_all(&blobs_dir)?;
+        if tmp_dir.exists() {
+            fs_util::remove_dir_all(&tmp_dir)?;
+        }
+        fs_util::create_dir_all(&tmp_dir)?;
+
+        let total_bytes: i64 = tables
+            .connection
+            .lock()
+            .query_row(
+                &format!("SELECT COALESCE(SUM(size), 0) FROM {}", BLOBS_TABLE_NAME),
+                [],
+                |row| row.get(0),
+            )
+            .with_context(|| format!("reading sqlite table {}", BLOBS_TABLE_NAME))?;
+
+        Ok(Self {
+            blobs_dir,
+            tmp_dir,
+            max_bytes,
+            total_bytes: AtomicU64::new(total_bytes as u64),
+            tmp_counter: AtomicU64::new(0),
+            tables,
+        })
+    }
+
+    fn blob_path(&self, blob: &str) -> AbsNormPathBuf {
+        self.blobs_dir.join(FileName::unchecked_new(blob))
+    }
+
+    /// Total size of the blobs currently in the cache.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Find the result of an action. Returns `None` if the action is not in the cache, or if some
+    /// of its blobs have gone missing.
+    pub fn lookup(&self, action_digest: &str) -> anyhow::Result<Option<CachedActionResult>> {
+        static ACTION_SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "SELECT stdout, stderr FROM {} WHERE action_digest = ?1",
+                ACTION_RESULTS_TABLE_NAME
+            )
+        });
+        static OUTPUTS_SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "SELECT o.path, o.entry_type, o.blob, o.file_is_executable, o.symlink_target, b.size FROM {} o LEFT JOIN {} b ON o.blob = b.blob WHERE o.action_digest = ?1 ORDER BY o.rowid",
+                ACTION_OUTPUTS_TABLE_NAME, BLOBS_TABLE_NAME,
+            )
+        });
+
+        let mut connection = self.tables.connection.lock();
+
+        let streams: Option<(Vec<u8>, Vec<u8>)> = connection
+            .query_row(&ACTION_SQL, [action_digest], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()
+            .with_context(|| format!("reading from sqlite table {}", ACTION_RESULTS_TABLE_NAME))?;
+        let (stdout, stderr) = match streams {
+            Some(streams) => streams,
+            None => return Ok(None),
+        };
+
+        let rows = connection
+            .prepare(&OUTPUTS_SQL)?
+            .query_map([action_digest], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<bool>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<u64>>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("reading from sqlite table {}", ACTION_OUTPUTS_TABLE_NAME))?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        let mut missing_blobs = false;
+        for (path, entry_type, blob, is_executable, symlink_target, size) in rows {
+            let kind = match entry_type.as_str() {
+                "directory" => CachedOutputKind::Directory,
+                "file" => {
+                    let blob = blob.ok_or_else(|| LocalActionCacheError::ExpectedNotNull {
+                        field: "blob",
+                        entry_type: entry_type.clone(),
+                    })?;
+                    // A blob with no row in the blobs table has been evicted.
+                    let size = match size {
+                        Some(size) if self.blob_path(&blob).exists() => size,
+                        _ => {
+                            missing_blobs = true;
+                            0
+                        }
+                    };
+                    CachedOutputKind::File {
+                        blob,
+                        size,
+                        is_executable: is_executable.unwrap_or(false),
+                    }
+                }
+                "symlink" => CachedOutputKind::Symlink {
+                    target: symlink_target.ok_or_else(|| {
+                        LocalActionCacheError::ExpectedNotNull {
+                            field: "symlink_target",
+                            entry_type: entry_type.clone(),
+                        }
+                    })?,
+                },
+                _ => return Err(LocalActionCacheError::UnknownEntryType(entry_type).into()),
+            };
+            entries.push(CachedOutputEntry {
+                path: ProjectRelativePathBuf::unchecked_new(path),
+                kind,
+            });
+        }
+
+        if missing_blobs {
+            tracing::debug!(
+                "Dropping local action cache entry for `{}`: missing blobs",
+                action_digest
+            );
+            let tx = connection.transaction()?;
+            delete_actions(&tx, &[action_digest.to_owned()])?;
+            tx.commit()?;
+            return Ok(None);
+        }
+
+        let blobs: Vec<&str> = entries
+            .iter()
+            .filter_map(|e| match &e.kind {
+                CachedOutputKind::File { blob, .. } => Some(blob.as_str()),
+                _ => None,
+            })
+            .unique()
+            .collect();
+
+        let now = Utc::now().timestamp();
+        let tx = connection.transaction()?;
+        tx.execute(
+            &format!(
+                "UPDATE {} SET last_access_time = ?1 WHERE action_digest = ?2",
+                ACTION_RESULTS_TABLE_NAME
+            ),
+            rusqlite::params![now, action_digest],
+        )
+        .with_context(|| format!("updating sqlite table {}", ACTION_RESULTS_TABLE_NAME))?;
+        for chunk in blobs.chunks(100) {
+            let sql = format!(
+                "UPDATE {} SET last_access_time = {} WHERE blob IN ({})",
+                BLOBS_TABLE_NAME,
+                now,
+                itertools::repeat_n("?", chunk.len()).join(","),
+            );
+            tx.execute(&sql, rusqlite::params_from_iter(chunk))
+                .with_context(|| format!("updating sqlite table {}", BLOBS_TABLE_NAME))?;
+        }
+        tx.commit()?;
+
+        Ok(Some(CachedActionResult {
+            entries,
+            stdout,
+            stderr,
+        }))
+    }
+
+    /// Record the result of an action whose outputs are on disk in `fs`, copying the contents of
+    /// its output files into the cache. Evicts old blobs if this takes the cache over budget.
+    pub fn store(
+        &self,
+        fs: &ProjectRoot,
+        action_digest: &str,
+        result: &CachedActionResult,
+    ) -> anyhow::Result<()> {
+        let mut new_blobs = Vec::new();
+        let mut seen = HashSet::new();
+        for entry in &result.entries {
+            if let CachedOutputKind::File { blob, size, .. } = &entry.kind {
+                if !seen.insert(blob.as_str()) {
+                    continue;
+                }
+                let blob_path = self.blob_path(blob);
+                if !blob_path.exists() {
+                    // Copy to a temporary file first so that concurrent readers never observe a
+                    // partially written blob.
+                    let tmp_path = self.tmp_dir.join(FileName::unchecked_new(&format!(
+                        "{}.{}",
+                        blob,
+                        self.tmp_counter.fetch_add(1, Ordering::Relaxed)
+                    )));
+                    fs_util::copy(fs.resolve(&entry.path), &tmp_path)?;
+                    fs_util::rename(&tmp_path, &blob_path)?;
+                }
+                new_blobs.push((blob.as_str(), *size));
+            }
+        }
+
+        static ACTION_SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "INSERT INTO {} (action_digest, stdout, stderr, last_access_time) VALUES (?1, ?2, ?3, ?4)",
+                ACTION_RESULTS_TABLE_NAME
+            )
+        });
+        static OUTPUT_SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "INSERT INTO {} (action_digest, path, entry_type, blob, file_is_executable, symlink_target) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                ACTION_OUTPUTS_TABLE_NAME
+            )
+        });
+        static BLOB_SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "INSERT OR IGNORE INTO {} (blob, size, last_access_time) VALUES (?1, ?2, ?3)",
+                BLOBS_TABLE_NAME
+            )
+        });
+
+        let now = Utc::now().timestamp();
+        let mut added_bytes = 0;
+        {
+            let mut connection = self.tables.connection.lock();
+            let tx = connection.transaction()?;
+            delete_actions(&tx, &[action_digest.to_owned()])?;
+            tx.execute(
+                &ACTION_SQL,
+                rusqlite::params![action_digest, result.stdout, result.stderr, now],
+            )
+            .with_context(|| {
+                format!("inserting into sqlite table {}", ACTION_RESULTS_TABLE_NAME)
+            })?;
+            for entry in &result.entries {
+                let (entry_type, blob, is_executable, symlink_target) = match &entry.kind {
+                    CachedOutputKind::Directory => ("directory", None, None, None),
+                    CachedOutputKind::File {
+                        blob,
+                        is_executable,
+                        ..
+                    } => ("file", Some(blob.as_str()), Some(*is_executable), None),
+                    CachedOutputKind::Symlink { target } => {
+                        ("symlink", None, None, Some(target.as_str()))
+                    }
+                };
+                tx.execute(
+                    &OUTPUT_SQL,
+                    rusqlite::params![
+                        action_digest,
+                        entry.path.as_str(),
+                        entry_type,
+                        blob,
+                        is_executable,
+                        symlink_target,
+                    ],
+                )
+                .with_context(|| {
+                    format!("inserting into sqlite table {}", ACTION_OUTPUTS_TABLE_NAME)
+                })?;
+            }
+            for (blob, size) in new_blobs {
+                let inserted = tx
+                    .execute(&BLOB_SQL, rusqlite::params![blob, size, now])
+                    .with_context(|| format!("inserting into sqlite table {}", BLOBS_TABLE_NAME))?;
+                if inserted > 0 {
+                    added_bytes += size;
+                } else {
+                    tx.execute(
+                        &format!(
+                            "UPDATE {} SET last_access_time = ?1 WHERE blob = ?2",
+                            BLOBS_TABLE_NAME
+                        ),
+                        rusqlite::params![now, blob],
+                    )
+                    .with_context(|| format!("updating sqlite table {}", BLOBS_TABLE_NAME))?;
+                }
+            }
+            tx.commit()?;
+        }
+
+        let total_bytes = self.total_bytes.fetch_add(added_bytes, Ordering::Relaxed) + added_bytes;
+        if total_bytes > self.max_bytes {
+            self.evict()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write out the outputs of a cached action into `fs`. The output paths are expected to have
+    /// been cleaned up already.
+    pub fn restore(&self, fs: &ProjectRoot, result: &CachedActionResult) -> anyhow::Result<()> {
+        for entry in &result.entries {
+            let dest = fs.resolve(&entry.path);
+            match &entry.kind {
+                CachedOutputKind::Directory => fs_util::create_dir_all(&dest)?,
+                CachedOutputKind::File {
+                    blob,
+                    is_executable,
+                    ..
+                } => {
+                    if let Some(parent) = dest.parent() {
+                        fs_util::create_dir_all(parent)?;
+                    }
+                    // Don't use `fs_util::copy` here: it would carry over the permissions of
+                    // whichever output first populated the blob.
+                    let mut src = fs_util::open_file(self.blob_path(blob))?;
+                    let mut dst = fs_util::create_file(&dest)?;
+                    std::io::copy(&mut src, &mut dst)
+                        .with_context(|| format!("restoring `{}` from blob `{}`", dest, blob))?;
+                    drop(dst);
+                    if *is_executable {
+                        fs_util::set_executable(&dest)?;
+                    }
+                }
+                CachedOutputKind::Symlink { target } => {
+                    if let Some(parent) = dest.parent() {
+                        fs_util::create_dir_all(parent)?;
+                    }
+                    fs_util::symlink(target, &dest)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete least recently used blobs (and the actions that reference them) until the cache
+    /// is back under its low water mark.
+    fn evict(&self) -> anyhow::Result<()> {
+        let target = (self.max_bytes as f64 * EVICTION_LOW_WATER_MARK) as u64;
+
+        let mut connection = self.tables.connection.lock();
+
+        // Re-check under the lock: another thread might have evicted already.
+        let total_bytes = self.total_bytes.load(Ordering::Relaxed);
+        if total_bytes <= self.max_bytes {
+            return Ok(());
+        }
+
+        let mut to_evict = Vec::new();
+        let mut freed = 0;
+        {
+            let mut stmt = connection.prepare(&format!(
+                "SELECT blob, size FROM {} ORDER BY last_access_time ASC",
+                BLOBS_TABLE_NAME
+            ))?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                if total_bytes.saturating_sub(freed) <= target {
+                    break;
+                }
+                let blob: String = row.get(0)?;
+                let size: u64 = row.get(1)?;
+                freed += size;
+                to_evict.push(blob);
+            }
+        }
+
+        let tx = connection.transaction()?;
+        let mut actions = Vec::new();
+        for chunk in to_evict.chunks(100) {
+            let sql = format!(
+                "SELECT DISTINCT action_digest FROM {} WHERE blob IN ({})",
+                ACTION_OUTPUTS_TABLE_NAME,
+                itertools::repeat_n("?", chunk.len()).join(","),
+            );
+            let mut stmt = tx.prepare(&sql)?;
+            let rows = stmt
+                .query_map(rusqlite::params_from_iter(chunk), |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            actions.extend(rows);
+        }
+        delete_actions(&tx, &actions)?;
+        for chunk in to_evict.chunks(100) {
+            let sql = format!(
+                "DELETE FROM {} WHERE blob IN ({})",
+                BLOBS_TABLE_NAME,
+                itertools::repeat_n("?", chunk.len()).join(","),
+            );
+            tx.execute(&sql, rusqlite::params_from_iter(chunk))
+                .with_context(|| format!("deleting from sqlite table {}", BLOBS_TABLE_NAME))?;
+        }
+        tx.commit()?;
+        self.total_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |t| {
+                Some(t.saturating_sub(freed))
+            })
+            .ok();
+
+        tracing::debug!(
+            "Evicted {} blobs ({} bytes) and {} actions from the local action cache",
+            to_evict.len(),
+            freed,
+            actions.len()
+        );
+
+        for blob in &to_evict {
+            let path = self.blob_path(blob);
+            if path.exists() {
+                fs_util::remove_file(&path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn delete_actions(tx: &rusqlite::Transaction, action_digests: &[String]) -> anyhow::Result<()> {
+    for chunk in action_digests.chunks(100) {
+        let placeholders = itertools::repeat_n("?", chunk.len()).join(",");
+        for table in [ACTION_RESULTS_TABLE_NAME, ACTION_OUTPUTS_TABLE_NAME] {
+            let sql = format!(
+                "DELETE FROM {} WHERE action_digest IN ({})",
+                table, placeholders
+            );
+            tracing::trace!(sql = %sql, chunk = ?chunk, "deleting from table");
+            tx.execute(&sql, rusqlite::params_from_iter(chunk))
+                .with_context(|| format!("deleting from sqlite table {}", table))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+
+    use super::*;
+
+    fn p(path: &str) -> &ProjectRelativePath {
+        ProjectRelativePath::unchecked_new(path)
+    }
+
+    fn file(path: &str, blob: &str, size: u64, is_executable: bool) -> CachedOutputEntry {
+        CachedOutputEntry {
+            path: ProjectRelativePathBuf::unchecked_new(path.to_owned()),
+            kind: CachedOutputKind::File {
+                blob: blob.to_owned(),
+                size,
+                is_executable,
+            },
+        }
+    }
+
+    fn cache(fs: &ProjectRoot, max_bytes: u64) -> anyhow::Result<LocalActionCache> {
+        LocalActionCache::initialize_impl(
+            fs.resolve(p("buck-out/v2/cache/local_action_cache")),
+            max_bytes,
+        )
+    }
+
+    #[test]
+    fn test_store_lookup_restore() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        fs.write_file(p("out/dir/a"), "aaa", true)?;
+        fs.write_file(p("out/b"), "bb", false)?;
+
+        let result = CachedActionResult {
+            entries: vec![
+                CachedOutputEntry {
+                    path: ProjectRelativePathBuf::unchecked_new("out/dir".to_owned()),
+                    kind: CachedOutputKind::Directory,
+                },
+                file("out/dir/a", "aaa_3", 3, true),
+                CachedOutputEntry {
+                    path: ProjectRelativePathBuf::unchecked_new("out/dir/link".to_owned()),
+                    kind: CachedOutputKind::Symlink {
+                        target: "a".to_owned(),
+                    },
+                },
+                file("out/b", "bb_2", 2, false),
+            ],
+            stdout: b"hello".to_vec(),
+            stderr: Vec::new(),
+        };
+
+        let cache = cache(fs, 1000)?;
+        assert_eq!(cache.lookup("action:1")?, None);
+        cache.store(fs, "action:1", &result)?;
+        assert_eq!(cache.total_bytes(), 5);
+        assert_eq!(cache.lookup("action:1")?, Some(result.clone()));
+
+        fs.remove_path_recursive(p("out"))?;
+        cache.restore(fs, &result)?;
+        assert_eq!(fs_util::read_to_string(fs.resolve(p("out/dir/a")))?, "aaa");
+        assert_eq!(fs_util::read_to_string(fs.resolve(p("out/b")))?, "bb");
+        assert_eq!(
+            fs_util::read_link(fs.resolve(p("out/dir/link")))?,
+            std::path::PathBuf::from("a")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path| -> anyhow::Result<u32> {
+                Ok(fs_util::metadata(fs.resolve(p(path)))?.permissions().mode())
+            };
+            assert_ne!(mode("out/dir/a")? & 0o111, 0);
+            assert_eq!(mode("out/b")? & 0o111, 0);
+        }
+
+        // Reopening keeps the contents.
+        drop(cache);
+        let cache = self::cache(fs, 1000)?;
+        assert_eq!(cache.total_bytes(), 5);
+        assert_eq!(cache.lookup("action:1")?, Some(result));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eviction() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        fs.write_file(p("out/a"), "aaaaaa", false)?;
+        fs.write_file(p("out/b"), "bbbbbb", false)?;
+        fs.write_file(p("out/c"), "cccccc", false)?;
+
+        let result = |path, blob| CachedActionResult {
+            entries: vec![file(path, blob, 6, false)],
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+
+        let cache = cache(fs, 15)?;
+        cache.store(fs, "a:1", &result("out/a", "a_6"))?;
+        cache.store(fs, "b:1", &result("out/b", "b_6"))?;
+        assert_eq!(cache.total_bytes(), 12);
+
+        // Force "a" to be older than "b" regardless of timestamp granularity.
+        cache.tables.connection.lock().execute(
+            &format!(
+                "UPDATE {} SET last_access_time = 0 WHERE blob = 'a_6'",
+                BLOBS_TABLE_NAME
+            ),
+            [],
+        )?;
+
+        cache.store(fs, "c:1", &result("out/c", "c_6"))?;
+        assert_eq!(cache.total_bytes(), 12);
+        assert_eq!(cache.lookup("a:1")?, None);
+        assert!(cache.lookup("b:1")?.is_some());
+        assert!(cache.lookup("c:1")?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_blob_is_a_miss() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        fs.write_file(p("out/a"), "aaa", false)?;
+
+        let cache = cache(fs, 1000)?;
+        let result = CachedActionResult {
+            entries: vec![file("out/a", "a_3", 3, false)],
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        cache.store(fs, "a:1", &result)?;
+        fs_util::remove_file(cache.blob_path("a_3"))?;
+
+        assert_eq!(cache.lookup("a:1")?, None);
+        // The entry got dropped, so it stays a miss.
+        assert_eq!(cache.lookup("a:1")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_version_mismatch_wipes_cache() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        fs.write_file(p("out/a"), "aaa", false)?;
+
+        let cache = cache(fs, 1000)?;
+        cache.store(
+            fs,
+            "a:1",
+            &CachedActionResult {
+                entries: vec![file("out/a", "a_3", 3, false)],
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            },
+        )?;
+        cache.tables.versions_table.insert_all(HashMap::from([(
+            "schema_version".to_owned(),
+            "0".to_owned(),
+        )]))?;
+        drop(cache);
+
+        let cache = self::cache(fs, 1000)?;
+        assert_eq!(cache.total_bytes(), 0);
+        assert_eq!(cache.lookup("a:1")?, None);
+
+        Ok(())
+    }
+}
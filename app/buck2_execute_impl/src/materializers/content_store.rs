@@ -0,0 +1,281 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A local content-addressed store under `buck-out`, used to avoid keeping multiple copies of
+//! identical files on disk.
+//!
+//! Files downloaded by the materializer are added to the store, and later materializations of the
+//! same digest are satisfied by linking from the store instead of downloading again.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use allocative::Allocative;
+use anyhow::Context;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use dupe::Dupe;
+use remote_execution::TDigest;
+
+/// How files are materialized from the content store.
+#[derive(Clone, Copy, Debug, Dupe, PartialEq, Allocative)]
+pub enum ContentStoreLinkMode {
+    /// Hardlink the stored file. This is supported by every filesystem Buck2 runs on, but all the
+    /// links share one inode, so an action that modifies one of its inputs in place would corrupt
+    /// every other copy.
+    Hardlink,
+    /// Clone the stored file with `FICLONE`. Outputs share storage but are otherwise independent
+    /// files. Only available on Linux, on filesystems that support it (Btrfs, XFS, ...).
+    Reflink,
+}
+
+#[derive(Debug, buck2_error::Error)]
+pub enum ContentStoreLinkModeError {
+    #[error(
+        "Invalid value for buckconfig `[buck2] materializer_content_store`. Got `{0}`. Expected one of `disabled`, `hardlink` or `reflink`."
+    )]
+    InvalidValueForConfig(String),
+}
+
+impl ContentStoreLinkMode {
+    pub fn try_new_from_config_value(config_value: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match config_value {
+            None | Some("") | Some("disabled") => Ok(None),
+            Some("hardlink") => Ok(Some(ContentStoreLinkMode::Hardlink)),
+            Some("reflink") => Ok(Some(ContentStoreLinkMode::Reflink)),
+            Some(v) => Err(ContentStoreLinkModeError::InvalidValueForConfig(v.to_owned()).into()),
+        }
+    }
+}
+
+#[derive(Allocative)]
+pub struct LocalContentStore {
+    root: AbsNormPathBuf,
+    mode: ContentStoreLinkMode,
+}
+
+impl LocalContentStore {
+    pub fn new(root: AbsNormPathBuf, mode: ContentStoreLinkMode) -> Self {
+        Self { root, mode }
+    }
+
+    /// Entries are keyed on the executable bit too, since hardlinks share permissions.
+    fn entry_path(&self, digest: &TDigest, is_executable: bool) -> PathBuf {
+        let shard = digest.hash.get(..2).unwrap_or("00");
+        let suffix = if is_executable { "_x" } else { "" };
+        self.root.as_path().join(shard).join(format!(
+            "{}_{}{}",
+            digest.hash, digest.size_in_bytes, suffix
+        ))
+    }
+
+    /// Materialize `dest` from the store. Returns `false` if the store does not have this digest
+    /// or `dest` could not be created from it, in which case the caller should fetch it instead.
+    pub fn materialize_from_store(
+        &self,
+        digest: &TDigest,
+        is_executable: bool,
+        dest: &Path,
+    ) -> bool {
+        let entry = self.entry_path(digest, is_executable);
+        let res = match self.mode {
+            ContentStoreLinkMode::Hardlink => fs::hard_link(&entry, dest),
+            ContentStoreLinkMode::Reflink => reflink(&entry, dest).and_then(|()| {
+                // Reflinks don't share an inode, so track usage via the store entry's mtime.
+                File::options()
+                    .write(true)
+                    .open(&entry)?
+                    .set_modified(SystemTime::now())
+            }),
+        };
+
+        match res {
+            Ok(()) => true,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => {
+                // The store has this file, but we couldn't link it (e.g. too many links, or the
+                // filesystem doesn't support reflinks). A plain copy still beats a download.
+                tracing::debug!(
+                    "Error linking `{}` from content store, copying instead: {}",
+                    dest.display(),
+                    e
+                );
+                let _ignored = fs::remove_file(dest);
+                fs::copy(&entry, dest).is_ok()
+            }
+        }
+    }
+
+    /// Add the file at `src` to the store, if it isn't there already.
+    pub fn add_to_store(
+        &self,
+        digest: &TDigest,
+        is_executable: bool,
+        src: &Path,
+    ) -> anyhow::Result<()> {
+        static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let entry = self.entry_path(digest, is_executable);
+        if entry.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = entry.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Error creating `{}`", parent.display()))?;
+        }
+
+        // Link to a temporary path and rename, so that a concurrent lookup never sees a
+        // partially written entry.
+        let tmp = entry.with_extension(format!(
+            "tmp{}",
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let res = match self.mode {
+            ContentStoreLinkMode::Hardlink => fs::hard_link(src, &tmp),
+            ContentStoreLinkMode::Reflink => reflink(src, &tmp),
+        }
+        .and_then(|()| fs::rename(&tmp, &entry));
+
+        if res.is_err() {
+            let _ignored = fs::remove_file(&tmp);
+        }
+        res.with_context(|| {
+            format!(
+                "Error adding `{}` to content store at `{}`",
+                src.display(),
+                entry.display()
+            )
+        })
+    }
+
+    /// Remove entries that are no longer used by anything in `buck-out`: with hardlinks, that's
+    /// entries with no other links. Reflinked entries are removed if they were last used before
+    /// `keep_since`. Returns the number of entries and bytes removed.
+    pub fn prune(&self, keep_since: SystemTime) -> anyhow::Result<(u64, u64)> {
+        let mut removed_count = 0;
+        let mut removed_bytes = 0;
+
+        if !fs_util::try_exists(&self.root)? {
+            return Ok((removed_count, removed_bytes));
+        }
+
+        for shard in fs_util::read_dir(&self.root)? {
+            let shard = shard?;
+            for entry in fs_util::read_dir(&shard.path())? {
+                let entry = entry?;
+                let metadata = fs::symlink_metadata(entry.path())?;
+                let unused = match self.mode {
+                    ContentStoreLinkMode::Hardlink => link_count(&metadata) <= 1,
+                    ContentStoreLinkMode::Reflink => metadata.modified()? < keep_since,
+                };
+                if unused {
+                    fs_util::remove_file(entry.path())?;
+                    removed_count += 1;
+                    removed_bytes += metadata.len();
+                }
+            }
+        }
+
+        Ok((removed_count, removed_bytes))
+    }
+}
+
+#[cfg(unix)]
+fn link_count(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &fs::Metadata) -> u64 {
+    // We can't tell, so assume the entry is still in use.
+    u64::MAX
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // From `linux/fs.h`: `_IOW(0x94, 9, int)`.
+    const FICLONE: libc::c_ulong = 0x40049409;
+
+    let src_file = File::open(src)?;
+    let dest_file = File::options().write(true).create_new(true).open(dest)?;
+
+    // SAFETY: both file descriptors are open for the duration of the call.
+    let res = unsafe { libc::ioctl(dest_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd()) };
+    if res == -1 {
+        let e = io::Error::last_os_error();
+        drop(dest_file);
+        let _ignored = fs::remove_file(dest);
+        return Err(e);
+    }
+
+    dest_file.set_permissions(src_file.metadata()?.permissions())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(hash: &str) -> TDigest {
+        TDigest {
+            hash: hash.to_owned(),
+            size_in_bytes: 5,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hardlink_store_round_trip() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let root = AbsNormPathBuf::new(tempdir.path().join("store"))?;
+        let store = LocalContentStore::new(root, ContentStoreLinkMode::Hardlink);
+
+        let src = tempdir.path().join("src");
+        fs::write(&src, "hello")?;
+        let dest = tempdir.path().join("dest");
+
+        assert!(!store.materialize_from_store(&digest("abcd"), false, &dest));
+
+        store.add_to_store(&digest("abcd"), false, &src)?;
+        // Adding twice is fine.
+        store.add_to_store(&digest("abcd"), false, &src)?;
+
+        // The executable bit is part of the key.
+        assert!(!store.materialize_from_store(&digest("abcd"), true, &dest));
+        assert!(store.materialize_from_store(&digest("abcd"), false, &dest));
+        assert_eq!(fs::read_to_string(&dest)?, "hello");
+
+        // Still in use by `src` and `dest`.
+        assert_eq!(store.prune(SystemTime::now())?, (0, 0));
+
+        fs::remove_file(&src)?;
+        fs::remove_file(&dest)?;
+        assert_eq!(store.prune(SystemTime::now())?, (1, 5));
+        assert!(!store.materialize_from_store(&digest("abcd"), false, &dest));
+
+        Ok(())
+    }
+}
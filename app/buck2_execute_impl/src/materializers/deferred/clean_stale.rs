@@ -9,6 +9,7 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use anyhow::Context;
 use buck2_common::file_ops::FileType;
@@ -47,6 +48,12 @@ pub struct CleanStaleArtifacts {
     pub keep_since_time: DateTime<Utc>,
     pub dry_run: bool,
     pub tracked_only: bool,
+    /// If set, the least recently accessed artifacts are also evicted until the remaining
+    /// tracked artifacts fit within this many bytes.
+    pub max_bytes: Option<u64>,
+    /// Report untracked paths but leave them on disk. This is used by the background clean,
+    /// which may run concurrently with actions writing to `buck-out`.
+    pub keep_untracked: bool,
     #[derivative(Debug = "ignore")]
     pub sender: Sender<BoxFuture<'static, anyhow::Result<buck2_cli_proto::CleanStaleResponse>>>,
     pub dispatcher: EventDispatcher,
@@ -80,6 +87,8 @@ impl<T: IoHandler> ExtensionCommand<T> for CleanStaleArtifacts {
                     self.keep_since_time,
                     self.dry_run,
                     self.tracked_only,
+                    self.max_bytes,
+                    self.keep_untracked,
                     sqlite_db,
                     &processor.io,
                     processor.cancellations,
@@ -114,6 +123,8 @@ fn gather_clean_futures_for_stale_artifacts<T: IoHandler>(
     keep_since_time: DateTime<Utc>,
     dry_run: bool,
     tracked_only: bool,
+    max_bytes: Option<u64>,
+    keep_untracked: bool,
     sqlite_db: &mut MaterializerStateSqliteDb,
     io: &Arc<T>,
     cancellations: &'static CancellationContext,
@@ -134,9 +145,16 @@ fn gather_clean_futures_for_stale_artifacts<T: IoHandler>(
     let mut stats = buck2_data::CleanStaleStats::default();
     let mut paths_to_remove = Vec::new();
     let mut paths_to_invalidate = Vec::new();
+    let mut eviction_candidates = Vec::new();
 
     if tracked_only {
-        find_stale_tracked_only(tree, keep_since_time, &mut stats, &mut paths_to_invalidate)?
+        find_stale_tracked_only(
+            tree,
+            keep_since_time,
+            &mut stats,
+            &mut paths_to_invalidate,
+            &mut eviction_candidates,
+        )?
     } else {
         let gen_subtree = tree
             .get_subtree(&mut gen_path.iter())
@@ -156,13 +174,26 @@ fn gather_clean_futures_for_stale_artifacts<T: IoHandler>(
             fs: io.fs(),
             dispatcher,
             keep_since_time,
+            keep_untracked,
             stats: &mut stats,
             paths_to_remove: &mut paths_to_remove,
             paths_to_invalidate: &mut paths_to_invalidate,
+            eviction_candidates: &mut eviction_candidates,
         }
         .visit_recursively(gen_path, gen_subtree)?;
     };
 
+    if let Some(max_bytes) = max_bytes {
+        evict_least_recently_accessed(
+            eviction_candidates,
+            max_bytes,
+            &mut stats,
+            &mut paths_to_invalidate,
+            // In tracked-only mode we only ever invalidate, so do the same here.
+            (!tracked_only).then_some(&mut paths_to_remove),
+        );
+    }
+
     // If no stale or retained artifact founds, the db should be empty.
     if stats.stale_artifact_count + stats.retained_artifact_count == 0 {
         // Just need to know if any entries exist, could be a simpler query.
@@ -188,8 +219,12 @@ fn gather_clean_futures_for_stale_artifacts<T: IoHandler>(
         let io = io.dupe();
 
         stats.cleaned_path_count = paths_to_remove.len() as u64;
-        stats.cleaned_artifact_count = stats.stale_artifact_count + stats.untracked_artifact_count;
-        stats.cleaned_bytes = stats.untracked_bytes + stats.stale_bytes;
+        stats.cleaned_artifact_count = stats.stale_artifact_count;
+        stats.cleaned_bytes = stats.stale_bytes;
+        if !keep_untracked {
+            stats.cleaned_artifact_count += stats.untracked_artifact_count;
+            stats.cleaned_bytes += stats.untracked_bytes;
+        }
 
         let existing_futs =
             tree.invalidate_paths_and_collect_futures(paths_to_invalidate, Some(sqlite_db))?;
@@ -209,6 +244,20 @@ fn gather_clean_futures_for_stale_artifacts<T: IoHandler>(
             }))
             .await?;
 
+            // Deleting outputs may have left entries in the content store unused.
+            if let Some(content_store) = io.content_store() {
+                let keep_since = if keep_since_time.timestamp() < 0 {
+                    SystemTime::UNIX_EPOCH
+                } else {
+                    keep_since_time.into()
+                };
+                let (count, bytes) = io
+                    .io_executor()
+                    .execute_io_inline(|| content_store.prune(keep_since))
+                    .await?;
+                tracing::debug!(count, bytes, "pruned content store");
+            }
+
             anyhow::Ok(())
         }
         .boxed()
@@ -236,15 +285,57 @@ pub fn get_size(path: &AbsNormPath) -> anyhow::Result<u64> {
     Ok(result)
 }
 
+/// A retained artifact that may be evicted to satisfy a size budget.
+struct EvictionCandidate {
+    path: ProjectRelativePathBuf,
+    last_access_time: DateTime<Utc>,
+    size: u64,
+}
+
+/// Evict the least recently accessed candidates until the retained artifacts fit within
+/// `max_bytes`. Evicted artifacts are moved from the retained to the stale stats.
+fn evict_least_recently_accessed(
+    mut candidates: Vec<EvictionCandidate>,
+    max_bytes: u64,
+    stats: &mut buck2_data::CleanStaleStats,
+    paths_to_invalidate: &mut Vec<ProjectRelativePathBuf>,
+    mut paths_to_remove: Option<&mut Vec<ProjectRelativePathBuf>>,
+) {
+    if stats.retained_bytes <= max_bytes {
+        return;
+    }
+
+    candidates.sort_by_key(|c| c.last_access_time);
+
+    for candidate in candidates {
+        if stats.retained_bytes <= max_bytes {
+            break;
+        }
+        tracing::trace!(path = %candidate.path, "evicting to fit size budget");
+        stats.retained_artifact_count -= 1;
+        stats.retained_bytes -= candidate.size;
+        stats.stale_artifact_count += 1;
+        stats.stale_bytes += candidate.size;
+        if let Some(paths_to_remove) = paths_to_remove.as_mut() {
+            paths_to_remove.push(candidate.path.clone());
+        }
+        paths_to_invalidate.push(candidate.path);
+    }
+}
+
 struct StaleFinder<'a> {
     fs: &'a ProjectRoot,
     dispatcher: &'a EventDispatcher,
     keep_since_time: DateTime<Utc>,
+    /// Whether untracked paths should be left on disk.
+    keep_untracked: bool,
     stats: &'a mut buck2_data::CleanStaleStats,
     /// Those paths will be deleted on disk.
     paths_to_remove: &'a mut Vec<ProjectRelativePathBuf>,
     /// Those paths will be invalidated in the materiaizer.
     paths_to_invalidate: &'a mut Vec<ProjectRelativePathBuf>,
+    /// Retained artifacts that are not in use and could be evicted.
+    eviction_candidates: &'a mut Vec<EvictionCandidate>,
 }
 
 impl<'a> StaleFinder<'a> {
@@ -307,7 +398,9 @@ impl<'a> StaleFinder<'a> {
                             file_type: format!("{:?}", file_type),
                         });
                     }
-                    self.paths_to_remove.push(path);
+                    if !self.keep_untracked {
+                        self.paths_to_remove.push(path);
+                    }
                     continue;
                 }
             };
@@ -332,6 +425,24 @@ impl<'a> StaleFinder<'a> {
                     self.paths_to_invalidate.push(path.clone());
                     self.paths_to_remove.push(path);
                 }
+                ArtifactTree::Data(box ArtifactMaterializationData {
+                    stage:
+                        ArtifactMaterializationStage::Materialized {
+                            active: false,
+                            last_access_time,
+                            metadata,
+                        },
+                    ..
+                }) => {
+                    tracing::trace!(path = %path, file_type = ?file_type, "marking as retained");
+                    self.stats.retained_artifact_count += 1;
+                    self.stats.retained_bytes += metadata.size();
+                    self.eviction_candidates.push(EvictionCandidate {
+                        path,
+                        last_access_time: *last_access_time,
+                        size: metadata.size(),
+                    });
+                }
                 ArtifactTree::Data(box ArtifactMaterializationData {
                     stage: ArtifactMaterializationStage::Materialized { metadata, .. },
                     ..
@@ -357,24 +468,105 @@ fn find_stale_tracked_only(
     keep_since_time: DateTime<Utc>,
     stats: &mut buck2_data::CleanStaleStats,
     paths_to_invalidate: &mut Vec<ProjectRelativePathBuf>,
+    eviction_candidates: &mut Vec<EvictionCandidate>,
 ) -> anyhow::Result<()> {
     for (f_path, v) in tree.iter_with_paths() {
         if let ArtifactMaterializationStage::Materialized {
             last_access_time,
             active,
-            ..
+            metadata,
         } = &v.stage
         {
             let path = ProjectRelativePathBuf::from(f_path);
             if *last_access_time < keep_since_time && !active {
                 tracing::trace!(path = %path, "stale artifact");
                 stats.stale_artifact_count += 1;
+                stats.stale_bytes += metadata.size();
                 paths_to_invalidate.push(path);
             } else {
                 tracing::trace!(path = %path, "retaining artifact");
                 stats.retained_artifact_count += 1;
+                stats.retained_bytes += metadata.size();
+                if !active {
+                    eviction_candidates.push(EvictionCandidate {
+                        path,
+                        last_access_time: *last_access_time,
+                        size: metadata.size(),
+                    });
+                }
             }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn candidate(path: &str, last_access_time: i64, size: u64) -> EvictionCandidate {
+        EvictionCandidate {
+            path: ProjectRelativePathBuf::unchecked_new(path.to_owned()),
+            last_access_time: Utc.timestamp_opt(last_access_time, 0).unwrap(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_evict_least_recently_accessed() {
+        let mut stats = buck2_data::CleanStaleStats {
+            retained_artifact_count: 3,
+            retained_bytes: 60,
+            ..Default::default()
+        };
+        let mut paths_to_invalidate = Vec::new();
+        let mut paths_to_remove = Vec::new();
+
+        evict_least_recently_accessed(
+            vec![
+                candidate("b", 20, 10),
+                candidate("c", 30, 30),
+                candidate("a", 10, 20),
+            ],
+            35,
+            &mut stats,
+            &mut paths_to_invalidate,
+            Some(&mut paths_to_remove),
+        );
+
+        // "a" alone is not enough to fit in the budget, so "b" goes too.
+        let expected = vec![
+            ProjectRelativePathBuf::unchecked_new("a".to_owned()),
+            ProjectRelativePathBuf::unchecked_new("b".to_owned()),
+        ];
+        assert_eq!(paths_to_invalidate, expected);
+        assert_eq!(paths_to_remove, expected);
+        assert_eq!(stats.retained_artifact_count, 1);
+        assert_eq!(stats.retained_bytes, 30);
+        assert_eq!(stats.stale_artifact_count, 2);
+        assert_eq!(stats.stale_bytes, 30);
+    }
+
+    #[test]
+    fn test_evict_least_recently_accessed_under_budget() {
+        let mut stats = buck2_data::CleanStaleStats {
+            retained_artifact_count: 1,
+            retained_bytes: 10,
+            ..Default::default()
+        };
+        let mut paths_to_invalidate = Vec::new();
+
+        evict_least_recently_accessed(
+            vec![candidate("a", 10, 10)],
+            10,
+            &mut stats,
+            &mut paths_to_invalidate,
+            None,
+        );
+
+        assert!(paths_to_invalidate.is_empty());
+        assert_eq!(stats.retained_artifact_count, 1);
+    }
+}
@@ -405,6 +405,7 @@ impl<T: IoHandler> DeferredMaterializerExtensions for DeferredMaterializerAccess
         keep_since_time: DateTime<Utc>,
         dry_run: bool,
         tracked_only: bool,
+        max_bytes: Option<u64>,
     ) -> anyhow::Result<buck2_cli_proto::CleanStaleResponse> {
         let dispatcher = get_dispatcher();
         let (sender, recv) = oneshot::channel();
@@ -414,6 +415,8 @@ impl<T: IoHandler> DeferredMaterializerExtensions for DeferredMaterializerAccess
                     keep_since_time,
                     dry_run,
                     tracked_only,
+                    max_bytes,
+                    keep_untracked: false,
                     sender,
                     dispatcher,
                 },
@@ -9,6 +9,7 @@
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -52,6 +53,7 @@ use remote_execution::TCode;
 use remote_execution::TDigest;
 use tracing::instrument;
 
+use crate::materializers::content_store::LocalContentStore;
 use crate::materializers::deferred::ArtifactMaterializationMethod;
 use crate::materializers::deferred::ArtifactMaterializationStage;
 use crate::materializers::deferred::ArtifactTree;
@@ -74,6 +76,8 @@ pub struct DefaultIoHandler {
     /// Executor for blocking IO operations
     io_executor: Arc<dyn BlockingExecutor>,
     http_client: HttpClient,
+    /// If set, downloaded files are deduplicated through this store.
+    content_store: Option<LocalContentStore>,
 }
 
 struct MaterializationStat {
@@ -120,6 +124,7 @@ pub trait IoHandler: Sized + Sync + Send + 'static {
     fn re_client_manager(&self) -> &Arc<ReConnectionManager>;
     fn fs(&self) -> &ProjectRoot;
     fn digest_config(&self) -> DigestConfig;
+    fn content_store(&self) -> Option<&LocalContentStore>;
 }
 
 impl DefaultIoHandler {
@@ -130,6 +135,7 @@ impl DefaultIoHandler {
         re_client_manager: Arc<ReConnectionManager>,
         io_executor: Arc<dyn BlockingExecutor>,
         http_client: HttpClient,
+        content_store: Option<LocalContentStore>,
     ) -> Self {
         Self {
             fs,
@@ -138,6 +144,7 @@ impl DefaultIoHandler {
             re_client_manager,
             io_executor,
             http_client,
+            content_store,
         }
     }
     /// Materializes an `entry` at `path`, using the materialization `method`
@@ -199,6 +206,38 @@ impl DefaultIoHandler {
                     .map(|x| u64::try_from(x.named_digest.digest.size_in_bytes).unwrap_or_default())
                     .sum();
 
+                if let Some(content_store) = &self.content_store {
+                    files = self
+                        .io_executor
+                        .execute_io_inline(|| {
+                            Ok(files
+                                .into_iter()
+                                .filter(|f| {
+                                    !content_store.materialize_from_store(
+                                        &f.named_digest.digest,
+                                        f.is_executable,
+                                        Path::new(&f.named_digest.name),
+                                    )
+                                })
+                                .collect())
+                        })
+                        .await?;
+                }
+
+                // Only the files we actually download need to be added to the store.
+                let files_to_store = self.content_store.as_ref().map(|_| {
+                    files
+                        .iter()
+                        .map(|f| {
+                            (
+                                f.named_digest.digest.clone(),
+                                f.is_executable,
+                                f.named_digest.name.clone(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                });
+
                 let connection = self.re_client_manager.get_re_connection();
                 let re_client = connection.get_client();
 
@@ -217,6 +256,29 @@ impl DefaultIoHandler {
                             )
                         })),
                     })?;
+
+                if let (Some(content_store), Some(files_to_store)) =
+                    (&self.content_store, files_to_store)
+                {
+                    // The store is just an optimization, so don't fail the materialization if we
+                    // can't populate it.
+                    let res = self
+                        .io_executor
+                        .execute_io_inline(|| {
+                            for (digest, is_executable, name) in &files_to_store {
+                                content_store.add_to_store(
+                                    digest,
+                                    *is_executable,
+                                    Path::new(name),
+                                )?;
+                            }
+                            Ok(())
+                        })
+                        .await;
+                    if let Err(e) = res {
+                        tracing::warn!("Error populating content store: {:#}", e);
+                    }
+                }
             }
             ArtifactMaterializationMethod::HttpDownload { info } => {
                 async {
@@ -411,6 +473,10 @@ impl IoHandler for DefaultIoHandler {
     fn digest_config(&self) -> DigestConfig {
         self.digest_config
     }
+
+    fn content_store(&self) -> Option<&LocalContentStore> {
+        self.content_store.as_ref()
+    }
 }
 
 /// This is used for testing to ingest digests (via BUCK2_TEST_TOMBSTONED_DIGESTS).
@@ -93,6 +93,9 @@ use tokio::time::Instant;
 use tokio::time::Interval;
 use tracing::instrument;
 
+use crate::materializers::content_store::ContentStoreLinkMode;
+use crate::materializers::content_store::LocalContentStore;
+use crate::materializers::deferred::clean_stale::CleanStaleArtifacts;
 use crate::materializers::deferred::extension::ExtensionCommand;
 use crate::materializers::deferred::file_tree::FileTree;
 use crate::materializers::deferred::io_handler::DefaultIoHandler;
@@ -172,6 +175,19 @@ pub struct DeferredMaterializerConfigs {
     pub defer_write_actions: bool,
     pub ttl_refresh: TtlRefreshConfiguration,
     pub update_access_times: AccessTimesUpdates,
+    pub buck_out_size_limit: Option<BuckOutSizeLimitConfiguration>,
+    /// If set, materialized files are deduplicated through a local content store.
+    pub content_store: Option<ContentStoreLinkMode>,
+}
+
+/// Periodically evict the least recently accessed artifacts to keep `buck-out` under a size
+/// budget.
+pub struct BuckOutSizeLimitConfiguration {
+    pub max_bytes: u64,
+    /// How often to check whether `buck-out` is over budget.
+    pub frequency: std::time::Duration,
+    /// Only clean if the materializer has not received any commands for this long.
+    pub idle_time: std::time::Duration,
 }
 
 pub struct TtlRefreshConfiguration {
@@ -299,6 +315,8 @@ struct DeferredMaterializerCommandProcessor<T: 'static> {
     ttl_refresh_history: Vec<TtlRefreshHistoryEntry>,
     /// The current ttl_refresh instance, if any exists.
     ttl_refresh_instance: Option<oneshot::Receiver<(DateTime<Utc>, anyhow::Result<()>)>>,
+    /// The current background clean of `buck-out`, if any is running.
+    background_clean_instance: Option<oneshot::Receiver<()>>,
     cancellations: &'static CancellationContext<'static>,
     stats: Arc<DeferredMaterializerStats>,
     access_times_buffer: Option<HashSet<ProjectRelativePathBuf>>,
@@ -978,6 +996,13 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
             }
         }
 
+        let content_store = configs.content_store.map(|mode| {
+            let root = fs.resolve(&buck_out_path.join(ProjectRelativePathBuf::unchecked_new(
+                "content_store".to_owned(),
+            )));
+            LocalContentStore::new(root, mode)
+        });
+
         let io = Arc::new(DefaultIoHandler::new(
             fs,
             digest_config,
@@ -985,6 +1010,7 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
             re_client_manager,
             io_executor,
             http_client,
+            content_store,
         ));
 
         let command_processor = {
@@ -1004,6 +1030,7 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
                 subscriptions: MaterializerSubscriptions::new(),
                 ttl_refresh_history: Vec::new(),
                 ttl_refresh_instance: None,
+                background_clean_instance: None,
                 cancellations,
                 stats,
                 access_times_buffer,
@@ -1026,6 +1053,7 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
                     rt.block_on(command_processor(cancellations).run(
                         command_receiver,
                         configs.ttl_refresh,
+                        configs.buck_out_size_limit,
                         access_time_update_max_buffer_size,
                         configs.update_access_times,
                     ));
@@ -1079,6 +1107,7 @@ struct CommandStream<T: 'static> {
     high_priority: UnboundedReceiver<MaterializerCommand<T>>,
     low_priority: UnboundedReceiver<LowPriorityMaterializerCommand>,
     refresh_ttl_ticker: Option<Interval>,
+    clean_buck_out_ticker: Option<Interval>,
     io_buffer_ticker: Interval,
 }
 
@@ -1086,6 +1115,7 @@ enum Op<T: 'static> {
     Command(MaterializerCommand<T>),
     LowPriorityCommand(LowPriorityMaterializerCommand),
     RefreshTtls,
+    CleanBuckOut,
     Tick,
 }
 
@@ -1109,6 +1139,12 @@ impl<T: 'static> Stream for CommandStream<T> {
             }
         }
 
+        if let Some(ticker) = this.clean_buck_out_ticker.as_mut() {
+            if ticker.poll_tick(cx).is_ready() {
+                return Poll::Ready(Some(Op::CleanBuckOut));
+            }
+        }
+
         if this.io_buffer_ticker.poll_tick(cx).is_ready() {
             return Poll::Ready(Some(Op::Tick));
         }
@@ -1126,6 +1162,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         mut self,
         commands: MaterializerReceiver<T>,
         ttl_refresh: TtlRefreshConfiguration,
+        buck_out_size_limit: Option<BuckOutSizeLimitConfiguration>,
         access_time_update_max_buffer_size: usize,
         access_time_updates: AccessTimesUpdates,
     ) {
@@ -1144,18 +1181,29 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
             None
         };
 
+        let clean_buck_out_ticker = buck_out_size_limit.as_ref().map(|limit| {
+            tokio::time::interval_at(
+                tokio::time::Instant::now() + limit.frequency,
+                limit.frequency,
+            )
+        });
+
         let io_buffer_ticker = tokio::time::interval(std::time::Duration::from_secs(5));
 
         let mut stream = CommandStream {
             high_priority,
             low_priority,
             refresh_ttl_ticker,
+            clean_buck_out_ticker,
             io_buffer_ticker,
         };
 
+        let mut last_command_at = Instant::now();
+
         while let Some(op) = stream.next().await {
             match op {
                 Op::Command(command) => {
+                    last_command_at = Instant::now();
                     self.log_buffer.push(format!("{:?}", command));
                     self.process_one_command(command);
                     counters.ack_received();
@@ -1203,6 +1251,15 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                         }
                     }
                 }
+                Op::CleanBuckOut => {
+                    if let Some(limit) = &buck_out_size_limit {
+                        // Cleaning invalidates artifacts, so don't race with builds that might
+                        // be about to use them.
+                        if last_command_at.elapsed() >= limit.idle_time {
+                            self.clean_buck_out_to_size_limit(limit.max_bytes);
+                        }
+                    }
+                }
                 Op::Tick => {
                     if matches!(access_time_updates, AccessTimesUpdates::Full) {
                         // Force a periodic flush.
@@ -1325,6 +1382,47 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         };
     }
 
+    /// Evict the least recently accessed artifacts until `buck-out` fits in `max_bytes`. Untracked
+    /// paths are left alone, since they may be outputs of actions that are still running. Does
+    /// nothing if the previous background clean is still in progress.
+    fn clean_buck_out_to_size_limit(&mut self, max_bytes: u64) {
+        if let Some(curr) = self.background_clean_instance.as_mut() {
+            if let Err(TryRecvError::Empty) = curr.try_recv() {
+                return;
+            }
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let command = Box::new(CleanStaleArtifacts {
+            keep_since_time: DateTime::<Utc>::MIN_UTC,
+            dry_run: false,
+            tracked_only: false,
+            max_bytes: Some(max_bytes),
+            keep_untracked: true,
+            sender,
+            dispatcher: EventDispatcher::null(),
+        });
+        command.execute(self);
+
+        let (done_sender, done_receiver) = oneshot::channel();
+        self.rt.spawn(async move {
+            let res = match receiver.await {
+                Ok(fut) => fut.await,
+                Err(_) => Err(anyhow::anyhow!("Shutdown")),
+            };
+            match res {
+                Ok(response) => tracing::debug!(
+                    stats = ?response.stats,
+                    message = ?response.message,
+                    "Background clean of buck-out finished"
+                ),
+                Err(e) => tracing::warn!("Background clean of buck-out failed: {:#}", e),
+            }
+            let _ignored = done_sender.send(());
+        });
+        self.background_clean_instance = Some(done_receiver);
+    }
+
     fn is_path_materialized(&self, path: &ProjectRelativePath) -> bool {
         match self.tree.prefix_get(&mut path.iter()) {
             None => false,
@@ -97,6 +97,7 @@ mod state_machine {
     use tokio::time::Duration as TokioDuration;
 
     use super::*;
+    use crate::materializers::content_store::LocalContentStore;
 
     #[derive(Debug, Eq, PartialEq)]
     enum Op {
@@ -225,6 +226,10 @@ mod state_machine {
         fn digest_config(&self) -> DigestConfig {
             self.digest_config
         }
+
+        fn content_store(&self) -> Option<&LocalContentStore> {
+            None
+        }
     }
 
     /// A stub command sender. We are calling materializer methods directly so that's all we need.
@@ -282,6 +287,7 @@ mod state_machine {
                 subscriptions: MaterializerSubscriptions::new(),
                 ttl_refresh_history: Default::default(),
                 ttl_refresh_instance: Default::default(),
+                background_clean_instance: Default::default(),
                 cancellations: CancellationContext::testing(),
                 stats: Arc::new(DeferredMaterializerStats::default()),
                 access_times_buffer: Default::default(),
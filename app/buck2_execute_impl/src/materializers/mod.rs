@@ -10,6 +10,7 @@
 #[cfg(fbcode_build)]
 pub mod eden;
 
+pub mod content_store;
 pub mod deferred;
 pub mod immediate;
 pub mod io;
@@ -136,7 +136,7 @@ pub async fn download_action_results<'a>(
                         stage: Some(buck2_data::MaterializeFailedInputs {}.into()),
                     },
                     async move {
-                        match materialize_inputs(artifact_fs, materializer, request).await {
+                        match materialize_inputs(artifact_fs, materializer, request, None).await {
                             Ok(materialized_paths) => Some(materialized_paths.paths.clone()),
                             Err(e) => {
                                 console_message(format!(
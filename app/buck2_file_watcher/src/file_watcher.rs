@@ -32,6 +32,19 @@ pub trait FileWatcher: Allocative + Send + Sync + 'static {
         &self,
         dice: DiceTransactionUpdater,
     ) -> anyhow::Result<(DiceTransactionUpdater, Mergebase)>;
+
+    /// Describes the state of the file system as of the last sync, such that a watcher created
+    /// by a later daemon can `resume` from it. Returns `None` if this watcher can't be resumed.
+    async fn save_state(&self) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Make the next sync report all the changes since the state saved by `save_state`. This
+    /// must be called before the first sync. Returns false if this watcher can't be resumed, in
+    /// which case the next sync only reports changes from now on.
+    fn resume(&self, _state: &str) -> anyhow::Result<bool> {
+        Ok(false)
+    }
 }
 
 impl dyn FileWatcher {
@@ -62,6 +75,11 @@ impl dyn FileWatcher {
                 FsHashCrawler::new(project_root, cells, ignore_specs)
                     .context("Creating fs_crawler file watcher")?,
             )),
+            #[cfg(target_os = "linux")]
+            "inotify" => Ok(Arc::new(
+                crate::inotify::InotifyFileWatcher::new(project_root, cells, ignore_specs)
+                    .context("Creating inotify file watcher")?,
+            )),
             other => Err(anyhow::anyhow!("Invalid buck2.file_watcher: {}", other)),
         }
     }
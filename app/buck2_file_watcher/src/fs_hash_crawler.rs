@@ -7,8 +7,13 @@
  * of this source tree.
  */
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::fs;
 use std::fs::File;
+use std::hash::Hash as _;
+use std::hash::Hasher;
+use std::io;
 use std::io::Read;
 use std::mem;
 use std::path::Path;
@@ -16,7 +21,6 @@ use std::sync::Arc;
 use std::sync::Mutex;
 
 use allocative::Allocative;
-use anyhow::Context;
 use async_trait::async_trait;
 use blake3::Hash;
 use buck2_common::dice::file_ops::FileChangeTracker;
@@ -108,17 +112,27 @@ impl FileWatcher for FsHashCrawler {
     }
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
-struct FsEvent {
-    cell_path: CellPath,
-    event: FileWatcherEventType,
-    kind: FileWatcherKind,
+#[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct FsEvent {
+    pub(crate) cell_path: CellPath,
+    pub(crate) event: FileWatcherEventType,
+    pub(crate) kind: FileWatcherKind,
+}
+
+/// How files are compared between snapshots.
+#[derive(Clone, Copy, Dupe, PartialEq, Eq)]
+pub(crate) enum SnapshotKind {
+    /// Hash the contents of every file. Slow, but reliable.
+    ContentHash,
+    /// Only compare file metadata (size, modification time, inode and mode).
+    Metadata,
 }
 
 #[derive(Allocative)]
 enum EntryInfo {
     #[allocative(skip)]
     File(Hash),
+    FileMetadata(u64),
     Directory,
     Symlink,
 }
@@ -126,7 +140,7 @@ enum EntryInfo {
 impl EntryInfo {
     fn to_file_watcher_kind(&self) -> FileWatcherKind {
         match self {
-            EntryInfo::File(_) => FileWatcherKind::File,
+            EntryInfo::File(_) | EntryInfo::FileMetadata(_) => FileWatcherKind::File,
             EntryInfo::Directory => FileWatcherKind::Directory,
             EntryInfo::Symlink => FileWatcherKind::Symlink,
         }
@@ -134,12 +148,23 @@ impl EntryInfo {
 }
 
 #[derive(Allocative)]
-struct FsSnapshot(HashMap<CellPath, EntryInfo>);
+pub(crate) struct FsSnapshot(HashMap<CellPath, EntryInfo>);
 
 impl FsSnapshot {
     fn build(root: &ProjectRoot, cells: &CellResolver) -> anyhow::Result<Self> {
+        Self::build_with(root, cells, SnapshotKind::ContentHash, None)
+    }
+
+    /// Build a snapshot of the repository. If `ignore_specs` is set, ignored directories are not
+    /// descended into.
+    pub(crate) fn build_with(
+        root: &ProjectRoot,
+        cells: &CellResolver,
+        kind: SnapshotKind,
+        ignore_specs: Option<&HashMap<CellName, IgnoreSet>>,
+    ) -> anyhow::Result<Self> {
         let mut snapshot = FsSnapshot(HashMap::new());
-        snapshot.build_fs_snapshot(root, cells, root.root())?;
+        snapshot.build_fs_snapshot(root, cells, root.root(), kind, ignore_specs)?;
         Ok(snapshot)
     }
 
@@ -147,7 +172,7 @@ impl FsSnapshot {
         self.0.insert(cell, info);
     }
 
-    fn get_updates(&self, new_snapshot: &FsSnapshot) -> anyhow::Result<Vec<FsEvent>> {
+    pub(crate) fn get_updates(&self, new_snapshot: &FsSnapshot) -> anyhow::Result<Vec<FsEvent>> {
         let mut events = Vec::new();
         for (cell_path, prev_info) in self.0.iter() {
             if let Some(current_info) = new_snapshot.0.get(cell_path) {
@@ -159,6 +184,15 @@ impl FsSnapshot {
                             kind: prev_info.to_file_watcher_kind(),
                         });
                     }
+                    (EntryInfo::FileMetadata(cur), EntryInfo::FileMetadata(prev))
+                        if cur != prev =>
+                    {
+                        events.push(FsEvent {
+                            cell_path: cell_path.to_owned(),
+                            event: FileWatcherEventType::Modify,
+                            kind: prev_info.to_file_watcher_kind(),
+                        });
+                    }
                     _ => (),
                 }
             } else {
@@ -189,52 +223,7 @@ impl FsSnapshot {
         ignore_specs: &HashMap<CellName, IgnoreSet>,
     ) -> anyhow::Result<(buck2_data::FileWatcherStats, FileChangeTracker)> {
         let events = self.get_updates(new_snapshot)?;
-        let mut changed = FileChangeTracker::new();
-        let mut stats = FileWatcherStats::new(events.len(), None, None, None);
-        let mut ignored = 0;
-        for event in events.into_iter() {
-            let ignore = ignore_specs
-                .get(&event.cell_path.cell())
-                .map_or(false, |i| i.is_match(event.cell_path.path()));
-
-            if ignore {
-                ignored += 1;
-                continue;
-            }
-
-            stats.add(event.cell_path.to_string(), event.event, event.kind);
-            match (event.event, event.kind) {
-                (
-                    FileWatcherEventType::Create,
-                    FileWatcherKind::File | FileWatcherKind::Symlink,
-                ) => {
-                    changed.file_added(event.cell_path);
-                }
-                (FileWatcherEventType::Create, FileWatcherKind::Directory) => {
-                    changed.dir_added(event.cell_path);
-                }
-                (
-                    FileWatcherEventType::Modify,
-                    FileWatcherKind::File | FileWatcherKind::Symlink,
-                ) => {
-                    changed.file_changed(event.cell_path);
-                }
-                (FileWatcherEventType::Modify, FileWatcherKind::Directory) => {
-                    changed.dir_changed(event.cell_path);
-                }
-                (
-                    FileWatcherEventType::Delete,
-                    FileWatcherKind::File | FileWatcherKind::Symlink,
-                ) => {
-                    changed.file_removed(event.cell_path);
-                }
-                (FileWatcherEventType::Delete, FileWatcherKind::Directory) => {
-                    changed.dir_removed(event.cell_path);
-                }
-            }
-        }
-        stats.add_ignored(ignored);
-        Ok((stats.finish(), changed))
+        Ok(events_to_dice(events, ignore_specs))
     }
 
     fn build_fs_snapshot(
@@ -242,16 +231,38 @@ impl FsSnapshot {
         root: &ProjectRoot,
         cells: &CellResolver,
         disk_path: &AbsNormPath,
+        kind: SnapshotKind,
+        ignore_specs: Option<&HashMap<CellName, IgnoreSet>>,
     ) -> anyhow::Result<()> {
-        for file in fs_util::read_dir(disk_path)? {
+        // Files can be created and deleted while we crawl (e.g. during a `git checkout`), so
+        // entries that vanish before we get to them are skipped rather than failing the snapshot.
+        let entries = match fs_util::read_dir_if_exists(disk_path)? {
+            Some(entries) => entries,
+            None => return Ok(()),
+        };
+        for file in entries {
             let file = file?;
-            let filetype = file.file_type()?;
+            let filetype = match file.file_type() {
+                Ok(filetype) => filetype,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
             let filename = file.file_name();
 
-            let filename = FileNameBuf::try_from(CompactString::new(
-                filename.to_str().context("Filename is not UTF-8")?,
-            ))
-            .with_context(|| format!("Invalid filename: {}", disk_path.display()))?;
+            let filename = match filename
+                .to_str()
+                .and_then(|f| FileNameBuf::try_from(CompactString::new(f)).ok())
+            {
+                Some(filename) => filename,
+                None => {
+                    tracing::debug!(
+                        "Ignoring invalid file name in `{}`: {:?}",
+                        disk_path.display(),
+                        filename
+                    );
+                    continue;
+                }
+            };
 
             let disk_path = disk_path.join(filename);
             let rel_path = root.relativize(&disk_path)?;
@@ -265,11 +276,26 @@ impl FsSnapshot {
             let filetype = FileType::from(filetype);
             match filetype {
                 FileType::File => {
-                    let hash = file_hash(disk_path.as_maybe_relativized())?;
-                    self.add_entry(cell_path, EntryInfo::File(hash));
+                    let info = match kind {
+                        SnapshotKind::ContentHash => {
+                            file_hash(disk_path.as_maybe_relativized()).map(EntryInfo::File)
+                        }
+                        SnapshotKind::Metadata => {
+                            file_metadata_hash(disk_path.as_maybe_relativized())
+                                .map(EntryInfo::FileMetadata)
+                        }
+                    };
+                    match info {
+                        Ok(info) => self.add_entry(cell_path, info),
+                        Err(e) if is_not_found(&e) => continue,
+                        Err(e) => return Err(e),
+                    }
                 }
                 FileType::Directory => {
-                    self.build_fs_snapshot(root, cells, &disk_path)?;
+                    if is_ignored(ignore_specs, &cell_path) {
+                        continue;
+                    }
+                    self.build_fs_snapshot(root, cells, &disk_path, kind, ignore_specs)?;
                     self.add_entry(cell_path, EntryInfo::Directory);
                 }
                 FileType::Symlink => {
@@ -282,6 +308,79 @@ impl FsSnapshot {
     }
 }
 
+/// Convert file system events into changes to write to DICE, dropping ignored paths.
+pub(crate) fn events_to_dice(
+    events: impl IntoIterator<Item = FsEvent>,
+    ignore_specs: &HashMap<CellName, IgnoreSet>,
+) -> (buck2_data::FileWatcherStats, FileChangeTracker) {
+    let events = events.into_iter();
+    let mut changed = FileChangeTracker::new();
+    let mut stats = FileWatcherStats::new(events.size_hint().0, None, None, None);
+    let mut ignored = 0;
+    for event in events {
+        if is_ignored(Some(ignore_specs), &event.cell_path) {
+            ignored += 1;
+            continue;
+        }
+
+        stats.add(event.cell_path.to_string(), event.event, event.kind);
+        match (event.event, event.kind) {
+            (FileWatcherEventType::Create, FileWatcherKind::File | FileWatcherKind::Symlink) => {
+                changed.file_added(event.cell_path);
+            }
+            (FileWatcherEventType::Create, FileWatcherKind::Directory) => {
+                changed.dir_added(event.cell_path);
+            }
+            (FileWatcherEventType::Modify, FileWatcherKind::File | FileWatcherKind::Symlink) => {
+                changed.file_changed(event.cell_path);
+            }
+            (FileWatcherEventType::Modify, FileWatcherKind::Directory) => {
+                changed.dir_changed(event.cell_path);
+            }
+            (FileWatcherEventType::Delete, FileWatcherKind::File | FileWatcherKind::Symlink) => {
+                changed.file_removed(event.cell_path);
+            }
+            (FileWatcherEventType::Delete, FileWatcherKind::Directory) => {
+                changed.dir_removed(event.cell_path);
+            }
+        }
+    }
+    stats.add_ignored(ignored);
+    (stats.finish(), changed)
+}
+
+pub(crate) fn is_ignored(
+    ignore_specs: Option<&HashMap<CellName, IgnoreSet>>,
+    cell_path: &CellPath,
+) -> bool {
+    ignore_specs
+        .and_then(|specs| specs.get(&cell_path.cell()))
+        .map_or(false, |i| i.is_match(cell_path.path()))
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<io::Error>()
+        .map_or(false, |e| e.kind() == io::ErrorKind::NotFound)
+}
+
+/// A fingerprint of the file's metadata, which changes whenever the file is written to.
+fn file_metadata_hash(path: &Path) -> anyhow::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut hasher = DefaultHasher::new();
+    metadata.len().hash(&mut hasher);
+    metadata.modified()?.hash(&mut hasher);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        metadata.ino().hash(&mut hasher);
+        metadata.mode().hash(&mut hasher);
+        metadata.ctime().hash(&mut hasher);
+        metadata.ctime_nsec().hash(&mut hasher);
+    }
+    Ok(hasher.finish())
+}
+
 fn file_hash(path: &Path) -> anyhow::Result<Hash> {
     let mut reader = File::open(path)?;
     let mut hasher = blake3::Hasher::new();
@@ -377,4 +476,34 @@ mod tests {
         assert_eq!(events, expected);
         Ok(())
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fs_snapshot_skips_invalid_names() -> anyhow::Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let cell_resolver = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let tempdir = tempfile::tempdir()?;
+        let root_path = fs_util::canonicalize(AbsNormPathBuf::new(tempdir.path().to_owned())?)?;
+        let proj_root = ProjectRoot::new(root_path)?;
+
+        std::fs::write(tempdir.path().join("valid"), "content")?;
+        std::fs::write(
+            tempdir.path().join(OsStr::from_bytes(b"invalid\xff")),
+            "content",
+        )?;
+
+        let snapshot = FsSnapshot::build(&proj_root, &cell_resolver)?;
+        let paths = snapshot
+            .0
+            .keys()
+            .map(|p| p.path().as_str().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["valid"]);
+        Ok(())
+    }
 }
@@ -0,0 +1,551 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A file watcher using Linux's inotify directly.
+//!
+//! Unlike `notify`, this doesn't watch ignored directories (which matters when a large part of the
+//! repository is ignored), reads events in large batches, and recovers from the kernel queue
+//! overflowing by rescanning the repository instead of invalidating everything.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use allocative::Allocative;
+use anyhow::Context;
+use async_trait::async_trait;
+use buck2_common::file_ops::FileType;
+use buck2_common::ignores::ignore_set::IgnoreSet;
+use buck2_common::invocation_paths::InvocationPaths;
+use buck2_core::cells::name::CellName;
+use buck2_core::cells::CellResolver;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_data::FileWatcherEventType;
+use buck2_data::FileWatcherKind;
+use buck2_events::dispatch::span_async;
+use dice::DiceTransactionUpdater;
+use dupe::Dupe;
+use starlark_map::ordered_set::OrderedSet;
+use tokio::sync::watch;
+
+use crate::file_watcher::FileWatcher;
+use crate::fs_hash_crawler::events_to_dice;
+use crate::fs_hash_crawler::is_ignored;
+use crate::fs_hash_crawler::FsEvent;
+use crate::fs_hash_crawler::FsSnapshot;
+use crate::fs_hash_crawler::SnapshotKind;
+use crate::mergebase::Mergebase;
+
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ONLYDIR
+    | libc::IN_DONT_FOLLOW
+    | libc::IN_EXCL_UNLINK;
+
+/// Events are read in batches of up to this many bytes.
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+#[derive(Debug, buck2_error::Error)]
+enum InotifyError {
+    #[error(
+        "Ran out of inotify watches while watching `{0}`. Increase `fs.inotify.max_user_watches`, or add more directories to `project.ignore`."
+    )]
+    OutOfWatches(String),
+    #[error("The inotify file watcher stopped, restart the daemon with `buck2 kill`: {0}")]
+    Stopped(String),
+}
+
+/// Events that have happened since the last sync.
+#[derive(Allocative)]
+struct InotifyFileData {
+    #[allocative(skip)]
+    events: OrderedSet<FsEvent>,
+}
+
+impl InotifyFileData {
+    fn new() -> Self {
+        Self {
+            events: OrderedSet::new(),
+        }
+    }
+}
+
+#[derive(Allocative)]
+pub struct InotifyFileWatcher {
+    ignore_specs: Arc<HashMap<CellName, IgnoreSet>>,
+    data: Arc<Mutex<anyhow::Result<InotifyFileData>>>,
+    /// Whether the watcher thread is setting up watches or rescanning after an overflow. Syncs
+    /// wait for this to be false so they don't miss changes.
+    #[allocative(skip)]
+    busy: watch::Receiver<bool>,
+}
+
+impl InotifyFileWatcher {
+    pub fn new(
+        root: &ProjectRoot,
+        cells: CellResolver,
+        ignore_specs: HashMap<CellName, IgnoreSet>,
+    ) -> anyhow::Result<Self> {
+        // SAFETY: `inotify_init1` has no preconditions.
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error()).context("Error creating inotify instance");
+        }
+        // SAFETY: we just created this file descriptor and nothing else owns it.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let ignore_specs = Arc::new(ignore_specs);
+        let data = Arc::new(Mutex::new(Ok(InotifyFileData::new())));
+        let (busy_sender, busy) = watch::channel(true);
+
+        let thread = InotifyThread {
+            fd,
+            root: root.dupe(),
+            cells,
+            ignore_specs: ignore_specs.dupe(),
+            watches: HashMap::new(),
+            overflowed: false,
+            data: data.dupe(),
+        };
+        std::thread::Builder::new()
+            .name("buck2-inotify".to_owned())
+            .spawn(move || thread.run(busy_sender))
+            .context("Error starting inotify thread")?;
+
+        Ok(Self {
+            ignore_specs,
+            data,
+            busy,
+        })
+    }
+
+    async fn sync2(
+        &self,
+        mut dice: DiceTransactionUpdater,
+    ) -> anyhow::Result<(buck2_data::FileWatcherStats, DiceTransactionUpdater)> {
+        let mut busy = self.busy.clone();
+        while *busy.borrow_and_update() {
+            if busy.changed().await.is_err() {
+                // The thread exited, and will have recorded why in `data`.
+                break;
+            }
+        }
+
+        let events = take_events(&self.data)?;
+        let (stats, changes) = events_to_dice(events, &self.ignore_specs);
+        changes.write_to_dice(&mut dice)?;
+        Ok((stats, dice))
+    }
+}
+
+/// Take the events recorded since the last sync. Once the watcher thread has failed, we can't
+/// know what changed anymore, so this keeps failing rather than reporting no changes.
+fn take_events(
+    data: &Mutex<anyhow::Result<InotifyFileData>>,
+) -> anyhow::Result<OrderedSet<FsEvent>> {
+    match &mut *data.lock().unwrap() {
+        Ok(data) => Ok(mem::take(&mut data.events)),
+        Err(e) => Err(InotifyError::Stopped(format!("{:#}", e)).into()),
+    }
+}
+
+#[async_trait]
+impl FileWatcher for InotifyFileWatcher {
+    async fn sync(
+        &self,
+        dice: DiceTransactionUpdater,
+    ) -> anyhow::Result<(DiceTransactionUpdater, Mergebase)> {
+        span_async(
+            buck2_data::FileWatcherStart {
+                provider: buck2_data::FileWatcherProvider::Inotify as i32,
+            },
+            async {
+                let (stats, res) = match self.sync2(dice).await {
+                    Ok((stats, dice)) => {
+                        let mergebase = Mergebase(Arc::new(stats.branched_from_revision.clone()));
+                        ((Some(stats)), Ok((dice, mergebase)))
+                    }
+                    Err(e) => (None, Err(e)),
+                };
+                (res, buck2_data::FileWatcherEnd { stats })
+            },
+        )
+        .await
+    }
+}
+
+/// Owns the inotify instance, and reads events from it for as long as the daemon is alive.
+struct InotifyThread {
+    fd: OwnedFd,
+    root: ProjectRoot,
+    cells: CellResolver,
+    ignore_specs: Arc<HashMap<CellName, IgnoreSet>>,
+    /// The directory watched by each watch descriptor.
+    watches: HashMap<i32, ProjectRelativePathBuf>,
+    overflowed: bool,
+    data: Arc<Mutex<anyhow::Result<InotifyFileData>>>,
+}
+
+impl InotifyThread {
+    fn run(mut self, busy: watch::Sender<bool>) {
+        if let Err(e) = self.run_inner(&busy) {
+            tracing::warn!("inotify file watcher failed: {:#}", e);
+            *self.data.lock().unwrap() = Err(e);
+        }
+        busy.send_replace(false);
+    }
+
+    fn run_inner(&mut self, busy: &watch::Sender<bool>) -> anyhow::Result<()> {
+        self.add_watches(ProjectRelativePath::empty(), None)?;
+        // We only need this to find out what changed if we overflow, so file metadata is enough.
+        let mut snapshot = self.snapshot()?;
+        busy.send_replace(false);
+
+        let mut file = File::from(self.fd.try_clone()?);
+        let mut buf = vec![0; READ_BUFFER_SIZE];
+
+        loop {
+            let len = match file.read(&mut buf) {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).context("Error reading inotify events"),
+            };
+
+            let mut events = Vec::new();
+            for (wd, mask, name) in parse_events(&buf[..len]) {
+                self.handle_event(wd, mask, name, &mut events)?;
+            }
+
+            if self.overflowed {
+                busy.send_replace(true);
+                tracing::warn!("inotify queue overflowed, rescanning");
+                self.overflowed = false;
+                // We may have missed new directories too.
+                self.add_watches(ProjectRelativePath::empty(), None)?;
+                let new_snapshot = self.snapshot()?;
+                events.extend(snapshot.get_updates(&new_snapshot)?);
+                snapshot = new_snapshot;
+            }
+
+            if let Ok(data) = &mut *self.data.lock().unwrap() {
+                data.events.extend(events);
+            }
+            busy.send_if_modified(|busy| mem::replace(busy, false));
+        }
+    }
+
+    fn snapshot(&self) -> anyhow::Result<FsSnapshot> {
+        FsSnapshot::build_with(
+            &self.root,
+            &self.cells,
+            SnapshotKind::Metadata,
+            Some(&self.ignore_specs),
+        )
+    }
+
+    fn handle_event(
+        &mut self,
+        wd: i32,
+        mask: u32,
+        name: &[u8],
+        events: &mut Vec<FsEvent>,
+    ) -> anyhow::Result<()> {
+        if mask & libc::IN_Q_OVERFLOW != 0 {
+            self.overflowed = true;
+            return Ok(());
+        }
+        if mask & libc::IN_IGNORED != 0 {
+            self.watches.remove(&wd);
+            return Ok(());
+        }
+
+        let dir = match self.watches.get(&wd) {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let name = match std::str::from_utf8(name)
+            .ok()
+            .and_then(|n| FileName::new(n).ok())
+        {
+            Some(name) => name,
+            None => {
+                tracing::debug!("Ignoring invalid file name in `{}`: {:?}", dir, name);
+                return Ok(());
+            }
+        };
+        let path = dir.join(name);
+
+        // We ignore the buck-out prefix, as those are uninteresting events caused by us.
+        if path.starts_with(InvocationPaths::buck_out_dir_prefix()) {
+            return Ok(());
+        }
+
+        let is_dir = mask & libc::IN_ISDIR != 0;
+        let event = if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+            FileWatcherEventType::Create
+        } else if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+            FileWatcherEventType::Delete
+        } else {
+            FileWatcherEventType::Modify
+        };
+
+        if is_dir {
+            match event {
+                // Anything created in the new directory before we started watching it would be
+                // missed, so this also reports its contents.
+                FileWatcherEventType::Create => self.add_watches(&path, Some(events))?,
+                FileWatcherEventType::Delete => self.remove_watches(&path),
+                FileWatcherEventType::Modify => {}
+            }
+        }
+
+        events.push(FsEvent {
+            cell_path: self.cells.get_cell_path(&path)?,
+            event,
+            kind: if is_dir {
+                FileWatcherKind::Directory
+            } else {
+                FileWatcherKind::File
+            },
+        });
+        Ok(())
+    }
+
+    /// Watch `dir` and all the directories below it that aren't ignored. If `new_entries` is set,
+    /// a creation event is recorded for everything found.
+    fn add_watches(
+        &mut self,
+        dir: &ProjectRelativePath,
+        mut new_entries: Option<&mut Vec<FsEvent>>,
+    ) -> anyhow::Result<()> {
+        let mut queue = vec![dir.to_owned()];
+
+        while let Some(dir) = queue.pop() {
+            if dir.starts_with(InvocationPaths::buck_out_dir_prefix())
+                || is_ignored(Some(&self.ignore_specs), &self.cells.get_cell_path(&dir)?)
+            {
+                continue;
+            }
+
+            let abs_path = self.root.resolve(&dir);
+            let c_path = CString::new(abs_path.as_os_str().as_bytes())?;
+            // SAFETY: `c_path` is a valid NUL-terminated string.
+            let wd = unsafe {
+                libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), WATCH_MASK)
+            };
+            if wd == -1 {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    // The directory was deleted or replaced before we got to it.
+                    Some(libc::ENOENT | libc::ENOTDIR) => continue,
+                    Some(libc::ENOSPC) => {
+                        return Err(InotifyError::OutOfWatches(dir.to_string()).into());
+                    }
+                    _ => {
+                        return Err(e).with_context(|| format!("Error watching `{}`", abs_path));
+                    }
+                }
+            }
+            self.watches.insert(wd, dir.clone());
+
+            let entries = match fs_util::read_dir_if_exists(&abs_path)? {
+                Some(entries) => entries,
+                None => continue,
+            };
+            for entry in entries {
+                let entry = entry?;
+                let file_name = entry.file_name();
+                let file_name = match file_name.to_str().and_then(|f| FileName::new(f).ok()) {
+                    Some(file_name) => file_name,
+                    None => continue,
+                };
+                let path = dir.join(file_name);
+                let file_type = FileType::from(entry.file_type()?);
+
+                if let Some(new_entries) = new_entries.as_mut() {
+                    let kind = match file_type {
+                        FileType::Directory => FileWatcherKind::Directory,
+                        FileType::File => FileWatcherKind::File,
+                        FileType::Symlink => FileWatcherKind::Symlink,
+                        FileType::Unknown => continue,
+                    };
+                    new_entries.push(FsEvent {
+                        cell_path: self.cells.get_cell_path(&path)?,
+                        event: FileWatcherEventType::Create,
+                        kind,
+                    });
+                }
+
+                if file_type.is_dir() {
+                    queue.push(path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop watching `dir` and everything below it. Watches follow inodes, so this must be done
+    /// when a directory is moved away, otherwise we'd report its events under the old path.
+    fn remove_watches(&mut self, dir: &ProjectRelativePath) {
+        let fd = self.fd.as_raw_fd();
+        self.watches.retain(|wd, path| {
+            if path.starts_with(dir) {
+                // SAFETY: removing a watch has no preconditions. This fails harmlessly if the
+                // kernel already removed it.
+                unsafe { libc::inotify_rm_watch(fd, *wd) };
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// Split a buffer read from an inotify file descriptor into `(wd, mask, name)` triples.
+fn parse_events(buf: &[u8]) -> impl Iterator<Item = (i32, u32, &[u8])> {
+    let header_size = mem::size_of::<libc::inotify_event>();
+    let mut offset = 0;
+
+    std::iter::from_fn(move || {
+        if offset + header_size > buf.len() {
+            return None;
+        }
+        // SAFETY: the kernel only returns whole events, and we checked the header fits.
+        let event = unsafe {
+            std::ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+        };
+        let name_start = offset + header_size;
+        let name_end = (name_start + event.len as usize).min(buf.len());
+        let name = &buf[name_start..name_end];
+        // The name is padded with NULs.
+        let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+        offset = name_end;
+        Some((event.wd, event.mask, name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_events() {
+        fn event(wd: i32, mask: u32, name: &[u8], padded_len: usize) -> Vec<u8> {
+            let header = libc::inotify_event {
+                wd,
+                mask,
+                cookie: 0,
+                len: padded_len as u32,
+            };
+            // SAFETY: `inotify_event` is plain old data.
+            let mut bytes = unsafe {
+                std::slice::from_raw_parts(
+                    &header as *const _ as *const u8,
+                    mem::size_of::<libc::inotify_event>(),
+                )
+            }
+            .to_vec();
+            let mut name = name.to_vec();
+            name.resize(padded_len, 0);
+            bytes.extend(name);
+            bytes
+        }
+
+        let mut buf = event(1, libc::IN_CREATE, b"foo", 16);
+        buf.extend(event(2, libc::IN_Q_OVERFLOW, b"", 0));
+
+        let events = parse_events(&buf).collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (1, libc::IN_CREATE, b"foo".as_slice()),
+                (2, libc::IN_Q_OVERFLOW, b"".as_slice()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_take_events_keeps_failing() {
+        let data = Mutex::new(Err(anyhow::anyhow!("thread died")));
+        assert!(take_events(&data).is_err());
+        assert!(take_events(&data).is_err());
+    }
+
+    #[test]
+    fn test_add_watches_skips_ignored() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let root = ProjectRoot::new(fs_util::canonicalize(AbsNormPathBuf::new(
+            tempdir.path().to_owned(),
+        )?)?)?;
+        fs_util::create_dir_all(root.resolve(ProjectRelativePath::unchecked_new("a/b")))?;
+        fs_util::create_dir_all(root.resolve(ProjectRelativePath::unchecked_new("ignored/c")))?;
+
+        let cells = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let ignore_specs = HashMap::from([(
+            CellName::testing_new("root"),
+            IgnoreSet::from_ignore_spec("ignored", true)?,
+        )]);
+
+        // SAFETY: `inotify_init1` has no preconditions.
+        let fd = unsafe { OwnedFd::from_raw_fd(libc::inotify_init1(libc::IN_CLOEXEC)) };
+        let mut thread = InotifyThread {
+            fd,
+            root,
+            cells,
+            ignore_specs: Arc::new(ignore_specs),
+            watches: HashMap::new(),
+            overflowed: false,
+            data: Arc::new(Mutex::new(Ok(InotifyFileData::new()))),
+        };
+        let mut events = Vec::new();
+        thread.add_watches(ProjectRelativePath::empty(), Some(&mut events))?;
+
+        let mut watched = thread
+            .watches
+            .values()
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        watched.sort();
+        assert_eq!(watched, vec!["", "a", "a/b"]);
+
+        // The ignored directory itself is reported, but nothing below it.
+        let mut created = events
+            .iter()
+            .map(|e| e.cell_path.path().as_str().to_owned())
+            .collect::<Vec<_>>();
+        created.sort();
+        assert_eq!(created, vec!["a", "a/b", "ignored"]);
+
+        Ok(())
+    }
+}
@@ -13,6 +13,8 @@
 pub mod dep_files;
 pub mod file_watcher;
 mod fs_hash_crawler;
+#[cfg(target_os = "linux")]
+mod inotify;
 pub mod mergebase;
 mod notify;
 mod stats;
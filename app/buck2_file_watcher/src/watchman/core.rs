@@ -215,6 +215,10 @@ pub trait SyncableQueryProcessor: Send + Sync {
 /// commands to be sent to the SyncableQueryHandler.
 enum SyncableQueryCommand<T, P> {
     Sync(P, oneshot::Sender<anyhow::Result<(T, P)>>),
+    /// Get the clock and mergebase of the last sync.
+    GetPosition(oneshot::Sender<(ClockSpec, Option<String>)>),
+    /// Make the next sync report the changes since this clock and mergebase.
+    Resume(ClockSpec, Option<String>),
 }
 
 /// A SyncableQuery is similar to a subscription. When created, it accepts a query expression
@@ -284,6 +288,13 @@ where
                     // job. That's fine.
                     let _ignore = sync_tx.send(res);
                 }
+                Some(SyncableQueryCommand::GetPosition(tx)) => {
+                    let _ignore = tx.send((self.last_clock.clone(), self.last_mergebase.clone()));
+                }
+                Some(SyncableQueryCommand::Resume(clock, mergebase)) => {
+                    self.last_clock = clock;
+                    self.last_mergebase = mergebase;
+                }
                 None => {
                     // This indicates the controlling SyncableQuery has been dropped.
                     return;
@@ -474,6 +485,26 @@ where
         }
     }
 
+    /// The clock and mergebase that the last sync was up to.
+    pub async fn position(&self) -> anyhow::Result<(ClockSpec, Option<String>)> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.control_tx
+            .send(SyncableQueryCommand::GetPosition(tx))
+            .ok()
+            .context("SyncableQueryHandler has exited")?;
+        rx.await
+            .context("SyncableQueryHandler did not return a response for position request")
+    }
+
+    /// Make the next sync report the changes since the `position` of another query. If Watchman
+    /// can't tell what changed since then, it reports a fresh instance instead.
+    pub fn resume_from(&self, clock: ClockSpec, mergebase: Option<String>) -> anyhow::Result<()> {
+        self.control_tx
+            .send(SyncableQueryCommand::Resume(clock, mergebase))
+            .ok()
+            .context("SyncableQueryHandler has exited")
+    }
+
     pub fn new(
         connector: Connector,
         path: impl AsRef<Path>,
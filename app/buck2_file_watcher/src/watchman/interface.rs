@@ -28,6 +28,7 @@ use dice::DiceTransactionUpdater;
 use tracing::info;
 use tracing::warn;
 use watchman_client::expr::Expr;
+use watchman_client::prelude::ClockSpec;
 use watchman_client::prelude::Connector;
 use watchman_client::prelude::FileType;
 
@@ -381,4 +382,25 @@ impl FileWatcher for WatchmanFileWatcher {
         )
         .await
     }
+
+    async fn save_state(&self) -> anyhow::Result<Option<String>> {
+        Ok(match self.query.position().await? {
+            (ClockSpec::StringClock(clock), mergebase) => {
+                Some(format!("{}\n{}", clock, mergebase.unwrap_or_default()))
+            }
+            _ => None,
+        })
+    }
+
+    fn resume(&self, state: &str) -> anyhow::Result<bool> {
+        let (clock, mergebase) = state
+            .split_once('\n')
+            .context("Invalid saved Watchman state")?;
+        let mergebase = Some(mergebase).filter(|m| !m.is_empty());
+        self.query.resume_from(
+            ClockSpec::StringClock(clock.to_owned()),
+            mergebase.map(str::to_owned),
+        )?;
+        Ok(true)
+    }
 }
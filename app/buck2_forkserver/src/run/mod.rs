@@ -9,6 +9,8 @@
 
 mod interruptible_async_read;
 pub mod process_group;
+#[cfg(target_os = "linux")]
+pub mod sandbox;
 pub mod status_decoder;
 
 use std::borrow::Cow;
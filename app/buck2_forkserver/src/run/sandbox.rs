@@ -0,0 +1,459 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Running commands in a hermetic sandbox, where only the paths they declared are visible.
+//!
+//! The command is started in new user and mount namespaces, with a tmpfs as its root directory
+//! into which the visible paths are bind-mounted at the same locations they have outside. So
+//! anything else, such as an undeclared input, appears not to exist. The root is read-only, so
+//! anything written outside the writable paths fails too.
+//!
+//! Everything that could fail is worked out before the fork. After it, the child only makes
+//! syscalls, as allocating or taking locks there is not safe.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::ptr;
+
+use anyhow::Context as _;
+
+/// The paths visible in a sandbox.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxSpec {
+    /// An empty directory, which is mounted over to become the root of the sandbox.
+    /// It must not contain any of the visible paths.
+    pub root: PathBuf,
+    /// Absolute paths visible read-only inside the sandbox.
+    pub read_only: Vec<PathBuf>,
+    /// Absolute paths visible and writable inside the sandbox.
+    pub writable: Vec<PathBuf>,
+}
+
+#[derive(buck2_error::Error, Debug)]
+enum SandboxError {
+    #[error("Sandbox root `{}` is not an empty directory", .0.display())]
+    RootNotEmpty(PathBuf),
+    #[error("Sandbox path `{}` is not absolute", .0.display())]
+    NotAbsolute(PathBuf),
+    #[error("Path `{}` contains a NUL byte", .0.display())]
+    Nul(PathBuf),
+    #[error("Path `{}` to make visible in the sandbox does not exist", .0.display())]
+    NotFound(PathBuf),
+}
+
+// SAFETY: These are NUL-terminated with no other NULs.
+const SETGROUPS: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"/proc/self/setgroups\0") };
+const UID_MAP: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"/proc/self/uid_map\0") };
+const GID_MAP: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"/proc/self/gid_map\0") };
+const DENY: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"deny\0") };
+const SLASH: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"/\0") };
+const TMPFS: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"tmpfs\0") };
+
+/// Host paths every command needs, which are always visible and writable.
+const SYSTEM_PATHS: &[&str] = &["/dev", "/proc"];
+
+enum MountPoint {
+    Dir,
+    File,
+    /// Symlinks are recreated rather than mounted, so they resolve inside the sandbox.
+    Symlink(CString),
+}
+
+struct Mount {
+    src: CString,
+    /// The location of the mount point under the root, outside the sandbox.
+    dst: CString,
+    point: MountPoint,
+    read_only: bool,
+    /// Whether this is inside another mount, through which the mount point already exists.
+    nested: bool,
+}
+
+/// Everything needed to enter the sandbox, prepared before the fork.
+struct SandboxSetup {
+    root: CString,
+    tmp: CString,
+    /// Directories to create under the root, parents first.
+    dirs: Vec<CString>,
+    mounts: Vec<Mount>,
+    cwd: CString,
+    uid_map: CString,
+    gid_map: CString,
+}
+
+fn c_path(path: &Path) -> anyhow::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| SandboxError::Nul(path.to_owned()).into())
+}
+
+/// `path` as seen from outside the sandbox, which is under its root.
+fn under_root(root: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+    let relative = path
+        .strip_prefix("/")
+        .map_err(|_| SandboxError::NotAbsolute(path.to_owned()))?;
+    Ok(root.join(relative))
+}
+
+impl SandboxSetup {
+    fn new(spec: &SandboxSpec, cwd: &Path) -> anyhow::Result<Self> {
+        let root = &spec.root;
+        let is_empty = fs::read_dir(root)
+            .with_context(|| format!("Error reading sandbox root `{}`", root.display()))?
+            .next()
+            .is_none();
+        if !is_empty {
+            return Err(SandboxError::RootNotEmpty(root.clone()).into());
+        }
+
+        // Where a path is visible, so is everything inside it. So only mount the paths that aren't
+        // visible through another mount already, which leaves the outermost paths, and writable
+        // paths inside read-only ones. Those are mounted on top, after their parent: `visible` is
+        // sorted, so paths come right after the paths containing them.
+        let mut visible: BTreeMap<&Path, bool> = BTreeMap::new();
+        let paths = SYSTEM_PATHS
+            .iter()
+            .map(|x| (Path::new(*x), true))
+            .chain(spec.read_only.iter().map(|x| (x.as_path(), false)))
+            .chain(spec.writable.iter().map(|x| (x.as_path(), true)));
+        for (path, writable) in paths {
+            let writable = writable || visible.get(path).copied().unwrap_or_default();
+            visible.insert(path, writable);
+        }
+        let mut mounted: Vec<(&Path, bool, bool)> = Vec::new();
+        // The mounted paths containing the current one, innermost last.
+        let mut enclosing: Vec<(&Path, bool)> = Vec::new();
+        for (path, writable) in visible {
+            while enclosing
+                .last()
+                .map_or(false, |(x, _)| !path.starts_with(x))
+            {
+                enclosing.pop();
+            }
+            let nested = match enclosing.last() {
+                Some((_, enclosing_writable)) if *enclosing_writable || !writable => continue,
+                Some(_) => true,
+                None => false,
+            };
+            mounted.push((path, writable, nested));
+            enclosing.push((path, writable));
+        }
+
+        let mut dirs = BTreeSet::new();
+        let mut add_dirs = |path: &Path| -> anyhow::Result<()> {
+            for dir in path.ancestors().skip(1) {
+                if dir == Path::new("/") {
+                    break;
+                }
+                dirs.insert(under_root(root, dir)?);
+            }
+            Ok(())
+        };
+        add_dirs(&cwd.join("."))?;
+
+        let mut mounts = Vec::new();
+        for (path, writable, nested) in mounted {
+            let metadata = match fs::symlink_metadata(path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Err(SandboxError::NotFound(path.to_owned()).into());
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Error reading `{}`", path.display()));
+                }
+            };
+            if !nested {
+                add_dirs(path)?;
+            }
+            let point = if metadata.file_type().is_symlink() {
+                MountPoint::Symlink(c_path(&fs::read_link(path)?)?)
+            } else if metadata.is_dir() {
+                MountPoint::Dir
+            } else {
+                MountPoint::File
+            };
+            mounts.push(Mount {
+                src: c_path(path)?,
+                dst: c_path(&under_root(root, path)?)?,
+                point,
+                read_only: !writable,
+                nested,
+            });
+        }
+
+        // SAFETY: These can't fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Ok(Self {
+            root: c_path(root)?,
+            tmp: c_path(&under_root(root, Path::new("/tmp"))?)?,
+            dirs: dirs
+                .iter()
+                .map(|x| c_path(x))
+                .collect::<anyhow::Result<_>>()?,
+            mounts,
+            cwd: c_path(cwd)?,
+            uid_map: CString::new(format!("{} {} 1", uid, uid))?,
+            gid_map: CString::new(format!("{} {} 1", gid, gid))?,
+        })
+    }
+
+    /// Enter the sandbox. This runs in the child after the fork, so must only make syscalls.
+    fn enter(&self) -> io::Result<()> {
+        // SAFETY: All the pointers are to NUL-terminated strings which outlive the calls.
+        unsafe {
+            check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS))?;
+            // We are root in the new user namespace, but mapped to the original user outside it,
+            // so files the command creates are owned by the user running buck2.
+            write_file(SETGROUPS, DENY)?;
+            write_file(UID_MAP, &self.uid_map)?;
+            write_file(GID_MAP, &self.gid_map)?;
+
+            // Don't let our mounts propagate out of the namespace.
+            check(libc::mount(
+                ptr::null(),
+                SLASH.as_ptr(),
+                ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                ptr::null(),
+            ))?;
+            mount_tmpfs(&self.root)?;
+            // Mounted first, as visible paths may be in `/tmp` outside.
+            mkdir(&self.tmp)?;
+            mount_tmpfs(&self.tmp)?;
+            for dir in &self.dirs {
+                mkdir(dir)?;
+            }
+
+            for mount in &self.mounts {
+                match &mount.point {
+                    // Already there, and possibly read-only.
+                    MountPoint::Symlink(_) if mount.nested => continue,
+                    MountPoint::Dir | MountPoint::File if mount.nested => {}
+                    MountPoint::Symlink(target) => {
+                        check(libc::symlink(target.as_ptr(), mount.dst.as_ptr()))?;
+                        continue;
+                    }
+                    MountPoint::Dir => mkdir(&mount.dst)?,
+                    MountPoint::File => {
+                        let fd = check(libc::open(
+                            mount.dst.as_ptr(),
+                            libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                            0o644,
+                        ))?;
+                        libc::close(fd);
+                    }
+                }
+                check(libc::mount(
+                    mount.src.as_ptr(),
+                    mount.dst.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    ptr::null(),
+                ))?;
+                if mount.read_only {
+                    remount_read_only(&mount.dst)?;
+                }
+            }
+            remount_read_only(&self.root)?;
+
+            check(libc::chroot(self.root.as_ptr()))?;
+            check(libc::chdir(self.cwd.as_ptr()))?;
+        }
+        Ok(())
+    }
+}
+
+fn check<T: Default + PartialOrd>(res: T) -> io::Result<T> {
+    if res < T::default() {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+unsafe fn write_file(path: &CStr, contents: &CStr) -> io::Result<()> {
+    let fd = check(libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC))?;
+    let bytes = contents.to_bytes();
+    let res = libc::write(fd, bytes.as_ptr().cast(), bytes.len());
+    libc::close(fd);
+    check(res).map(|_| ())
+}
+
+unsafe fn mkdir(path: &CStr) -> io::Result<()> {
+    if libc::mkdir(path.as_ptr(), 0o755) < 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+unsafe fn mount_tmpfs(path: &CStr) -> io::Result<()> {
+    check(libc::mount(
+        TMPFS.as_ptr(),
+        path.as_ptr(),
+        TMPFS.as_ptr(),
+        0,
+        ptr::null(),
+    ))
+    .map(|_| ())
+}
+
+/// Remounting a bind mount must keep the flags the kernel locked when it was copied into the
+/// namespace, or it fails.
+unsafe fn remount_read_only(path: &CStr) -> io::Result<()> {
+    let mut stat: libc::statvfs = std::mem::zeroed();
+    check(libc::statvfs(path.as_ptr(), &mut stat))?;
+    let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+    for (st, ms) in [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if stat.f_flag & st != 0 {
+            flags |= ms;
+        }
+    }
+    check(libc::mount(
+        ptr::null(),
+        path.as_ptr(),
+        ptr::null(),
+        flags,
+        ptr::null(),
+    ))
+    .map(|_| ())
+}
+
+impl SandboxSpec {
+    /// Make the command enter the sandbox when it is spawned, in the directory `cwd`.
+    pub fn apply(&self, cmd: &mut Command, cwd: &Path) -> anyhow::Result<()> {
+        let setup = SandboxSetup::new(self, cwd).context("Error preparing hermetic sandbox")?;
+        // SAFETY: `enter` only makes syscalls.
+        unsafe {
+            cmd.pre_exec(move || setup.enter());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outermost_paths_are_mounted() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let repo = tempfile::tempdir()?;
+        let src = repo.path().join("src");
+        fs::create_dir_all(src.join("lib"))?;
+        fs::write(src.join("lib/a.c"), "")?;
+        let out = repo.path().join("out");
+        fs::create_dir_all(&out)?;
+
+        let setup = SandboxSetup::new(
+            &SandboxSpec {
+                root: root.path().to_owned(),
+                read_only: vec![src.join("lib/a.c"), src.clone()],
+                writable: vec![out.clone()],
+            },
+            repo.path(),
+        )?;
+        let mounts: Vec<_> = setup
+            .mounts
+            .iter()
+            .map(|x| (x.src.as_bytes(), x.read_only))
+            .collect();
+        let mut expected = vec![
+            (b"/dev".as_slice(), false),
+            (b"/proc".as_slice(), false),
+            (out.as_os_str().as_bytes(), false),
+            (src.as_os_str().as_bytes(), true),
+        ];
+        expected.sort();
+        assert_eq!(expected, mounts);
+        assert!(
+            setup
+                .dirs
+                .contains(&c_path(&under_root(root.path(), repo.path())?)?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_writable_paths_in_read_only_paths_are_mounted_on_top() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let repo = tempfile::tempdir()?;
+        let out = repo.path().join("buck-out/gen");
+        fs::create_dir_all(&out)?;
+        let scratch = out.join("scratch");
+        fs::create_dir_all(&scratch)?;
+
+        let setup = SandboxSetup::new(
+            &SandboxSpec {
+                root: root.path().to_owned(),
+                read_only: vec![repo.path().to_owned(), scratch.clone()],
+                writable: vec![out.clone()],
+            },
+            repo.path(),
+        )?;
+        let mounts: Vec<_> = setup
+            .mounts
+            .iter()
+            .map(|x| (x.src.as_bytes(), x.read_only, x.nested))
+            .collect();
+        // Sorted, so `out` is mounted after the repo containing it. `scratch` is writable through
+        // `out`.
+        let mut expected = vec![
+            (b"/dev".as_slice(), false, false),
+            (b"/proc".as_slice(), false, false),
+            (repo.path().as_os_str().as_bytes(), true, false),
+            (out.as_os_str().as_bytes(), false, true),
+        ];
+        expected.sort();
+        assert_eq!(expected, mounts);
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_paths_are_errors() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let repo = tempfile::tempdir()?;
+        let spec = SandboxSpec {
+            root: root.path().to_owned(),
+            read_only: vec![repo.path().join("missing")],
+            ..Default::default()
+        };
+        let e = SandboxSetup::new(&spec, repo.path()).unwrap_err();
+        assert!(e.to_string().contains("does not exist"), "{:#}", e);
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_must_be_empty() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        fs::write(root.path().join("x"), "")?;
+        let spec = SandboxSpec {
+            root: root.path().to_owned(),
+            ..Default::default()
+        };
+        assert!(SandboxSetup::new(&spec, Path::new("/")).is_err());
+        Ok(())
+    }
+}
@@ -123,6 +123,7 @@ impl StatusDecoder for MiniperfStatusDecoder {
                                 ),
                                 userspace_events: Some(counters.user_instructions.to_proto()),
                                 kernel_events: Some(counters.kernel_instructions.to_proto()),
+                                ..Default::default()
                             });
 
                     if let Err(e) = execution_stats.as_ref() {
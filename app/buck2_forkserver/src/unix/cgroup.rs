@@ -0,0 +1,337 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Running each command in a cgroup (v2) of its own, to record the resources it uses and to
+//! enforce its memory limit.
+//!
+//! The forkserver must be started in a cgroup it is allowed to manage and which holds no other
+//! processes, such as a systemd scope with `Delegate=yes`. It moves itself into a leaf of that
+//! cgroup, as controllers can only be enabled for a cgroup's children if it has no processes, and
+//! then creates the commands' cgroups next to that leaf.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+
+use crate::run::status_decoder::DecodedStatus;
+use crate::run::status_decoder::StatusDecoder;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+const CONTROLLERS: &[&str] = &["cpu", "io", "memory"];
+
+#[derive(buck2_error::Error, Debug)]
+enum CgroupError {
+    #[error("The forkserver is not in a cgroup v2 hierarchy")]
+    NotV2,
+}
+
+pub(crate) struct CgroupPool {
+    dir: PathBuf,
+    /// Whether the memory controller is enabled for the commands' cgroups. Without it, memory
+    /// usage isn't recorded and memory limits aren't enforced.
+    memory: bool,
+    next: AtomicU64,
+}
+
+impl CgroupPool {
+    pub(crate) fn new() -> anyhow::Result<Self> {
+        let own =
+            fs::read_to_string("/proc/self/cgroup").context("Error reading `/proc/self/cgroup`")?;
+        let dir = Path::new(CGROUP_ROOT).join(parse_own_cgroup(&own).ok_or(CgroupError::NotV2)?);
+
+        let leaf = dir.join("forkserver");
+        create_dir(&leaf)?;
+        fs::write(leaf.join("cgroup.procs"), std::process::id().to_string())
+            .with_context(|| format!("Error moving the forkserver to `{}`", leaf.display()))?;
+
+        let available = read(&dir, "cgroup.controllers")?;
+        for controller in CONTROLLERS {
+            if !available.split_whitespace().any(|c| c == *controller) {
+                continue;
+            }
+            if let Err(e) = fs::write(
+                dir.join("cgroup.subtree_control"),
+                format!("+{}", controller),
+            ) {
+                tracing::warn!(
+                    "Error enabling the `{}` cgroup controller: {}",
+                    controller,
+                    e
+                );
+            }
+        }
+
+        let memory = read(&dir, "cgroup.subtree_control")?
+            .split_whitespace()
+            .any(|c| c == "memory");
+        if !memory {
+            tracing::warn!(
+                "The memory cgroup controller is not available in `{}`: memory usage will not be \
+                recorded and memory limits will not be enforced",
+                dir.display()
+            );
+        }
+
+        Ok(Self {
+            dir,
+            memory,
+            next: AtomicU64::new(0),
+        })
+    }
+
+    /// Create the cgroup for a command, which is killed if it uses more than `memory_limit`
+    /// bytes.
+    pub(crate) fn create(&self, memory_limit: Option<u64>) -> anyhow::Result<ActionCgroup> {
+        let path = self.dir.join(format!(
+            "action-{}",
+            self.next.fetch_add(1, Ordering::Relaxed)
+        ));
+        create_dir(&path)?;
+
+        let procs = (|| {
+            if let (Some(limit), true) = (memory_limit, self.memory) {
+                fs::write(path.join("memory.max"), limit.to_string()).with_context(|| {
+                    format!("Error setting memory limit of `{}`", path.display())
+                })?;
+                // Swapping would only make a command over its limit slower rather than killing it.
+                let _ignored = fs::write(path.join("memory.swap.max"), "0");
+            }
+
+            File::options()
+                .write(true)
+                .open(path.join("cgroup.procs"))
+                .with_context(|| format!("Error opening `{}/cgroup.procs`", path.display()))
+        })();
+
+        match procs {
+            Ok(procs) => Ok(ActionCgroup { path, procs }),
+            Err(e) => {
+                let _ignored = fs::remove_dir(&path);
+                Err(e)
+            }
+        }
+    }
+}
+
+pub(crate) struct ActionCgroup {
+    path: PathBuf,
+    /// Opened before forking, so joining the cgroup in the child is a single syscall.
+    procs: File,
+}
+
+impl ActionCgroup {
+    /// Make `cmd` join this cgroup when it is spawned. This must be called before anything else
+    /// using `pre_exec`, as e.g. entering a sandbox may lose the permission to do so.
+    pub(crate) fn apply(&self, cmd: &mut Command) {
+        let fd = self.procs.as_raw_fd();
+
+        unsafe {
+            cmd.pre_exec(move || {
+                // Writing 0 moves the writing process.
+                if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    fn collect_stats(&self, stats: &mut buck2_data::CommandExecutionStats) {
+        if let Ok(cpu) = read(&self.path, "cpu.stat") {
+            stats.cpu_user_usec = flat_keyed_value(&cpu, "user_usec");
+            stats.cpu_system_usec = flat_keyed_value(&cpu, "system_usec");
+        }
+        // This is only available since Linux 5.19.
+        if let Ok(peak) = read(&self.path, "memory.peak") {
+            stats.memory_peak_bytes = peak.trim().parse().ok();
+        }
+        if let Ok(events) = read(&self.path, "memory.events") {
+            stats.memory_limit_exceeded = flat_keyed_value(&events, "oom_kill").unwrap_or(0) > 0;
+        }
+        if let Ok(io) = read(&self.path, "io.stat") {
+            let (read_bytes, write_bytes) = parse_io_stat(&io);
+            stats.io_read_bytes = Some(read_bytes);
+            stats.io_write_bytes = Some(write_bytes);
+        }
+    }
+
+    /// Kill anything left in the cgroup, and remove it once it is empty.
+    async fn remove(self) {
+        // This is only available since Linux 5.14. Otherwise, the command's process group has
+        // been killed already, which is usually everything.
+        let _ignored = fs::write(self.path.join("cgroup.kill"), "1");
+
+        for _ in 0..100 {
+            match fs::remove_dir(&self.path) {
+                Ok(()) => return,
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                Err(e) => {
+                    tracing::warn!("Error removing cgroup `{}`: {}", self.path.display(), e);
+                    return;
+                }
+            }
+        }
+
+        tracing::warn!(
+            "Not removing cgroup `{}`, as it still has processes",
+            self.path.display()
+        );
+    }
+}
+
+impl Drop for ActionCgroup {
+    /// If the command was never spawned (e.g. setting up its sandbox failed), the cgroup is empty
+    /// and can be removed right away. After `remove`, this does nothing.
+    fn drop(&mut self) {
+        let _ignored = fs::remove_dir(&self.path);
+    }
+}
+
+/// Adds the resources used by a command, recorded by its cgroup, to its execution stats.
+pub(crate) struct CgroupStatusDecoder<D> {
+    inner: D,
+    cgroup: Option<ActionCgroup>,
+}
+
+impl<D> CgroupStatusDecoder<D> {
+    pub(crate) fn new(inner: D, cgroup: Option<ActionCgroup>) -> Self {
+        Self { inner, cgroup }
+    }
+}
+
+#[async_trait]
+impl<D: StatusDecoder + Send> StatusDecoder for CgroupStatusDecoder<D> {
+    async fn decode_status(
+        self,
+        status: std::process::ExitStatus,
+    ) -> anyhow::Result<DecodedStatus> {
+        let mut decoded = self.inner.decode_status(status).await;
+
+        if let Some(cgroup) = self.cgroup {
+            if let Ok(DecodedStatus::Status {
+                execution_stats, ..
+            }) = &mut decoded
+            {
+                let mut stats = execution_stats.unwrap_or_default();
+                cgroup.collect_stats(&mut stats);
+                *execution_stats = Some(stats);
+            }
+            cgroup.remove().await;
+        }
+
+        decoded
+    }
+
+    async fn cancel(self) -> anyhow::Result<()> {
+        let res = self.inner.cancel().await;
+        if let Some(cgroup) = self.cgroup {
+            cgroup.remove().await;
+        }
+        res
+    }
+}
+
+fn create_dir(path: &Path) -> anyhow::Result<()> {
+    match fs::create_dir(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        Err(e) => {
+            Err(anyhow::Error::from(e).context(format!("Error creating `{}`", path.display())))
+        }
+    }
+}
+
+fn read(dir: &Path, file: &str) -> anyhow::Result<String> {
+    let path = dir.join(file);
+    fs::read_to_string(&path).with_context(|| format!("Error reading `{}`", path.display()))
+}
+
+/// The path of our cgroup relative to the root of the hierarchy, from `/proc/self/cgroup`. In
+/// cgroup v2, that is the only entry, and reads `0::/path`.
+fn parse_own_cgroup(proc_self_cgroup: &str) -> Option<&str> {
+    proc_self_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| path.trim_start_matches('/'))
+}
+
+/// A value from a file made of `key value` lines, like `cpu.stat`.
+fn flat_keyed_value(contents: &str, key: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        if k == key {
+            v.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// The total bytes read and written across all devices, from `io.stat`, which has lines like
+/// `8:0 rbytes=1 wbytes=2 rios=3 wios=4 dbytes=0 dios=0`.
+fn parse_io_stat(contents: &str) -> (u64, u64) {
+    let mut read = 0;
+    let mut write = 0;
+    for field in contents.split_whitespace() {
+        if let Some((key, value)) = field.split_once('=') {
+            let value: u64 = value.parse().unwrap_or(0);
+            match key {
+                "rbytes" => read += value,
+                "wbytes" => write += value,
+                _ => {}
+            }
+        }
+    }
+    (read, write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_own_cgroup() {
+        assert_eq!(
+            parse_own_cgroup("0::/user.slice/buck2-forkserver.scope\n"),
+            Some("user.slice/buck2-forkserver.scope")
+        );
+        assert_eq!(parse_own_cgroup("0::/\n"), Some(""));
+        assert_eq!(
+            parse_own_cgroup("12:memory:/foo\n1:name=systemd:/foo\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let cpu = "usage_usec 300\nuser_usec 200\nsystem_usec 100\n";
+        assert_eq!(flat_keyed_value(cpu, "user_usec"), Some(200));
+        assert_eq!(flat_keyed_value(cpu, "system_usec"), Some(100));
+        assert_eq!(flat_keyed_value(cpu, "nr_periods"), None);
+
+        let io = "8:0 rbytes=10 wbytes=20 rios=1 wios=2 dbytes=0 dios=0\n\
+                  8:16 rbytes=1 wbytes=2 rios=1 wios=1 dbytes=0 dios=0\n";
+        assert_eq!(parse_io_stat(io), (11, 22));
+        assert_eq!(parse_io_stat(""), (0, 0));
+    }
+}
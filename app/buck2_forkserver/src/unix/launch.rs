@@ -11,6 +11,7 @@ use std::ffi::OsStr;
 use std::io;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::process::CommandExt;
+use std::path::Path;
 use std::process::Stdio;
 
 use anyhow::Context;
@@ -21,10 +22,55 @@ use tokio::process::Command;
 
 use crate::client::ForkserverClient;
 
+/// A `systemd-run` invocation creating a transient scope with a delegated cgroup. The command to
+/// run in it must be appended.
+fn systemd_run_scope() -> std::process::Command {
+    // `systemd-run --scope` execs the command itself once the scope is set up, so the
+    // forkserver keeps its PID and the FDs we pass it.
+    let mut command = background_command("systemd-run");
+    if unsafe { libc::getuid() } != 0 {
+        command.arg("--user");
+    }
+    command.args([
+        "--scope",
+        "--quiet",
+        "--collect",
+        "--property=Delegate=yes",
+        "--",
+    ]);
+    command
+}
+
+/// Check that we can create a scope, which requires e.g. a user session for `--user`.
+async fn check_systemd_run_scope() -> anyhow::Result<()> {
+    let mut command = systemd_run_scope();
+    command
+        .arg("true")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let output = Command::from(command)
+        .output()
+        .await
+        .context("Failed to run `systemd-run`")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "`systemd-run` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Launch the forkserver. With `delegated_cgroup`, if systemd is running, the forkserver is
+/// started in a scope of its own, which it is allowed to manage to run commands in cgroups. If
+/// the scope can't be created, the forkserver is started without it.
 pub async fn launch_forkserver(
     exe: impl AsRef<OsStr>,
     args: impl IntoIterator<Item = impl AsRef<OsStr>>,
     state_dir: &AbsNormPath,
+    delegated_cgroup: bool,
 ) -> anyhow::Result<ForkserverClient> {
     let (client_io, server_io) =
         UnixStream::pair().context("Failed to create fork server channel")?;
@@ -35,7 +81,25 @@ pub async fn launch_forkserver(
 
     let exe = exe.as_ref();
 
-    let mut command = background_command(exe);
+    let delegated_cgroup = if delegated_cgroup && Path::new("/run/systemd/system").exists() {
+        match check_systemd_run_scope().await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!("Not starting the forkserver in a delegated cgroup: {:#}", e);
+                false
+            }
+        }
+    } else {
+        false
+    };
+
+    let mut command = if delegated_cgroup {
+        let mut command = systemd_run_scope();
+        command.arg(exe);
+        command
+    } else {
+        background_command(exe)
+    };
     command
         .stdin(Stdio::null())
         .stdout(Stdio::inherit()) // TODO
@@ -52,6 +52,8 @@ use crate::run::stream_command_events;
 use crate::run::timeout_into_cancellation;
 use crate::run::DefaultKillProcess;
 use crate::run::GatherOutputStatus;
+use crate::unix::cgroup::CgroupPool;
+use crate::unix::cgroup::CgroupStatusDecoder;
 
 // Not quite BoxStream: it has to be Sync (...)
 type RunStream =
@@ -62,18 +64,35 @@ pub struct UnixForkserverService {
 
     /// State for Miniperf.
     miniperf: Option<MiniperfContainer>,
+
+    /// Where commands get their own cgroups, if enabled.
+    cgroups: Option<CgroupPool>,
 }
 
 impl UnixForkserverService {
     pub fn new(
         log_reload_handle: Arc<dyn LogConfigurationReloadHandle>,
         state_dir: &AbsNormPath,
+        cgroups: bool,
     ) -> anyhow::Result<Self> {
         let miniperf = MiniperfContainer::new(state_dir)?;
 
+        let cgroups = if cgroups {
+            match CgroupPool::new() {
+                Ok(cgroups) => Some(cgroups),
+                Err(e) => {
+                    tracing::warn!("Not running commands in cgroups: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         Ok(Self {
             log_reload_handle,
             miniperf,
+            cgroups,
         })
     }
 }
@@ -116,6 +135,8 @@ impl Forkserver for UnixForkserverService {
                 enable_miniperf,
                 std_redirects,
                 graceful_shutdown_timeout_s,
+                sandbox,
+                memory_limit_bytes,
             } = msg;
 
             let exe = OsStr::from_bytes(&exe);
@@ -161,6 +182,19 @@ impl Forkserver for UnixForkserverService {
                 }
             }
 
+            let cgroup = self
+                .cgroups
+                .as_ref()
+                .map(|cgroups| cgroups.create(memory_limit_bytes))
+                .transpose()?;
+            if let Some(cgroup) = &cgroup {
+                cgroup.apply(&mut cmd);
+            }
+
+            if let Some(sandbox) = sandbox {
+                apply_sandbox(&mut cmd, sandbox, cwd)?;
+            }
+
             let mut cmd = prepare_command(cmd);
             let stream_stdio = std_redirects.is_none();
             if let Some(std_redirects) = std_redirects {
@@ -180,7 +214,7 @@ impl Forkserver for UnixForkserverService {
                 Some(out) => stream_command_events(
                     process_group,
                     cancellation,
-                    MiniperfStatusDecoder::new(out),
+                    CgroupStatusDecoder::new(MiniperfStatusDecoder::new(out), cgroup),
                     DefaultKillProcess {
                         graceful_shutdown_timeout_s,
                     },
@@ -190,7 +224,7 @@ impl Forkserver for UnixForkserverService {
                 None => stream_command_events(
                     process_group,
                     cancellation,
-                    DefaultStatusDecoder,
+                    CgroupStatusDecoder::new(DefaultStatusDecoder, cgroup),
                     DefaultKillProcess {
                         graceful_shutdown_timeout_s,
                     },
@@ -218,6 +252,41 @@ impl Forkserver for UnixForkserverService {
     }
 }
 
+#[cfg(target_os = "linux")]
+fn apply_sandbox(
+    cmd: &mut std::process::Command,
+    sandbox: buck2_forkserver_proto::Sandbox,
+    cwd: &AbsPath,
+) -> anyhow::Result<()> {
+    use std::os::unix::ffi::OsStringExt;
+    use std::path::PathBuf;
+
+    use crate::run::sandbox::SandboxSpec;
+
+    let paths = |xs: Vec<Vec<u8>>| {
+        xs.into_iter()
+            .map(|x| PathBuf::from(std::ffi::OsString::from_vec(x)))
+            .collect()
+    };
+    SandboxSpec {
+        root: PathBuf::from(std::ffi::OsString::from_vec(sandbox.root)),
+        read_only: paths(sandbox.read_only),
+        writable: paths(sandbox.writable),
+    }
+    .apply(cmd, cwd.as_path())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_sandbox(
+    _cmd: &mut std::process::Command,
+    _sandbox: buck2_forkserver_proto::Sandbox,
+    _cwd: &AbsPath,
+) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!(
+        "Hermetic sandboxing is only supported on Linux"
+    ))
+}
+
 struct MiniperfContainer {
     /// The Miniperf binary
     miniperf: AbsNormPathBuf,
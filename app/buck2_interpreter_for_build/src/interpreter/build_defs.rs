@@ -137,6 +137,7 @@ pub fn starlark_library_extensions_for_buck2() -> &'static [LibraryExtension] {
         LibraryExtension::Pprint,
         LibraryExtension::Print,
         LibraryExtension::RecordType,
+        LibraryExtension::SetType,
         LibraryExtension::StructType,
         LibraryExtension::Typing,
         LibraryExtension::Internal,
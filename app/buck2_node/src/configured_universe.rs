@@ -104,6 +104,16 @@ impl CqueryUniverse {
             .sum()
     }
 
+    /// All the targets in the universe.
+    pub fn iter(&self) -> impl Iterator<Item = ConfiguredTargetNodeRef<'_>> + '_ {
+        self.data
+            .data()
+            .targets
+            .values()
+            .flat_map(|package_targets| package_targets.values().flatten())
+            .map(|node| node.0)
+    }
+
     pub fn build(universe: &TargetSet<ConfiguredTargetNode>) -> anyhow::Result<CqueryUniverse> {
         span(buck2_data::CqueryUniverseBuildStart {}, || {
             let r = SelfRef::try_new(universe.clone(), |universe| {
@@ -170,4 +170,8 @@ impl QueryTarget for ConfiguredGraphNodeRef {
         }
         Ok(())
     }
+
+    fn is_visible_to(&self, target: &Self) -> anyhow::Result<bool> {
+        self.0.is_visible_to(target.0.label().unconfigured())
+    }
 }
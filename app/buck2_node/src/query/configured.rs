@@ -110,6 +110,10 @@ impl QueryTarget for ConfiguredTargetNode {
         }
         Ok(())
     }
+
+    fn is_visible_to(&self, target: &Self) -> anyhow::Result<bool> {
+        ConfiguredTargetNode::is_visible_to(self, target.label().unconfigured())
+    }
 }
 
 impl<'a> LabeledNode for ConfiguredTargetNodeRef<'a> {
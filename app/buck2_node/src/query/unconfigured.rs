@@ -100,4 +100,8 @@ impl QueryTarget for TargetNode {
         }
         Ok(())
     }
+
+    fn is_visible_to(&self, target: &Self) -> anyhow::Result<bool> {
+        TargetNode::is_visible_to(self, target.label())
+    }
 }
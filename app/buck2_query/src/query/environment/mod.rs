@@ -114,6 +114,14 @@ pub trait QueryTarget: LabeledNode + Dupe + Send + Sync + 'static {
     ) -> Result<(), E>;
 
     fn map_attr<R, F: FnMut(Option<&Self::Attr<'_>>) -> R>(&self, key: &str, func: F) -> R;
+
+    /// Whether `target` is allowed to depend on this node. `visible()` function uses this.
+    ///
+    /// Target nodes, configured or not, implement this. Other nodes, like actions in aquery,
+    /// have no visibility, so `visible()` fails for them.
+    fn is_visible_to(&self, _target: &Self) -> anyhow::Result<bool> {
+        Err(QueryError::FunctionUnimplemented("visible").into())
+    }
 }
 
 #[async_trait]
@@ -251,6 +259,21 @@ pub trait QueryEnvironment: Send + Sync {
         Ok(rdeps)
     }
 
+    /// Like `rdeps`, with the universe being every target the environment knows about.
+    ///
+    /// Only cquery implements this, with its universe. uquery, aquery and the query attributes
+    /// have no universe that is known before the query is evaluated, so `allrdeps()` fails there,
+    /// and `rdeps()` should be used with an explicit universe instead.
+    async fn allrdeps(
+        &self,
+        _targets: &TargetSet<Self::Target>,
+        _depth: Option<i32>,
+    ) -> anyhow::Result<TargetSet<Self::Target>> {
+        Err(anyhow::anyhow!(QueryError::FunctionUnimplemented(
+            "allrdeps"
+        )))
+    }
+
     async fn testsof(
         &self,
         targets: &TargetSet<Self::Target>,
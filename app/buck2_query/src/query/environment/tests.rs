@@ -10,6 +10,7 @@
 #![cfg(test)]
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 use std::sync::Arc;
 
@@ -22,6 +23,8 @@ use dupe::OptionDupedExt;
 use indexmap::IndexSet;
 
 use super::*;
+use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use crate::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use crate::query::traversal::AsyncNodeLookup;
 
 #[derive(Debug, Copy, Clone, Dupe, Eq, PartialEq, Hash, Display, From)]
@@ -36,6 +39,10 @@ struct TestTargetAttr;
 struct TestTarget {
     id: TestTargetId,
     deps: Arc<IndexSet<TestTargetId>>,
+    /// Targets are in the package `root//p{id / 10}`.
+    buildfile_path: Arc<BuildFilePath>,
+    /// Private targets are only visible to the targets in their package.
+    private: bool,
 }
 
 /// Custom debug to make the test output more readable
@@ -65,7 +72,7 @@ impl QueryTarget for TestTarget {
     }
 
     fn buildfile_path(&self) -> &BuildFilePath {
-        unimplemented!()
+        &self.buildfile_path
     }
 
     fn deps<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Self::Key> + Send + 'a> {
@@ -104,6 +111,10 @@ impl QueryTarget for TestTarget {
     fn map_attr<R, F: FnMut(Option<&Self::Attr<'_>>) -> R>(&self, _key: &str, _func: F) -> R {
         unimplemented!()
     }
+
+    fn is_visible_to(&self, target: &Self) -> anyhow::Result<bool> {
+        Ok(!self.private || self.buildfile_path.package() == target.buildfile_path.package())
+    }
 }
 
 struct TestEnv {
@@ -147,8 +158,30 @@ impl QueryEnvironment for TestEnv {
         unimplemented!()
     }
 
-    async fn eval_literals(&self, _literal: &[&str]) -> anyhow::Result<TargetSet<Self::Target>> {
-        unimplemented!()
+    /// Literals are either target ids or packages, like `root//p1:`.
+    async fn eval_literals(&self, literals: &[&str]) -> anyhow::Result<TargetSet<Self::Target>> {
+        let mut set = TargetSet::new();
+        for literal in literals {
+            match literal.strip_suffix(':') {
+                Some(package) => {
+                    let mut targets: Vec<_> = self
+                        .graph
+                        .values()
+                        .filter(|target| target.buildfile_path.package().to_string() == package)
+                        .collect();
+                    targets.sort_by_key(|target| target.id.0);
+                    for target in targets {
+                        set.insert(target.dupe());
+                    }
+                }
+                None => {
+                    for target in self.set(literal)?.iter() {
+                        set.insert(target.dupe());
+                    }
+                }
+            }
+        }
+        Ok(set)
     }
 
     async fn eval_file_literal(&self, _literal: &str) -> anyhow::Result<FileSet> {
@@ -175,6 +208,15 @@ impl QueryEnvironment for TestEnv {
         async_depth_limited_traversal(self, root.iter_names(), delegate, visit, depth).await
     }
 
+    async fn allrdeps(
+        &self,
+        targets: &TargetSet<Self::Target>,
+        depth: Option<i32>,
+    ) -> anyhow::Result<TargetSet<Self::Target>> {
+        let universe: TargetSet<_> = self.graph.values().map(|target| target.dupe()).collect();
+        self.rdeps(&universe, targets, depth).await
+    }
+
     async fn owner(&self, _paths: &FileSet) -> anyhow::Result<TargetSet<Self::Target>> {
         unimplemented!()
     }
@@ -190,11 +232,19 @@ impl TestEnv {
         }
         Ok(set)
     }
+
+    async fn eval(&self, query: &str) -> anyhow::Result<TargetSet<TestTarget>> {
+        QueryEvaluator::new(self, &DefaultQueryFunctionsModule::new())
+            .eval_query(query)
+            .await?
+            .try_into_targets()
+    }
 }
 
 #[derive(Default)]
 pub struct TestEnvBuilder {
     graph: HashMap<u64, IndexSet<u64>>,
+    private: HashSet<u64>,
 }
 
 impl TestEnvBuilder {
@@ -203,6 +253,10 @@ impl TestEnvBuilder {
         self.graph.entry(to).or_default();
     }
 
+    fn private(&mut self, id: u64) {
+        self.private.insert(id);
+    }
+
     fn build(&self) -> TestEnv {
         TestEnv {
             graph: self
@@ -211,7 +265,20 @@ impl TestEnvBuilder {
                 .map(|(id, vs)| {
                     let id = TestTargetId(*id);
                     let deps = Arc::new(vs.iter().map(|v| TestTargetId(*v)).collect());
-                    (id, TestTarget { id, deps })
+                    let buildfile_path = Arc::new(BuildFilePath::testing_new(&format!(
+                        "root//p{}:BUCK",
+                        id.0 / 10
+                    )));
+                    let private = self.private.contains(&id.0);
+                    (
+                        id,
+                        TestTarget {
+                            id,
+                            deps,
+                            buildfile_path,
+                            private,
+                        },
+                    )
                 })
                 .collect(),
         }
@@ -379,3 +446,105 @@ async fn test_rdeps() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_direct_rdeps_of() -> anyhow::Result<()> {
+    let mut env = TestEnvBuilder::default();
+    env.edge(1, 2);
+    env.edge(2, 3);
+    env.edge(1, 3);
+    env.edge(4, 5);
+    let env = env.build();
+
+    let rdeps = env.set("1,2,3,4,5")?.direct_rdeps_of(&env.set("3")?)?;
+    assert_eq!(rdeps, env.set("1,2")?);
+
+    let rdeps = env.set("1,2,3,4,5")?.direct_rdeps_of(&env.set("2,5")?)?;
+    assert_eq!(rdeps, env.set("1,4")?);
+
+    Ok(())
+}
+
+/// Packages `root//p1` and `root//p2`, where `12` is private to `root//p1`.
+fn packages_env() -> TestEnv {
+    let mut env = TestEnvBuilder::default();
+    env.edge(10, 11);
+    env.edge(12, 11);
+    env.edge(13, 20);
+    env.edge(20, 11);
+    env.edge(21, 20);
+    env.private(12);
+    env.build()
+}
+
+#[tokio::test]
+async fn test_eval_siblings() -> anyhow::Result<()> {
+    let env = packages_env();
+
+    assert_eq!(env.eval("siblings('11')").await?, env.set("10,11,12,13")?);
+    assert_eq!(
+        env.eval("siblings(set('11' '21'))").await?,
+        env.set("10,11,12,13,20,21")?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eval_same_pkg_direct_rdeps() -> anyhow::Result<()> {
+    let env = packages_env();
+
+    // `20` depends on `11` too, but is in another package.
+    assert_eq!(
+        env.eval("same_pkg_direct_rdeps('11')").await?,
+        env.set("10,12")?
+    );
+    assert_eq!(
+        env.eval("same_pkg_direct_rdeps('20')").await?,
+        env.set("21")?
+    );
+    assert_eq!(
+        env.eval("same_pkg_direct_rdeps('10')").await?,
+        TargetSet::new()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eval_allrdeps() -> anyhow::Result<()> {
+    let env = packages_env();
+
+    assert_eq!(
+        env.eval("allrdeps('11')").await?,
+        env.set("10,11,12,13,20,21")?
+    );
+    assert_eq!(
+        env.eval("allrdeps('11', 1)").await?,
+        env.set("10,11,12,20")?
+    );
+    assert_eq!(env.eval("allrdeps('20', 0)").await?, env.set("20")?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eval_visible() -> anyhow::Result<()> {
+    let env = packages_env();
+
+    // `12` is only visible to its own package.
+    assert_eq!(
+        env.eval("visible('21', siblings('11'))").await?,
+        env.set("10,11,13")?
+    );
+    assert_eq!(
+        env.eval("visible('13', siblings('11'))").await?,
+        env.set("10,11,12,13")?
+    );
+    assert_eq!(
+        env.eval("visible(set('13' '21'), siblings('11'))").await?,
+        env.set("10,11,13")?
+    );
+
+    Ok(())
+}
@@ -18,6 +18,8 @@ use buck2_query_parser::spanned::Spanned;
 pub enum QueryError {
     #[error("unknown function `{0}`")]
     UnknownFunction(String),
+    #[error("variable `${0}` is not defined")]
+    UndefinedVariable(String),
     #[error("binary op `{0}` unsupported in this context")]
     UnsupportedBinaryOp(String),
     #[error("expected a literal, got value of type `{actual}`")]
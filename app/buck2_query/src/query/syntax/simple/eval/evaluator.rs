@@ -9,6 +9,9 @@
 
 //! Implementation of the cli and query_* attr query language.
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use buck2_query_parser::parse_expr;
 use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::Expr;
@@ -29,11 +32,17 @@ use crate::query::syntax::simple::functions::QueryFunctions;
 pub struct QueryEvaluator<'e, Env: QueryEnvironment> {
     env: &'e Env,
     functions: &'e dyn QueryFunctions<Env = Env>,
+    /// Values bound by the `let` expressions enclosing the expression being evaluated.
+    bindings: HashMap<String, Arc<QueryValue<Env::Target>>>,
 }
 
 impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
     pub fn new(env: &'e Env, functions: &'e dyn QueryFunctions<Env = Env>) -> Self {
-        Self { env, functions }
+        Self {
+            env,
+            functions,
+            bindings: HashMap::new(),
+        }
     }
 
     pub fn env(&self) -> &Env {
@@ -105,6 +114,22 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
 
                 Ok(files.into())
             }
+            Expr::Let { name, value, body } => {
+                // The value is computed once here, and every reference to it in the body reuses it.
+                let value = self.eval(value).await?.value;
+                let mut bindings = self.bindings.clone();
+                bindings.insert((*name.fragment()).to_owned(), Arc::new(value));
+                let evaluator = QueryEvaluator {
+                    env: self.env,
+                    functions: self.functions,
+                    bindings,
+                };
+                Ok(evaluator.eval(body).await?.value)
+            }
+            Expr::Variable(name) => match self.bindings.get(*name.fragment()) {
+                Some(value) => Ok((**value).clone()),
+                None => Err(QueryError::UndefinedVariable((*name.fragment()).to_owned())),
+            },
         }
     }
 
@@ -214,6 +214,23 @@ impl<T: QueryTarget> TargetSet<T> {
         self.filter(|node| Ok(re.is_match(&node.rule_type())?))
     }
 
+    /// Targets that are visible to every target in `universe`.
+    pub fn visible_to(&self, universe: &TargetSet<T>) -> anyhow::Result<TargetSet<T>> {
+        self.filter(|node| {
+            for target in universe.iter() {
+                if !node.is_visible_to(target)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    /// Targets that directly depend on any of `targets`.
+    pub fn direct_rdeps_of(&self, targets: &TargetSet<T>) -> anyhow::Result<TargetSet<T>> {
+        self.filter(|node| Ok(node.deps().any(|dep| targets.contains(dep))))
+    }
+
     pub fn intersect(&self, right: &TargetSet<T>) -> anyhow::Result<TargetSet<T>> {
         self.filter(|node| Ok(right.contains(node.node_key())))
     }
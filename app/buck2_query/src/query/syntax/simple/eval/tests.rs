@@ -30,6 +30,7 @@ use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
 use crate::query::syntax::simple::eval::file_set::FileSet;
 use crate::query::syntax::simple::eval::set::TargetSet;
+use crate::query::syntax::simple::eval::values::QueryValue;
 use crate::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 
 #[derive(Clone, Hash, PartialEq, Eq, Debug, Display)]
@@ -172,3 +173,38 @@ pub async fn test_missing_arg() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[tokio::test]
+pub async fn test_let() -> anyhow::Result<()> {
+    let input = "let x = 3 in let y = $x in $y";
+    let parsed = parse_expr(input)?;
+    match QueryEvaluator::new(&Env, &DefaultQueryFunctionsModule::new())
+        .eval(&parsed)
+        .await
+    {
+        Ok(v) => assert_eq!(QueryValue::Integer(3), v.value),
+        Err(e) => return Err(QueryError::convert_error(e, input)),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_undefined_variable() -> anyhow::Result<()> {
+    let input = "let x = 3 in $y";
+    let parsed = parse_expr(input)?;
+    match QueryEvaluator::new(&Env, &DefaultQueryFunctionsModule::new())
+        .eval(&parsed)
+        .await
+    {
+        Ok(_) => panic!(),
+        Err(e) => {
+            let err = QueryError::convert_error(e, input);
+            let msg = format!("{:#}", err);
+            let expected = "variable `$y` is not defined";
+            if !msg.contains(expected) {
+                return Err(err.context(format!("Expected error to contain `{}`", expected)));
+            }
+        }
+    }
+    Ok(())
+}
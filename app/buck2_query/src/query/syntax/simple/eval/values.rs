@@ -24,7 +24,7 @@ pub enum QueryEvaluationResult<T: QueryTarget> {
 }
 
 /// Used as a value in query evaluation, may appear in arguments to functions, results of functions etc.
-#[derive(Debug, VariantName, Eq, PartialEq)]
+#[derive(Debug, VariantName, Eq, PartialEq, Clone)]
 pub enum QueryValue<T: QueryTarget> {
     String(String),
     Integer(u64),
@@ -19,6 +19,7 @@ use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::BinaryOp;
 use buck2_query_parser::Expr;
 use gazebo::variants::VariantName;
+use itertools::Itertools;
 
 use crate::query::environment::QueryEnvironment;
 use crate::query::syntax::simple::eval::error::QueryError;
@@ -116,6 +117,13 @@ impl<F: QueryFunctions> QueryFunctionsVisitLiterals for F {
                     Ok(())
                 }
                 Expr::FileSet(_args) => Ok(()),
+                Expr::Let { value, body, .. } => {
+                    // Bound values are usually targets, like top-level expressions.
+                    visit_literals_item(this, visitor, value, true)?;
+                    visit_literals_item(this, visitor, body, true)
+                }
+                // The literals of the bound value were visited at the `let`.
+                Expr::Variable(..) => Ok(()),
                 Expr::String(..) | Expr::Integer(..) => {
                     panic!(
                         "This shouldn't be called with literals, they should be handled in the caller"
@@ -384,10 +392,64 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
             .into())
     }
 
+    /// The `allrdeps(targets, depth)` function returns the reverse dependencies of `targets` within the
+    /// whole universe the query runs in, rather than within an explicitly given one as `rdeps` does.
+    ///
+    /// For example, `buck2 cquery "allrdeps('//foo:bar', 1)" --target-universe //...` returns the targets in
+    /// `//...` that directly depend on `//foo:bar`, plus `//foo:bar` itself.
+    ///
+    /// This is only available in cquery, which has a target universe.
+    async fn allrdeps(
+        &self,
+        env: &Env,
+        targets: TargetSet<Env::Target>,
+        depth: Option<u64>,
+    ) -> QueryFuncResult<Env> {
+        Ok(self
+            .implementation
+            .allrdeps(env, &targets, depth.map(|v| v as i32))
+            .await?
+            .into())
+    }
+
+    /// The `same_pkg_direct_rdeps(targets)` function returns the targets in the same packages as
+    /// `targets` that directly depend on any of them.
+    async fn same_pkg_direct_rdeps(
+        &self,
+        env: &Env,
+        targets: TargetSet<Env::Target>,
+    ) -> QueryFuncResult<Env> {
+        Ok(self
+            .implementation
+            .same_pkg_direct_rdeps(env, &targets)
+            .await?
+            .into())
+    }
+
+    /// The `siblings(targets)` function returns all the targets in the packages of `targets`.
+    ///
+    /// For example, `buck2 uquery "siblings('//foo:bar')"` is the same as `buck2 uquery "//foo:"`.
+    async fn siblings(&self, env: &Env, targets: TargetSet<Env::Target>) -> QueryFuncResult<Env> {
+        Ok(self.implementation.siblings(env, &targets).await?.into())
+    }
+
     async fn testsof(&self, env: &Env, targets: TargetSet<Env::Target>) -> QueryFuncResult<Env> {
         Ok(self.implementation.testsof(env, &targets).await?.into())
     }
 
+    /// The `visible(universe, targets)` function returns the targets in `targets` that every target in
+    /// `universe` is allowed to depend on, according to their `visibility`.
+    ///
+    /// For example, `buck2 uquery "visible('//foo:bar', '//lib/...')"` returns the targets in `//lib/...`
+    /// that `//foo:bar` could depend on.
+    async fn visible(
+        &self,
+        universe: TargetSet<Env::Target>,
+        targets: TargetSet<Env::Target>,
+    ) -> QueryFuncResult<Env> {
+        Ok(self.implementation.visible(&universe, &targets)?.into())
+    }
+
     // These three functions are intentionally implemented as errors. They are only available within the context
     // of a deps functions 3rd parameter expr. When used in that context, the QueryFunctions will be augmented to
     // have non-erroring implementations.
@@ -624,6 +686,41 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
         env.rdeps(universe, targets, depth).await
     }
 
+    pub async fn allrdeps(
+        &self,
+        env: &Env,
+        targets: &TargetSet<Env::Target>,
+        depth: Option<i32>,
+    ) -> anyhow::Result<TargetSet<Env::Target>> {
+        env.allrdeps(targets, depth).await
+    }
+
+    pub async fn same_pkg_direct_rdeps(
+        &self,
+        env: &Env,
+        targets: &TargetSet<Env::Target>,
+    ) -> anyhow::Result<TargetSet<Env::Target>> {
+        self.siblings(env, targets).await?.direct_rdeps_of(targets)
+    }
+
+    pub async fn siblings(
+        &self,
+        env: &Env,
+        targets: &TargetSet<Env::Target>,
+    ) -> anyhow::Result<TargetSet<Env::Target>> {
+        let packages: Vec<String> = targets
+            .iter()
+            .map(|target| target.buildfile_path().package())
+            .unique()
+            .map(|package| format!("{}:", package))
+            .collect();
+        if packages.is_empty() {
+            return Ok(TargetSet::new());
+        }
+        env.eval_literals(&packages.iter().map(|p| p.as_str()).collect::<Vec<_>>())
+            .await
+    }
+
     pub async fn testsof(
         &self,
         env: &Env,
@@ -640,6 +737,14 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
         env.testsof_with_default_target_platform(targets).await
     }
 
+    pub fn visible(
+        &self,
+        universe: &TargetSet<Env::Target>,
+        targets: &TargetSet<Env::Target>,
+    ) -> anyhow::Result<TargetSet<Env::Target>> {
+        targets.visible_to(universe)
+    }
+
     pub async fn intersect(
         &self,
         env: &Env,
@@ -264,6 +264,16 @@ impl<'c> QueryEnvironment for CqueryEnvironment<'c> {
         return rbuildfiles(universe, argset, self.delegate.uquery_delegate()).await;
     }
 
+    async fn allrdeps(
+        &self,
+        targets: &TargetSet<Self::Target>,
+        depth: Option<i32>,
+    ) -> anyhow::Result<TargetSet<Self::Target>> {
+        let universe = self.universe.as_ref().context(CqueryError::NoUniverse)?;
+        let universe: TargetSet<_> = universe.iter().map(|node| node.to_owned()).collect();
+        self.rdeps(&universe, targets, depth).await
+    }
+
     async fn owner(&self, paths: &FileSet) -> anyhow::Result<TargetSet<Self::Target>> {
         let mut result = TargetSet::new();
 
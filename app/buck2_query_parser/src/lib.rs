@@ -31,6 +31,8 @@
 //!        | EXPR ' + ' EXPR
 //!        | EXPR ' except ' EXPR
 //!        | EXPR ' - ' EXPR
+//!        | 'let' NAME '=' EXPR 'in' EXPR
+//!        | '$' NAME
 //!
 //! # word is much broader than a normal identifier-like thing would allow since we don't want to require
 //! # quoting targets "@fbcode//some:target" or common regexes ".*" or filenames "Foo.java".
@@ -41,8 +43,14 @@
 //!
 //! INTEGER ::= "0" | ("1-9" "0-9"*)
 //!
-//! FUNCTION_NAME ::= "a-zA-Z_" "a-zA-Z0-9_" *
+//! FUNCTION_NAME ::= NAME
+//!
+//! NAME ::= "a-zA-Z_" "a-zA-Z0-9_" *
 //! ```
+//!
+//! As in Bazel, `let` binds the value of an expression to a name, which can then be referenced
+//! as `$name` in the body of the `let`. The body extends as far to the right as possible, so
+//! `let x = a in $x + b` is `let x = a in ($x + b)`.
 
 pub mod multi_query;
 pub mod placeholder;
@@ -67,6 +75,7 @@ use nom::character::complete::multispace0;
 use nom::character::complete::multispace1;
 use nom::combinator::all_consuming;
 use nom::combinator::cut;
+use nom::combinator::not;
 use nom::combinator::recognize;
 use nom::error::context;
 use nom::error::convert_error;
@@ -84,8 +93,6 @@ use nom::IResult;
 use crate::span::Span;
 use crate::spanned::Spanned;
 
-// TODO(cjhopman): Add `LET WORD = expr IN expr`
-
 // TODO(cjhopman): We should switch to our own error type here. VerboseError doesn't even allow us to construct
 // our own error messages (so, for example, we can't have a good error message for too large integers) and doesn't
 // support propagating anyhow or std errors (and since we can't do a custom message, we can't even capture them as a string).
@@ -124,6 +131,12 @@ pub enum Expr<'a> {
     BinaryOpSequence(Box<SpannedExpr<'a>>, Vec<(BinaryOp, SpannedExpr<'a>)>),
     Set(Vec<Span<'a>>),
     FileSet(Vec<Span<'a>>),
+    Let {
+        name: Span<'a>,
+        value: Box<SpannedExpr<'a>>,
+        body: Box<SpannedExpr<'a>>,
+    },
+    Variable(Span<'a>),
 }
 
 impl Display for Expr<'_> {
@@ -180,6 +193,10 @@ impl Display for Expr<'_> {
                 }
                 f.write_str(")")?;
             }
+            Expr::Let { name, value, body } => {
+                write!(f, "let {} = {} in {}", name.fragment(), value, body)?;
+            }
+            Expr::Variable(name) => write!(f, "${}", name.fragment())?,
         }
         Ok(())
     }
@@ -189,6 +206,9 @@ const INTERSECT: &str = "^";
 const EXCEPT: &str = "-";
 const UNION: &str = "+";
 
+/// Characters other than alphanumerics that may appear in an unquoted word.
+const WORD_SYMBOLS: &str = "*/@.-_:$#%";
+
 #[derive(Debug, Enum, Copy, Dupe, Clone)]
 pub enum BinaryOp {
     Intersect,
@@ -275,10 +295,12 @@ fn single_expr<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spann
     // parse an expression from the beginning of the input and check after if there's a "trailing" infix operator.
     let (input, left_expr) = alt((
         preceded(char('('), cut(terminated(expr, char(')')))),
+        expr_let,
         expr_set,
         expr_fileset,
         expr_function,
         expr_int,
+        expr_variable,
         expr_word,
     ))(input)?;
 
@@ -354,7 +376,7 @@ fn expr_int<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedE
 
 fn word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
     fn non_quoted_word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
-        recognize(many1(alt((alphanumeric1, is_a("*/@.-_:$#%")))))(input)
+        recognize(many1(alt((alphanumeric1, is_a(WORD_SYMBOLS)))))(input)
     }
 
     alt((
@@ -370,6 +392,45 @@ fn word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E>
     ))(input)
 }
 
+fn name<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_")))),
+    ))(input)
+}
+
+/// Tries to parse an Expr::Variable
+fn expr_variable<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
+    spanned(|input| {
+        let (remaining, name) = preceded(char('$'), name)(input)?;
+        // Something like `$foo.bar` is a word, not a variable.
+        let (remaining, _) = not(alt((alphanumeric1, is_a(WORD_SYMBOLS))))(remaining)?;
+        Ok((remaining, Expr::Variable(name)))
+    })(input)
+}
+
+/// Tries to parse an Expr::Let. Will fail if it detects an unfinished "let name ="
+fn expr_let<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
+    spanned(|input| {
+        let (input, _) = terminated(tag("let"), multispace1)(input)?;
+        let (input, name) =
+            terminated(name, delimited(multispace0, char('='), multispace0))(input)?;
+        cut(move |input| {
+            let (input, value) = expr(input)?;
+            let (input, _) = terminated(tag("in"), multispace1)(input)?;
+            let (input, body) = expr(input)?;
+            Ok((
+                input,
+                Expr::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                },
+            ))
+        })(input)
+    })(input)
+}
+
 fn binary_op(input: Span) -> NomResult<BinaryOp, ()> {
     fn keyword(long: &'static str) -> impl Fn(Span) -> NomResult<Span, ()> {
         // keywords require spaces separating from the exprs
@@ -438,10 +499,7 @@ fn expr_function<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spa
     }
 
     spanned(|input| {
-        let (input, function_name) = recognize(pair(
-            alt((alpha1, tag("_"))),
-            many0(alt((alphanumeric1, tag("_")))),
-        ))(input)?;
+        let (input, function_name) = name(input)?;
         let (input, _) = char('(')(input)?;
         cut(move |input| {
             let (input, args) = terminated(function_args, char(')'))(input)?;
@@ -503,9 +561,15 @@ mod tests {
                 "a + b",
                 "(a - (b))",
                 "123",
+                "let x = a in $x",
+                "let x = deps(a) in $x + rdeps($x, b)",
+                "let x = a in let y = $x in $y",
+                "let + b",
+                "letters",
+                "$x.y",
             ],
             &[],
-            &["func(", "set(", "(a", "01234"],
+            &["func(", "set(", "(a", "01234", "let x = a", "let x = in b"],
         );
 
         match parse_expr("set(a b c)") {
@@ -543,6 +607,52 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_let() -> anyhow::Result<()> {
+        run_tests(
+            expr_let,
+            &[
+                "let x = a in b",
+                "let x=a in b",
+                "let _x1 = set(a b) in $_x1",
+            ],
+            // As long as we don't match "let name =", it should be recoverable
+            &[
+                "let",
+                "letx = a in b",
+                "let x a in b",
+                "let + b",
+                "lets x = a in b",
+            ],
+            // An error after "let name =" is non-recoverable
+            &["let x = a", "let x = a inb", "let x = in b", "let x = a in"],
+        );
+
+        match parse_expr("let x = a in $x + b") {
+            Ok(Spanned {
+                value: Expr::Let { name, body, .. },
+                ..
+            }) => {
+                assert_eq!("x", *name.fragment());
+                assert!(matches!(body.value, Expr::BinaryOpSequence(..)));
+            }
+            v => panic!("expected let expr, got `{:?}`", v),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_variable() -> anyhow::Result<()> {
+        run_tests(
+            expr_variable,
+            &["$x", "$_foo_1"],
+            &["x", "$", "$1", "$x.y", "$x:y", "'$x'"],
+            &[],
+        );
+        Ok(())
+    }
+
     fn run_tests<'a, O: Debug, F: FnMut(Span<'a>) -> IResult<Span<'a>, O, ()> + Copy>(
         mut parser: F,
         good_cases: &'a [&'a str],
@@ -182,6 +182,16 @@ pub struct Buck2OssReConfiguration {
     pub engine_address: Option<String>,
     /// Address for RBE Action Cache service.
     pub action_cache_address: Option<String>,
+    /// Base URL of a cache server implementing the plain HTTP `/ac/` and `/cas/` protocol. When
+    /// set, this is used instead of the gRPC services above, and remote execution is unavailable.
+    ///
+    /// This can contain environment variables using shell interpolation syntax (i.e. $VAR). They
+    /// will be substituted before using the value.
+    pub http_cache_address: Option<String>,
+    /// The digest algorithm Buck2 uses (the first of `buck2.digest_algorithms`). The HTTP cache
+    /// needs it to compute the digests of blobs it uploads and to verify the blobs it downloads.
+    /// Defaults to SHA256.
+    pub digest_algorithm: Option<String>,
     /// Whether to use TLS to interact with remote execution.
     pub tls: bool,
     /// Path to a CA certificates bundle. This must be PEM-encoded. If none is set, a default
@@ -206,6 +216,35 @@ pub struct Buck2OssReConfiguration {
     pub capabilities: Option<bool>,
     /// The instance name to use in requests.
     pub instance_name: Option<String>,
+    /// Compression to use for CAS transfers. This is only used if the server advertises support
+    /// for it in its capabilities (or if capabilities are disabled, in which case it is assumed
+    /// to be supported).
+    pub compression: CasCompressor,
+}
+
+/// A compression format for CAS transfers, as defined in the REAPI `Compressor` message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Allocative)]
+pub enum CasCompressor {
+    #[default]
+    Identity,
+    Zstd,
+    Deflate,
+}
+
+impl FromStr for CasCompressor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "identity" | "none" => Ok(Self::Identity),
+            "zstd" => Ok(Self::Zstd),
+            "deflate" => Ok(Self::Deflate),
+            _ => Err(anyhow::anyhow!(
+                "Invalid compressor (expected `zstd`, `deflate` or `none`): `{}`",
+                s
+            )),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Allocative)]
@@ -249,6 +288,12 @@ impl Buck2OssReConfiguration {
             action_cache_address: legacy_config
                 .parse(BUCK2_RE_CLIENT_CFG_SECTION, "action_cache_address")?
                 .or(default_address),
+            http_cache_address: legacy_config
+                .parse(BUCK2_RE_CLIENT_CFG_SECTION, "http_cache_address")?,
+            digest_algorithm: legacy_config
+                .get("buck2", "digest_algorithms")
+                .and_then(|algos| algos.split(',').next())
+                .map(|algo| algo.trim().to_owned()),
             tls: legacy_config
                 .parse(BUCK2_RE_CLIENT_CFG_SECTION, "tls")?
                 .unwrap_or(true),
@@ -259,6 +304,9 @@ impl Buck2OssReConfiguration {
                 .unwrap_or_default(), // Empty list is as good None.
             capabilities: legacy_config.parse(BUCK2_RE_CLIENT_CFG_SECTION, "capabilities")?,
             instance_name: legacy_config.parse(BUCK2_RE_CLIENT_CFG_SECTION, "instance_name")?,
+            compression: legacy_config
+                .parse(BUCK2_RE_CLIENT_CFG_SECTION, "compression")?
+                .unwrap_or_default(),
         })
     }
 }
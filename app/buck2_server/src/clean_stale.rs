@@ -65,7 +65,12 @@ impl ServerCommandTemplate for CleanStaleServerCommand {
                     .context("Invalid timestamp")?;
 
                 extension
-                    .clean_stale_artifacts(keep_since_time, self.req.dry_run, self.req.tracked_only)
+                    .clean_stale_artifacts(
+                        keep_since_time,
+                        self.req.dry_run,
+                        self.req.tracked_only,
+                        self.req.max_bytes,
+                    )
                     .await
                     .context("Failed to clean stale artifacts.")
             })
@@ -69,12 +69,15 @@ use buck2_events::metadata;
 use buck2_execute::execute::blocking::BlockingExecutor;
 use buck2_execute::execute::blocking::SetBlockingExecutor;
 use buck2_execute::knobs::ExecutorGlobalKnobs;
+use buck2_execute::knobs::DEFAULT_HERMETIC_SANDBOX_HOST_PATHS;
 use buck2_execute::materialize::materializer::Materializer;
 use buck2_execute::materialize::materializer::SetMaterializer;
 use buck2_execute::re::client::RemoteExecutionClient;
 use buck2_execute::re::manager::ReConnectionHandle;
 use buck2_execute::re::manager::ReConnectionObserver;
+use buck2_execute_impl::executors::action_cache_verification::ActionCacheVerifier;
 use buck2_execute_impl::executors::worker::WorkerPool;
+use buck2_execute_impl::local_action_cache::LocalActionCache;
 use buck2_execute_impl::low_pass_filter::LowPassFilter;
 use buck2_execute_impl::re::paranoid_download::ParanoidDownloader;
 use buck2_file_watcher::file_watcher::FileWatcher;
@@ -101,6 +104,7 @@ use buck2_server_ctx::stderr_output_guard::StderrOutputGuard;
 use buck2_server_ctx::stderr_output_guard::StderrOutputWriter;
 use buck2_server_starlark_debug::create_debugger_handle;
 use buck2_server_starlark_debug::BuckStarlarkDebuggerHandle;
+use buck2_util::system_stats::system_memory_stats;
 use buck2_util::truncate::truncate_container;
 use dice::DiceComputations;
 use dice::DiceData;
@@ -344,11 +348,18 @@ impl<'a> ServerCommandContext<'a> {
                 ExecutionStrategy::from_i32(strategy).expect("execution strategy should be valid")
             });
 
+        let check_determinism = self
+            .build_options
+            .as_ref()
+            .map_or(false, |opts| opts.check_determinism);
+
+        // Cache hits would not run the action at all, let alone twice.
         let skip_cache_read = self
             .build_options
             .as_ref()
             .map(|opts| opts.skip_cache_read)
-            .unwrap_or_default();
+            .unwrap_or_default()
+            || check_determinism;
 
         let skip_cache_write = self
             .build_options
@@ -419,6 +430,9 @@ impl<'a> ServerCommandContext<'a> {
                 .build_options
                 .as_ref()
                 .map_or(false, |opts| opts.materialize_failed_inputs),
+            local_action_cache: self.base_context.daemon.local_action_cache.dupe(),
+            action_cache_verifier: self.base_context.daemon.action_cache_verifier.dupe(),
+            check_determinism,
         }
     }
 
@@ -526,6 +540,9 @@ struct DiceCommandDataProvider {
     paranoid: Option<ParanoidDownloader>,
     spawner: Arc<BuckSpawner>,
     materialize_failed_inputs: bool,
+    local_action_cache: Option<Arc<LocalActionCache>>,
+    action_cache_verifier: Option<Arc<ActionCacheVerifier>>,
+    check_determinism: bool,
 }
 
 #[async_trait]
@@ -571,13 +588,60 @@ impl DiceDataProvider for DiceCommandDataProvider {
             .parse::<u32>("build", "persistent_worker_shutdown_timeout_s")?
             .or(Some(10));
 
+        let hermetic_sandbox_host_paths = if root_config
+            .parse::<bool>("build", "hermetic_local_execution")?
+            .unwrap_or(false)
+        {
+            let configured =
+                root_config.parse_list::<String>("build", "hermetic_sandbox_host_paths")?;
+            let is_configured = configured.is_some();
+            let paths = configured
+                .unwrap_or_else(|| {
+                    DEFAULT_HERMETIC_SANDBOX_HOST_PATHS
+                        .iter()
+                        .map(|p| (*p).to_owned())
+                        .collect()
+                })
+                .into_iter()
+                .map(|p| AbsNormPathBuf::from(p.trim().to_owned()))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .context("Invalid `build.hermetic_sandbox_host_paths`")?;
+            // The sandbox fails on paths that don't exist. Not all the default ones exist on
+            // every host, but configured ones should.
+            let (paths, missing): (Vec<_>, Vec<_>) = paths
+                .into_iter()
+                .partition(|p| std::fs::symlink_metadata(p.as_path()).is_ok());
+            if is_configured && !missing.is_empty() {
+                warn!(
+                    "Ignoring `build.hermetic_sandbox_host_paths` which don't exist: {}",
+                    truncate_container(missing.iter().map(|p| p.to_string()), 200)
+                );
+            }
+            Some(Arc::new(paths))
+        } else {
+            None
+        };
+
+        let local_action_cgroups = root_config
+            .parse::<bool>("buck2", "local_action_cgroups")?
+            .unwrap_or(false);
+
         let executor_global_knobs = ExecutorGlobalKnobs {
             enable_miniperf,
             log_action_keys,
+            hermetic_sandbox_host_paths,
+            local_action_cgroups,
         };
 
+        let local_memory_budget_mb =
+            match root_config.parse::<usize>("build", "local_memory_budget_mb")? {
+                Some(budget) => budget,
+                None => (system_memory_stats() / (1024 * 1024)) as usize,
+            };
+
         let host_sharing_broker =
-            HostSharingBroker::new(HostSharingStrategy::SmallerTasksFirst, concurrency);
+            HostSharingBroker::new(HostSharingStrategy::SmallerTasksFirst, concurrency)
+                .with_memory_budget(local_memory_budget_mb);
 
         // We use the job count for the low pass filter too. The low pass filter prevents sending
         // RE-eligile tasks to local if their concurrency is higher than our threshold. While it
@@ -639,6 +703,9 @@ impl DiceDataProvider for DiceCommandDataProvider {
             worker_pool,
             self.paranoid.dupe(),
             self.materialize_failed_inputs,
+            self.local_action_cache.dupe(),
+            self.action_cache_verifier.dupe(),
+            self.check_determinism,
         )));
         data.set_blocking_executor(self.blocking_executor.dupe());
         data.set_http_client(self.http_client.dupe());
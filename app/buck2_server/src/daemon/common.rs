@@ -43,13 +43,19 @@ use buck2_execute::re::manager::ReConnectionHandle;
 use buck2_execute_impl::executors::action_cache::ActionCacheChecker;
 use buck2_execute_impl::executors::action_cache::RemoteDepFileCacheChecker;
 use buck2_execute_impl::executors::action_cache_upload_permission_checker::ActionCacheUploadPermissionChecker;
+use buck2_execute_impl::executors::action_cache_verification::ActionCacheVerifier;
+use buck2_execute_impl::executors::action_cache_verification::ActionCacheVerifyingExecutor;
 use buck2_execute_impl::executors::caching::CacheUploader;
+use buck2_execute_impl::executors::determinism_check::DeterminismCheckingExecutor;
 use buck2_execute_impl::executors::hybrid::HybridExecutor;
 use buck2_execute_impl::executors::local::LocalExecutor;
+use buck2_execute_impl::executors::local_action_cache::LocalActionCacheChecker;
+use buck2_execute_impl::executors::local_action_cache::LocalActionCacheWriter;
 use buck2_execute_impl::executors::re::ReExecutor;
 use buck2_execute_impl::executors::stacked::StackedExecutor;
 use buck2_execute_impl::executors::to_re_platform::RePlatformFieldsToRePlatform;
 use buck2_execute_impl::executors::worker::WorkerPool;
+use buck2_execute_impl::local_action_cache::LocalActionCache;
 use buck2_execute_impl::low_pass_filter::LowPassFilter;
 use buck2_execute_impl::re::paranoid_download::ParanoidDownloader;
 use buck2_forkserver::client::ForkserverClient;
@@ -88,6 +94,9 @@ pub struct CommandExecutorFactory {
     worker_pool: Arc<WorkerPool>,
     paranoid: Option<ParanoidDownloader>,
     materialize_failed_inputs: bool,
+    local_action_cache: Option<Arc<LocalActionCache>>,
+    action_cache_verifier: Option<Arc<ActionCacheVerifier>>,
+    check_determinism: bool,
     /// Cache permission checks per command.
     cache_upload_permission_checker: Arc<ActionCacheUploadPermissionChecker>,
 }
@@ -109,6 +118,9 @@ impl CommandExecutorFactory {
         worker_pool: Arc<WorkerPool>,
         paranoid: Option<ParanoidDownloader>,
         materialize_failed_inputs: bool,
+        local_action_cache: Option<Arc<LocalActionCache>>,
+        action_cache_verifier: Option<Arc<ActionCacheVerifier>>,
+        check_determinism: bool,
     ) -> Self {
         let cache_upload_permission_checker = Arc::new(ActionCacheUploadPermissionChecker::new(
             re_connection.get_client(),
@@ -129,9 +141,102 @@ impl CommandExecutorFactory {
             worker_pool,
             paranoid,
             materialize_failed_inputs,
+            local_action_cache,
+            action_cache_verifier,
+            check_determinism,
             cache_upload_permission_checker,
         }
     }
+
+    /// Run every command twice if we were asked to check determinism.
+    fn with_determinism_check(
+        &self,
+        artifact_fs: &ArtifactFs,
+        response: CommandExecutorResponse,
+    ) -> CommandExecutorResponse {
+        if !self.check_determinism {
+            return response;
+        }
+
+        CommandExecutorResponse {
+            executor: Arc::new(DeterminismCheckingExecutor {
+                artifact_fs: artifact_fs.clone(),
+                inner: response.executor,
+            }),
+            ..response
+        }
+    }
+
+    /// Compare the results of cache hits sampled for verification against their re-execution.
+    fn with_action_cache_verification(
+        &self,
+        artifact_fs: &ArtifactFs,
+        response: CommandExecutorResponse,
+    ) -> CommandExecutorResponse {
+        let verifier = match &self.action_cache_verifier {
+            Some(verifier) if !self.skip_cache_read => verifier,
+            _ => return response,
+        };
+
+        CommandExecutorResponse {
+            executor: Arc::new(ActionCacheVerifyingExecutor {
+                artifact_fs: artifact_fs.clone(),
+                verifier: verifier.dupe(),
+                inner: response.executor,
+            }),
+            ..response
+        }
+    }
+
+    /// Put the local action cache (if enabled) in front of the other caches, and have it record
+    /// the results of actions that end up running locally.
+    fn with_local_action_cache(
+        &self,
+        artifact_fs: &ArtifactFs,
+        response: CommandExecutorResponse,
+    ) -> CommandExecutorResponse {
+        let local_action_cache = match &self.local_action_cache {
+            Some(local_action_cache) => local_action_cache,
+            None => return response,
+        };
+
+        let CommandExecutorResponse {
+            executor,
+            platform,
+            cache_checker,
+            cache_uploader,
+        } = response;
+
+        let cache_checker = if self.skip_cache_read {
+            cache_checker
+        } else {
+            Arc::new(LocalActionCacheChecker {
+                artifact_fs: artifact_fs.clone(),
+                materializer: self.materializer.dupe(),
+                blocking_executor: self.blocking_executor.dupe(),
+                local_action_cache: local_action_cache.dupe(),
+                next_checker: cache_checker,
+            }) as _
+        };
+
+        let executor = if self.skip_cache_write {
+            executor
+        } else {
+            Arc::new(LocalActionCacheWriter {
+                artifact_fs: artifact_fs.clone(),
+                blocking_executor: self.blocking_executor.dupe(),
+                local_action_cache: local_action_cache.dupe(),
+                inner: executor,
+            }) as _
+        };
+
+        CommandExecutorResponse {
+            executor,
+            platform,
+            cache_checker,
+            cache_uploader,
+        }
+    }
 }
 
 impl HasCommandExecutor for CommandExecutorFactory {
@@ -174,12 +279,16 @@ impl HasCommandExecutor for CommandExecutorFactory {
                 ));
             }
 
-            return Ok(CommandExecutorResponse {
-                executor: Arc::new(local_executor_new(&LocalExecutorOptions::default())),
-                platform: Default::default(),
-                cache_checker: Arc::new(NoOpCommandOptionalExecutor {}),
-                cache_uploader: Arc::new(NoOpCacheUploader {}),
-            });
+            let response = self.with_determinism_check(
+                artifact_fs,
+                CommandExecutorResponse {
+                    executor: Arc::new(local_executor_new(&LocalExecutorOptions::default())),
+                    platform: Default::default(),
+                    cache_checker: Arc::new(NoOpCommandOptionalExecutor {}),
+                    cache_uploader: Arc::new(NoOpCacheUploader {}),
+                },
+            );
+            return Ok(self.with_local_action_cache(artifact_fs, response));
         }
 
         let remote_executor_new =
@@ -202,6 +311,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                     paranoid: self.paranoid.dupe(),
                     materialize_failed_inputs: self.materialize_failed_inputs,
                     dependencies: dependencies.to_vec(),
+                    verifier: self.action_cache_verifier.dupe(),
                 }
             };
 
@@ -276,6 +386,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                             knobs: self.executor_global_knobs.dupe(),
                             paranoid: self.paranoid.dupe(),
                             remote_dep_file_checker,
+                            verifier: self.action_cache_verifier.dupe(),
                         }) as _
                     }
                 };
@@ -359,6 +470,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                         re_properties.clone(),
                         None,
                         self.cache_upload_permission_checker.dupe(),
+                        self.action_cache_verifier.dupe(),
                     )) as _
                 } else if disable_caching {
                     Arc::new(NoOpCacheUploader {}) as _
@@ -371,16 +483,22 @@ impl HasCommandExecutor for CommandExecutorFactory {
                         re_properties.clone(),
                         *max_bytes,
                         self.cache_upload_permission_checker.dupe(),
+                        self.action_cache_verifier.dupe(),
                     )) as _
                 } else {
                     Arc::new(NoOpCacheUploader {}) as _
                 };
 
-                executor.map(|executor| CommandExecutorResponse {
-                    executor,
-                    platform: re_properties.to_re_platform(),
-                    cache_checker,
-                    cache_uploader,
+                executor.map(|executor| {
+                    self.with_action_cache_verification(
+                        artifact_fs,
+                        CommandExecutorResponse {
+                            executor,
+                            platform: re_properties.to_re_platform(),
+                            cache_checker,
+                            cache_uploader,
+                        },
+                    )
                 })
             }
         };
@@ -390,7 +508,8 @@ impl HasCommandExecutor for CommandExecutorFactory {
 "The desired execution strategy (`{:?}`) is incompatible with the executor config that was selected: {:?}",
 self.strategy, executor_config))?;
 
-        Ok(response)
+        let response = self.with_determinism_check(artifact_fs, response);
+        Ok(self.with_local_action_cache(artifact_fs, response))
     }
 }
 
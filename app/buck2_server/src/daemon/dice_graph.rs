@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Saving the DICE graph when the daemon shuts down, and loading it into the next daemon
+//! (`buck2.persist_dice_graph`), so that the next daemon doesn't start cold. Only file metadata is
+//! saved, so that the next daemon doesn't hash every file again: parsing and evaluation results
+//! are Starlark heaps, which can't be saved.
+//!
+//! The graph is saved along with the state of the file watcher. The next daemon's file watcher
+//! resumes from that state, so that its first sync invalidates the files that changed while no
+//! daemon was running. File watchers that can't do that don't get their graph saved.
+
+use std::io::BufWriter;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_file_watcher::file_watcher::FileWatcher;
+use dice::Dice;
+
+fn graph_path(dir: &AbsNormPath) -> AbsNormPathBuf {
+    dir.join(FileName::unchecked_new("graph"))
+}
+
+fn file_watcher_state_path(dir: &AbsNormPath) -> AbsNormPathBuf {
+    dir.join(FileName::unchecked_new("file_watcher_state"))
+}
+
+/// Read the graph saved by the last daemon, if any, and make `file_watcher` resume from where
+/// the last daemon's left off. The saved graph is removed, so that it is only loaded once.
+pub(crate) fn take_saved_dice_graph(
+    dir: &AbsNormPath,
+    file_watcher: &dyn FileWatcher,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let graph = fs_util::read_if_exists(graph_path(dir))?;
+    let state = fs_util::read_to_string_if_exists(file_watcher_state_path(dir))?;
+    fs_util::remove_all(dir)?;
+
+    match (graph, state) {
+        (Some(graph), Some(state)) if file_watcher.resume(&state)? => Ok(Some(graph)),
+        _ => Ok(None),
+    }
+}
+
+pub(crate) async fn save_dice_graph(
+    dir: AbsNormPathBuf,
+    dice: Arc<Dice>,
+    file_watcher: &dyn FileWatcher,
+) -> anyhow::Result<()> {
+    let Some(state) = file_watcher.save_state().await? else {
+        tracing::warn!(
+            "Not saving the DICE graph, as the file watcher can't report what changes before the next daemon starts"
+        );
+        return Ok(());
+    };
+
+    tokio::task::spawn_blocking(move || {
+        fs_util::remove_all(&dir)?;
+        fs_util::create_dir_all(&dir)?;
+
+        let saved =
+            dice.save_graph(&mut BufWriter::new(fs_util::create_file(graph_path(&dir))?))?;
+        // Written last, so that the graph is only loaded if it was written completely.
+        fs_util::write(file_watcher_state_path(&dir), state)?;
+
+        tracing::info!("Saved {} DICE nodes", saved);
+        anyhow::Ok(())
+    })
+    .await
+    .context("Failed to spawn")?
+}
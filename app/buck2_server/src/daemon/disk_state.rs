@@ -22,6 +22,7 @@ use buck2_core::rollout_percentage::RolloutPercentage;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::execute::blocking::BlockingExecutor;
 use buck2_execute::materialize::materializer::MaterializationMethod;
+use buck2_execute_impl::local_action_cache::LocalActionCache;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializerConfigs;
 use buck2_execute_impl::materializers::sqlite::MaterializerState;
 use buck2_execute_impl::materializers::sqlite::MaterializerStateSqliteDb;
@@ -32,6 +33,8 @@ use crate::daemon::server::BuckdServerInitPreferences;
 #[derive(Allocative)]
 pub struct DiskStateOptions {
     pub sqlite_materializer_state: bool,
+    /// Size budget of the local action cache, which is disabled if unset.
+    pub local_action_cache_max_bytes: Option<u64>,
     // In future, this will include the config for dep files on disk
 }
 
@@ -48,8 +51,12 @@ impl DiskStateOptions {
             .parse::<RolloutPercentage>("buck2", "sqlite_materializer_state")?
             .unwrap_or_else(RolloutPercentage::never)
             .roll();
+        let local_action_cache_max_bytes = root_config
+            .parse::<u64>("buck2", "local_action_cache_max_bytes")?
+            .filter(|max_bytes| *max_bytes > 0);
         Ok(Self {
             sqlite_materializer_state,
+            local_action_cache_max_bytes,
         })
     }
 }
@@ -116,6 +123,31 @@ pub(crate) async fn maybe_initialize_materializer_sqlite_db(
     Ok((Some(db), materializer_state))
 }
 
+pub(crate) async fn maybe_initialize_local_action_cache(
+    options: &DiskStateOptions,
+    paths: &InvocationPaths,
+    io_executor: Arc<dyn BlockingExecutor>,
+    fs: ProjectRoot,
+) -> anyhow::Result<Option<Arc<LocalActionCache>>> {
+    let max_bytes = match options.local_action_cache_max_bytes {
+        Some(max_bytes) => max_bytes,
+        None => {
+            // Like the materializer state, a disabled cache is deleted rather than left to go
+            // stale while builds that don't update it run.
+            io_executor
+                .execute_io_inline(|| fs.remove_path_recursive(&paths.local_action_cache_path()))
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    let cache =
+        LocalActionCache::initialize(paths.local_action_cache_path(), max_bytes, io_executor)
+            .await
+            .context("initializing the local action cache")?;
+    Ok(Some(Arc::new(cache)))
+}
+
 // Once we start storing disk state in the cache directory, we need to make sure
 // buck2 always deletes the cache directory if the cache is disabled.
 // Otherwise, buck-out state can diverge from the state of on-disk cache when
@@ -27,11 +27,18 @@ pub async fn maybe_launch_forkserver(
         return Ok(None);
     }
 
+    let cgroups = root_config
+        .parse::<bool>("buck2", "local_action_cgroups")?
+        .unwrap_or(false);
+
+    let mut args = vec!["forkserver"];
+    if cgroups {
+        args.push("--cgroups");
+    }
+
     let exe = std::env::current_exe().context("Cannot access current_exe")?;
-    Some(
-        buck2_forkserver::unix::launch_forkserver(exe, &["forkserver"], forkserver_state_dir).await,
-    )
-    .transpose()
+    Some(buck2_forkserver::unix::launch_forkserver(exe, &args, forkserver_state_dir, cgroups).await)
+        .transpose()
 }
 
 #[cfg(not(unix))]
@@ -37,6 +37,7 @@ use buck2_common::legacy_configs::init::DaemonStartupConfig;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_common::memory;
 use buck2_core::buck2_env;
+use buck2_core::cells::CellResolver;
 use buck2_core::error::reload_hard_error_config;
 use buck2_core::error::reset_soft_error_counters;
 use buck2_core::fs::cwd::WorkingDirectory;
@@ -65,6 +66,7 @@ use buck2_server_ctx::test_command::TEST_COMMAND;
 use buck2_server_starlark_debug::run::run_dap_server_command;
 use dice::DetectCycles;
 use dice::Dice;
+use dice::EvictionPolicy;
 use dice::WhichDice;
 use dupe::Dupe;
 use futures::channel::mpsc;
@@ -93,6 +95,7 @@ use crate::active_commands::ActiveCommand;
 use crate::active_commands::ActiveCommandStateWriter;
 use crate::clean_stale::clean_stale_command;
 use crate::ctx::ServerCommandContext;
+use crate::daemon::dice_graph::save_dice_graph;
 use crate::daemon::multi_event_stream::MultiEventStream;
 use crate::daemon::server_allocative::spawn_allocative;
 use crate::daemon::state::DaemonState;
@@ -157,13 +160,17 @@ impl BuckdServerInitPreferences {
         io: Arc<dyn IoProvider>,
         digest_config: DigestConfig,
         root_config: &LegacyBuckConfig,
+        cells: &CellResolver,
+        saved_graph: Option<&[u8]>,
     ) -> anyhow::Result<Arc<Dice>> {
         configure_dice_for_buck(
             io,
             digest_config,
             Some(root_config),
+            Some(cells),
             self.detect_cycles,
             self.which_dice,
+            saved_graph,
         )
         .await
     }
@@ -225,6 +232,8 @@ impl Interceptor for BuckCheckAuthTokenInterceptor {
 pub(crate) struct BuckdServerData {
     /// The flag that is set to true when server is shutting down.
     stop_accepting_requests: AtomicBool,
+    /// Set once the DICE graph was saved for the next daemon, which is only done once.
+    dice_graph_saved: AtomicBool,
     #[allocative(skip)]
     process_info: DaemonProcessInfo,
     base_daemon_constraints: buck2_cli_proto::DaemonConstraints,
@@ -290,6 +299,7 @@ impl BuckdServer {
         let auth_token = process_info.auth_token.clone();
         let api_server = BuckdServer(Arc::new(BuckdServerData {
             stop_accepting_requests: AtomicBool::new(false),
+            dice_graph_saved: AtomicBool::new(false),
             process_info,
             base_daemon_constraints,
             start_time: prost_types::Timestamp {
@@ -308,6 +318,7 @@ impl BuckdServer {
             rt,
         }));
 
+        let server_data = api_server.0.dupe();
         let shutdown = server_shutdown_signal(command_receiver, shutdown_receiver)?;
         let server = Server::builder()
             .layer(interceptor(BuckCheckAuthTokenInterceptor { auth_token }))
@@ -320,9 +331,31 @@ impl BuckdServer {
 
         server.await?;
 
+        // Also save the graph when the daemon shuts down on its own, e.g. after being inactive.
+        BuckdServer(server_data).save_dice_graph().await;
+
         Ok(())
     }
 
+    /// Save the DICE graph for the next daemon, if `buck2.persist_dice_graph` is set.
+    async fn save_dice_graph(&self) {
+        let Ok(data) = self.0.daemon_state.data() else {
+            return;
+        };
+        if !data.persist_dice_graph || self.0.dice_graph_saved.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = save_dice_graph(
+            self.0.daemon_state.paths.dice_graph_path(),
+            data.dice_manager.unsafe_dice().dupe(),
+            &*data.file_watcher,
+        )
+        .await
+        {
+            tracing::warn!("Error saving DICE graph: {:#}", e);
+        }
+    }
+
     /// Run a request that does bidirectional streaming.
     ///
     /// This mostly just ensures that a client context has been sent first, and passes a client
@@ -753,6 +786,9 @@ impl DaemonApi for BuckdServer {
                 callers: req.callers,
             };
 
+            // Save the graph before shutting down, as that may be forced after a short timeout.
+            self.save_dice_graph().await;
+
             self.0.daemon_shutdown.start_shutdown(reason, timeout);
             Ok(KillResponse {})
         })
@@ -1164,6 +1200,40 @@ impl DaemonApi for BuckdServer {
             .map_err(|e| Status::internal(format!("{:#}", e)))
     }
 
+    async fn unstable_dice_evict(
+        &self,
+        req: Request<UnstableDiceEvictRequest>,
+    ) -> Result<Response<UnstableDiceEvictResponse>, Status> {
+        self.check_if_accepting_requests()?;
+
+        let inner = req.into_inner();
+        let res: anyhow::Result<_> = try {
+            let policy = EvictionPolicy {
+                max_unused_versions: inner.max_unused_versions.map(|v| v as usize),
+                memory_budget: inner.memory_budget_bytes.map(|v| v as usize),
+            };
+
+            let stats = self
+                .0
+                .daemon_state
+                .data()?
+                .dice_manager
+                .unsafe_dice()
+                .evict(policy)
+                .await?
+                .context("Not evicting anything, as there are commands running")?;
+
+            UnstableDiceEvictResponse {
+                evicted_key_count: stats.evicted_key_count as u64,
+                remaining_key_count: stats.remaining_key_count as u64,
+                remaining_size_bytes: stats.remaining_size.map(|v| v as u64),
+            }
+        };
+
+        res.map(Response::new)
+            .map_err(|e| Status::internal(format!("{:#}", e)))
+    }
+
     type AllocativeStream = ResponseStream;
     async fn allocative(
         &self,
@@ -46,7 +46,11 @@ use buck2_execute::execute::blocking::BuckBlockingExecutor;
 use buck2_execute::materialize::materializer::MaterializationMethod;
 use buck2_execute::materialize::materializer::Materializer;
 use buck2_execute::re::manager::ReConnectionManager;
+use buck2_execute_impl::executors::action_cache_verification::ActionCacheVerifier;
+use buck2_execute_impl::local_action_cache::LocalActionCache;
+use buck2_execute_impl::materializers::content_store::ContentStoreLinkMode;
 use buck2_execute_impl::materializers::deferred::AccessTimesUpdates;
+use buck2_execute_impl::materializers::deferred::BuckOutSizeLimitConfiguration;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializer;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializerConfigs;
 use buck2_execute_impl::materializers::deferred::TtlRefreshConfiguration;
@@ -73,7 +77,9 @@ use tokio::sync::Mutex;
 use crate::active_commands::ActiveCommandDropGuard;
 use crate::ctx::BaseServerCommandContext;
 use crate::daemon::check_working_dir;
+use crate::daemon::dice_graph::take_saved_dice_graph;
 use crate::daemon::disk_state::delete_unknown_disk_state;
+use crate::daemon::disk_state::maybe_initialize_local_action_cache;
 use crate::daemon::disk_state::maybe_initialize_materializer_sqlite_db;
 use crate::daemon::disk_state::DiskStateOptions;
 use crate::daemon::forkserver::maybe_launch_forkserver;
@@ -171,6 +177,17 @@ pub struct DaemonStateData {
 
     /// Spawner
     pub spawner: Arc<BuckSpawner>,
+
+    /// If enabled, the on-disk cache of locally executed actions.
+    #[allocative(skip)]
+    pub local_action_cache: Option<Arc<LocalActionCache>>,
+
+    /// If enabled, re-executes a sample of action cache hits to check their outputs.
+    #[allocative(skip)]
+    pub action_cache_verifier: Option<Arc<ActionCacheVerifier>>,
+
+    /// Whether to save the DICE graph when the daemon is killed, for the next daemon to load.
+    pub persist_dice_graph: bool,
 }
 
 impl DaemonStateData {
@@ -333,6 +350,31 @@ impl DaemonState {
                     root_config.get("buck2", "update_access_times"),
                 )?;
 
+                // Disabled unless a budget is set. By default, check hourly and only clean
+                // once the daemon has been idle for 5 minutes.
+                let buck_out_size_limit = root_config
+                    .parse::<u64>("buck2", "buck_out_max_bytes")?
+                    .map(|max_bytes| {
+                        anyhow::Ok(BuckOutSizeLimitConfiguration {
+                            max_bytes,
+                            frequency: std::time::Duration::from_secs(
+                                root_config
+                                    .parse("buck2", "buck_out_clean_frequency_seconds")?
+                                    .unwrap_or(3600),
+                            ),
+                            idle_time: std::time::Duration::from_secs(
+                                root_config
+                                    .parse("buck2", "buck_out_clean_idle_seconds")?
+                                    .unwrap_or(300),
+                            ),
+                        })
+                    })
+                    .transpose()?;
+
+                let content_store = ContentStoreLinkMode::try_new_from_config_value(
+                    root_config.get("buck2", "materializer_content_store"),
+                )?;
+
                 DeferredMaterializerConfigs {
                     materialize_final_artifacts: matches!(
                         materializations,
@@ -345,35 +387,46 @@ impl DaemonState {
                         enabled: ttl_refresh_enabled,
                     },
                     update_access_times,
+                    buck_out_size_limit,
+                    content_store,
                 }
             };
 
-            let (io, _, (materializer_db, materializer_state)) = futures::future::try_join3(
-                create_io_provider(
-                    fb,
-                    fs.dupe(),
-                    root_config,
-                    digest_config.cas_digest_config(),
-                    init_ctx.enable_trace_io,
-                ),
-                (blocking_executor.dupe() as Arc<dyn BlockingExecutor>).execute_io_inline(|| {
-                    // Using `execute_io_inline` is just out of convenience.
-                    // It doesn't really matter what's used here since there's no IO-heavy
-                    // operations on daemon startup
-                    delete_unknown_disk_state(&cache_dir_path, &valid_cache_dirs, fs_duped)
-                }),
-                maybe_initialize_materializer_sqlite_db(
-                    &disk_state_options,
-                    paths.clone(),
-                    blocking_executor.dupe() as Arc<dyn BlockingExecutor>,
-                    root_config,
-                    &deferred_materializer_configs,
-                    fs.clone(),
-                    digest_config,
-                    &init_ctx,
-                ),
-            )
-            .await?;
+            let (io, _, (materializer_db, materializer_state), local_action_cache) =
+                futures::future::try_join4(
+                    create_io_provider(
+                        fb,
+                        fs.dupe(),
+                        root_config,
+                        digest_config.cas_digest_config(),
+                        init_ctx.enable_trace_io,
+                    ),
+                    (blocking_executor.dupe() as Arc<dyn BlockingExecutor>).execute_io_inline(
+                        || {
+                            // Using `execute_io_inline` is just out of convenience.
+                            // It doesn't really matter what's used here since there's no IO-heavy
+                            // operations on daemon startup
+                            delete_unknown_disk_state(&cache_dir_path, &valid_cache_dirs, fs_duped)
+                        },
+                    ),
+                    maybe_initialize_materializer_sqlite_db(
+                        &disk_state_options,
+                        paths.clone(),
+                        blocking_executor.dupe() as Arc<dyn BlockingExecutor>,
+                        root_config,
+                        &deferred_materializer_configs,
+                        fs.clone(),
+                        digest_config,
+                        &init_ctx,
+                    ),
+                    maybe_initialize_local_action_cache(
+                        &disk_state_options,
+                        &paths,
+                        blocking_executor.dupe() as Arc<dyn BlockingExecutor>,
+                        fs.clone(),
+                    ),
+                )
+                .await?;
 
             let http_client = http_client_from_startup_config(&init_ctx.daemon_startup_config)
                 .context("Error creating HTTP client")?
@@ -410,10 +463,6 @@ impl DaemonState {
             let forkserver =
                 maybe_launch_forkserver(root_config, &paths.forkserver_state_dir()).await?;
 
-            let dice = init_ctx
-                .construct_dice(io.dupe(), digest_config, root_config)
-                .await?;
-
             // TODO(cjhopman): We want to use Expr::True here, but we need to workaround
             // https://github.com/facebook/watchman/issues/911. Adding other filetypes to
             // this list should be safe until we can revert it to Expr::True.
@@ -431,6 +480,30 @@ impl DaemonState {
                 )
             })?;
 
+            let persist_dice_graph = root_config
+                .parse("buck2", "persist_dice_graph")?
+                .unwrap_or(false);
+            let saved_dice_graph = if persist_dice_graph {
+                take_saved_dice_graph(&paths.dice_graph_path(), &*file_watcher).unwrap_or_else(
+                    |e| {
+                        tracing::warn!("Error reading saved DICE graph: {:#}", e);
+                        None
+                    },
+                )
+            } else {
+                None
+            };
+
+            let dice = init_ctx
+                .construct_dice(
+                    io.dupe(),
+                    digest_config,
+                    root_config,
+                    &cells,
+                    saved_dice_graph.as_deref(),
+                )
+                .await?;
+
             let hash_all_commands = root_config
                 .parse::<RolloutPercentage>("buck2", "hash_all_commands")?
                 .unwrap_or_else(RolloutPercentage::never)
@@ -468,6 +541,18 @@ impl DaemonState {
                 .unwrap_or_else(RolloutPercentage::never)
                 .roll();
 
+            let action_cache_verifier = root_config
+                .parse::<RolloutPercentage>("buck2", "action_cache_verification_rate")?
+                .map(|sample_rate| {
+                    anyhow::Ok(Arc::new(ActionCacheVerifier::new(
+                        sample_rate,
+                        root_config
+                            .parse("buck2", "action_cache_verification_quarantine")?
+                            .unwrap_or(false),
+                    )))
+                })
+                .transpose()?;
+
             let paranoid = if init_ctx.daemon_startup_config.paranoid {
                 Some(ParanoidDownloader::new(
                     fs.clone(),
@@ -503,6 +588,9 @@ impl DaemonState {
                 http_client,
                 paranoid,
                 spawner: Arc::new(BuckSpawner::new(daemon_state_data_rt)),
+                local_action_cache,
+                action_cache_verifier,
+                persist_dice_graph,
             }))
         })
         .await?
@@ -671,6 +759,11 @@ impl DaemonState {
                 data.disk_state_options.sqlite_materializer_state
             ),
             format!("paranoid:{}", data.paranoid.is_some()),
+            format!("local-action-cache:{}", data.local_action_cache.is_some()),
+            format!(
+                "action-cache-verification:{}",
+                data.action_cache_verifier.is_some()
+            ),
         ];
 
         dispatcher.instant_event(buck2_data::TagEvent { tags });
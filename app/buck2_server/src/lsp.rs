@@ -7,7 +7,9 @@
  * of this source tree.
  */
 
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::future::Future;
 use std::io;
 use std::io::ErrorKind;
@@ -22,6 +24,7 @@ use buck2_common::package_listing::dice::HasPackageListingResolver;
 use buck2_core::bzl::ImportPath;
 use buck2_core::cells::build_file_cell::BuildFileCell;
 use buck2_core::cells::cell_path::CellPathRef;
+use buck2_core::cells::paths::CellRelativePath;
 use buck2_core::cells::CellResolver;
 use buck2_core::fs::paths::abs_path::AbsPath;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
@@ -34,6 +37,7 @@ use buck2_core::target::name::TargetName;
 use buck2_events::dispatch::span_async;
 use buck2_events::dispatch::with_dispatcher;
 use buck2_events::dispatch::with_dispatcher_async;
+use buck2_interpreter::file_type::StarlarkFileType;
 use buck2_interpreter::paths::bxl::BxlFilePath;
 use buck2_interpreter::paths::module::OwnedStarlarkModulePath;
 use buck2_interpreter::paths::path::StarlarkPath;
@@ -64,6 +68,7 @@ use starlark::docs::DocItem;
 use starlark::docs::DocModule;
 use starlark::docs::Identifier;
 use starlark::docs::Location;
+use starlark::environment::Globals;
 use starlark::errors::EvalMessage;
 use starlark::syntax::AstModule;
 use starlark_lsp::error::eval_message_to_lsp_diagnostic;
@@ -768,6 +773,93 @@ impl<'a> LspContext for BuckLspContext<'a> {
     fn get_environment(&self, _uri: &LspUrl) -> DocModule {
         DocModule::default()
     }
+
+    fn get_typecheck_globals(&self, uri: &LspUrl) -> Globals {
+        let dispatcher = self.server_ctx.events().dupe();
+        self.runtime
+            .block_on(with_dispatcher_async(dispatcher, async {
+                let import_path: OwnedStarlarkModulePath = match uri {
+                    LspUrl::File(path) => self.import_path(path).await,
+                    LspUrl::Starlark(path) => self.starlark_import_path(path).await,
+                    LspUrl::Other(_) => Err(BuckLspContextError::WrongScheme(
+                        "file:// or starlark:".to_owned(),
+                        uri.clone(),
+                    )
+                    .into()),
+                }?;
+
+                self.with_dice_ctx(async move |dice_ctx| {
+                    let cell_resolver = dice_ctx.get_cell_resolver().await?;
+                    let module_path = import_path.borrow();
+                    // Build files are parsed as load files above, but see different globals.
+                    let is_build_file = cell_resolver
+                        .get(module_path.cell())?
+                        .buildfiles()
+                        .iter()
+                        .any(|name| uri.path().file_name() == Some(OsStr::new(name.as_str())));
+                    let file_type = if is_build_file {
+                        StarlarkFileType::Buck
+                    } else {
+                        module_path.starlark_path().file_type()
+                    };
+                    Ok(dice_ctx
+                        .get_global_interpreter_state()
+                        .await?
+                        .globals_for_file_type(file_type)
+                        .dupe())
+                })
+                .await
+            }))
+            // The standard globals still give useful signatures if the interpreter fails to load.
+            .unwrap_or_else(|_| Globals::standard())
+    }
+
+    fn get_workspace_files(
+        &self,
+        _workspace_root: Option<&Path>,
+    ) -> anyhow::Result<Option<Vec<LspUrl>>> {
+        let dispatcher = self.server_ctx.events().dupe();
+        self.runtime
+            .block_on(with_dispatcher_async(dispatcher, async {
+                let paths = self
+                    .with_dice_ctx(async move |dice_ctx| {
+                        let cell_resolver = dice_ctx.get_cell_resolver().await?;
+                        let file_ops = dice_ctx.file_ops();
+                        // Nested cells are listed by their parent cell too.
+                        let mut paths = BTreeSet::new();
+                        for (cell, instance) in cell_resolver.cells() {
+                            let mut dirs =
+                                vec![CellPathRef::new(cell, CellRelativePath::empty()).to_owned()];
+                            while let Some(dir) = dirs.pop() {
+                                // Ignored files are already left out of the listing.
+                                let listing = file_ops.read_dir(dir.as_ref()).await?;
+                                for entry in listing.included.iter() {
+                                    let path = dir.join(&entry.file_name);
+                                    if entry.file_type.is_dir() {
+                                        dirs.push(path);
+                                    } else if entry.file_type.is_file()
+                                        && (instance.buildfiles().contains(&entry.file_name)
+                                            || matches!(
+                                                entry.file_name.extension(),
+                                                Some("bzl" | "bxl")
+                                            ))
+                                    {
+                                        paths.insert(cell_resolver.resolve_path(path.as_ref())?);
+                                    }
+                                }
+                            }
+                        }
+                        Ok(paths)
+                    })
+                    .await?;
+                Ok(Some(
+                    paths
+                        .into_iter()
+                        .map(|path| LspUrl::File(self.fs.resolve(&path).into_path_buf()))
+                        .collect(),
+                ))
+            }))
+    }
 }
 
 pub(crate) async fn run_lsp_server_command(
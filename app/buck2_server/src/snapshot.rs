@@ -140,6 +140,7 @@ impl SnapshotCollector {
         snapshot.dice_key_count = metrics.key_count as u64;
         snapshot.dice_currently_active_key_count = metrics.currently_active_key_count as u64;
         snapshot.dice_active_transaction_count = metrics.active_transaction_count;
+        snapshot.dice_evicted_key_count = metrics.evicted_key_count as u64;
     }
 
     fn add_materializer_metrics(&self, snapshot: &mut buck2_data::Snapshot) {
@@ -19,4 +19,8 @@ enum QueryCommandError {
         "query result was a set of files and one or more --output-attribute was requested, but files have not attributes"
     )]
     FileSetHasNoAttributes,
+    #[error(
+        "query result was a set of files, which has no edges to output in a graph format (try --json or the default output)"
+    )]
+    FileSetHasNoGraph,
 }
@@ -40,6 +40,9 @@ use serde::Serializer;
 
 use crate::commands::query::query_target_ext::QueryCommandTarget;
 use crate::commands::query::QueryCommandError;
+use crate::dot::graphml::GraphMl;
+use crate::dot::mermaid::Mermaid;
+use crate::dot::proto::GraphProto;
 use crate::dot::targets::DotTargetGraph;
 use crate::dot::Dot;
 use crate::dot::DotCompact;
@@ -369,6 +372,33 @@ impl<'a> QueryResultPrinter<'a> {
                         &mut output,
                     )?;
                 }
+                QueryOutputFormat::Graphml => {
+                    GraphMl::render(
+                        &DotTargetGraph {
+                            targets,
+                            attributes: self.attributes.clone(),
+                        },
+                        &mut output,
+                    )?;
+                }
+                QueryOutputFormat::Mermaid => {
+                    Mermaid::render(
+                        &DotTargetGraph {
+                            targets,
+                            attributes: self.attributes.clone(),
+                        },
+                        &mut output,
+                    )?;
+                }
+                QueryOutputFormat::Proto => {
+                    GraphProto::render(
+                        &DotTargetGraph {
+                            targets,
+                            attributes: self.attributes.clone(),
+                        },
+                        &mut output,
+                    )?;
+                }
             },
             QueryEvaluationValue::FileSet(files) => {
                 if self.attributes.is_some() {
@@ -401,6 +431,11 @@ impl<'a> QueryResultPrinter<'a> {
                     QueryOutputFormat::DotCompact => {
                         unimplemented!("dot_compact output for files not implemented yet")
                     }
+                    QueryOutputFormat::Graphml
+                    | QueryOutputFormat::Mermaid
+                    | QueryOutputFormat::Proto => {
+                        return Err(QueryCommandError::FileSetHasNoGraph.into());
+                    }
                 }
             }
         }
@@ -443,7 +478,7 @@ async fn print_action_node(
     output_attributes: &[String],
     cell_resolver: &CellResolver,
 ) -> anyhow::Result<()> {
-    // Dot/DotCompact output format don't make sense here.
+    // Graph output formats don't make sense here.
     let unstable_output_format = if json {
         QueryOutputFormat::Json
     } else {
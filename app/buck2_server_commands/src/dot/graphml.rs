@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Renders a `DotDigraph` as GraphML (see <http://graphml.graphdrawing.org/specification.html>).
+
+use std::io::Write;
+
+use starlark_map::small_set::SmallSet;
+
+use crate::dot::DotDigraph;
+use crate::dot::DotNode;
+
+pub struct GraphMl {}
+
+impl GraphMl {
+    pub fn render<'a, T: DotDigraph<'a>, W: Write>(graph: &'a T, mut w: W) -> anyhow::Result<()> {
+        // GraphML requires all the `<key>`s to be declared before the graph, but we only learn
+        // which attributes exist by visiting the nodes, so buffer the graph body.
+        let mut keys = SmallSet::new();
+        let mut body = Vec::new();
+        graph.for_each_node(|node| {
+            let attrs = node.attrs()?;
+            let id = escape_xml(&node.id());
+            if attrs.extra.is_empty() {
+                writeln!(body, "    <node id=\"{}\"/>", id)?;
+            } else {
+                writeln!(body, "    <node id=\"{}\">", id)?;
+                for (key, value) in &attrs.extra {
+                    writeln!(
+                        body,
+                        "      <data key=\"{}\">{}</data>",
+                        escape_xml(key),
+                        escape_xml(value)
+                    )?;
+                    keys.insert(key.clone());
+                }
+                writeln!(body, "    </node>")?;
+            }
+            graph.for_each_edge(node, |edge| {
+                writeln!(
+                    body,
+                    "    <edge source=\"{}\" target=\"{}\"/>",
+                    escape_xml(edge.from),
+                    escape_xml(edge.to)
+                )?;
+                Ok(())
+            })?;
+            Ok(())
+        })?;
+
+        writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            w,
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+        )?;
+        for key in keys.iter() {
+            let key = escape_xml(key);
+            writeln!(
+                w,
+                "  <key id=\"{}\" for=\"node\" attr.name=\"{}\" attr.type=\"string\"/>",
+                key, key
+            )?;
+        }
+        writeln!(
+            w,
+            "  <graph id=\"{}\" edgedefault=\"directed\">",
+            escape_xml(graph.name())
+        )?;
+        w.write_all(&body)?;
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")?;
+        Ok(())
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dot::testing::TestGraph;
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!("root//foo:bar", escape_xml("root//foo:bar"));
+        assert_eq!(
+            "a &lt;b&gt; &amp; &quot;c&quot; &apos;d&apos;",
+            escape_xml("a <b> & \"c\" 'd'")
+        );
+    }
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let graph = TestGraph::default()
+            .node(
+                "root//foo:bar",
+                &[("kind", "rule"), ("srcs", "[\"a<b>.c\"]")],
+                &["root//foo:baz", "root//qux:q&q"],
+            )
+            .node("root//foo:baz", &[("kind", "other")], &[])
+            .node("root//qux:q&q", &[], &[]);
+
+        let mut output = Vec::new();
+        GraphMl::render(&graph, &mut output)?;
+        assert_eq!(
+            [
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>",
+                "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">",
+                "  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>",
+                "  <key id=\"srcs\" for=\"node\" attr.name=\"srcs\" attr.type=\"string\"/>",
+                "  <graph id=\"test\" edgedefault=\"directed\">",
+                "    <node id=\"root//foo:bar\">",
+                "      <data key=\"kind\">rule</data>",
+                "      <data key=\"srcs\">[&quot;a&lt;b&gt;.c&quot;]</data>",
+                "    </node>",
+                "    <edge source=\"root//foo:bar\" target=\"root//foo:baz\"/>",
+                "    <edge source=\"root//foo:bar\" target=\"root//qux:q&amp;q\"/>",
+                "    <node id=\"root//foo:baz\">",
+                "      <data key=\"kind\">other</data>",
+                "    </node>",
+                "    <node id=\"root//qux:q&amp;q\"/>",
+                "  </graph>",
+                "</graphml>",
+                "",
+            ]
+            .join("\n"),
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+}
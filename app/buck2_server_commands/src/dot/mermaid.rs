@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Renders a `DotDigraph` as a Mermaid flowchart (see <https://mermaid.js.org/syntax/flowchart.html>).
+//!
+//! Mermaid node ids are restricted to simple identifiers, so nodes are numbered (like
+//! `DotCompact` does) and the target label is used as the node text. Mermaid has no notion of
+//! node attributes, so those are not rendered.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::dot::DotDigraph;
+use crate::dot::DotNode;
+
+pub struct Mermaid {}
+
+impl Mermaid {
+    pub fn render<'a, T: DotDigraph<'a>, W: Write>(graph: &'a T, mut w: W) -> anyhow::Result<()> {
+        writeln!(w, "flowchart LR")?;
+
+        let mut next_id: u32 = 0;
+        let mut lookup_numeric_id: HashMap<String, u32> = HashMap::new();
+
+        let mut name_to_number = |node_name: &str| -> u32 {
+            match lookup_numeric_id.entry(node_name.to_owned()) {
+                Entry::Vacant(entry) => {
+                    next_id += 1;
+                    entry.insert(next_id);
+                    next_id
+                }
+                Entry::Occupied(entry) => *entry.get(),
+            }
+        };
+
+        graph.for_each_node(|node| {
+            let id = node.id();
+            writeln!(w, "  n{}[\"{}\"]", name_to_number(&id), escape_label(&id))?;
+            graph.for_each_edge(node, |edge| {
+                writeln!(
+                    w,
+                    "  n{} --> n{}",
+                    name_to_number(edge.from),
+                    name_to_number(edge.to)
+                )?;
+                Ok(())
+            })?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+/// Labels are quoted, and Mermaid uses entity codes (rather than backslashes) to escape quotes.
+/// `#` starts an entity code, so it is escaped too.
+fn escape_label(value: &str) -> String {
+    value.replace('#', "#35;").replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dot::testing::TestGraph;
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!("root//foo:bar", escape_label("root//foo:bar"));
+        assert_eq!("a #quot;b#quot; #35;quot;", escape_label("a \"b\" #quot;"));
+    }
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let graph = TestGraph::default()
+            .node(
+                "root//foo:bar",
+                &[("kind", "rule")],
+                &["root//foo:baz", "root//qux:\"q\""],
+            )
+            .node("root//foo:baz", &[], &["root//qux:\"q\""])
+            .node("root//qux:\"q\"", &[], &[]);
+
+        let mut output = Vec::new();
+        Mermaid::render(&graph, &mut output)?;
+        assert_eq!(
+            "flowchart LR\n\
+             \x20 n1[\"root//foo:bar\"]\n\
+             \x20 n1 --> n2\n\
+             \x20 n1 --> n3\n\
+             \x20 n2[\"root//foo:baz\"]\n\
+             \x20 n2 --> n3\n\
+             \x20 n3[\"root//qux:#quot;q#quot;\"]\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+}
@@ -27,7 +27,12 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use starlark_map::small_map::SmallMap;
 
+pub mod graphml;
+pub mod mermaid;
+pub mod proto;
 pub mod targets;
+#[cfg(test)]
+mod testing;
 
 #[derive(Default, Debug)]
 pub struct DotNodeAttrs {
@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Renders a `DotDigraph` as a stream of length-delimited `QueryGraphNode` protobuf messages,
+//! one per node, so that consumers can process the graph without holding all of it in memory.
+
+use std::io::Write;
+
+use buck2_cli_proto::query_graph_node::Attr;
+use buck2_cli_proto::QueryGraphNode;
+use prost::Message;
+
+use crate::dot::DotDigraph;
+use crate::dot::DotNode;
+
+pub struct GraphProto {}
+
+impl GraphProto {
+    pub fn render<'a, T: DotDigraph<'a>, W: Write>(graph: &'a T, mut w: W) -> anyhow::Result<()> {
+        graph.for_each_node(|node| {
+            let attrs = node.attrs()?;
+            let mut deps = Vec::new();
+            graph.for_each_edge(node, |edge| {
+                deps.push(edge.to.to_owned());
+                Ok(())
+            })?;
+            let message = QueryGraphNode {
+                id: node.id(),
+                attrs: attrs
+                    .extra
+                    .into_iter()
+                    .map(|(name, value)| Attr { name, value })
+                    .collect(),
+                deps,
+            };
+            w.write_all(&message.encode_length_delimited_to_vec())?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dot::testing::TestGraph;
+
+    fn decode(mut output: &[u8]) -> anyhow::Result<Vec<QueryGraphNode>> {
+        let mut nodes = Vec::new();
+        while !output.is_empty() {
+            nodes.push(QueryGraphNode::decode_length_delimited(&mut output)?);
+        }
+        Ok(nodes)
+    }
+
+    fn attr(name: &str, value: &str) -> Attr {
+        Attr {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let graph = TestGraph::default()
+            .node(
+                "root//foo:bar",
+                &[("kind", "rule"), ("srcs", "[\"a.c\"]")],
+                &["root//foo:baz"],
+            )
+            .node("root//foo:baz", &[], &[]);
+
+        let mut output = Vec::new();
+        GraphProto::render(&graph, &mut output)?;
+        assert_eq!(
+            vec![
+                QueryGraphNode {
+                    id: "root//foo:bar".to_owned(),
+                    attrs: vec![attr("kind", "rule"), attr("srcs", "[\"a.c\"]")],
+                    deps: vec!["root//foo:baz".to_owned()],
+                },
+                QueryGraphNode {
+                    id: "root//foo:baz".to_owned(),
+                    attrs: Vec::new(),
+                    deps: Vec::new(),
+                },
+            ],
+            decode(&output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_does_not_escape() -> anyhow::Result<()> {
+        // Unlike the text formats, the messages hold ids and values verbatim.
+        let id = "root//foo:\"bar\" <#quot;>\n";
+        let value = "a \\ \"b\" &amp;\n";
+        let graph = TestGraph::default().node(id, &[("name", value)], &[id]);
+
+        let mut output = Vec::new();
+        GraphProto::render(&graph, &mut output)?;
+        assert_eq!(
+            vec![QueryGraphNode {
+                id: id.to_owned(),
+                attrs: vec![attr("name", value)],
+                deps: vec![id.to_owned()],
+            }],
+            decode(&output)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A small `DotDigraph` to test the renderers with.
+
+#![cfg(test)]
+
+use crate::dot::DotDigraph;
+use crate::dot::DotEdge;
+use crate::dot::DotNode;
+use crate::dot::DotNodeAttrs;
+
+pub(crate) struct TestNode {
+    id: String,
+    extra: Vec<(String, String)>,
+    deps: Vec<String>,
+}
+
+impl DotNode for TestNode {
+    fn attrs(&self) -> anyhow::Result<DotNodeAttrs> {
+        Ok(DotNodeAttrs {
+            extra: self.extra.iter().cloned().collect(),
+            ..Default::default()
+        })
+    }
+
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TestGraph {
+    nodes: Vec<TestNode>,
+}
+
+impl TestGraph {
+    pub(crate) fn node(mut self, id: &str, extra: &[(&str, &str)], deps: &[&str]) -> Self {
+        self.nodes.push(TestNode {
+            id: id.to_owned(),
+            extra: extra
+                .iter()
+                .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+                .collect(),
+            deps: deps.iter().map(|dep| (*dep).to_owned()).collect(),
+        });
+        self
+    }
+}
+
+impl<'a> DotDigraph<'a> for TestGraph {
+    type Node = TestNode;
+
+    fn name(&self) -> &str {
+        "test"
+    }
+
+    fn for_each_node<F: FnMut(&Self::Node) -> anyhow::Result<()>>(
+        &'a self,
+        mut f: F,
+    ) -> anyhow::Result<()> {
+        for node in &self.nodes {
+            f(node)?;
+        }
+        Ok(())
+    }
+
+    fn for_each_edge<F: FnMut(&DotEdge) -> anyhow::Result<()>>(
+        &'a self,
+        node: &Self::Node,
+        mut f: F,
+    ) -> anyhow::Result<()> {
+        for dep in &node.deps {
+            f(&DotEdge {
+                from: &node.id,
+                to: dep,
+            })?;
+        }
+        Ok(())
+    }
+}
@@ -108,6 +108,7 @@ async fn lint_file(
                 severity: EvalSeverity::Error,
                 problem: format!("{:#}", err.without_diagnostic()),
                 original: "".to_owned(),
+                fix: None,
             }])
         }
     }
@@ -69,6 +69,7 @@ use buck2_server_ctx::pattern::parse_patterns_from_cli_args;
 use buck2_server_ctx::template::run_server_command;
 use buck2_server_ctx::template::ServerCommandTemplate;
 use buck2_server_ctx::test_command::TEST_COMMAND;
+use buck2_test_api::data::TestCaseShard;
 use buck2_test_api::data::TestResult;
 use buck2_test_api::data::TestStatus;
 use buck2_test_api::protocol::TestExecutor;
@@ -97,6 +98,7 @@ use crate::orchestrator::BuckTestOrchestrator;
 use crate::orchestrator::ExecutorMessage;
 use crate::session::TestSession;
 use crate::session::TestSessionOptions;
+use crate::shard::TestShard;
 use crate::translations::build_configured_target_handle;
 
 #[derive(Debug, Serialize)]
@@ -319,6 +321,7 @@ async fn test(
         allow_re: options.allow_re,
         force_use_project_relative_paths: options.force_use_project_relative_paths,
         force_run_from_project_root: options.force_run_from_project_root,
+        cache_test_results: options.cache_test_results,
     });
 
     let build_opts = request
@@ -333,6 +336,12 @@ async fn test(
         .transpose()
         .context("Invalid `duration`")?;
 
+    let shard = request
+        .shard
+        .as_ref()
+        .map(TestShard::from_proto)
+        .transpose()?;
+
     let test_outcome = test_targets(
         ctx,
         resolved_pattern,
@@ -351,6 +360,7 @@ async fn test(
         build_opts.skip_incompatible_targets,
         MissingTargetBehavior::from_skip(build_opts.skip_missing_targets),
         timeout,
+        shard,
     )
     .await?;
 
@@ -425,6 +435,7 @@ async fn test_targets(
     skip_incompatible_targets: bool,
     missing_target_behavior: MissingTargetBehavior,
     timeout: Option<Duration>,
+    shard: Option<TestShard>,
 ) -> anyhow::Result<TestOutcome> {
     let session = Arc::new(session);
 
@@ -503,6 +514,7 @@ async fn test_targets(
                     cell_resolver: &cell_resolver,
                     working_dir_cell,
                     missing_target_behavior,
+                    shard: shard.as_ref(),
                 });
 
                 driver.push_pattern(
@@ -616,6 +628,10 @@ enum TestDriverTask {
     TestTarget {
         label: ConfiguredProvidersLabel,
     },
+    /// A test target that will be tested if it's part of the shard being run.
+    ShardCandidate {
+        label: ConfiguredProvidersLabel,
+    },
 }
 
 #[derive(Copy, Clone, Dupe)]
@@ -628,6 +644,7 @@ pub(crate) struct TestDriverState<'a, 'e> {
     cell_resolver: &'a CellResolver,
     working_dir_cell: CellName,
     missing_target_behavior: MissingTargetBehavior,
+    shard: Option<&'a TestShard>,
 }
 
 /// Maintains the state of an ongoing test execution.
@@ -636,6 +653,7 @@ struct TestDriver<'a, 'e> {
     work: FuturesUnordered<BoxFuture<'a, anyhow::Result<Vec<TestDriverTask>>>>,
     labels_configured: HashSet<(ProvidersLabel, bool)>,
     labels_tested: HashSet<ConfiguredProvidersLabel>,
+    shard_candidates: Vec<ConfiguredProvidersLabel>,
     build_errors: Vec<buck2_error::Error>,
 }
 
@@ -646,6 +664,7 @@ impl<'a, 'e> TestDriver<'a, 'e> {
             work: FuturesUnordered::new(),
             labels_configured: HashSet::new(),
             labels_tested: HashSet::new(),
+            shard_candidates: Vec::new(),
             build_errors: Vec::new(),
         }
     }
@@ -670,6 +689,32 @@ impl<'a, 'e> TestDriver<'a, 'e> {
 
     /// Drive the test loop until all work is complete.
     async fn drive_to_completion(&mut self) {
+        self.drive_work().await;
+
+        // When sharding, the test targets are only collected while resolving the patterns. Now
+        // that they are all known, test the ones that belong to this shard.
+        if let Some(shard) = self.state.shard {
+            let candidates = std::mem::take(&mut self.shard_candidates);
+            let total = candidates.len();
+            let selected = shard.select(candidates);
+            let split = selected
+                .iter()
+                .filter(|(_, case_shard)| case_shard.is_some())
+                .count();
+            console_message(format!(
+                "Running {} of {} test targets in this shard ({} split by test case between all shards)",
+                selected.len(),
+                total,
+                split,
+            ));
+            for (label, case_shard) in selected {
+                self.spawn_test(label, case_shard);
+            }
+            self.drive_work().await;
+        }
+    }
+
+    async fn drive_work(&mut self) {
         while let Some(tasks) = self.work.next().await {
             match tasks {
                 Ok(tasks) => {
@@ -688,6 +733,9 @@ impl<'a, 'e> TestDriver<'a, 'e> {
                             TestDriverTask::TestTarget { label } => {
                                 self.test_target(label);
                             }
+                            TestDriverTask::ShardCandidate { label } => {
+                                self.shard_candidates.push(label);
+                            }
                         }
                     }
                 }
@@ -789,11 +837,45 @@ impl<'a, 'e> TestDriver<'a, 'e> {
             return;
         }
 
+        if self.state.shard.is_some() {
+            self.shard_candidate(label);
+        } else {
+            self.spawn_test(label, None);
+        }
+    }
+
+    /// Analyze a target to find out whether it's a test, so that only tests are balanced across
+    /// shards. Nothing is built until the shards are known. Targets that aren't tests are dropped
+    /// here: `buck2 test` only analyzes them, sharded or not, and every shard already did that.
+    fn shard_candidate(&mut self, label: ConfiguredProvidersLabel) {
+        let state = self.state;
+        let fut = async move {
+            let frozen_providers = state
+                .ctx
+                .get_providers(&label)
+                .await?
+                .require_compatible()?;
+            let is_test =
+                <dyn TestProvider>::from_collection(frozen_providers.provider_collection())
+                    .is_some();
+            anyhow::Ok(if is_test {
+                vec![TestDriverTask::ShardCandidate { label }]
+            } else {
+                vec![]
+            })
+        }
+        .boxed();
+
+        self.work.push(fut);
+    }
+
+    fn spawn_test(&mut self, label: ConfiguredProvidersLabel, case_shard: Option<TestCaseShard>) {
         let state = self.state;
         let fut = async move {
             test_target(
                 state.ctx,
                 label,
+                case_shard,
                 state.test_executor.dupe(),
                 state.session,
                 state.label_filtering.dupe(),
@@ -848,6 +930,7 @@ fn spec_to_targets(
 async fn test_target(
     ctx: &DiceComputations,
     target: ConfiguredProvidersLabel,
+    case_shard: Option<TestCaseShard>,
     test_executor: Arc<dyn TestExecutor + '_>,
     session: &TestSession,
     label_filtering: Arc<TestLabelFiltering>,
@@ -869,6 +952,7 @@ async fn test_target(
             run_tests(
                 test_executor,
                 target,
+                case_shard,
                 test_info,
                 session,
                 cell_resolver,
@@ -942,6 +1026,7 @@ async fn build_artifacts(
 fn run_tests<'a, 'b>(
     test_executor: Arc<dyn TestExecutor + 'a>,
     providers_label: ConfiguredProvidersLabel,
+    case_shard: Option<TestCaseShard>,
     test_info: &'b dyn TestProvider,
     session: &'b TestSession,
     cell_resolver: &'b CellResolver,
@@ -952,7 +1037,7 @@ fn run_tests<'a, 'b>(
 
     match maybe_handle {
         Ok(handle) => {
-            let fut = test_info.dispatch(handle, test_executor, working_dir_cell);
+            let fut = test_info.dispatch(handle, test_executor, working_dir_cell, case_shard);
 
             (async move {
                 fut.await
@@ -11,6 +11,7 @@
 
 use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::ops::ControlFlow;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -65,6 +66,7 @@ use buck2_execute::artifact::fs::ExecutorFs;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::digest_config::HasDigestConfig;
 use buck2_execute::execute::blocking::HasBlockingExecutor;
+use buck2_execute::execute::cache_uploader::CacheUploadInfo;
 use buck2_execute::execute::cache_uploader::NoOpCacheUploader;
 use buck2_execute::execute::claim::MutexClaimManager;
 use buck2_execute::execute::command_executor::CommandExecutor;
@@ -221,8 +223,19 @@ impl<'a> BuckTestOrchestrator<'a> {
         let fs = self.dice.get_artifact_fs().await?;
 
         let test_info = self.get_test_info(&test_target).await?;
+        // Tests that use local resources depend on state that isn't part of their inputs, so
+        // their results are never cached.
+        let allow_caching = self.session.options().cache_test_results
+            && test_info.allow_result_caching()
+            && required_local_resources.resources.is_empty();
         let test_executor = self
-            .get_test_executor(&test_target, &test_info, executor_override, &fs)
+            .get_test_executor(
+                &test_target,
+                &test_info,
+                executor_override,
+                allow_caching,
+                &fs,
+            )
             .await?;
         let test_executable_expanded = self
             .expand_test_executable(
@@ -287,7 +300,13 @@ impl<'a> BuckTestOrchestrator<'a> {
             .await?;
 
         let (stdout, stderr, status, timing, execution_kind, outputs) = self
-            .execute_request(&test_target, metadata, &test_executor, execution_request)
+            .execute_request(
+                &test_target,
+                metadata,
+                &test_executor,
+                execution_request,
+                allow_caching,
+            )
             .await?;
 
         self.require_alive().await?;
@@ -442,7 +461,7 @@ impl<'a> TestOrchestrator for BuckTestOrchestrator<'a> {
         let test_info = self.get_test_info(&test_target).await?;
         // Tests are not run, so there is no executor override.
         let executor = self
-            .get_test_executor(&test_target, &test_info, None, &fs)
+            .get_test_executor(&test_target, &test_info, None, false, &fs)
             .await?;
         let test_executable_expanded = self
             .expand_test_executable(
@@ -482,7 +501,7 @@ impl<'a> TestOrchestrator for BuckTestOrchestrator<'a> {
         let materializer = self.dice.per_transaction_data().get_materializer();
         let blocking_executor = self.dice.get_blocking_executor();
 
-        materialize_inputs(&fs, materializer.as_ref(), &execution_request).await?;
+        materialize_inputs(&fs, materializer.as_ref(), &execution_request, None).await?;
 
         create_output_dirs(
             &fs,
@@ -525,13 +544,15 @@ impl<'b> BuckTestOrchestrator<'b> {
         Ok(executor_preference)
     }
 
-    /// Core request execution logic.
+    /// Core request execution logic. If `allow_caching` is set, a cached result is used if there is
+    /// one, and a passing result is uploaded to the cache.
     async fn execute_request(
         &self,
         test_target: &ConfiguredProvidersLabel,
         metadata: DisplayMetadata,
         executor: &CommandExecutor,
         request: CommandExecutionRequest,
+        allow_caching: bool,
     ) -> Result<
         (
             ExecutionStream,
@@ -564,16 +585,53 @@ impl<'b> BuckTestOrchestrator<'b> {
             action_key_suffix,
         };
 
-        // For test execution, we currently do not do any cache queries
-
         let prepared_action = executor.prepare_action(&request, self.digest_config)?;
         let prepared_command = PreparedCommand {
             target: &test_target as _,
             request: &request,
             prepared_action: &prepared_action,
             digest_config: self.digest_config,
+            scratch_path_suffix: None,
+        };
+        let command = async {
+            // Without caching, the executor's cache checker and uploader are no-ops.
+            let manager = match executor
+                .action_cache(manager, &prepared_command, self.cancellations)
+                .await
+            {
+                ControlFlow::Break(result) => return result,
+                ControlFlow::Continue(manager) => manager,
+            };
+
+            let mut result = executor
+                .exec_cmd(manager, &prepared_command, self.cancellations)
+                .await;
+
+            // Only passing results are cached: failures are always re-run.
+            if allow_caching && result.was_success() {
+                let upload = executor
+                    .cache_upload(
+                        &CacheUploadInfo {
+                            target: &test_target as _,
+                            digest_config: self.digest_config,
+                        },
+                        &result,
+                        None,
+                        &prepared_action.action_and_blobs,
+                    )
+                    .await;
+                match upload {
+                    Ok(upload) => result.did_cache_upload = upload.did_cache_upload,
+                    Err(e) => tracing::warn!(
+                        "Error uploading test result of `{}` to the cache: {:#}",
+                        test_target.re_action_key(),
+                        e
+                    ),
+                }
+            }
+
+            result
         };
-        let command = executor.exec_cmd(manager, &prepared_command, self.cancellations);
 
         // instrument execution with a span.
         // TODO(brasselsprouts): migrate this into the executor to get better accuracy.
@@ -710,6 +768,7 @@ impl<'b> BuckTestOrchestrator<'b> {
         fs: &ArtifactFs,
         test_target_node: &ConfiguredTargetNode,
         executor_override: Option<&CommandExecutorConfig>,
+        allow_caching: bool,
     ) -> anyhow::Result<CommandExecutor> {
         let executor_config = match executor_override {
             Some(o) => o,
@@ -722,17 +781,24 @@ impl<'b> BuckTestOrchestrator<'b> {
         let CommandExecutorResponse {
             executor,
             platform,
-            cache_checker: _,
-            cache_uploader: _,
+            cache_checker,
+            cache_uploader,
         } = self
             .dice
             .get_command_executor_from_dice(executor_config)
             .await?;
+        let (cache_checker, cache_uploader) = if allow_caching {
+            (cache_checker, cache_uploader)
+        } else {
+            (
+                Arc::new(NoOpCommandOptionalExecutor {}) as _,
+                Arc::new(NoOpCacheUploader {}) as _,
+            )
+        };
         let executor = CommandExecutor::new(
             executor,
-            // Caching is not enabled for tests yet. Use the NoOp
-            Arc::new(NoOpCommandOptionalExecutor {}),
-            Arc::new(NoOpCacheUploader {}),
+            cache_checker,
+            cache_uploader,
             fs.clone(),
             executor_config.options,
             platform,
@@ -789,6 +855,7 @@ impl<'b> BuckTestOrchestrator<'b> {
         test_target: &ConfiguredProvidersLabel,
         test_info: &FrozenExternalRunnerTestInfo,
         executor_override: Option<ExecutorConfigOverride>,
+        allow_caching: bool,
         fs: &ArtifactFs,
     ) -> anyhow::Result<CommandExecutor> {
         // NOTE: get_providers() implicitly calls this already but it's not the end of the world
@@ -819,6 +886,7 @@ impl<'b> BuckTestOrchestrator<'b> {
             fs,
             &node,
             resolved_executor_override.as_ref().map(|a| &***a),
+            allow_caching,
         )
         .await
         .context("Error constructing CommandExecutor")
@@ -1048,6 +1116,7 @@ impl<'b> BuckTestOrchestrator<'b> {
             request: &context.execution_request,
             prepared_action: &prepared_action,
             digest_config,
+            scratch_path_suffix: None,
         };
         let command = executor.exec_cmd(manager, &prepared_command, cancellations);
 
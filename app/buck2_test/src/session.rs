@@ -25,6 +25,8 @@ pub struct TestSessionOptions {
     pub allow_re: bool,
     pub force_use_project_relative_paths: bool,
     pub force_run_from_project_root: bool,
+    /// Whether passing test results may be served from, and uploaded to, the action cache.
+    pub cache_test_results: bool,
 }
 
 /// The state of a buck2 test command.
@@ -0,0 +1,256 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Support for `buck2 test --shard N/M`: the test targets are partitioned into `M` shards, and
+//! only the targets of shard `N` are built and tested. Every shard resolves the same targets, and
+//! the partition only depends on those targets (and their historical durations, if provided), so
+//! every CI worker running one shard agrees on the partition without coordinating.
+//!
+//! A target that takes longer than a shard should is not assigned to any one shard: it is tested
+//! in all of them, and the test executor is asked to split its test cases between them. That is
+//! only done when the durations are known and there are at least as many targets as shards:
+//! otherwise whole targets are assigned to shards by hash.
+
+use std::collections::HashMap;
+
+use buck2_core::provider::label::ConfiguredProvidersLabel;
+use buck2_test_api::data::TestCaseShard;
+
+#[derive(Debug, buck2_error::Error)]
+enum TestShardError {
+    #[error("Invalid shard `{}/{}`: the shard count must be at least 1", .index + 1, .count)]
+    InvalidCount { index: u32, count: u32 },
+    #[error("Invalid shard `{}/{}`: the shard must be between 1 and {}", .index + 1, .count, .count)]
+    InvalidIndex { index: u32, count: u32 },
+}
+
+pub(crate) struct TestShard {
+    /// Zero-based index of the shard to run.
+    index: usize,
+    count: usize,
+    /// Historical durations of test targets, keyed by unconfigured target label.
+    target_durations: HashMap<String, f64>,
+}
+
+impl TestShard {
+    pub(crate) fn from_proto(shard: &buck2_cli_proto::TestShard) -> anyhow::Result<Self> {
+        let buck2_cli_proto::TestShard {
+            index,
+            count,
+            target_durations,
+        } = shard;
+
+        if *count == 0 {
+            return Err(TestShardError::InvalidCount {
+                index: *index,
+                count: *count,
+            }
+            .into());
+        }
+        if index >= count {
+            return Err(TestShardError::InvalidIndex {
+                index: *index,
+                count: *count,
+            }
+            .into());
+        }
+
+        Ok(Self {
+            index: *index as usize,
+            count: *count as usize,
+            target_durations: target_durations.clone(),
+        })
+    }
+
+    /// Keep the test targets that belong to this shard, along with the share of their test cases
+    /// to run if they are split between all the shards.
+    pub(crate) fn select(
+        &self,
+        labels: Vec<ConfiguredProvidersLabel>,
+    ) -> Vec<(ConfiguredProvidersLabel, Option<TestCaseShard>)> {
+        let weighted = labels
+            .into_iter()
+            .map(|label| {
+                let duration = self
+                    .target_durations
+                    .get(&label.target().unconfigured().to_string())
+                    .copied();
+                // Targets are resolved concurrently, so they come in no particular order: the full
+                // label is what makes the partition deterministic.
+                (label.to_string(), duration, label)
+            })
+            .collect::<Vec<_>>();
+
+        let durations = weighted
+            .iter()
+            .map(|(key, duration, _)| (key.as_str(), *duration))
+            .collect::<Vec<_>>();
+        let shards = assign_shards(&durations, self.count);
+
+        let case_shard = TestCaseShard {
+            index: self.index as u32,
+            count: self.count as u32,
+        };
+
+        weighted
+            .into_iter()
+            .zip(shards)
+            .filter_map(|((_, _, label), shard)| match shard {
+                Some(shard) if shard == self.index => Some((label, None)),
+                Some(_) => None,
+                None => Some((label, Some(case_shard))),
+            })
+            .collect()
+    }
+}
+
+/// Assign each item, given its historical duration if known, to one of `count` shards. Returns
+/// `None` for the items to split between all the shards.
+///
+/// Without any history, or with fewer items than shards, every item would look heavier than a
+/// balanced shard, and splitting them all gains nothing: whole items are assigned by hash.
+fn assign_shards(items: &[(&str, Option<f64>)], count: usize) -> Vec<Option<usize>> {
+    let known = items
+        .iter()
+        .filter_map(|(_, duration)| *duration)
+        .collect::<Vec<_>>();
+    if known.is_empty() || items.len() < count {
+        return items
+            .iter()
+            .map(|(key, _)| Some(shard_by_hash(key, count)))
+            .collect();
+    }
+
+    // Items we have no history for (e.g. new tests) are assumed to take as long as an average one.
+    let default_weight = known.iter().sum::<f64>() / known.len() as f64;
+    let weights = items
+        .iter()
+        .map(|(key, duration)| (*key, duration.unwrap_or(default_weight)))
+        .collect::<Vec<_>>();
+    partition(&weights, count)
+}
+
+/// FNV-1a, which unlike the std hasher is stable across builds, so that every worker agrees.
+fn shard_by_hash(key: &str, count: usize) -> usize {
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    (hash % count as u64) as usize
+}
+
+/// Assign each item to one of `count` shards, balancing the total weight of every shard: items are
+/// assigned from heaviest to lightest, each to the currently lightest shard. Ties are broken by
+/// key and by shard index, so the result does not depend on the order of `items`.
+///
+/// Items heavier than a balanced shard (the total weight divided by `count`) can't fit in one:
+/// they are split between all the shards instead, and get `None`. Returns the shard of every item.
+fn partition(items: &[(&str, f64)], count: usize) -> Vec<Option<usize>> {
+    let ideal_load = items.iter().map(|(_, weight)| weight).sum::<f64>() / count as f64;
+
+    let mut order = (0..items.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| {
+        let (key_a, weight_a) = items[*a];
+        let (key_b, weight_b) = items[*b];
+        weight_b.total_cmp(&weight_a).then(key_a.cmp(key_b))
+    });
+
+    let mut loads = vec![0.0f64; count];
+    let mut shards = vec![None; items.len()];
+    for i in order {
+        if count > 1 && items[i].1 > ideal_load {
+            continue;
+        }
+        let (lightest, _) = loads
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+        loads[lightest] += items[i].1;
+        shards[i] = Some(lightest);
+    }
+
+    shards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_balances_weights() {
+        let items = [("a", 10.0), ("b", 1.0), ("c", 4.0), ("d", 5.0), ("e", 1.0)];
+        let shards = partition(&items, 2);
+
+        let mut loads = [0.0; 2];
+        for ((_, weight), shard) in items.iter().zip(&shards) {
+            loads[shard.unwrap()] += weight;
+        }
+        assert_eq!(loads, [11.0, 10.0]);
+    }
+
+    #[test]
+    fn test_partition_is_independent_of_order() {
+        let items = [("a", 1.0), ("b", 1.0), ("c", 1.0), ("d", 1.0), ("e", 1.0)];
+        let reversed = items.iter().rev().copied().collect::<Vec<_>>();
+
+        let shards = partition(&items, 3);
+        let mut reversed_shards = partition(&reversed, 3);
+        reversed_shards.reverse();
+
+        assert_eq!(shards, reversed_shards);
+        assert_eq!(shards.iter().filter(|s| **s == Some(0)).count(), 2);
+    }
+
+    #[test]
+    fn test_partition_splits_heavy_items() {
+        let items = [("a", 10.0), ("b", 1.0), ("c", 2.0), ("d", 3.0)];
+        // A balanced shard takes 8, so `a` is split and the rest is balanced without it.
+        assert_eq!(partition(&items, 2), vec![None, Some(1), Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn test_assign_more_shards_than_items() {
+        let shards = assign_shards(&[("a", Some(1.0)), ("b", Some(2.0))], 4);
+        assert_eq!(
+            shards,
+            vec![Some(shard_by_hash("a", 4)), Some(shard_by_hash("b", 4))]
+        );
+    }
+
+    #[test]
+    fn test_assign_without_history() {
+        let items = [("a", None), ("b", None), ("c", None)];
+        let shards = assign_shards(&items, 2);
+        assert!(shards.iter().all(|s| s.map_or(false, |s| s < 2)));
+        assert_eq!(shards, assign_shards(&items, 2));
+    }
+
+    #[test]
+    fn test_assign_splits_heavy_items_with_history() {
+        let items = [
+            ("a", Some(10.0)),
+            ("b", Some(1.0)),
+            ("c", None),
+            ("d", Some(3.0)),
+        ];
+        // `c` is assumed to take the average, 14/3, so a balanced shard takes about 9.3.
+        assert_eq!(
+            assign_shards(&items, 2),
+            vec![None, Some(1), Some(0), Some(1)]
+        );
+    }
+
+    #[test]
+    fn test_partition_single_shard() {
+        assert_eq!(
+            partition(&[("a", 10.0), ("b", 1.0)], 1),
+            vec![Some(0), Some(0)]
+        );
+    }
+}
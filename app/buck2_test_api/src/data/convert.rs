@@ -34,6 +34,7 @@ use crate::data::ExecutorConfigOverride;
 use crate::data::ExternalRunnerSpec;
 use crate::data::ExternalRunnerSpecValue;
 use crate::data::Output;
+use crate::data::TestCaseShard;
 use crate::data::TestExecutable;
 use crate::data::TestResult;
 use crate::data::TestStatus;
@@ -298,6 +299,8 @@ impl TryFrom<buck2_test_proto::ExternalRunnerSpec> for ExternalRunnerSpec {
             contacts,
             oncall,
             working_dir_cell,
+            executor_overrides,
+            case_shard,
         } = s;
 
         Ok(Self {
@@ -317,6 +320,8 @@ impl TryFrom<buck2_test_proto::ExternalRunnerSpec> for ExternalRunnerSpec {
             contacts,
             oncall,
             working_dir_cell: CellName::unchecked_new(&working_dir_cell)?,
+            executor_overrides,
+            case_shard: case_shard.map(|s| s.into()),
         })
     }
 }
@@ -334,6 +339,8 @@ impl TryInto<buck2_test_proto::ExternalRunnerSpec> for ExternalRunnerSpec {
             contacts,
             oncall,
             working_dir_cell,
+            executor_overrides,
+            case_shard,
         } = self;
         Ok(buck2_test_proto::ExternalRunnerSpec {
             target: Some(target.try_into().context("Invalid `target`")?),
@@ -349,6 +356,8 @@ impl TryInto<buck2_test_proto::ExternalRunnerSpec> for ExternalRunnerSpec {
             contacts,
             oncall,
             working_dir_cell: working_dir_cell.as_str().to_owned(),
+            executor_overrides,
+            case_shard: case_shard.map(|s| s.into()),
         })
     }
 }
@@ -417,6 +426,24 @@ impl From<buck2_test_proto::ExecutorConfigOverride> for ExecutorConfigOverride {
     }
 }
 
+impl From<TestCaseShard> for buck2_test_proto::TestCaseShard {
+    fn from(s: TestCaseShard) -> Self {
+        Self {
+            index: s.index,
+            count: s.count,
+        }
+    }
+}
+
+impl From<buck2_test_proto::TestCaseShard> for TestCaseShard {
+    fn from(s: buck2_test_proto::TestCaseShard) -> Self {
+        Self {
+            index: s.index,
+            count: s.count,
+        }
+    }
+}
+
 impl From<LocalResourceType> for buck2_test_proto::LocalResourceType {
     fn from(r: LocalResourceType) -> Self {
         Self {
@@ -870,6 +897,8 @@ mod tests {
             contacts: vec!["contact1".to_owned(), "contact2".to_owned()],
             oncall: Some("contact1".to_owned()),
             working_dir_cell: CellName::testing_new("qux"),
+            executor_overrides: vec!["listing".to_owned()],
+            case_shard: Some(TestCaseShard { index: 1, count: 3 }),
         };
         assert_roundtrips::<buck2_test_proto::ExternalRunnerSpec, ExternalRunnerSpec>(&test_spec);
     }
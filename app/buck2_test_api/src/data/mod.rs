@@ -135,6 +135,20 @@ pub struct ExternalRunnerSpec {
     pub oncall: Option<String>,
     /// Cell of current working directory for test command.
     pub working_dir_cell: CellName,
+    /// Names of the executor overrides defined on the rule.
+    pub executor_overrides: Vec<String>,
+    /// Set when the test cases of this target are split between the shards of a sharded run
+    /// (`buck2 test --shard`): the target is sent to every shard, and each runs its own share of
+    /// the test cases.
+    pub case_shard: Option<TestCaseShard>,
+}
+
+/// The share of the test cases of a target that the test executor should run.
+#[derive(Clone, Copy, Debug, Dupe, Eq, Hash, PartialEq)]
+pub struct TestCaseShard {
+    /// Zero-based index of this shard.
+    pub index: u32,
+    pub count: u32,
 }
 
 /// Command line argument or environment variable value
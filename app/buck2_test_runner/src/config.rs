@@ -31,6 +31,27 @@ pub struct Config {
     /// Available as a workaround for when test features are available.
     #[clap(long, multiple = true, allow_hyphen_values = true)]
     pub test_arg: Vec<String>,
+
+    /// Run each test target as a single test, rather than listing its test cases and reporting
+    /// them individually.
+    #[clap(long)]
+    pub no_listing: bool,
+
+    /// Max number of test cases to run in a single invocation of a test binary. Use 1 to run
+    /// every test case on its own.
+    #[clap(long, default_value = "50", parse(try_from_str=try_parse_batch_size_from_str))]
+    pub batch_size: usize,
+
+    /// Number of times to retry a failing test case. A test case that passes on a retry is
+    /// reported as flaky.
+    #[clap(long, default_value = "2")]
+    pub max_retries: usize,
+
+    /// Run tests with the executor override of this name (see `executor_overrides` on
+    /// `ExternalRunnerTestInfo`). Test targets that don't define it run with their default
+    /// executor.
+    #[clap(long)]
+    pub executor_override: Option<String>,
 }
 
 /// Uiltity that can be used to parse Env values from CLI arguments.
@@ -66,6 +87,16 @@ pub enum EnvValueParseError {
     IncorrectSyntax(String),
 }
 
+fn try_parse_batch_size_from_str(input: &str) -> anyhow::Result<usize> {
+    let batch_size = input
+        .parse()
+        .context("Could not parse provided batch size")?;
+    if batch_size == 0 {
+        return Err(anyhow::anyhow!("Batch size must be at least 1"));
+    }
+    Ok(batch_size)
+}
+
 fn try_parse_timeout_from_str(input: &str) -> anyhow::Result<Duration> {
     let seconds = input.parse().context("Could not parse provided timeout")?;
     Ok(Duration::from_secs(seconds))
@@ -0,0 +1,351 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Knowledge of the test frameworks whose binaries we know how to list test cases from, run
+//! subsets of test cases with, and read per-case results from.
+
+use std::collections::HashMap;
+
+use buck2_test_api::data::TestStatus;
+
+/// A test framework, as identified by the `type` of the `ExternalRunnerTestInfo`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestFramework {
+    /// GoogleTest, used by `cxx_test`.
+    Gtest,
+    /// The Rust `libtest` harness, used by `rust_test`.
+    LibTest,
+    /// The unittest-based `__test_main__` from the prelude, used by `python_test`.
+    PyUnit,
+    /// pytest, for `python_test` targets that override their `type`.
+    Pytest,
+    /// Buck's JUnit runner, used by `java_test`.
+    JUnit,
+}
+
+impl TestFramework {
+    pub fn from_test_type(test_type: &str) -> Option<Self> {
+        match test_type {
+            "gtest" => Some(Self::Gtest),
+            "rust" => Some(Self::LibTest),
+            "pyunit" => Some(Self::PyUnit),
+            "pytest" => Some(Self::Pytest),
+            "junit" => Some(Self::JUnit),
+            _ => None,
+        }
+    }
+
+    /// Arguments that make the test binary print its test cases instead of running them.
+    pub fn list_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            Self::Gtest => &["--gtest_list_tests"],
+            Self::LibTest => &["--list", "--format", "terse"],
+            Self::PyUnit => &["--list-tests", "--list-format", "buck"],
+            Self::Pytest => &["--collect-only", "-q"],
+            Self::JUnit => &["--dry-run"],
+        };
+        args.iter().map(|a| (*a).to_owned()).collect()
+    }
+
+    /// Parse the output of running the test binary with `list_args`.
+    pub fn parse_listing(self, stdout: &str) -> Vec<String> {
+        match self {
+            Self::Gtest => parse_gtest_listing(stdout),
+            Self::LibTest => stdout
+                .lines()
+                .filter_map(|l| l.strip_suffix(": test"))
+                .map(|l| l.to_owned())
+                .collect(),
+            Self::PyUnit => stdout
+                .lines()
+                .map(|l| l.trim())
+                .filter(|l| l.contains('#'))
+                .map(|l| l.to_owned())
+                .collect(),
+            Self::Pytest => stdout
+                .lines()
+                .map(|l| l.trim())
+                .filter(|l| l.contains("::"))
+                .map(|l| l.to_owned())
+                .collect(),
+            Self::JUnit => parse_junit_testcases(stdout)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+
+    /// Arguments that make the test binary run only these test cases.
+    pub fn run_args(self, cases: &[String]) -> Vec<String> {
+        match self {
+            Self::Gtest => vec![format!("--gtest_filter={}", cases.join(":"))],
+            Self::LibTest => std::iter::once("--exact".to_owned())
+                .chain(cases.iter().cloned())
+                .collect(),
+            // `__test_main__` loads tests by their Python name: `module.Class.method`.
+            Self::PyUnit => cases.iter().map(|c| c.replace('#', ".")).collect(),
+            Self::Pytest => std::iter::once("-v".to_owned())
+                .chain(cases.iter().cloned())
+                .collect(),
+            Self::JUnit => vec!["--test-selectors".to_owned(), cases.join("\n")],
+        }
+    }
+
+    /// Find per-case results in the output of running the test binary with `run_args`. Cases
+    /// that don't appear in the output aren't returned.
+    pub fn parse_results(self, stdout: &str, stderr: &str) -> HashMap<String, TestStatus> {
+        match self {
+            Self::Gtest => parse_gtest_results(stdout),
+            Self::LibTest => parse_libtest_results(stdout),
+            // unittest reports results on stderr.
+            Self::PyUnit => parse_pyunit_results(stderr),
+            Self::Pytest => parse_pytest_results(stdout),
+            Self::JUnit => parse_junit_testcases(stdout).into_iter().collect(),
+        }
+    }
+}
+
+/// Strip a trailing `# GetParam() = ...` style comment from a line of gtest output.
+fn strip_gtest_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn parse_gtest_listing(stdout: &str) -> Vec<String> {
+    let mut cases = Vec::new();
+    let mut suite = None;
+
+    for line in stdout.lines() {
+        if let Some(name) = line.strip_prefix("  ") {
+            if let Some(suite) = suite {
+                cases.push(format!("{}{}", suite, strip_gtest_comment(name).trim()));
+            }
+        } else {
+            // Suites end with a `.`, anything else is unrelated output.
+            suite = Some(strip_gtest_comment(line).trim()).filter(|s| s.ends_with('.'));
+        }
+    }
+
+    cases
+}
+
+fn parse_gtest_results(stdout: &str) -> HashMap<String, TestStatus> {
+    let mut results = HashMap::new();
+
+    for line in stdout.lines() {
+        let (status, rest) = if let Some(rest) = line.strip_prefix("[       OK ] ") {
+            (TestStatus::PASS, rest)
+        } else if let Some(rest) = line.strip_prefix("[  FAILED  ] ") {
+            (TestStatus::FAIL, rest)
+        } else if let Some(rest) = line.strip_prefix("[  SKIPPED ] ") {
+            (TestStatus::SKIP, rest)
+        } else {
+            continue;
+        };
+        // Names are followed by the duration, e.g. `Suite.Test (3 ms)`.
+        let name = rest.split(" (").next().unwrap_or(rest).trim();
+        results.insert(name.to_owned(), status);
+    }
+
+    results
+}
+
+fn parse_libtest_results(stdout: &str) -> HashMap<String, TestStatus> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let (name, outcome) = line.strip_prefix("test ")?.split_once(" ... ")?;
+            let status = if outcome == "ok" {
+                TestStatus::PASS
+            } else if outcome == "FAILED" {
+                TestStatus::FAIL
+            } else if outcome.starts_with("ignored") {
+                TestStatus::SKIP
+            } else {
+                return None;
+            };
+            Some((name.to_owned(), status))
+        })
+        .collect()
+}
+
+fn parse_pyunit_results(stderr: &str) -> HashMap<String, TestStatus> {
+    stderr
+        .lines()
+        .filter_map(|line| {
+            // `test_method (module.Class) ... ok`, or since Python 3.11
+            // `test_method (module.Class.test_method) ... ok`.
+            let (test, outcome) = line.split_once(" ... ")?;
+            let (method, class) = test.split_once(" (")?;
+            let class = class.strip_suffix(')')?;
+            let class = class
+                .strip_suffix(method)
+                .and_then(|c| c.strip_suffix('.'))
+                .unwrap_or(class);
+            let status = if outcome == "ok" || outcome == "expected failure" {
+                TestStatus::PASS
+            } else if outcome == "FAIL" || outcome == "ERROR" || outcome == "unexpected success" {
+                TestStatus::FAIL
+            } else if outcome.starts_with("skipped") {
+                TestStatus::SKIP
+            } else {
+                return None;
+            };
+            Some((format!("{}#{}", class, method), status))
+        })
+        .collect()
+}
+
+fn parse_pytest_results(stdout: &str) -> HashMap<String, TestStatus> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            // `path/to/test.py::test_name PASSED [ 50%]`
+            let mut parts = line.split_whitespace();
+            let name = parts.next().filter(|n| n.contains("::"))?;
+            let status = match parts.next()? {
+                "PASSED" | "XFAIL" => TestStatus::PASS,
+                "FAILED" | "ERROR" | "XPASS" => TestStatus::FAIL,
+                "SKIPPED" => TestStatus::SKIP,
+                _ => return None,
+            };
+            Some((name.to_owned(), status))
+        })
+        .collect()
+}
+
+/// Find the `<testcase>` elements in the XML report written by Buck's JUnit runner, as
+/// `Class#method`, with the result they record.
+fn parse_junit_testcases(stdout: &str) -> Vec<(String, TestStatus)> {
+    fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+        let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+        let len = tag[start..].find('"')?;
+        Some(&tag[start..start + len])
+    }
+
+    let mut cases = Vec::new();
+    let mut rest = stdout;
+
+    while let Some(start) = rest.find("<testcase ") {
+        rest = &rest[start..];
+        let tag_end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..tag_end];
+        // The body of the element, if it has one.
+        let body = if tag.ends_with('/') {
+            ""
+        } else {
+            let body = &rest[tag_end..];
+            &body[..body.find("</testcase>").unwrap_or(body.len())]
+        };
+
+        if let (Some(class), Some(method)) = (attribute(tag, "classname"), attribute(tag, "name")) {
+            let status = if body.contains("<failure") || body.contains("<error") {
+                TestStatus::FAIL
+            } else if body.contains("<skipped") {
+                TestStatus::SKIP
+            } else {
+                TestStatus::PASS
+            };
+            cases.push((format!("{}#{}", class, method), status));
+        }
+
+        rest = &rest[tag_end..];
+    }
+
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gtest() {
+        let listing = "Running main() from gtest_main.cc\n\
+                       Math.\n  Add\n  Sub\n\
+                       Param/Math.  # TypeParam = int\n  Mul/0  # GetParam() = 1\n";
+        assert_eq!(
+            TestFramework::Gtest.parse_listing(listing),
+            vec!["Math.Add", "Math.Sub", "Param/Math.Mul/0"]
+        );
+
+        let results = TestFramework::Gtest.parse_results(
+            "[ RUN      ] Math.Add\n[       OK ] Math.Add (0 ms)\n\
+             [ RUN      ] Math.Sub\n[  FAILED  ] Math.Sub (1 ms)\n",
+            "",
+        );
+        assert_eq!(results.get("Math.Add"), Some(&TestStatus::PASS));
+        assert_eq!(results.get("Math.Sub"), Some(&TestStatus::FAIL));
+    }
+
+    #[test]
+    fn test_libtest() {
+        assert_eq!(
+            TestFramework::LibTest.parse_listing("a::b: test\nc: benchmark\nd: test\n"),
+            vec!["a::b", "d"]
+        );
+
+        let results = TestFramework::LibTest.parse_results(
+            "running 3 tests\ntest a::b ... ok\ntest d ... FAILED\ntest e ... ignored, slow\n",
+            "",
+        );
+        assert_eq!(results.get("a::b"), Some(&TestStatus::PASS));
+        assert_eq!(results.get("d"), Some(&TestStatus::FAIL));
+        assert_eq!(results.get("e"), Some(&TestStatus::SKIP));
+    }
+
+    #[test]
+    fn test_pyunit() {
+        assert_eq!(
+            TestFramework::PyUnit.run_args(&["mod.Class#test_a".to_owned()]),
+            vec!["mod.Class.test_a"]
+        );
+
+        let results = TestFramework::PyUnit.parse_results(
+            "",
+            "test_a (mod.Class) ... ok\ntest_b (mod.Class.test_b) ... FAIL\n",
+        );
+        assert_eq!(results.get("mod.Class#test_a"), Some(&TestStatus::PASS));
+        assert_eq!(results.get("mod.Class#test_b"), Some(&TestStatus::FAIL));
+    }
+
+    #[test]
+    fn test_pytest() {
+        assert_eq!(
+            TestFramework::Pytest
+                .parse_listing("t.py::test_a\nt.py::C::test_b\n\n2 tests collected in 0.01s\n"),
+            vec!["t.py::test_a", "t.py::C::test_b"]
+        );
+
+        let results = TestFramework::Pytest.parse_results(
+            "t.py::test_a PASSED [ 50%]\nt.py::C::test_b FAILED [100%]\n",
+            "",
+        );
+        assert_eq!(results.get("t.py::test_a"), Some(&TestStatus::PASS));
+        assert_eq!(results.get("t.py::C::test_b"), Some(&TestStatus::FAIL));
+    }
+
+    #[test]
+    fn test_junit() {
+        let report = r#"<testsuite name="Foo">
+            <testcase classname="com.Foo" name="testA" time="0"/>
+            <testcase classname="com.Foo" name="testB" time="0"><failure message="x"/></testcase>
+            </testsuite>"#;
+        assert_eq!(
+            TestFramework::JUnit.parse_listing(report),
+            vec!["com.Foo#testA", "com.Foo#testB"]
+        );
+
+        let results = TestFramework::JUnit.parse_results(report, "");
+        assert_eq!(results.get("com.Foo#testA"), Some(&TestStatus::PASS));
+        assert_eq!(results.get("com.Foo#testB"), Some(&TestStatus::FAIL));
+    }
+}
@@ -12,6 +12,7 @@
 
 mod config;
 mod executor;
+mod framework;
 mod runner;
 mod service;
 pub mod tcp;
@@ -7,37 +7,57 @@
  * of this source tree.
  */
 
+use std::borrow::Cow;
+use std::time::Duration;
+
 use anyhow::Context;
+use buck2_data::command_execution_kind::Command;
 use buck2_test_api::data::ArgValue;
 use buck2_test_api::data::ArgValueContent;
-use buck2_test_api::data::ConfiguredTargetHandle;
 use buck2_test_api::data::DisplayMetadata;
 use buck2_test_api::data::ExecuteResponse;
 use buck2_test_api::data::ExecutionResult2;
 use buck2_test_api::data::ExecutionStatus;
+use buck2_test_api::data::ExecutionStream;
+use buck2_test_api::data::ExecutorConfigOverride;
 use buck2_test_api::data::ExternalRunnerSpec;
 use buck2_test_api::data::ExternalRunnerSpecValue;
 use buck2_test_api::data::RequiredLocalResources;
+use buck2_test_api::data::TestCaseShard;
 use buck2_test_api::data::TestResult;
 use buck2_test_api::data::TestStatus;
 use buck2_test_api::grpc::TestOrchestratorClient;
 use clap::Parser;
 use futures::channel::mpsc::UnboundedReceiver;
+use futures::future;
 use futures::StreamExt;
 use host_sharing::HostSharingRequirements;
 use parking_lot::Mutex;
 
 use crate::config::Config;
 use crate::config::EnvValue;
+use crate::framework::TestFramework;
 
 pub type SpecReceiver = UnboundedReceiver<ExternalRunnerSpec>;
 
+/// Name of the executor override the prelude defines for listing test cases, when a test sets
+/// `listing_capabilities` in its `remote_execution` properties.
+const LISTING_EXECUTOR_OVERRIDE: &str = "listing";
+
 /// Internal test runner implementation for Buck2.
 ///
 /// This is a basic test runner intended to be used by the open-source Buck2 build
 /// if no external test runner is provided. This ensures that `buck2 test` works
 /// out-of-the-box for open-source users.
 ///
+/// For test frameworks it knows about (see `TestFramework`), it lists the test cases of each
+/// target, runs them in batches, reports them individually, and retries failing ones. Other
+/// test targets run as a single test.
+///
+/// When the test cases of a target are split between the shards of a sharded run (see
+/// `case_shard` on `ExternalRunnerSpec`), it only runs the listed test cases of its own shard.
+/// Targets it can't list run in the first shard only.
+///
 /// **This is intended for open-source use only.**
 pub struct Buck2TestRunner {
     orchestrator_client: TestOrchestratorClient,
@@ -45,6 +65,15 @@ pub struct Buck2TestRunner {
     config: Config,
 }
 
+/// The outcome of one run of a test case.
+struct CaseAttempt {
+    status: TestStatus,
+    duration: Option<Duration>,
+    details: String,
+    /// Whether the result was replayed from the cache rather than obtained by running the test.
+    cached: bool,
+}
+
 impl Buck2TestRunner {
     pub fn new(
         orchestrator_client: TestOrchestratorClient,
@@ -70,44 +99,17 @@ impl Buck2TestRunner {
         }
         let run_verdict = receiver
             .map(async move |spec| {
-                let name = format!(
-                    "{}//{}:{}",
-                    spec.target.cell, spec.target.package, spec.target.target
-                );
-                let target_handle = spec.target.handle.to_owned();
-
-                let execution_response = self
-                    .execute_test_from_spec(spec)
-                    .await
-                    .expect("Test execution request failed");
-
-                let execution_result = match execution_response {
-                    ExecuteResponse::Result(r) => r,
-                    ExecuteResponse::Cancelled => return TestStatus::OMITTED,
-                };
-
-                let test_result = get_test_result(name, target_handle, execution_result);
-                let test_status = test_result.status.clone();
-
-                self.report_test_result(test_result)
+                self.run_spec(spec)
                     .await
-                    .expect("Test result reporting failed");
-
-                test_status
+                    .expect("Test execution request failed")
             })
             // Use an arbitrarily large buffer -- execution throttling will be handled by the Buck2
             // executor, so no need to hold back on requests here.
             .buffer_unordered(10000)
             // If any individual test failed, consider the entire run to have failed.
-            .fold(
-                RunVerdict::Pass,
-                async move |mut run_verdict, test_status| {
-                    if test_status != TestStatus::PASS {
-                        run_verdict = RunVerdict::Fail;
-                    }
-                    run_verdict
-                },
-            )
+            .fold(RunVerdict::Pass, async move |run_verdict, verdict| {
+                run_verdict.and(verdict)
+            })
             .await;
 
         self.orchestrator_client
@@ -115,52 +117,322 @@ impl Buck2TestRunner {
             .await
     }
 
-    async fn execute_test_from_spec(
+    async fn run_spec(&self, spec: ExternalRunnerSpec) -> anyhow::Result<RunVerdict> {
+        let framework = if self.config.no_listing {
+            None
+        } else {
+            TestFramework::from_test_type(&spec.test_type)
+        };
+
+        match framework {
+            Some(framework) => self.run_test_cases(framework, &spec).await,
+            None => self.run_target(&spec).await,
+        }
+    }
+
+    /// Run the whole test target as a single test.
+    async fn run_target(&self, spec: &ExternalRunnerSpec) -> anyhow::Result<RunVerdict> {
+        // A target whose test cases can't be listed can't be split: the first shard runs it whole.
+        if let Some(shard) = spec.case_shard.filter(|shard| shard.index != 0) {
+            self.report_test_result(TestResult {
+                target: spec.target.handle,
+                name: target_name(spec),
+                status: TestStatus::SKIP,
+                msg: Some(format!(
+                    "Run by shard 1/{}, as its test cases can't be listed",
+                    shard.count
+                )),
+                duration: None,
+                details: String::new(),
+            })
+            .await?;
+            return Ok(RunVerdict::Pass);
+        }
+
+        let display_metadata = DisplayMetadata::Testing {
+            suite: spec.target.target.clone(),
+            testcases: Vec::new(),
+        };
+
+        let execution_result = match self.execute(spec, display_metadata, Vec::new()).await? {
+            ExecuteResponse::Result(r) => r,
+            ExecuteResponse::Cancelled => return Ok(RunVerdict::Fail),
+        };
+
+        let test_result = TestResult {
+            target: spec.target.handle,
+            name: target_name(spec),
+            status: execution_status(&execution_result),
+            msg: cached_msg(is_cached(&execution_result)),
+            duration: Some(execution_result.execution_time),
+            details: execution_details(&execution_result),
+        };
+        let verdict = RunVerdict::from_status(&test_result.status);
+        self.report_test_result(test_result).await?;
+
+        Ok(verdict)
+    }
+
+    /// List the test cases of the target, and run them in batches.
+    async fn run_test_cases(
+        &self,
+        framework: TestFramework,
+        spec: &ExternalRunnerSpec,
+    ) -> anyhow::Result<RunVerdict> {
+        let suite = spec.target.target.clone();
+
+        let listing = match self
+            .execute(
+                spec,
+                DisplayMetadata::Listing(suite.clone()),
+                framework.list_args(),
+            )
+            .await?
+        {
+            ExecuteResponse::Result(r) => r,
+            ExecuteResponse::Cancelled => return Ok(RunVerdict::Fail),
+        };
+
+        let listing_status = match execution_status(&listing) {
+            TestStatus::PASS => TestStatus::LISTING_SUCCESS,
+            _ => TestStatus::LISTING_FAILED,
+        };
+        let cases = if listing_status == TestStatus::LISTING_SUCCESS {
+            framework.parse_listing(&stream_to_string(&listing.stdout))
+        } else {
+            Vec::new()
+        };
+
+        self.report_test_result(TestResult {
+            target: spec.target.handle,
+            name: target_name(spec),
+            status: listing_status.clone(),
+            msg: None,
+            duration: Some(listing.execution_time),
+            details: execution_details(&listing),
+        })
+        .await?;
+
+        if listing_status == TestStatus::LISTING_FAILED {
+            return Ok(RunVerdict::Fail);
+        }
+
+        // The binary might not actually be using the framework its type suggests: just run it.
+        if cases.is_empty() {
+            return self.run_target(spec).await;
+        }
+
+        let cases = match spec.case_shard {
+            Some(shard) => shard_cases(cases, shard),
+            None => cases,
+        };
+        if cases.is_empty() {
+            return Ok(RunVerdict::Pass);
+        }
+
+        self.orchestrator_client
+            .report_tests_discovered(spec.target.handle, suite, cases.clone())
+            .await?;
+
+        let verdicts = future::try_join_all(
+            cases
+                .chunks(self.config.batch_size)
+                .map(|batch| self.run_batch(framework, spec, batch.to_vec())),
+        )
+        .await?;
+
+        Ok(verdicts.into_iter().fold(RunVerdict::Pass, RunVerdict::and))
+    }
+
+    /// Run a batch of test cases in a single invocation of the test binary. Cases whose result
+    /// can't be told apart from the output run again on their own.
+    async fn run_batch(
+        &self,
+        framework: TestFramework,
+        spec: &ExternalRunnerSpec,
+        cases: Vec<String>,
+    ) -> anyhow::Result<RunVerdict> {
+        let execution_result = match self.execute_cases(framework, spec, &cases).await? {
+            ExecuteResponse::Result(r) => r,
+            ExecuteResponse::Cancelled => return Ok(RunVerdict::Fail),
+        };
+
+        let parsed = framework.parse_results(
+            &stream_to_string(&execution_result.stdout),
+            &stream_to_string(&execution_result.stderr),
+        );
+        let batch_status = execution_status(&execution_result);
+        let details = execution_details(&execution_result);
+        let cached = is_cached(&execution_result);
+        // Durations are only meaningful for cases that ran on their own.
+        let duration = (cases.len() == 1).then_some(execution_result.execution_time);
+
+        let mut verdict = RunVerdict::Pass;
+        for case in &cases {
+            let attempt = match parsed.get(case) {
+                Some(status) => Some(CaseAttempt {
+                    status: status.clone(),
+                    duration,
+                    details: details.clone(),
+                    cached,
+                }),
+                None if cases.len() == 1 || batch_status == TestStatus::PASS => Some(CaseAttempt {
+                    status: batch_status.clone(),
+                    duration,
+                    details: details.clone(),
+                    cached,
+                }),
+                // The batch failed, and we don't know whether this case is responsible.
+                None => None,
+            };
+
+            verdict = verdict.and(self.run_case(framework, spec, case, attempt).await?);
+        }
+
+        Ok(verdict)
+    }
+
+    /// Report the result of a test case, running it first if needed, and retrying it if it
+    /// fails.
+    async fn run_case(
+        &self,
+        framework: TestFramework,
+        spec: &ExternalRunnerSpec,
+        case: &str,
+        attempt: Option<CaseAttempt>,
+    ) -> anyhow::Result<RunVerdict> {
+        let name = format!("{} - {}", target_name(spec), case);
+
+        let mut attempt = match attempt {
+            Some(attempt) => attempt,
+            None => match self.run_single_case(framework, spec, case).await? {
+                Some(attempt) => attempt,
+                None => return Ok(RunVerdict::Fail),
+            },
+        };
+
+        let mut retries = 0;
+        while is_failure(&attempt.status) && retries < self.config.max_retries {
+            self.report_test_result(TestResult {
+                target: spec.target.handle,
+                name: name.clone(),
+                status: TestStatus::RERUN,
+                msg: None,
+                duration: attempt.duration,
+                details: attempt.details,
+            })
+            .await?;
+
+            retries += 1;
+            attempt = match self.run_single_case(framework, spec, case).await? {
+                Some(attempt) => attempt,
+                None => return Ok(RunVerdict::Fail),
+            };
+        }
+
+        let msg = if retries > 0 && !is_failure(&attempt.status) {
+            Some(format!("Flaky: passed after {} failed attempt(s)", retries))
+        } else {
+            cached_msg(attempt.cached)
+        };
+        let verdict = RunVerdict::from_status(&attempt.status);
+
+        self.report_test_result(TestResult {
+            target: spec.target.handle,
+            name,
+            status: attempt.status,
+            msg,
+            duration: attempt.duration,
+            details: attempt.details,
+        })
+        .await?;
+
+        Ok(verdict)
+    }
+
+    /// Run a single test case. Returns `None` if the execution was cancelled.
+    async fn run_single_case(
+        &self,
+        framework: TestFramework,
+        spec: &ExternalRunnerSpec,
+        case: &str,
+    ) -> anyhow::Result<Option<CaseAttempt>> {
+        let cases = [case.to_owned()];
+        let execution_result = match self.execute_cases(framework, spec, &cases).await? {
+            ExecuteResponse::Result(r) => r,
+            ExecuteResponse::Cancelled => return Ok(None),
+        };
+
+        let status = framework
+            .parse_results(
+                &stream_to_string(&execution_result.stdout),
+                &stream_to_string(&execution_result.stderr),
+            )
+            .remove(case)
+            .unwrap_or_else(|| execution_status(&execution_result));
+
+        Ok(Some(CaseAttempt {
+            status,
+            duration: Some(execution_result.execution_time),
+            details: execution_details(&execution_result),
+            cached: is_cached(&execution_result),
+        }))
+    }
+
+    async fn execute_cases(
         &self,
-        spec: ExternalRunnerSpec,
+        framework: TestFramework,
+        spec: &ExternalRunnerSpec,
+        cases: &[String],
     ) -> anyhow::Result<ExecuteResponse> {
         let display_metadata = DisplayMetadata::Testing {
-            suite: spec.target.target,
-            testcases: Vec::new(),
+            suite: spec.target.target.clone(),
+            testcases: cases.to_vec(),
         };
 
-        let config_args = self.config.test_arg.iter().map(|arg| ArgValue {
+        self.execute(spec, display_metadata, framework.run_args(cases))
+            .await
+    }
+
+    /// Run the test binary, with the `extra_args` added to its command line.
+    async fn execute(
+        &self,
+        spec: &ExternalRunnerSpec,
+        display_metadata: DisplayMetadata,
+        extra_args: Vec<String>,
+    ) -> anyhow::Result<ExecuteResponse> {
+        let verbatim = |arg: String| ArgValue {
             content: ArgValueContent::ExternalRunnerSpecValue(ExternalRunnerSpecValue::Verbatim(
-                arg.to_owned(),
+                arg,
             )),
             format: None,
-        });
+        };
 
         let command = spec
             .command
-            .into_iter()
+            .iter()
             .map(|spec_value| ArgValue {
-                content: ArgValueContent::ExternalRunnerSpecValue(spec_value),
+                content: ArgValueContent::ExternalRunnerSpecValue(spec_value.clone()),
                 format: None,
             })
-            .chain(config_args)
+            .chain(self.config.test_arg.iter().cloned().map(verbatim))
+            .chain(extra_args.into_iter().map(verbatim))
             .collect();
 
-        let config_env = self.config.env.iter().map(|EnvValue { name, value }| {
-            (
-                name.to_owned(),
-                ArgValue {
-                    content: ArgValueContent::ExternalRunnerSpecValue(
-                        ExternalRunnerSpecValue::Verbatim(value.to_owned()),
-                    ),
-                    format: None,
-                },
-            )
-        });
+        let config_env = self
+            .config
+            .env
+            .iter()
+            .map(|EnvValue { name, value }| (name.to_owned(), verbatim(value.to_owned())));
 
         let env = spec
             .env
-            .into_iter()
+            .iter()
             .map(|(key, value)| {
                 (
-                    key,
+                    key.clone(),
                     ArgValue {
-                        content: ArgValueContent::ExternalRunnerSpecValue(value),
+                        content: ArgValueContent::ExternalRunnerSpecValue(value.clone()),
                         format: None,
                     },
                 )
@@ -171,7 +443,7 @@ impl Buck2TestRunner {
         let target_handle = spec.target.handle;
         let host_sharing_requirements = HostSharingRequirements::default();
         let pre_create_dirs = Vec::new();
-        let executor_override = None;
+        let executor_override = self.executor_override(spec, &display_metadata);
 
         self.orchestrator_client
             .execute2(
@@ -188,6 +460,26 @@ impl Buck2TestRunner {
             .await
     }
 
+    /// Pick the executor override to run a command with, if the test defines it. Without one, the
+    /// command runs with the test's default executor.
+    fn executor_override(
+        &self,
+        spec: &ExternalRunnerSpec,
+        display_metadata: &DisplayMetadata,
+    ) -> Option<ExecutorConfigOverride> {
+        let name = match display_metadata {
+            DisplayMetadata::Listing(_) => LISTING_EXECUTOR_OVERRIDE,
+            DisplayMetadata::Testing { .. } => self.config.executor_override.as_deref()?,
+        };
+
+        spec.executor_overrides
+            .iter()
+            .any(|o| o == name)
+            .then(|| ExecutorConfigOverride {
+                name: name.to_owned(),
+            })
+    }
+
     async fn report_test_result(&self, test_result: TestResult) -> anyhow::Result<()> {
         self.orchestrator_client
             .report_test_result(test_result)
@@ -195,31 +487,73 @@ impl Buck2TestRunner {
     }
 }
 
-fn get_test_result(
-    name: String,
-    target: ConfiguredTargetHandle,
-    execution_result: ExecutionResult2,
-) -> TestResult {
-    let status = match execution_result.status {
+/// Keep the test cases of a shard. Every shard lists the same cases in the same order, so taking
+/// every `count`-th case gives every shard a disjoint share of about the same size.
+fn shard_cases(cases: Vec<String>, shard: TestCaseShard) -> Vec<String> {
+    cases
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| *i % shard.count as usize == shard.index as usize)
+        .map(|(_, case)| case)
+        .collect()
+}
+
+fn target_name(spec: &ExternalRunnerSpec) -> String {
+    format!(
+        "{}//{}:{}",
+        spec.target.cell, spec.target.package, spec.target.target
+    )
+}
+
+fn stream_to_string(stream: &ExecutionStream) -> Cow<'_, str> {
+    match stream {
+        ExecutionStream::Inline(d) => String::from_utf8_lossy(d),
+    }
+}
+
+fn execution_status(execution_result: &ExecutionResult2) -> TestStatus {
+    match execution_result.status {
         ExecutionStatus::Finished { exitcode } => match exitcode {
             0 => TestStatus::PASS,
             _ => TestStatus::FAIL,
         },
         ExecutionStatus::TimedOut { .. } => TestStatus::TIMEOUT,
-    };
-    TestResult {
-        target,
-        name,
-        status,
-        msg: None,
-        duration: Some(execution_result.execution_time),
-        details: format!(
-            "---- STDOUT ----\n{:?}\n---- STDERR ----\n{:?}\n",
-            execution_result.stdout, execution_result.stderr
-        ),
     }
 }
 
+fn execution_details(execution_result: &ExecutionResult2) -> String {
+    format!(
+        "---- STDOUT ----\n{:?}\n---- STDERR ----\n{:?}\n",
+        execution_result.stdout, execution_result.stderr
+    )
+}
+
+/// Whether the result was served by the action cache (when test result caching is enabled),
+/// rather than by running the test.
+fn is_cached(execution_result: &ExecutionResult2) -> bool {
+    match execution_result
+        .execution_details
+        .execution_kind
+        .as_ref()
+        .and_then(|k| k.command.as_ref())
+    {
+        Some(Command::RemoteCommand(remote)) => remote.cache_hit,
+        Some(Command::LocalActionCacheCommand(_)) => true,
+        _ => false,
+    }
+}
+
+fn cached_msg(cached: bool) -> Option<String> {
+    cached.then(|| "Cached".to_owned())
+}
+
+fn is_failure(status: &TestStatus) -> bool {
+    matches!(
+        status,
+        TestStatus::FAIL | TestStatus::TIMEOUT | TestStatus::FATAL
+    )
+}
+
 #[derive(Debug)]
 enum RunVerdict {
     Pass,
@@ -227,6 +561,20 @@ enum RunVerdict {
 }
 
 impl RunVerdict {
+    fn from_status(status: &TestStatus) -> Self {
+        match status {
+            TestStatus::PASS | TestStatus::SKIP | TestStatus::LISTING_SUCCESS => RunVerdict::Pass,
+            _ => RunVerdict::Fail,
+        }
+    }
+
+    fn and(self, other: RunVerdict) -> Self {
+        match (self, other) {
+            (RunVerdict::Pass, RunVerdict::Pass) => RunVerdict::Pass,
+            _ => RunVerdict::Fail,
+        }
+    }
+
     fn exit_code(&self) -> i32 {
         match self {
             RunVerdict::Pass => 0,
@@ -234,3 +582,17 @@ impl RunVerdict {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_cases() {
+        let cases = ["a", "b", "c", "d", "e"].map(str::to_owned).to_vec();
+        let shard = |index| shard_cases(cases.clone(), TestCaseShard { index, count: 2 });
+
+        assert_eq!(shard(0), vec!["a", "c", "e"]);
+        assert_eq!(shard(1), vec!["b", "d"]);
+    }
+}
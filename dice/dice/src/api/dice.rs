@@ -186,6 +186,7 @@
 //! ```
 
 use std::fmt::Debug;
+use std::io::Read;
 use std::io::Write;
 use std::sync::Arc;
 
@@ -194,8 +195,11 @@ use futures::future::Future;
 use serde::Serializer;
 
 use crate::api::cycles::DetectCycles;
+use crate::api::eviction::EvictionPolicy;
+use crate::api::persistence::PersistentKey;
 use crate::api::transaction::DiceTransactionUpdater;
 use crate::api::user_data::UserComputationData;
+use crate::metrics::EvictionStats;
 use crate::metrics::Metrics;
 use crate::DiceDataBuilderImpl;
 use crate::DiceImplementation;
@@ -273,6 +277,26 @@ impl Dice {
     pub async fn is_idle(&self) -> bool {
         self.implementation.is_idle().await
     }
+
+    /// Writes out the values of `PersistentKey`s that are up to date at the current version,
+    /// returning how many were written. Only supported by modern DICE.
+    pub fn save_graph(&self, out: &mut dyn Write) -> anyhow::Result<usize> {
+        self.implementation.save_graph(out)
+    }
+
+    /// Loads a graph written by `save_graph`, returning how many values were loaded. The loaded
+    /// values are treated as up to date, so the caller must then invalidate everything that
+    /// changed since the graph was saved. This must be called before DICE is used.
+    pub fn load_graph(&self, input: &mut dyn Read) -> anyhow::Result<usize> {
+        self.implementation.load_graph(input)
+    }
+
+    /// Evicts nodes from the graph according to the given policy, rather than the one DICE was
+    /// built with. Returns `None` without evicting anything if there are active transactions.
+    /// Only supported by modern DICE.
+    pub async fn evict(&self, policy: EvictionPolicy) -> anyhow::Result<Option<EvictionStats>> {
+        self.implementation.evict(policy).await
+    }
 }
 
 pub struct DiceDataBuilder(DiceDataBuilderImpl);
@@ -282,6 +306,16 @@ impl DiceDataBuilder {
         self.0.set(val);
     }
 
+    /// Allow values of `K` to be saved with `Dice::save_graph`.
+    pub fn register_persistent_key<K: PersistentKey>(&mut self) {
+        self.0.register_persistent_key::<K>();
+    }
+
+    /// Evict nodes from the graph according to `policy` whenever DICE becomes idle.
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.0.set_eviction_policy(policy);
+    }
+
     pub fn build(self, detect_cycles: DetectCycles) -> Arc<Dice> {
         self.0.build(detect_cycles)
     }
@@ -0,0 +1,34 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use allocative::Allocative;
+use dupe::Dupe;
+
+/// Which nodes DICE evicts from its graph, so that a long running DICE does not hold onto every
+/// value it has ever computed. Evicted values are computed again if they are requested again.
+///
+/// A node is only evicted along with every node that depends on it, and nodes holding injected
+/// values are never evicted. Nodes are only evicted while there are no active transactions.
+///
+/// With the default policy, nothing is evicted.
+#[derive(Allocative, Clone, Copy, Dupe, Debug, Default, PartialEq, Eq)]
+pub struct EvictionPolicy {
+    /// Evict nodes that have not been used in this many versions.
+    pub max_unused_versions: Option<usize>,
+    /// Evict the least recently used nodes while the estimated size of the computed values in
+    /// the graph exceeds this many bytes. Data that values share through e.g. `Arc`s is not
+    /// counted.
+    pub memory_budget: Option<usize>,
+}
+
+impl EvictionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_unused_versions.is_some() || self.memory_budget.is_some()
+    }
+}
@@ -16,9 +16,11 @@ pub mod data;
 pub mod dice;
 pub mod error;
 pub mod events;
+pub mod eviction;
 pub mod injected;
 pub mod key;
 pub mod opaque;
+pub mod persistence;
 pub mod projection;
 pub mod storage_type;
 pub mod transaction;
@@ -0,0 +1,35 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use crate::api::data::DiceData;
+use crate::api::key::Key;
+
+/// A `Key` whose computed values can be written out with `Dice::save_graph`, and loaded into the
+/// DICE of a later process with `Dice::load_graph`, so that it does not have to compute them
+/// again. Key types must be registered with `DiceDataBuilder::register_persistent_key`.
+///
+/// A value is only saved if every key it was computed from is persistent as well, so that a
+/// change to any of them still invalidates the loaded value.
+pub trait PersistentKey: Key {
+    /// Identifies this key type in saved graphs. It must be unique among the registered key
+    /// types, and must be changed whenever the encoding of the key or value changes.
+    const NAME: &'static str;
+
+    fn key_to_bytes(&self) -> Vec<u8>;
+
+    fn key_from_bytes(bytes: &[u8]) -> anyhow::Result<Self>;
+
+    /// Returns `None` if this particular value should not be saved.
+    fn value_to_bytes(value: &Self::Value) -> Option<Vec<u8>>;
+
+    /// `data` is the global data of the DICE loading the value, for values that must be checked
+    /// against the environment of the new process. Returning an error skips the value, along with
+    /// everything computed from it.
+    fn value_from_bytes(bytes: &[u8], data: &DiceData) -> anyhow::Result<Self::Value>;
+}
@@ -0,0 +1,310 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Evicting nodes that haven't been used in a while from the graph, so that a long running DICE
+//! doesn't hold onto every value it has ever computed.
+//!
+//! Invalidations only propagate through the nodes that are in the graph, so a node is only ever
+//! evicted along with all of its transitive rdeps. Otherwise, a change to the deps of an evicted
+//! node would never reach the rdeps that were kept, and they would keep their stale values.
+
+use std::borrow::Cow;
+use std::cmp;
+
+use allocative::Allocative;
+
+use crate::api::eviction::EvictionPolicy;
+use crate::impls::core::graph::storage::VersionedGraph;
+use crate::impls::key::DiceKey;
+use crate::impls::value::DiceValidValue;
+use crate::metrics::EvictionStats;
+use crate::versions::VersionNumber;
+use crate::HashMap;
+use crate::HashSet;
+
+/// Tracks when each node was last used and how large its value is, to decide what to evict.
+#[derive(Allocative)]
+pub(super) struct NodeUsage {
+    policy: EvictionPolicy,
+    last_used: HashMap<DiceKey, LastUse>,
+    /// The estimated size of the latest value of each computed node. Only tracked when the policy
+    /// has a memory budget, as estimating the size requires traversing the data the value owns.
+    sizes: HashMap<DiceKey, usize>,
+    total_size: usize,
+    next_use: u64,
+    last_eviction: VersionNumber,
+    evicted_key_count: usize,
+    eviction_count: usize,
+}
+
+#[derive(Allocative, Clone, Copy)]
+struct LastUse {
+    version: VersionNumber,
+    /// Increases with every use, to order the uses within a version.
+    order: u64,
+}
+
+impl LastUse {
+    /// For nodes that were never used since they were added to the graph, e.g. loaded ones.
+    const NEVER: LastUse = LastUse {
+        version: VersionNumber::ZERO,
+        order: 0,
+    };
+}
+
+impl NodeUsage {
+    pub(super) fn new(policy: EvictionPolicy) -> Self {
+        Self {
+            policy,
+            last_used: HashMap::default(),
+            sizes: HashMap::default(),
+            total_size: 0,
+            next_use: 1,
+            last_eviction: VersionNumber::ZERO,
+            evicted_key_count: 0,
+            eviction_count: 0,
+        }
+    }
+
+    pub(super) fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    pub(super) fn evicted_key_count(&self) -> usize {
+        self.evicted_key_count
+    }
+
+    pub(super) fn eviction_count(&self) -> usize {
+        self.eviction_count
+    }
+
+    fn tracks_sizes(&self) -> bool {
+        self.policy.memory_budget.is_some()
+    }
+
+    fn last_use(&self, key: DiceKey) -> LastUse {
+        self.last_used.get(&key).copied().unwrap_or(LastUse::NEVER)
+    }
+
+    pub(super) fn used(&mut self, key: DiceKey, v: VersionNumber) {
+        let order = self.next_use;
+        self.next_use += 1;
+
+        let last_use = self.last_used.entry(key).or_insert(LastUse::NEVER);
+        last_use.version = cmp::max(last_use.version, v);
+        last_use.order = order;
+    }
+
+    pub(super) fn computed(&mut self, key: DiceKey, v: VersionNumber, value: &DiceValidValue) {
+        self.used(key, v);
+
+        if self.tracks_sizes() {
+            let size = estimated_size(value);
+            let previous = self.sizes.insert(key, size).unwrap_or(0);
+            self.total_size = self.total_size - previous + size;
+        }
+    }
+
+    /// Forget everything, for when the whole graph is dropped.
+    pub(super) fn clear(&mut self) {
+        self.last_used.clear();
+        self.sizes.clear();
+        self.total_size = 0;
+    }
+
+    /// Whether the policy calls for evicting nodes at the current version. Finding the unused
+    /// nodes requires going through the whole graph, so that is only done once every
+    /// `max_unused_versions` versions.
+    pub(super) fn should_evict(&self, current: VersionNumber) -> bool {
+        if current <= self.last_eviction {
+            return false;
+        }
+
+        self.policy
+            .max_unused_versions
+            .map_or(false, |max_unused_versions| {
+                current - self.last_eviction >= cmp::max(max_unused_versions, 1) as isize
+            })
+            || self
+                .policy
+                .memory_budget
+                .map_or(false, |budget| self.total_size > budget)
+    }
+
+    /// Evicts nodes from the graph according to the given policy, which need not be the one
+    /// usage is being tracked for. There must be no active transactions.
+    pub(super) fn evict(
+        &mut self,
+        graph: &mut VersionedGraph,
+        current: VersionNumber,
+        policy: &EvictionPolicy,
+    ) -> EvictionStats {
+        let mut evicted = match policy.max_unused_versions {
+            Some(max_unused_versions) => self.unused(graph, current, max_unused_versions),
+            None => HashSet::default(),
+        };
+
+        let remaining_size = policy.memory_budget.map(|budget| {
+            let sizes = if self.tracks_sizes() {
+                Cow::Borrowed(&self.sizes)
+            } else {
+                Cow::Owned(estimated_sizes(graph))
+            };
+            self.least_recently_used(graph, &sizes, budget, &mut evicted)
+        });
+
+        graph.evict(&evicted);
+        for key in &evicted {
+            self.last_used.remove(key);
+            if let Some(size) = self.sizes.remove(key) {
+                self.total_size -= size;
+            }
+        }
+
+        self.last_eviction = current;
+        self.evicted_key_count += evicted.len();
+        self.eviction_count += 1;
+
+        EvictionStats {
+            evicted_key_count: evicted.len(),
+            remaining_key_count: graph.last_n.len(),
+            remaining_size,
+        }
+    }
+
+    /// The nodes that weren't used in the last `max_unused_versions` versions, and don't have
+    /// any rdeps that were.
+    fn unused(
+        &self,
+        graph: &VersionedGraph,
+        current: VersionNumber,
+        max_unused_versions: usize,
+    ) -> HashSet<DiceKey> {
+        let mut unused: HashSet<DiceKey> = graph
+            .last_n
+            .keys()
+            .copied()
+            .filter(|key| {
+                graph.is_evictable(*key)
+                    && current - self.last_use(*key).version > max_unused_versions as isize
+            })
+            .collect();
+
+        // An unused node must be kept if any of its rdeps are, which in turn keeps its deps.
+        let mut kept = Vec::new();
+        let mut unused_deps: HashMap<DiceKey, Vec<DiceKey>> = HashMap::default();
+        for key in &unused {
+            for rdep in graph.rdeps(*key) {
+                if unused.contains(&rdep) {
+                    unused_deps.entry(rdep).or_default().push(*key);
+                } else {
+                    kept.push(*key);
+                }
+            }
+        }
+
+        while let Some(key) = kept.pop() {
+            if unused.remove(&key) {
+                kept.extend(unused_deps.remove(&key).into_iter().flatten());
+            }
+        }
+
+        unused
+    }
+
+    /// Adds the least recently used nodes to `evicted`, along with their rdeps, until the size of
+    /// the remaining values is within the budget. Returns the size of the remaining values.
+    fn least_recently_used(
+        &self,
+        graph: &VersionedGraph,
+        sizes: &HashMap<DiceKey, usize>,
+        budget: usize,
+        evicted: &mut HashSet<DiceKey>,
+    ) -> usize {
+        let size_of = |key: &DiceKey| sizes.get(key).copied().unwrap_or(0);
+
+        let mut size: usize = sizes
+            .keys()
+            .filter(|key| !evicted.contains(*key))
+            .map(size_of)
+            .sum();
+        if size <= budget {
+            return size;
+        }
+
+        let mut candidates: Vec<(u64, DiceKey)> = graph
+            .last_n
+            .keys()
+            .copied()
+            .filter(|key| !evicted.contains(key) && graph.is_evictable(*key))
+            .map(|key| (self.last_use(key).order, key))
+            .collect();
+        candidates.sort_unstable();
+
+        for (_, key) in candidates {
+            if size <= budget {
+                break;
+            }
+
+            if let Some(nodes) = with_rdeps(graph, key, evicted) {
+                for key in nodes {
+                    size = size.saturating_sub(size_of(&key));
+                    evicted.insert(key);
+                }
+            }
+        }
+
+        size
+    }
+}
+
+/// The key along with its transitive rdeps that weren't evicted already, or `None` if any of them
+/// can't be evicted.
+fn with_rdeps(
+    graph: &VersionedGraph,
+    key: DiceKey,
+    evicted: &HashSet<DiceKey>,
+) -> Option<HashSet<DiceKey>> {
+    let mut nodes = HashSet::default();
+    let mut queue = vec![key];
+
+    while let Some(key) = queue.pop() {
+        if evicted.contains(&key) || !nodes.insert(key) {
+            continue;
+        }
+        if !graph.is_evictable(key) {
+            return None;
+        }
+        queue.extend(graph.rdeps(key));
+    }
+
+    Some(nodes)
+}
+
+/// This runs on the state thread for every computed value, so it only counts the data the value
+/// owns: data behind shared pointers could be held by other values as well, and evicting this one
+/// would not free it.
+fn estimated_size(value: &DiceValidValue) -> usize {
+    value.shallow_size()
+}
+
+fn estimated_sizes(graph: &VersionedGraph) -> HashMap<DiceKey, usize> {
+    graph
+        .last_n
+        .iter()
+        .filter(|(key, _)| graph.is_evictable(**key))
+        .filter_map(|(key, versioned)| {
+            let latest = versioned
+                .values()
+                .filter_map(|node| node.unpack_occupied())
+                .last()?;
+            Some((*key, estimated_size(latest.val())))
+        })
+        .collect()
+}
@@ -82,4 +82,8 @@ impl VersionedRevDependencies {
     pub(crate) fn rdeps(&self) -> &HashMap<DiceKey, VersionNumber> {
         &self.rdeps
     }
+
+    pub(crate) fn remove_rdep(&mut self, dependent: DiceKey) {
+        self.rdeps.remove(&dependent);
+    }
 }
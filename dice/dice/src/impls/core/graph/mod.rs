@@ -12,6 +12,6 @@ mod dependencies;
 pub(crate) mod history;
 #[allow(unused)]
 pub(crate) mod introspection;
-mod nodes;
+pub(crate) mod nodes;
 pub(crate) mod storage;
 pub(crate) mod types;
@@ -41,6 +41,7 @@ use crate::impls::value::DiceValidValue;
 use crate::versions::VersionNumber;
 use crate::versions::VersionRanges;
 use crate::HashMap;
+use crate::HashSet;
 
 /// The actual incremental cache that checks versions and dependency's versions
 /// to maintain correct caching based on versions and the versions of its
@@ -56,12 +57,71 @@ pub(crate) struct VersionedGraph {
     /// VacantGraphEntries can only be present when no other entries are present for the key at
     /// any version.
     pub(crate) last_n: HashMap<DiceKey, SortedVectorMap<VersionNumber, VersionedGraphNode>>,
+    /// Keys that had their values injected rather than computed. These can't be evicted, as
+    /// there would be no way to get their values back.
+    injected: HashSet<DiceKey>,
 }
 
 impl VersionedGraph {
     pub(crate) fn new() -> Self {
         Self {
             last_n: Default::default(),
+            injected: Default::default(),
+        }
+    }
+
+    /// Whether the node for the key can be evicted, which is the case if it holds a computed value.
+    pub(crate) fn is_evictable(&self, key: DiceKey) -> bool {
+        !self.injected.contains(&key)
+            && self.last_n.get(&key).map_or(false, |versioned| {
+                versioned
+                    .values()
+                    .any(|node| matches!(node, VersionedGraphNode::Occupied(_)))
+            })
+    }
+
+    /// The rdeps of the key, at any version, that are still in the graph.
+    pub(crate) fn rdeps(&self, key: DiceKey) -> impl Iterator<Item = DiceKey> + '_ {
+        self.last_n
+            .get(&key)
+            .into_iter()
+            .flat_map(|versioned| versioned.values())
+            .filter_map(|node| node.unpack_occupied())
+            .flat_map(|node| node.metadata().rdeps.rdeps().keys().copied())
+            .filter(move |rdep| self.last_n.contains_key(rdep))
+    }
+
+    /// Removes the nodes for the given keys from the graph. Every rdep of a removed node must be
+    /// removed along with it, since invalidations only propagate through nodes in the graph.
+    pub(crate) fn evict(&mut self, keys: &HashSet<DiceKey>) {
+        let mut edges = Vec::new();
+        for key in keys {
+            if let Some(versioned) = self.last_n.remove(key) {
+                for node in versioned.values() {
+                    if let VersionedGraphNode::Occupied(node) = node {
+                        edges.extend(
+                            node.metadata()
+                                .deps
+                                .deps()
+                                .iter()
+                                .filter(|dep| !keys.contains(dep))
+                                .map(|dep| (*dep, *key)),
+                        );
+                    }
+                }
+            }
+        }
+
+        // Drop the edges from the remaining deps to the removed nodes, so that they don't
+        // accumulate over repeated evictions.
+        for (dep, rdep) in edges {
+            if let Some(versioned) = self.last_n.get_mut(&dep) {
+                for node in versioned.values_mut() {
+                    if let VersionedGraphNode::Occupied(node) = node {
+                        node.metadata_mut().rdeps.remove_rdep(rdep);
+                    }
+                }
+            }
         }
     }
 
@@ -422,6 +482,8 @@ impl VersionedGraph {
                     }
                 }
                 InvalidateKind::Update(value, StorageType::LastN(num_to_keep)) => {
+                    self.injected.insert(key.k);
+
                     let rdeps = {
                         let entry = self.last_n.get(&key.k).and_then(|versioned_map| {
                             versioned_map
@@ -7,12 +7,19 @@
  * of this source tree.
  */
 
+use std::ops::Bound;
+
+use dupe::Dupe;
 use gazebo::prelude::SliceExt;
 
+use crate::api::eviction::EvictionPolicy;
 use crate::api::storage_type::StorageType;
 use crate::arc::Arc;
 use crate::impls::cache::SharedCache;
+use crate::impls::core::eviction::NodeUsage;
+use crate::impls::core::graph::history::HistoryState;
 use crate::impls::core::graph::introspection::VersionedGraphIntrospectable;
+use crate::impls::core::graph::nodes::VersionedGraphNode;
 use crate::impls::core::graph::storage::InvalidateKind;
 use crate::impls::core::graph::storage::ValueReusable;
 use crate::impls::core::graph::storage::VersionedGraph;
@@ -22,11 +29,14 @@ use crate::impls::core::versions::introspection::VersionIntrospectable;
 use crate::impls::core::versions::VersionEpoch;
 use crate::impls::core::versions::VersionTracker;
 use crate::impls::key::DiceKey;
+use crate::impls::persistence::GraphNodeSnapshot;
+use crate::impls::persistence::RestoredGraphNode;
 use crate::impls::task::dice::DiceTask;
 use crate::impls::task::dice::TerminationObserver;
 use crate::impls::transaction::ChangeType;
 use crate::impls::value::DiceComputedValue;
 use crate::impls::value::DiceValidValue;
+use crate::metrics::EvictionStats;
 use crate::metrics::Metrics;
 use crate::result::CancellableResult;
 use crate::result::Cancelled;
@@ -36,14 +46,16 @@ use crate::versions::VersionNumber;
 pub(super) struct CoreState {
     version_tracker: VersionTracker,
     graph: VersionedGraph,
+    usage: NodeUsage,
     pending_termination_tasks: Vec<DiceTask>,
 }
 
 impl CoreState {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(eviction_policy: EvictionPolicy) -> Self {
         Self {
             version_tracker: VersionTracker::new(),
             graph: VersionedGraph::new(),
+            usage: NodeUsage::new(eviction_policy),
             pending_termination_tasks: Vec::new(),
         }
     }
@@ -89,9 +101,21 @@ impl CoreState {
             self.pending_termination_tasks
                 .extend(evicted_cache.cancel_pending_tasks());
         }
+
+        if self.usage.should_evict(self.current_version()) {
+            let policy = self.usage.policy();
+            if let Some(stats) = self.evict(&policy) {
+                debug!(
+                    msg = "evicted unused nodes",
+                    evicted = stats.evicted_key_count,
+                    remaining = stats.remaining_key_count
+                );
+            }
+        }
     }
 
     pub(super) fn lookup_key(&mut self, key: VersionedGraphKey) -> VersionedGraphResult {
+        self.usage.used(key.k, key.v);
         self.graph.get(key)
     }
 
@@ -107,6 +131,7 @@ impl CoreState {
         if self.version_tracker.is_relevant(key.v, epoch) {
             debug!(msg = "update graph entry", k = ?key.k, v = %key.v, v_epoch = %epoch);
 
+            self.usage.computed(key.k, key.v, &value);
             Ok(self.graph.update(key, value, reusability, deps, storage).0)
         } else {
             debug!(msg = "update is rejected due to outdated epoch", k = ?key.k, v = %key.v, v_epoch = %epoch);
@@ -130,6 +155,18 @@ impl CoreState {
         // here.
         let map = std::mem::take(&mut self.graph.last_n);
         std::thread::spawn(move || drop(map));
+        self.usage.clear();
+    }
+
+    /// Evicts nodes according to the policy. Does nothing and returns `None` if there are active
+    /// transactions, as their computations may still depend on any node.
+    pub(super) fn evict(&mut self, policy: &EvictionPolicy) -> Option<EvictionStats> {
+        if !self.version_tracker.is_idle() {
+            return None;
+        }
+
+        let current = self.current_version();
+        Some(self.usage.evict(&mut self.graph, current, policy))
     }
 
     pub(super) fn metrics(&self) -> Metrics {
@@ -146,6 +183,8 @@ impl CoreState {
             key_count: self.graph.last_n.len(),
             currently_active_key_count: currently_running_key_count,
             active_transaction_count: active_transaction_count as u32, // probably won't support more than u32 transactions
+            evicted_key_count: self.usage.evicted_key_count(),
+            eviction_count: self.usage.eviction_count(),
         }
     }
 
@@ -155,6 +194,56 @@ impl CoreState {
 
         (graph, version_data)
     }
+
+    pub(super) fn snapshot_graph(&self) -> Vec<GraphNodeSnapshot> {
+        let v = self.version_tracker.current();
+
+        self.graph
+            .last_n
+            .iter()
+            .filter_map(|(key, versioned)| {
+                match versioned
+                    .range((Bound::Unbounded, Bound::Included(v)))
+                    .next_back()?
+                    .1
+                {
+                    VersionedGraphNode::Occupied(entry)
+                        if matches!(
+                            entry.metadata().hist.get_history(&v),
+                            HistoryState::Verified
+                        ) =>
+                    {
+                        Some(GraphNodeSnapshot {
+                            key: *key,
+                            value: entry.val().dupe(),
+                            deps: entry.metadata().deps.deps(),
+                        })
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// The nodes must be ordered such that each node comes after its deps.
+    pub(super) fn restore_graph(&mut self, nodes: Vec<RestoredGraphNode>) -> bool {
+        if self.current_version() != VersionNumber::ZERO || !self.graph.last_n.is_empty() {
+            return false;
+        }
+
+        for node in nodes {
+            self.usage
+                .computed(node.key, VersionNumber::ZERO, &node.value);
+            self.graph.update(
+                VersionedGraphKey::new(VersionNumber::ZERO, node.key),
+                node.value,
+                ValueReusable::EqualityBased,
+                node.deps,
+                node.storage,
+            );
+        }
+        true
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +260,7 @@ mod tests {
     use tokio::sync::Semaphore;
 
     use crate::api::computations::DiceComputations;
+    use crate::api::eviction::EvictionPolicy;
     use crate::api::key::Key;
     use crate::arc::Arc;
     use crate::impls::cache::DiceTaskRef;
@@ -189,7 +279,7 @@ mod tests {
 
     #[test]
     fn update_state_gets_next_version() {
-        let mut core = CoreState::new();
+        let mut core = CoreState::new(EvictionPolicy::default());
 
         assert_eq!(
             core.update_state([(DiceKey { index: 0 }, ChangeType::Invalidate)]),
@@ -204,7 +294,7 @@ mod tests {
 
     #[test]
     fn state_ctx_at_version() {
-        let mut core = CoreState::new();
+        let mut core = CoreState::new(EvictionPolicy::default());
         let v = VersionNumber::new(0);
 
         let (epoch, ctx) = core.ctx_at_version(v);
@@ -327,7 +417,7 @@ mod tests {
 
     #[tokio::test]
     async fn state_tracks_pending_cancellation() {
-        let mut core = CoreState::new();
+        let mut core = CoreState::new(EvictionPolicy::default());
         let v = VersionNumber::new(0);
 
         let (_epoch, cache) = core.ctx_at_version(v);
@@ -9,6 +9,7 @@
 
 use gazebo::variants::VariantName;
 
+use crate::api::eviction::EvictionPolicy;
 use crate::impls::core::graph::storage::ValueReusable;
 use crate::impls::core::internals::CoreState;
 use crate::impls::core::state::CoreStateHandle;
@@ -21,9 +22,9 @@ pub(super) struct StateProcessor {
 }
 
 impl StateProcessor {
-    pub(super) fn spawn() -> CoreStateHandle {
+    pub(super) fn spawn(eviction_policy: EvictionPolicy) -> CoreStateHandle {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        let state = CoreState::new();
+        let state = CoreState::new(eviction_policy);
 
         std::thread::Builder::new()
             .name("buck2-dice".to_owned())
@@ -115,9 +116,18 @@ impl StateProcessor {
             StateRequest::Metrics { resp } => {
                 let _ignored = resp.send(self.state.metrics());
             }
+            StateRequest::Evict { policy, resp } => {
+                let _ignored = resp.send(self.state.evict(&policy));
+            }
             StateRequest::Introspection { resp } => {
                 let _ignored = resp.send(self.state.introspection());
             }
+            StateRequest::SnapshotGraph { resp } => {
+                let _ignored = resp.send(self.state.snapshot_graph());
+            }
+            StateRequest::RestoreGraph { nodes, resp } => {
+                let _ignored = resp.send(self.state.restore_graph(nodes));
+            }
         }
     }
 }
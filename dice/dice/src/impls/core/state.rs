@@ -13,6 +13,7 @@ use dupe::Dupe;
 use gazebo::variants::VariantName;
 use tokio::sync::oneshot::Sender;
 
+use crate::api::eviction::EvictionPolicy;
 use crate::api::storage_type::StorageType;
 use crate::arc::Arc;
 use crate::impls::core::graph::introspection::VersionedGraphIntrospectable;
@@ -24,11 +25,14 @@ use crate::impls::core::versions::introspection::VersionIntrospectable;
 use crate::impls::core::versions::VersionEpoch;
 use crate::impls::ctx::SharedLiveTransactionCtx;
 use crate::impls::key::DiceKey;
+use crate::impls::persistence::GraphNodeSnapshot;
+use crate::impls::persistence::RestoredGraphNode;
 use crate::impls::task::dice::TerminationObserver;
 use crate::impls::transaction::ActiveTransactionGuard;
 use crate::impls::transaction::ChangeType;
 use crate::impls::value::DiceComputedValue;
 use crate::impls::value::DiceValidValue;
+use crate::metrics::EvictionStats;
 use crate::metrics::Metrics;
 use crate::result::CancellableResult;
 use crate::versions::VersionNumber;
@@ -93,11 +97,29 @@ pub(crate) enum StateRequest {
     UnstableDropEverything,
     /// Collect metrics
     Metrics { resp: Sender<Metrics> },
+    /// Evicts nodes from the graph according to the policy. Responds with `None`, doing nothing,
+    /// if there are active transactions.
+    Evict {
+        policy: EvictionPolicy,
+        resp: Sender<Option<EvictionStats>>,
+    },
     /// Collects the introspectable dice state
     Introspection {
         #[derivative(Debug = "ignore")]
         resp: Sender<(VersionedGraphIntrospectable, VersionIntrospectable)>,
     },
+    /// Collects the nodes that are verified at the current version, to be saved
+    SnapshotGraph {
+        #[derivative(Debug = "ignore")]
+        resp: Sender<Vec<GraphNodeSnapshot>>,
+    },
+    /// Inserts previously saved nodes into an empty graph at the initial version. Responds with
+    /// false, doing nothing, if the graph is in use already.
+    RestoreGraph {
+        #[derivative(Debug = "ignore")]
+        nodes: Vec<RestoredGraphNode>,
+        resp: Sender<bool>,
+    },
 }
 
 /// A handle to the core state that allows sending requests
@@ -121,6 +143,6 @@ impl CoreStateHandle {
 impl Dupe for CoreStateHandle {}
 
 /// Start processing state
-pub(crate) fn init_state() -> CoreStateHandle {
-    StateProcessor::spawn()
+pub(crate) fn init_state(eviction_policy: EvictionPolicy) -> CoreStateHandle {
+    StateProcessor::spawn(eviction_policy)
 }
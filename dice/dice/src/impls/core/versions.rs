@@ -79,6 +79,11 @@ impl VersionTracker {
             .map(|data| (data.ref_count, &data.per_transaction_data))
     }
 
+    /// true when no context at any version is held
+    pub(crate) fn is_idle(&self) -> bool {
+        self.active_versions.is_empty()
+    }
+
     /// hands out the current "latest" committed version's associated transaction context
     pub(crate) fn current(&self) -> VersionNumber {
         self.current
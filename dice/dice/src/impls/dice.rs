@@ -16,14 +16,18 @@ use dupe::Dupe;
 
 use crate::api::cycles::DetectCycles;
 use crate::api::data::DiceData;
+use crate::api::eviction::EvictionPolicy;
+use crate::api::persistence::PersistentKey;
 use crate::api::user_data::UserComputationData;
 use crate::impls::core::state::init_state;
 use crate::impls::core::state::CoreStateHandle;
 use crate::impls::core::state::StateRequest;
 use crate::impls::key_index::DiceKeyIndex;
+use crate::impls::persistence::PersistentKeys;
 use crate::impls::transaction::TransactionUpdater;
 use crate::introspection::graph::GraphIntrospectable;
 use crate::introspection::graph::ModernIntrospectable;
+use crate::metrics::EvictionStats;
 use crate::metrics::Metrics;
 
 #[derive(Allocative)]
@@ -31,6 +35,7 @@ pub(crate) struct DiceModern {
     pub(crate) key_index: DiceKeyIndex,
     pub(crate) state_handle: CoreStateHandle,
     pub(crate) global_data: DiceData,
+    pub(crate) persistent_keys: PersistentKeys,
 }
 
 impl Debug for DiceModern {
@@ -39,30 +44,59 @@ impl Debug for DiceModern {
     }
 }
 
-pub(crate) struct DiceModernDataBuilder(DiceData);
+pub(crate) struct DiceModernDataBuilder {
+    data: DiceData,
+    persistent_keys: PersistentKeys,
+    eviction_policy: EvictionPolicy,
+}
 
 impl DiceModernDataBuilder {
     pub(crate) fn new() -> Self {
-        Self(DiceData::new())
+        Self {
+            data: DiceData::new(),
+            persistent_keys: PersistentKeys::default(),
+            eviction_policy: EvictionPolicy::default(),
+        }
     }
 
     pub fn set<K: Send + Sync + 'static>(&mut self, val: K) {
-        self.0.set(val);
+        self.data.set(val);
+    }
+
+    pub fn register_persistent_key<K: PersistentKey>(&mut self) {
+        self.persistent_keys.register::<K>();
+    }
+
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.eviction_policy = policy;
     }
 
     pub fn build(self, _detect_cycles: DetectCycles) -> Arc<DiceModern> {
-        DiceModern::new(self.0)
+        DiceModern::with_config(self.data, self.persistent_keys, self.eviction_policy)
     }
 }
 
 impl DiceModern {
     pub(crate) fn new(global_data: DiceData) -> Arc<Self> {
-        let state_handle = init_state();
+        Self::with_config(
+            global_data,
+            PersistentKeys::default(),
+            EvictionPolicy::default(),
+        )
+    }
+
+    pub(crate) fn with_config(
+        global_data: DiceData,
+        persistent_keys: PersistentKeys,
+        eviction_policy: EvictionPolicy,
+    ) -> Arc<Self> {
+        let state_handle = init_state(eviction_policy);
 
         Arc::new(DiceModern {
             key_index: Default::default(),
             state_handle,
             global_data,
+            persistent_keys,
         })
     }
 
@@ -131,6 +165,17 @@ impl DiceModern {
         }
     }
 
+    /// Evicts nodes from the graph according to the policy, rather than the one DICE was built
+    /// with. Returns `None` without evicting anything if there are active transactions.
+    pub async fn evict(&self, policy: EvictionPolicy) -> Option<EvictionStats> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        self.state_handle
+            .request(StateRequest::Evict { policy, resp: tx });
+
+        rx.await.unwrap()
+    }
+
     /// true when there are no tasks pending cancellation
     pub async fn is_idle(&self) -> bool {
         let (tx, rx) = tokio::sync::oneshot::channel();
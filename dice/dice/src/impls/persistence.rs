@@ -0,0 +1,386 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Saving the graph of modern DICE to disk, and loading it into a new DICE.
+//!
+//! Only the nodes of `PersistentKey`s which are verified at the current version are saved, along
+//! with their deps. A node is skipped if any of its deps could not be saved, so that every loaded
+//! node can still be invalidated through its deps.
+//!
+//! The saved graph lists the nodes in dependency order:
+//!
+//! ```text
+//! magic, format version: u32
+//! node count: u64
+//! for each node:
+//!     key type name, key, value: length-prefixed bytes
+//!     deps: u32 count, then a u32 for each dep, its position among the nodes listed before
+//! ```
+//!
+//! All integers are little endian, and lengths are u32.
+
+use std::any::Any;
+use std::any::TypeId;
+use std::io::Read;
+use std::io::Write;
+use std::marker::PhantomData;
+
+use allocative::Allocative;
+use anyhow::Context;
+
+use crate::api::data::DiceData;
+use crate::api::persistence::PersistentKey;
+use crate::api::storage_type::StorageType;
+use crate::arc::Arc;
+use crate::impls::core::state::StateRequest;
+use crate::impls::dice::DiceModern;
+use crate::impls::key::DiceKey;
+use crate::impls::key::DiceKeyErased;
+use crate::impls::key_index::DiceKeyIndex;
+use crate::impls::value::DiceValidValue;
+use crate::HashMap;
+
+const MAGIC: &[u8] = b"DICEGRAPH\0";
+
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+enum PersistenceError {
+    #[error("Not a saved DICE graph")]
+    NotAGraph,
+    #[error("Unsupported saved DICE graph format version {0} (expected {FORMAT_VERSION})")]
+    UnsupportedFormat(u32),
+    #[error("A saved DICE graph can only be loaded before DICE is used")]
+    GraphInUse,
+    #[error("Dep {0} of a saved DICE graph node is not listed before it")]
+    InvalidDep(u32),
+    #[error("DICE state was shut down")]
+    StateDropped,
+}
+
+/// A node of the graph, as collected by the core state to be saved.
+pub(crate) struct GraphNodeSnapshot {
+    pub(crate) key: DiceKey,
+    pub(crate) value: DiceValidValue,
+    pub(crate) deps: Arc<Vec<DiceKey>>,
+}
+
+/// A node of a saved graph, for the core state to insert.
+pub(crate) struct RestoredGraphNode {
+    pub(crate) key: DiceKey,
+    pub(crate) value: DiceValidValue,
+    pub(crate) deps: Arc<Vec<DiceKey>>,
+    pub(crate) storage: StorageType,
+}
+
+trait PersistentKeyCodec: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+
+    /// Returns `None` if the value is not to be saved.
+    fn encode(&self, key: &dyn Any, value: &DiceValidValue) -> Option<(Vec<u8>, Vec<u8>)>;
+
+    fn decode(
+        &self,
+        key_index: &DiceKeyIndex,
+        data: &DiceData,
+        key: &[u8],
+        value: &[u8],
+    ) -> anyhow::Result<(DiceKey, DiceValidValue, StorageType)>;
+}
+
+struct Codec<K>(PhantomData<fn() -> K>);
+
+impl<K: PersistentKey> PersistentKeyCodec for Codec<K> {
+    fn name(&self) -> &'static str {
+        K::NAME
+    }
+
+    fn encode(&self, key: &dyn Any, value: &DiceValidValue) -> Option<(Vec<u8>, Vec<u8>)> {
+        let key = key.downcast_ref::<K>()?;
+        let value = K::value_to_bytes(value.downcast_ref::<K::Value>()?)?;
+        Some((key.key_to_bytes(), value))
+    }
+
+    fn decode(
+        &self,
+        key_index: &DiceKeyIndex,
+        data: &DiceData,
+        key: &[u8],
+        value: &[u8],
+    ) -> anyhow::Result<(DiceKey, DiceValidValue, StorageType)> {
+        let key = K::key_from_bytes(key)?;
+        let value = K::value_from_bytes(value, data)?;
+        if !K::validity(&value) {
+            return Err(anyhow::anyhow!("Saved value of `{}` is not valid", key));
+        }
+        Ok((
+            key_index.index_key(key),
+            DiceValidValue::key_value::<K>(value),
+            K::storage_type(),
+        ))
+    }
+}
+
+/// The key types registered with `DiceDataBuilder::register_persistent_key`.
+#[derive(Allocative, Default)]
+pub(crate) struct PersistentKeys {
+    #[allocative(skip)]
+    by_type: HashMap<TypeId, std::sync::Arc<dyn PersistentKeyCodec>>,
+    #[allocative(skip)]
+    by_name: HashMap<&'static str, std::sync::Arc<dyn PersistentKeyCodec>>,
+}
+
+impl PersistentKeys {
+    pub(crate) fn register<K: PersistentKey>(&mut self) {
+        let codec: std::sync::Arc<dyn PersistentKeyCodec> =
+            std::sync::Arc::new(Codec::<K>(PhantomData));
+        if let Some(existing) = self.by_name.insert(K::NAME, codec.clone()) {
+            assert!(
+                self.by_type.contains_key(&TypeId::of::<K>()),
+                "persistent key name `{}` is used by another key type",
+                existing.name()
+            );
+        }
+        self.by_type.insert(TypeId::of::<K>(), codec);
+    }
+
+    fn encode(&self, key: &DiceKeyErased, value: &DiceValidValue) -> Option<EncodedNode> {
+        // Projections are never persistent.
+        let DiceKeyErased::Key(key) = key else {
+            return None;
+        };
+        let codec = self.by_type.get(&Any::type_id(key.as_any()))?;
+        let (key, value) = codec.encode(key.as_any(), value)?;
+        Some(EncodedNode {
+            name: codec.name(),
+            key,
+            value,
+        })
+    }
+}
+
+struct EncodedNode {
+    name: &'static str,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl DiceModern {
+    pub(crate) fn save_graph(&self, out: &mut dyn Write) -> anyhow::Result<usize> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.state_handle
+            .request(StateRequest::SnapshotGraph { resp: tx });
+        let nodes = tokio::task::block_in_place(|| rx.blocking_recv())
+            .map_err(|_| PersistenceError::StateDropped)?;
+
+        let positions: HashMap<DiceKey, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.key, i))
+            .collect();
+        let encoded: Vec<Option<EncodedNode>> = nodes
+            .iter()
+            .map(|node| {
+                self.persistent_keys
+                    .encode(self.key_index.get(node.key), &node.value)
+            })
+            .collect();
+
+        let order = persisted_in_dependency_order(&nodes, &positions, &encoded);
+
+        out.write_all(MAGIC)?;
+        write_u32(out, FORMAT_VERSION)?;
+        out.write_all(&(order.len() as u64).to_le_bytes())?;
+
+        let mut written = HashMap::default();
+        for i in order {
+            let node = encoded[i].as_ref().unwrap();
+            write_bytes(out, node.name.as_bytes())?;
+            write_bytes(out, &node.key)?;
+            write_bytes(out, &node.value)?;
+            write_u32(out, nodes[i].deps.len() as u32)?;
+            for dep in nodes[i].deps.iter() {
+                write_u32(out, written[&positions[dep]])?;
+            }
+            written.insert(i, written.len() as u32);
+        }
+        out.flush()?;
+
+        Ok(written.len())
+    }
+
+    /// Returns the number of nodes loaded. Nodes of key types that are not registered, or that
+    /// fail to decode, are skipped along with everything depending on them.
+    pub(crate) fn load_graph(&self, input: &mut dyn Read) -> anyhow::Result<usize> {
+        let mut magic = [0; MAGIC.len()];
+        input
+            .read_exact(&mut magic)
+            .map_err(|_| PersistenceError::NotAGraph)?;
+        if magic != MAGIC {
+            return Err(PersistenceError::NotAGraph.into());
+        }
+        let format = read_u32(input)?;
+        if format != FORMAT_VERSION {
+            return Err(PersistenceError::UnsupportedFormat(format).into());
+        }
+        let count = read_u64(input)?;
+
+        // The key each listed node was loaded as, if it was.
+        let mut loaded: Vec<Option<DiceKey>> = Vec::new();
+        let mut nodes = Vec::new();
+        for _ in 0..count {
+            let name = read_bytes(input)?;
+            let key = read_bytes(input)?;
+            let value = read_bytes(input)?;
+            let mut deps = Vec::new();
+            let mut deps_loaded = true;
+            for _ in 0..read_u32(input)? {
+                let dep = read_u32(input)?;
+                match loaded.get(dep as usize) {
+                    Some(Some(dep)) => deps.push(*dep),
+                    Some(None) => deps_loaded = false,
+                    None => return Err(PersistenceError::InvalidDep(dep).into()),
+                }
+            }
+
+            let codec = std::str::from_utf8(&name)
+                .ok()
+                .and_then(|name| self.persistent_keys.by_name.get(name));
+            let decoded = match codec {
+                Some(codec) if deps_loaded => {
+                    match codec.decode(&self.key_index, &self.global_data, &key, &value) {
+                        Ok(decoded) => Some(decoded),
+                        Err(e) => {
+                            debug!("not loading saved `{}` node: {:#}", codec.name(), e);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+
+            loaded.push(decoded.as_ref().map(|(key, _, _)| *key));
+            if let Some((key, value, storage)) = decoded {
+                nodes.push(RestoredGraphNode {
+                    key,
+                    value,
+                    deps: Arc::new(deps),
+                    storage,
+                });
+            }
+        }
+
+        let restored = nodes.len();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.state_handle
+            .request(StateRequest::RestoreGraph { nodes, resp: tx });
+        let restored_into_unused_graph = tokio::task::block_in_place(|| rx.blocking_recv())
+            .map_err(|_| PersistenceError::StateDropped)?;
+        if !restored_into_unused_graph {
+            return Err(PersistenceError::GraphInUse.into());
+        }
+
+        Ok(restored)
+    }
+}
+
+/// The nodes that can be saved, ordered such that every node comes after its deps. A node can be
+/// saved if it is encoded, and all its deps are in the snapshot and can be saved.
+fn persisted_in_dependency_order(
+    nodes: &[GraphNodeSnapshot],
+    positions: &HashMap<DiceKey, usize>,
+    encoded: &[Option<EncodedNode>],
+) -> Vec<usize> {
+    enum Visit {
+        Enter(usize),
+        Exit(usize),
+    }
+
+    let mut visited = vec![false; nodes.len()];
+    let mut persisted = vec![false; nodes.len()];
+    let mut order = Vec::new();
+    let mut stack = Vec::new();
+
+    // The graph is acyclic, so when a node is exited, all its deps have been exited already.
+    for root in 0..nodes.len() {
+        stack.push(Visit::Enter(root));
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Enter(i) => {
+                    if visited[i] {
+                        continue;
+                    }
+                    visited[i] = true;
+                    stack.push(Visit::Exit(i));
+                    for dep in nodes[i].deps.iter() {
+                        if let Some(&j) = positions.get(dep) {
+                            if !visited[j] {
+                                stack.push(Visit::Enter(j));
+                            }
+                        }
+                    }
+                }
+                Visit::Exit(i) => {
+                    persisted[i] = encoded[i].is_some()
+                        && nodes[i]
+                            .deps
+                            .iter()
+                            .all(|dep| positions.get(dep).map_or(false, |&j| persisted[j]));
+                    if persisted[i] {
+                        order.push(i);
+                    }
+                }
+            }
+        }
+    }
+
+    order
+}
+
+fn write_u32(out: &mut dyn Write, v: u32) -> anyhow::Result<()> {
+    out.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_bytes(out: &mut dyn Write, bytes: &[u8]) -> anyhow::Result<()> {
+    write_u32(
+        out,
+        bytes
+            .len()
+            .try_into()
+            .context("Saved DICE graph entry is too large")?,
+    )?;
+    out.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_u32(input: &mut dyn Read) -> anyhow::Result<u32> {
+    let mut buf = [0; 4];
+    input
+        .read_exact(&mut buf)
+        .context("Saved DICE graph is truncated")?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(input: &mut dyn Read) -> anyhow::Result<u64> {
+    let mut buf = [0; 8];
+    input
+        .read_exact(&mut buf)
+        .context("Saved DICE graph is truncated")?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes(input: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
+    let len = read_u32(input)?;
+    let mut buf = vec![0; len as usize];
+    input
+        .read_exact(&mut buf)
+        .context("Saved DICE graph is truncated")?;
+    Ok(buf)
+}
@@ -0,0 +1,265 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::Arc;
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_futures::cancellation::CancellationContext;
+use derive_more::Display;
+use dupe::Dupe;
+
+use crate::api::computations::DiceComputations;
+use crate::api::cycles::DetectCycles;
+use crate::api::eviction::EvictionPolicy;
+use crate::api::injected::InjectedKey;
+use crate::api::key::Key;
+use crate::impls::dice::DiceModern;
+use crate::impls::dice::DiceModernDataBuilder;
+use crate::metrics::EvictionStats;
+use crate::DiceData;
+
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct Input(u32);
+
+impl InjectedKey for Input {
+    type Value = u32;
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+/// Changed to move to a new version without invalidating anything else.
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct Unrelated;
+
+impl InjectedKey for Unrelated {
+    type Value = u32;
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct Double(u32);
+
+#[async_trait]
+impl Key for Double {
+    type Value = u32;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        ctx.compute(&Input(self.0)).await.unwrap() * 2
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct DoublePlusOne(u32);
+
+#[async_trait]
+impl Key for DoublePlusOne {
+    type Value = u32;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        ctx.compute(&Double(self.0)).await.unwrap() + 1
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct Buffers;
+
+/// Owns 1000 bytes, and holds 1000 more behind an `Arc`.
+#[derive(Allocative, Clone, PartialEq)]
+struct BuffersValue {
+    owned: Vec<u8>,
+    shared: Arc<Vec<u8>>,
+}
+
+// Not actually cheap to clone, but that doesn't matter in tests.
+impl Dupe for BuffersValue {}
+
+#[async_trait]
+impl Key for Buffers {
+    type Value = BuffersValue;
+
+    async fn compute(
+        &self,
+        _ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        BuffersValue {
+            owned: vec![0; 1000],
+            shared: Arc::new(vec![0; 1000]),
+        }
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+const EVICT_UNUSED: EvictionPolicy = EvictionPolicy {
+    max_unused_versions: Some(0),
+    memory_budget: None,
+};
+
+/// Computes `DoublePlusOne(1)` at the first version, then moves on to the next version.
+async fn computed_then_unused(dice: &Arc<DiceModern>) -> anyhow::Result<()> {
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Input(1), 1)])?;
+    updater.changed_to(vec![(Unrelated, 0)])?;
+    let ctx = updater.commit().await;
+    assert_eq!(ctx.compute(&DoublePlusOne(1)).await?, 3);
+    drop(ctx);
+
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Unrelated, 1)])?;
+    drop(updater.commit().await);
+
+    Ok(())
+}
+
+/// Transactions are released asynchronously, as computations can hold onto them for a bit.
+async fn evict_when_idle(dice: &DiceModern, policy: EvictionPolicy) -> EvictionStats {
+    loop {
+        if let Some(stats) = dice.evict(policy).await {
+            return stats;
+        }
+        tokio::task::yield_now().await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unused_nodes_are_evicted_and_recomputed() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+    computed_then_unused(&dice).await?;
+
+    let stats = evict_when_idle(&dice, EVICT_UNUSED).await;
+    // `DoublePlusOne(1)` and `Double(1)`, but never the injected keys.
+    assert_eq!(stats.evicted_key_count, 2);
+    assert_eq!(stats.remaining_key_count, 2);
+    assert_eq!(stats.remaining_size, None);
+    assert_eq!(dice.metrics().evicted_key_count, 2);
+
+    let ctx = dice.updater().commit().await;
+    assert_eq!(ctx.compute(&DoublePlusOne(1)).await?, 3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nodes_with_used_rdeps_are_kept() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+    computed_then_unused(&dice).await?;
+
+    // Only looks up `DoublePlusOne(1)`, which is still valid, so `Double(1)` stays unused.
+    let ctx = dice.updater().commit().await;
+    assert_eq!(ctx.compute(&DoublePlusOne(1)).await?, 3);
+    drop(ctx);
+
+    let stats = evict_when_idle(&dice, EVICT_UNUSED).await;
+    assert_eq!(stats.evicted_key_count, 0);
+
+    // Had `Double(1)` been evicted, this change would not reach `DoublePlusOne(1)`.
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Input(1), 5)])?;
+    let ctx = updater.commit().await;
+    assert_eq!(ctx.compute(&DoublePlusOne(1)).await?, 11);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn memory_budget_evicts_least_recently_used() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+    computed_then_unused(&dice).await?;
+
+    let stats = evict_when_idle(
+        &dice,
+        EvictionPolicy {
+            max_unused_versions: None,
+            memory_budget: Some(0),
+        },
+    )
+    .await;
+    assert_eq!(stats.evicted_key_count, 2);
+    assert_eq!(stats.remaining_size, Some(0));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sizes_only_count_owned_data() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+    let ctx = dice.updater().commit().await;
+    ctx.compute(&Buffers).await?;
+    drop(ctx);
+
+    let stats = evict_when_idle(
+        &dice,
+        EvictionPolicy {
+            max_unused_versions: None,
+            memory_budget: Some(usize::MAX),
+        },
+    )
+    .await;
+    assert_eq!(stats.evicted_key_count, 0);
+    assert_eq!(
+        stats.remaining_size,
+        Some(1000 + std::mem::size_of::<BuffersValue>())
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nothing_is_evicted_during_transactions() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+    computed_then_unused(&dice).await?;
+
+    let ctx = dice.updater().commit().await;
+    assert!(dice.evict(EVICT_UNUSED).await.is_none());
+    drop(ctx);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn policy_is_applied_when_idle() -> anyhow::Result<()> {
+    let mut builder = DiceModernDataBuilder::new();
+    builder.set_eviction_policy(EVICT_UNUSED);
+    let dice = builder.build(DetectCycles::Disabled);
+    computed_then_unused(&dice).await?;
+
+    while dice.metrics().evicted_key_count < 2 {
+        tokio::task::yield_now().await;
+    }
+
+    let ctx = dice.updater().commit().await;
+    assert_eq!(ctx.compute(&DoublePlusOne(1)).await?, 3);
+
+    Ok(())
+}
@@ -10,8 +10,10 @@
 mod activation_tracker;
 mod demo;
 mod events;
+mod eviction;
 mod general;
 mod keys;
+mod persistence;
 mod spawner;
 mod transients;
 mod user_data;
@@ -0,0 +1,243 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_futures::cancellation::CancellationContext;
+use derive_more::Display;
+
+use crate::api::computations::DiceComputations;
+use crate::api::cycles::DetectCycles;
+use crate::api::data::DiceData;
+use crate::api::injected::InjectedKey;
+use crate::api::key::Key;
+use crate::api::persistence::PersistentKey;
+use crate::impls::dice::DiceModern;
+use crate::impls::dice::DiceModernDataBuilder;
+
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct Input(u32);
+
+impl InjectedKey for Input {
+    type Value = u32;
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+impl PersistentKey for Input {
+    const NAME: &'static str = "Input";
+
+    fn key_to_bytes(&self) -> Vec<u8> {
+        self.0.to_le_bytes().to_vec()
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(Input(u32::from_le_bytes(bytes.try_into()?)))
+    }
+
+    fn value_to_bytes(value: &Self::Value) -> Option<Vec<u8>> {
+        Some(value.to_le_bytes().to_vec())
+    }
+
+    fn value_from_bytes(bytes: &[u8], data: &DiceData) -> anyhow::Result<Self::Value> {
+        if data.get::<RejectSavedInputs>().is_ok() {
+            return Err(anyhow::anyhow!("Saved inputs are rejected"));
+        }
+        Ok(u32::from_le_bytes(bytes.try_into()?))
+    }
+}
+
+/// When set in the global data, saved `Input`s fail to load.
+struct RejectSavedInputs;
+
+/// Doubles an `Input`, counting how many times it was computed.
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct Double(u32);
+
+static DOUBLE_COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+#[async_trait]
+impl Key for Double {
+    type Value = u32;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        DOUBLE_COMPUTED.fetch_add(1, Ordering::SeqCst);
+        ctx.compute(&Input(self.0)).await.unwrap() * 2
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+impl PersistentKey for Double {
+    const NAME: &'static str = "Double";
+
+    fn key_to_bytes(&self) -> Vec<u8> {
+        Input(self.0).key_to_bytes()
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(Double(Input::key_from_bytes(bytes)?.0))
+    }
+
+    fn value_to_bytes(value: &Self::Value) -> Option<Vec<u8>> {
+        Input::value_to_bytes(value)
+    }
+
+    fn value_from_bytes(bytes: &[u8], data: &DiceData) -> anyhow::Result<Self::Value> {
+        Input::value_from_bytes(bytes, data)
+    }
+}
+
+/// Not persistent, so `DoubleOpaque`, which depends on it, is not saved either.
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct Opaque(u32);
+
+#[async_trait]
+impl Key for Opaque {
+    type Value = u32;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        ctx.compute(&Input(self.0)).await.unwrap()
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Allocative, Clone, Debug, Display, Eq, PartialEq, Hash)]
+struct DoubleOpaque(u32);
+
+#[async_trait]
+impl Key for DoubleOpaque {
+    type Value = u32;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        ctx.compute(&Opaque(self.0)).await.unwrap() * 2
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+impl PersistentKey for DoubleOpaque {
+    const NAME: &'static str = "DoubleOpaque";
+
+    fn key_to_bytes(&self) -> Vec<u8> {
+        Input(self.0).key_to_bytes()
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(DoubleOpaque(Input::key_from_bytes(bytes)?.0))
+    }
+
+    fn value_to_bytes(value: &Self::Value) -> Option<Vec<u8>> {
+        Input::value_to_bytes(value)
+    }
+
+    fn value_from_bytes(bytes: &[u8], data: &DiceData) -> anyhow::Result<Self::Value> {
+        Input::value_from_bytes(bytes, data)
+    }
+}
+
+fn dice() -> Arc<DiceModern> {
+    dice_with_data(|_| {})
+}
+
+fn dice_with_data(set_data: impl FnOnce(&mut DiceModernDataBuilder)) -> Arc<DiceModern> {
+    let mut builder = DiceModernDataBuilder::new();
+    set_data(&mut builder);
+    builder.register_persistent_key::<Input>();
+    builder.register_persistent_key::<Double>();
+    builder.register_persistent_key::<DoubleOpaque>();
+    builder.build(DetectCycles::Disabled)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn saved_graph_is_loaded_and_invalidated() -> anyhow::Result<()> {
+    let dice = dice();
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Input(1), 10)])?;
+    let ctx = updater.commit().await;
+    assert_eq!(ctx.compute(&Double(1)).await?, 20);
+    assert_eq!(ctx.compute(&DoubleOpaque(1)).await?, 20);
+    drop(ctx);
+
+    let mut saved = Vec::new();
+    // `Input(1)` and `Double(1)`, but not `DoubleOpaque(1)`.
+    assert_eq!(dice.save_graph(&mut saved)?, 2);
+
+    let dice = self::dice();
+    assert_eq!(dice.load_graph(&mut saved.as_slice())?, 2);
+    // Only a DICE that was never used can load a graph.
+    assert!(dice.load_graph(&mut saved.as_slice()).is_err());
+
+    let computed = DOUBLE_COMPUTED.load(Ordering::SeqCst);
+
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Input(1), 10)])?;
+    let ctx = updater.commit().await;
+    assert_eq!(ctx.compute(&Double(1)).await?, 20);
+    assert_eq!(DOUBLE_COMPUTED.load(Ordering::SeqCst), computed);
+    drop(ctx);
+
+    // Changing the input invalidates the loaded value.
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Input(1), 11)])?;
+    let ctx = updater.commit().await;
+    assert_eq!(ctx.compute(&Double(1)).await?, 22);
+    assert_eq!(DOUBLE_COMPUTED.load(Ordering::SeqCst), computed + 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn load_graph_rejects_other_files() {
+    let dice = dice();
+    assert!(dice.load_graph(&mut b"not a graph".as_slice()).is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn values_rejected_on_load_are_skipped_with_their_dependents() -> anyhow::Result<()> {
+    let dice = dice();
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Input(2), 10)])?;
+    let ctx = updater.commit().await;
+    assert_eq!(ctx.compute(&Double(2)).await?, 20);
+    drop(ctx);
+
+    let mut saved = Vec::new();
+    assert_eq!(dice.save_graph(&mut saved)?, 2);
+
+    let dice = dice_with_data(|builder| builder.set(RejectSavedInputs));
+    assert_eq!(dice.load_graph(&mut saved.as_slice())?, 0);
+
+    Ok(())
+}
@@ -31,7 +31,11 @@ impl Debug for DiceValidValue {
 }
 
 impl DiceValidValue {
-    #[cfg(test)]
+    /// The value of a `Key`, which the caller has checked to be valid.
+    pub(crate) fn key_value<K: Key>(value: K::Value) -> Self {
+        Self(std::sync::Arc::new(DiceKeyValue::<K>::new(value)))
+    }
+
     pub(crate) fn downcast_ref<V: Any>(&self) -> Option<&V> {
         self.0.downcast_ref()
     }
@@ -40,6 +44,23 @@ impl DiceValidValue {
     pub(crate) fn equality(&self, other: &DiceValidValue) -> bool {
         self.0.equality(&*other.0)
     }
+
+    /// The size of the value along with the data it owns, not counting anything behind shared
+    /// pointers such as `Arc`s, which other values may hold as well. This is cheap compared to
+    /// measuring the whole value, but underestimates values that mostly hold shared data.
+    pub(crate) fn shallow_size(&self) -> usize {
+        /// Lets `allocative` start from the value itself, rather than from the `Arc` holding it.
+        struct Unshared<'a>(&'a dyn DiceValueDyn);
+
+        impl Allocative for Unshared<'_> {
+            fn visit<'a, 'b: 'a>(&self, visitor: &'a mut allocative::Visitor<'b>) {
+                self.0.visit(visitor)
+            }
+        }
+
+        std::mem::size_of_val(&*self.0)
+            + allocative::size_of_unique_allocated_data(&Unshared(&*self.0))
+    }
 }
 
 /// Type erased value that may be transient, or whose dependencies are transient
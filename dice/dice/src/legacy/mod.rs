@@ -214,6 +214,9 @@ impl DiceLegacy {
             active_transaction_count: self
                 .active_transaction_count
                 .load(std::sync::atomic::Ordering::SeqCst),
+            // Legacy DICE never evicts anything.
+            evicted_key_count: 0,
+            eviction_count: 0,
         }
     }
 
@@ -216,6 +216,7 @@ mod transaction_update;
 mod versions;
 
 use std::fmt::Debug;
+use std::io::Read;
 use std::io::Write;
 use std::sync::Arc;
 
@@ -233,6 +234,7 @@ use legacy::dice_futures::future_handle::WeakDiceFutureHandle;
 use legacy::incremental::graph::GraphNode;
 use legacy::incremental::transaction_ctx::TransactionCtx;
 use legacy::key::StoragePropertiesForKey;
+use metrics::EvictionStats;
 use metrics::Metrics;
 use serde::Serializer;
 
@@ -248,9 +250,11 @@ pub use crate::api::error::DiceError;
 pub use crate::api::error::DiceResult;
 pub use crate::api::events::DiceEvent;
 pub use crate::api::events::DiceEventListener;
+pub use crate::api::eviction::EvictionPolicy;
 pub use crate::api::injected::InjectedKey;
 pub use crate::api::key::Key;
 pub use crate::api::opaque::OpaqueValue;
+pub use crate::api::persistence::PersistentKey;
 pub use crate::api::projection::DiceProjectionComputations;
 pub use crate::api::projection::ProjectionKey;
 pub use crate::api::transaction::DiceEquality;
@@ -351,6 +355,33 @@ impl DiceImplementation {
             DiceImplementation::Modern(dice) => dice.is_idle().await,
         }
     }
+
+    pub fn save_graph(&self, out: &mut dyn Write) -> anyhow::Result<usize> {
+        match self {
+            DiceImplementation::Legacy(_) => Err(anyhow::anyhow!(
+                "Saving the graph is not supported by legacy DICE"
+            )),
+            DiceImplementation::Modern(dice) => dice.save_graph(out),
+        }
+    }
+
+    pub fn load_graph(&self, input: &mut dyn Read) -> anyhow::Result<usize> {
+        match self {
+            DiceImplementation::Legacy(_) => Err(anyhow::anyhow!(
+                "Loading a saved graph is not supported by legacy DICE"
+            )),
+            DiceImplementation::Modern(dice) => dice.load_graph(input),
+        }
+    }
+
+    pub async fn evict(&self, policy: EvictionPolicy) -> anyhow::Result<Option<EvictionStats>> {
+        match self {
+            DiceImplementation::Legacy(_) => Err(anyhow::anyhow!(
+                "Evicting nodes is not supported by legacy DICE"
+            )),
+            DiceImplementation::Modern(dice) => Ok(dice.evict(policy).await),
+        }
+    }
 }
 
 pub(crate) enum DiceDataBuilderImpl {
@@ -374,6 +405,22 @@ impl DiceDataBuilderImpl {
         }
     }
 
+    pub fn register_persistent_key<K: PersistentKey>(&mut self) {
+        match self {
+            // Legacy DICE can't save its graph, so this is unused.
+            DiceDataBuilderImpl::Legacy(_) => {}
+            DiceDataBuilderImpl::Modern(d) => d.register_persistent_key::<K>(),
+        }
+    }
+
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        match self {
+            // Legacy DICE never evicts anything.
+            DiceDataBuilderImpl::Legacy(_) => {}
+            DiceDataBuilderImpl::Modern(d) => d.set_eviction_policy(policy),
+        }
+    }
+
     pub fn build(self, detect_cycles: DetectCycles) -> Arc<Dice> {
         Dice::new(match self {
             DiceDataBuilderImpl::Legacy(d) => DiceImplementation::Legacy(d.build(detect_cycles)),
@@ -14,4 +14,18 @@ pub struct Metrics {
     /// The number of keys currently active in the per transaction cache
     pub currently_active_key_count: usize,
     pub active_transaction_count: u32,
+    /// The number of keys evicted from the graph since DICE was created
+    pub evicted_key_count: usize,
+    /// The number of times nodes were evicted from the graph
+    pub eviction_count: usize,
+}
+
+/// The result of evicting nodes from the graph.
+#[derive(Debug)]
+pub struct EvictionStats {
+    pub evicted_key_count: usize,
+    pub remaining_key_count: usize,
+    /// The estimated size in bytes of the computed values left in the graph, if eviction was
+    /// bounded by a memory budget
+    pub remaining_size: Option<usize>,
 }
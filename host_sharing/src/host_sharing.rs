@@ -103,11 +103,18 @@ pub struct HostSharingGuard {
     _name_guard: Option<SharedSemaphoreReleaser>,
 }
 
+/// A guard for the memory acquired by a HostSharingBroker.acquire_memory request.
+pub struct MemoryGuard {
+    _guard: Option<SharedSemaphoreReleaser>,
+}
+
 /// Used to ensure that host resources are properly reserved before executing a command spec.
 pub struct HostSharingBroker {
     permits: SharedSemaphore,
     num_machine_permits: usize,
     named_semaphores: NamedSemaphores,
+    /// Megabytes of memory, shared by the commands which declare how much they need.
+    memory: Option<(SharedSemaphore, usize)>,
 }
 
 pub struct RequestedPermits {
@@ -155,9 +162,33 @@ impl HostSharingBroker {
             permits,
             num_machine_permits,
             named_semaphores: NamedSemaphores::new(),
+            memory: None,
         }
     }
 
+    /// Only run commands which declare how much memory they need once it fits in `budget_mb`,
+    /// along with that of the others running.
+    pub fn with_memory_budget(mut self, budget_mb: usize) -> Self {
+        // This is fair, so that the biggest commands don't wait forever for smaller ones.
+        self.memory = Some((SharedSemaphore::new(true, budget_mb), budget_mb));
+        self
+    }
+
+    // As with permits, a command needing more memory than the budget is capped to it, so that it
+    // can still run, on its own.
+    fn requested_memory(&self, memory_mb: usize) -> Option<(&SharedSemaphore, usize)> {
+        let (semaphore, budget_mb) = self.memory.as_ref()?;
+        Some((semaphore, memory_mb.min(*budget_mb)))
+    }
+
+    pub async fn acquire_memory(&self, memory_mb: usize) -> MemoryGuard {
+        let _guard = match self.requested_memory(memory_mb) {
+            Some((semaphore, memory_mb)) => Some(semaphore.acquire(memory_mb).await),
+            None => None,
+        };
+        MemoryGuard { _guard }
+    }
+
     pub fn num_machine_permits(&self) -> usize {
         self.num_machine_permits
     }
@@ -227,6 +258,16 @@ mod tests {
         assert_eq!(4, permits);
     }
 
+    #[test]
+    fn test_memory_capped_to_budget() {
+        let broker = HostSharingBroker::new(HostSharingStrategy::SmallerTasksFirst, 2);
+        assert!(broker.requested_memory(4096).is_none());
+
+        let broker = broker.with_memory_budget(1024);
+        assert_eq!(broker.requested_memory(512).map(|(_, mb)| mb), Some(512));
+        assert_eq!(broker.requested_memory(4096).map(|(_, mb)| mb), Some(1024));
+    }
+
     #[test]
     fn test_percentage() {
         let broker = HostSharingBroker::new(HostSharingStrategy::SmallerTasksFirst, 10);
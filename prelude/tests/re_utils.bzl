@@ -50,8 +50,9 @@ def get_re_executors_from_props(ctx: AnalysisContext) -> ([CommandExecutorConfig
         fail("found unexpected re props: " + unexpected_props)
 
     remote_execution_action_key = None
-    build_mode_info = ctx.attrs.remote_execution_action_key_providers[BuildModeInfo]
-    if build_mode_info != None:
+    action_key_providers = ctx.attrs.remote_execution_action_key_providers
+    if action_key_providers != None:
+        build_mode_info = action_key_providers[BuildModeInfo]
         remote_execution_action_key = "{}={}".format(build_mode_info.cell, build_mode_info.mode)
 
     default_executor = CommandExecutorConfig(
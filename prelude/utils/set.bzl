@@ -5,31 +5,23 @@
 # License, Version 2.0 found in the LICENSE-APACHE file in the root directory
 # of this source tree.
 
-# A set is useful when the `dedupe` builtin is not applicable. Dedupe looks at
-# identity of the value (some kind of pointer) rather than equality, so for
-# example doesn't eliminate duplicates of the same string value obtained from
-# different places:
+# Deprecated: use the builtin `set` instead, which supports `in`, `len`, `add`,
+# `discard`, `update`, `union` and the other set operations directly.
 #
-#     things = ["huh", "huh"]
-#     expect(len(dedupe(things)) == 2)
-#
-#     huh = "huh"
-#     things = [huh, huh]
-#     expect(len(dedupe(things)) == 1)
-#
-# In contrast a set compares its entries for equality, not identity, and will
-# never contain one entry equal to another entry.
-#
-# Example usage:
+# This module keeps the old record interface for existing callers, backed by the
+# builtin `set`:
 #
 #     things = set()
 #     for x in somewhere:
 #         things.add(x)
 #     return things.list()
 
+load(":native_set.bzl", "native_set")
+
 # Name the record `set_record` to enable users to use `set` to initialize a set.
 set_record = record(
-    _entries = field(dict),
+    # The builtin set holding the entries.
+    _entries = field(typing.Any),
     list = field(typing.Callable),
     # Adds the value to the set, returning whether the value existed in the set
     add = field(typing.Callable),
@@ -47,32 +39,36 @@ set_record = record(
 set_type = set_record
 
 def set(initial_entries: list[typing.Any] = []) -> set_type:
+    entries = native_set()
+
     def set_list():
-        return self._entries.keys()
+        return list(entries)
 
     def set_add(v: typing.Any) -> bool:
-        if self.contains(v):
+        if v in entries:
             return True
-        self._entries[v] = None
+        entries.add(v)
         return False
 
     def set_contains(v: typing.Any) -> bool:
-        return v in self._entries
+        return v in entries
 
     def set_remove(v: typing.Any) -> bool:
-        if self.contains(v):
-            self._entries.pop(v)
+        if v in entries:
+            entries.discard(v)
             return True
         return False
 
     def set_update(values: list[typing.Any]) -> list[typing.Any]:
-        return filter(None, [v for v in values if not self.add(v)])
+        return filter(None, [v for v in values if not set_add(v)])
 
     def set_size() -> int:
-        return len(self._entries)
+        return len(entries)
+
+    set_update(initial_entries)
 
-    self = set_record(
-        _entries = {},
+    return set_record(
+        _entries = entries,
         list = set_list,
         add = set_add,
         remove = set_remove,
@@ -80,7 +76,3 @@ def set(initial_entries: list[typing.Any] = []) -> set_type:
         contains = set_contains,
         size = set_size,
     )
-
-    self.update(initial_entries)
-
-    return self
@@ -14,6 +14,7 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use buck2_re_configuration::Buck2OssReConfiguration;
+use buck2_re_configuration::CasCompressor;
 use buck2_re_configuration::HttpHeader;
 use dupe::Dupe;
 use futures::future::BoxFuture;
@@ -28,7 +29,6 @@ use prost::Message;
 use re_grpc_proto::build::bazel::remote::execution::v2::action_cache_client::ActionCacheClient;
 use re_grpc_proto::build::bazel::remote::execution::v2::batch_update_blobs_request::Request;
 use re_grpc_proto::build::bazel::remote::execution::v2::capabilities_client::CapabilitiesClient;
-use re_grpc_proto::build::bazel::remote::execution::v2::compressor;
 use re_grpc_proto::build::bazel::remote::execution::v2::content_addressable_storage_client::ContentAddressableStorageClient;
 use re_grpc_proto::build::bazel::remote::execution::v2::execution_client::ExecutionClient;
 use re_grpc_proto::build::bazel::remote::execution::v2::execution_stage;
@@ -69,7 +69,9 @@ use tonic::transport::Channel;
 use tonic::transport::Identity;
 use tonic::transport::Uri;
 
+use crate::compression::*;
 use crate::error::*;
+use crate::http_cache::HttpCacheClient;
 use crate::metadata::*;
 use crate::request::*;
 use crate::response::*;
@@ -81,14 +83,14 @@ const CONCURRENT_UPLOAD_LIMIT: usize = 64;
 
 const DEFAULT_MAX_MSG_SIZE: usize = 4 * 1000 * 1000;
 
-fn tdigest_to(tdigest: TDigest) -> Digest {
+pub(crate) fn tdigest_to(tdigest: TDigest) -> Digest {
     Digest {
         hash: tdigest.hash,
         size_bytes: tdigest.size_in_bytes,
     }
 }
 
-fn tdigest_from(digest: Digest) -> TDigest {
+pub(crate) fn tdigest_from(digest: Digest) -> TDigest {
     TDigest {
         hash: digest.hash,
         size_in_bytes: digest.size_bytes,
@@ -96,7 +98,7 @@ fn tdigest_from(digest: Digest) -> TDigest {
     }
 }
 
-fn tstatus_ok() -> TStatus {
+pub(crate) fn tstatus_ok() -> TStatus {
     TStatus {
         code: TCode::OK,
         message: "".to_owned(),
@@ -115,7 +117,7 @@ fn check_status(status: Status) -> Result<(), REClientError> {
     })
 }
 
-fn ttimestamp_from(ts: Option<::prost_types::Timestamp>) -> TTimestamp {
+pub(crate) fn ttimestamp_from(ts: Option<::prost_types::Timestamp>) -> TTimestamp {
     match ts {
         Some(timestamp) => TTimestamp {
             seconds: timestamp.seconds,
@@ -205,6 +207,8 @@ pub struct RECapabilities {
     max_msg_size: usize,
     /// Does the remote server support execution.
     exec_enabled: bool,
+    /// Compressors to use for CAS transfers.
+    compressors: CasCompressors,
 }
 
 struct InstanceName(Option<String>);
@@ -229,6 +233,14 @@ pub struct REClientBuilder;
 
 impl REClientBuilder {
     pub async fn build_and_connect(opts: &Buck2OssReConfiguration) -> anyhow::Result<REClient> {
+        if let Some(address) = &opts.http_cache_address {
+            let client =
+                HttpCacheClient::new(address, opts).context("Invalid HTTP cache config")?;
+            return Ok(REClient {
+                backend: REClientBackend::Http(client),
+            });
+        }
+
         // We just always create this just in case, so that we implicitly validate it if set.
         let tls_config = create_tls_config(opts)
             .await
@@ -292,11 +304,18 @@ impl REClientBuilder {
         let instance_name = InstanceName(opts.instance_name.clone());
 
         let capabilities = if opts.capabilities.unwrap_or(true) {
-            Self::fetch_rbe_capabilities(&mut grpc_clients, &instance_name).await?
+            Self::fetch_rbe_capabilities(&mut grpc_clients, &instance_name, opts.compression)
+                .await?
         } else {
             RECapabilities {
                 exec_enabled: true,
                 max_msg_size: DEFAULT_MAX_MSG_SIZE,
+                // Without capabilities we can't negotiate, so trust the configuration.
+                compressors: CasCompressors {
+                    bytestream: opts.compression,
+                    batch_update: opts.compression,
+                    batch_read: opts.compression,
+                },
             }
         };
 
@@ -304,12 +323,19 @@ impl REClientBuilder {
             return Err(anyhow::anyhow!("Server has remote execution disabled."));
         }
 
-        Ok(REClient::new(grpc_clients, capabilities, instance_name))
+        Ok(REClient {
+            backend: REClientBackend::Grpc(GrpcREClient::new(
+                grpc_clients,
+                capabilities,
+                instance_name,
+            )),
+        })
     }
 
     async fn fetch_rbe_capabilities(
         clients: &mut GRPCClients,
         instance_name: &InstanceName,
+        compression: CasCompressor,
     ) -> anyhow::Result<RECapabilities> {
         // TODO use more of the capabilities of the remote build executor
 
@@ -325,6 +351,7 @@ impl REClientBuilder {
         // with enough room for headers.
         let mut max_msg_size = DEFAULT_MAX_MSG_SIZE;
         let mut exec_enabled = true;
+        let mut compressors = CasCompressors::default();
 
         if let Some(cache_cap) = resp.cache_capabilities {
             let size = cache_cap.max_batch_total_size_bytes as usize;
@@ -332,6 +359,23 @@ impl REClientBuilder {
             if size != 0 {
                 max_msg_size = size;
             }
+
+            compressors = CasCompressors::negotiate(
+                compression,
+                &cache_cap.supported_compressors,
+                &cache_cap.supported_batch_update_compressors,
+            );
+        }
+
+        if compressors.bytestream != compression
+            || compressors.batch_update != compression
+            || compressors.batch_read != compression
+        {
+            tracing::info!(
+                "Requested compression `{:?}` is not fully supported by the server, using {:?}",
+                compression,
+                compressors,
+            );
         }
 
         if let Some(exec_cap) = resp.execution_capabilities {
@@ -341,6 +385,7 @@ impl REClientBuilder {
         Ok(RECapabilities {
             max_msg_size,
             exec_enabled,
+            compressors,
         })
     }
 }
@@ -399,12 +444,22 @@ pub struct GRPCClients {
     capabilities_client: CapabilitiesClient<InterceptedService<Channel, InjectHeadersInterceptor>>,
 }
 
-pub struct REClient {
+struct GrpcREClient {
     grpc_clients: GRPCClients,
     capabilities: RECapabilities,
     instance_name: InstanceName,
 }
 
+enum REClientBackend {
+    Grpc(GrpcREClient),
+    /// A cache-only backend, see `HttpCacheClient`.
+    Http(HttpCacheClient),
+}
+
+pub struct REClient {
+    backend: REClientBackend,
+}
+
 impl Drop for REClient {
     fn drop(&mut self) {
         // Important we have a drop implementation since the real one does, and we
@@ -461,12 +516,122 @@ impl BatchUploadReqAggregator {
 }
 
 impl REClient {
+    pub async fn get_action_result(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        request: ActionResultRequest,
+    ) -> anyhow::Result<ActionResultResponse> {
+        match &self.backend {
+            REClientBackend::Grpc(client) => client.get_action_result(metadata, request).await,
+            REClientBackend::Http(client) => client.get_action_result(request).await,
+        }
+    }
+
+    pub async fn write_action_result(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        request: WriteActionResultRequest,
+    ) -> anyhow::Result<WriteActionResultResponse> {
+        match &self.backend {
+            REClientBackend::Grpc(client) => client.write_action_result(metadata, request).await,
+            REClientBackend::Http(client) => client.write_action_result(request).await,
+        }
+    }
+
+    pub async fn execute_with_progress(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        execute_request: ExecuteRequest,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<ExecuteWithProgressResponse>>> {
+        match &self.backend {
+            REClientBackend::Grpc(client) => {
+                client
+                    .execute_with_progress(metadata, execute_request)
+                    .await
+            }
+            REClientBackend::Http(_) => Err(anyhow::anyhow!(
+                "Remote execution is not supported by the HTTP cache backend"
+            )),
+        }
+    }
+
+    pub async fn upload(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        request: UploadRequest,
+    ) -> anyhow::Result<UploadResponse> {
+        match &self.backend {
+            REClientBackend::Grpc(client) => client.upload(metadata, request).await,
+            REClientBackend::Http(client) => client.upload(request).await,
+        }
+    }
+
+    pub async fn upload_blob(
+        &self,
+        blob: Vec<u8>,
+        metadata: RemoteExecutionMetadata,
+    ) -> anyhow::Result<TDigest> {
+        match &self.backend {
+            REClientBackend::Grpc(client) => client.upload_blob(blob, metadata).await,
+            REClientBackend::Http(client) => client.upload_blob(blob).await,
+        }
+    }
+
+    pub async fn download(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        request: DownloadRequest,
+    ) -> anyhow::Result<DownloadResponse> {
+        match &self.backend {
+            REClientBackend::Grpc(client) => client.download(metadata, request).await,
+            REClientBackend::Http(client) => client.download(request).await,
+        }
+    }
+
+    pub async fn get_digests_ttl(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        request: GetDigestsTtlRequest,
+    ) -> anyhow::Result<GetDigestsTtlResponse> {
+        match &self.backend {
+            REClientBackend::Grpc(client) => client.get_digests_ttl(metadata, request).await,
+            REClientBackend::Http(client) => client.get_digests_ttl(request).await,
+        }
+    }
+
+    pub fn get_execution_client(&self) -> &Self {
+        self
+    }
+
+    pub fn get_cas_client(&self) -> &Self {
+        self
+    }
+
+    pub fn get_action_cache_client(&self) -> &Self {
+        self
+    }
+
+    pub fn get_metrics_client(&self) -> &Self {
+        self
+    }
+
+    pub fn get_session_id(&self) -> &str {
+        // TODO(aloiscochard): Return a unique ID, ideally from the GRPC client
+        "GRPC-SESSION-ID"
+    }
+
+    pub fn get_experiment_name(&self) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+impl GrpcREClient {
     fn new(
         grpc_clients: GRPCClients,
         capabilities: RECapabilities,
         instance_name: InstanceName,
     ) -> Self {
-        REClient {
+        GrpcREClient {
             grpc_clients,
             capabilities,
             instance_name,
@@ -631,6 +796,7 @@ impl REClient {
             &self.instance_name,
             request,
             self.capabilities.max_msg_size,
+            self.capabilities.compressors,
             |re_request| async {
                 let metadata = metadata.clone();
                 let mut cas_client = self.grpc_clients.cas_client.clone();
@@ -671,6 +837,7 @@ impl REClient {
             &self.instance_name,
             request,
             self.capabilities.max_msg_size,
+            self.capabilities.compressors,
             |re_request| async {
                 let metadata = metadata.clone();
                 let mut client = self.grpc_clients.cas_client.clone();
@@ -744,34 +911,9 @@ impl REClient {
             digests_with_ttl: remote_ttl.values().cloned().collect::<Vec<DigestWithTtl>>(),
         })
     }
-
-    pub fn get_execution_client(&self) -> &Self {
-        self
-    }
-
-    pub fn get_cas_client(&self) -> &Self {
-        self
-    }
-
-    pub fn get_action_cache_client(&self) -> &Self {
-        self
-    }
-
-    pub fn get_metrics_client(&self) -> &Self {
-        self
-    }
-
-    pub fn get_session_id(&self) -> &str {
-        // TODO(aloiscochard): Return a unique ID, ideally from the GRPC client
-        "GRPC-SESSION-ID"
-    }
-
-    pub fn get_experiment_name(&self) -> anyhow::Result<Option<String>> {
-        Ok(None)
-    }
 }
 
-fn convert_action_result(action_result: ActionResult) -> anyhow::Result<TActionResult2> {
+pub(crate) fn convert_action_result(action_result: ActionResult) -> anyhow::Result<TActionResult2> {
     let execution_metadata = action_result
         .execution_metadata
         .with_context(|| "The execution metadata are not defined.")?;
@@ -860,6 +1002,7 @@ async fn download_impl<Byt, BytRet, Cas>(
     instance_name: &InstanceName,
     request: DownloadRequest,
     max_msg_size: usize,
+    compressors: CasCompressors,
     cas_f: impl Fn(BatchReadBlobsRequest) -> Cas,
     bystream_fut: impl Fn(ReadRequest) -> Byt + Sync + Send + Copy,
 ) -> anyhow::Result<DownloadResponse>
@@ -873,19 +1016,27 @@ where
         let size_in_bytes = digest.size_in_bytes;
 
         let resource_name = format!(
-            "{}blobs/{}/{}",
+            "{}{}/{}/{}",
             instance_name.as_resource_prefix(),
+            blobs_resource_segment(compressors.bytestream),
             hash,
             size_in_bytes
         );
 
-        bystream_fut(ReadRequest {
+        let decoder = Decoder::new(compressors.bytestream)?;
+        let responses = bystream_fut(ReadRequest {
             resource_name: resource_name.clone(),
             read_offset: 0,
             read_limit: 0,
         })
         .await
-        .with_context(|| format!("Failed to read {} from Bytestream service", resource_name))
+        .with_context(|| format!("Failed to read {} from Bytestream service", resource_name))?;
+
+        anyhow::Ok(Box::pin(decompress_read_stream(
+            responses,
+            decoder,
+            size_in_bytes,
+        )))
     };
 
     let inlined_digests = request.inlined_digests.unwrap_or_default();
@@ -911,7 +1062,7 @@ where
             let read_blob_req = BatchReadBlobsRequest {
                 instance_name: instance_name.as_str().to_owned(),
                 digests: std::mem::take(&mut curr_digests),
-                acceptable_compressors: vec![compressor::Value::Identity as i32],
+                acceptable_compressors: vec![compressor_to_grpc(compressors.batch_read) as i32],
             };
             requests.push(read_blob_req);
         }
@@ -922,7 +1073,7 @@ where
         let read_blob_req = BatchReadBlobsRequest {
             instance_name: instance_name.as_str().to_owned(),
             digests: std::mem::take(&mut curr_digests),
-            acceptable_compressors: vec![compressor::Value::Identity as i32],
+            acceptable_compressors: vec![compressor_to_grpc(compressors.batch_read) as i32],
         };
         requests.push(read_blob_req);
     }
@@ -935,7 +1086,12 @@ where
         for r in resp.responses.into_iter() {
             let digest = tdigest_from(r.digest.context("Response digest not found.")?);
             check_status(r.status.unwrap_or_default())?;
-            batched_blobs_response.insert(digest, r.data);
+            let data = match compressor_from_grpc(r.compressor)? {
+                CasCompressor::Identity => r.data,
+                compressor => decompress(compressor, r.data, digest.size_in_bytes)
+                    .with_context(|| format!("Failed to decompress digest `{}`", digest))?,
+            };
+            batched_blobs_response.insert(digest, data);
         }
     }
 
@@ -956,9 +1112,8 @@ where
             let mut accum = vec![];
             let mut responses = bystream_fut(digest.clone()).await?;
             while let Some(resp) = responses.next().await {
-                let data = resp
-                    .with_context(|| format!("Failed to fetch inline digest: {digest}"))?
-                    .data;
+                let data =
+                    resp.with_context(|| format!("Failed to fetch inline digest: {digest}"))?;
                 accum.extend_from_slice(&data);
             }
             accum
@@ -1001,9 +1156,7 @@ where
             } else {
                 let mut responses = bystream_fut(req.named_digest.digest.clone()).await?;
                 while let Some(resp) = responses.next().await {
-                    let data = resp
-                        .with_context(|| format!("Failed to fetch file: {:?}", file))?
-                        .data;
+                    let data = resp.with_context(|| format!("Failed to fetch file: {:?}", file))?;
                     file.write_all(&data).await.with_context(|| {
                         format!("Error writing chunk of: {}", req.named_digest.digest)
                     })?;
@@ -1028,10 +1181,46 @@ where
     })
 }
 
+/// Turn a stream of (possibly compressed) `ReadResponse` into a stream of uncompressed chunks,
+/// checking that the total uncompressed size matches what we expect.
+fn decompress_read_stream<S>(
+    responses: Pin<Box<S>>,
+    decoder: Decoder,
+    expected_size: i64,
+) -> impl Stream<Item = anyhow::Result<Vec<u8>>>
+where
+    S: Stream<Item = Result<ReadResponse, tonic::Status>>,
+{
+    futures::stream::try_unfold(
+        (responses, Some(decoder), 0),
+        move |(mut responses, decoder, mut received)| async move {
+            let mut decoder = match decoder {
+                Some(decoder) => decoder,
+                None => return Ok(None),
+            };
+
+            match responses.next().await {
+                Some(resp) => {
+                    let data = decoder.write(&resp?.data)?;
+                    received += data.len() as i64;
+                    anyhow::Ok(Some((data, (responses, Some(decoder), received))))
+                }
+                None => {
+                    let data = decoder.finish()?;
+                    received += data.len() as i64;
+                    check_size(received, expected_size)?;
+                    anyhow::Ok(Some((data, (responses, None, received))))
+                }
+            }
+        },
+    )
+}
+
 async fn upload_impl<Byt, Cas>(
     instance_name: &InstanceName,
     request: UploadRequest,
     max_msg_size: usize,
+    compressors: CasCompressors,
     cas_f: impl Fn(BatchUpdateBlobsRequest) -> Cas + Sync + Send + Copy,
     bystream_fut: impl Fn(Vec<WriteRequest>) -> Byt + Sync + Send + Copy,
 ) -> anyhow::Result<UploadResponse>
@@ -1059,13 +1248,17 @@ where
         let data = blob.blob;
         let client_uuid = uuid::Uuid::new_v4().to_string();
         let resource_name = format!(
-            "{}uploads/{}/blobs/{}/{}",
+            "{}uploads/{}/{}/{}/{}",
             instance_name.as_resource_prefix(),
             client_uuid,
+            blobs_resource_segment(compressors.bytestream),
             hash,
             size
         );
         let fut = async move {
+            let data = compress(compressors.bytestream, data)?;
+            let sent = data.len() as i64;
+
             // Number of complete (non-partial) messages
             let mut upload_segments = vec![];
             for (i, chunk) in data.chunks(max_msg_size).enumerate() {
@@ -1079,7 +1272,7 @@ where
             upload_segments.last_mut().unwrap().finish_write = true;
 
             let resp = bystream_fut(upload_segments).await?;
-            if resp.committed_size != size {
+            if !committed_size_ok(compressors.bytestream, resp.committed_size, size, sent) {
                 return Err(anyhow::anyhow!(
                     "Failed to upload inline blob: invalid committed_size from WriteResponse"
                 ));
@@ -1101,9 +1294,10 @@ where
         }
         let client_uuid = uuid::Uuid::new_v4().to_string();
         let resource_name = format!(
-            "{}uploads/{}/blobs/{}/{}",
+            "{}uploads/{}/{}/{}/{}",
             instance_name.as_resource_prefix(),
             client_uuid,
+            blobs_resource_segment(compressors.bytestream),
             hash.clone(),
             size
         );
@@ -1112,9 +1306,24 @@ where
                 .await
                 .with_context(|| format!("Opening `{name}` for reading failed"))?;
             let mut data = vec![0; max_msg_size];
+            let mut encoder = Encoder::new(compressors.bytestream)?;
 
+            // When compressing, the write offset is the sum of the compressed data sent so far.
             let mut write_offset = 0;
             let mut upload_segments = Vec::new();
+            let mut push_segment = |data: Vec<u8>| {
+                if data.is_empty() {
+                    return;
+                }
+                let length = data.len() as i64;
+                upload_segments.push(WriteRequest {
+                    resource_name: resource_name.to_owned(),
+                    write_offset,
+                    finish_write: false,
+                    data,
+                });
+                write_offset += length;
+            };
             loop {
                 let length = file
                     .read(&mut data)
@@ -1123,21 +1332,17 @@ where
                 if length == 0 {
                     break;
                 }
-                upload_segments.push(WriteRequest {
-                    resource_name: resource_name.to_owned(),
-                    write_offset,
-                    finish_write: false,
-                    data: data[..length].to_owned(),
-                });
-                write_offset += length as i64;
+                push_segment(encoder.write(&data[..length])?);
             }
+            push_segment(encoder.finish()?);
+            let sent = write_offset;
             upload_segments
                 .last_mut()
                 .with_context(|| format!("Read no segments from `{name} "))?
                 .finish_write = true;
 
             let resp = bystream_fut(upload_segments).await?;
-            if resp.committed_size != size {
+            if !committed_size_ok(compressors.bytestream, resp.committed_size, size, sent) {
                 return Err(anyhow::anyhow!(
                     "Failed to upload `{name}`: invalid committed_size from WriteResponse"
                 ));
@@ -1161,8 +1366,8 @@ where
                     BatchUploadRequest::Blob(blob) => {
                         re_request.requests.push(Request {
                             digest: Some(tdigest_to(blob.digest.clone())),
-                            data: blob.blob.clone(),
-                            compressor: compressor::Value::Identity as i32,
+                            data: compress(compressors.batch_update, blob.blob)?,
+                            compressor: compressor_to_grpc(compressors.batch_update) as i32,
                         });
                     }
                     BatchUploadRequest::File(file) => {
@@ -1175,8 +1380,8 @@ where
 
                         re_request.requests.push(Request {
                             digest: Some(tdigest_to(file.digest.clone())),
-                            data,
-                            compressor: compressor::Value::Identity as i32,
+                            data: compress(compressors.batch_update, data)?,
+                            compressor: compressor_to_grpc(compressors.batch_update) as i32,
                         });
                     }
                 }
@@ -1223,6 +1428,16 @@ where
     Ok(UploadResponse {})
 }
 
+/// Check the `committed_size` of a `WriteResponse`. For uncompressed uploads, this is the size
+/// of the blob. For compressed uploads, servers report either the amount of compressed data they
+/// received, or -1 if the blob was already present.
+fn committed_size_ok(compressor: CasCompressor, committed_size: i64, size: i64, sent: i64) -> bool {
+    match compressor {
+        CasCompressor::Identity => committed_size == size,
+        _ => committed_size == -1 || committed_size == sent || committed_size == size,
+    }
+}
+
 fn with_internal_metadata<T>(t: T, metadata: RemoteExecutionMetadata) -> tonic::Request<T> {
     // This is pretty ugly, but the protobuf spec that defines this is internal, so considering
     // field numbers need to be stable anyway (= low risk), and this is not used in prod (= low
@@ -1255,7 +1470,7 @@ fn with_internal_metadata<T>(t: T, metadata: RemoteExecutionMetadata) -> tonic::
 }
 
 /// Replace occurrences of $FOO in a string with the value of the env var $FOO.
-fn substitute_env_vars(s: &str) -> anyhow::Result<String> {
+pub(crate) fn substitute_env_vars(s: &str) -> anyhow::Result<String> {
     substitute_env_vars_impl(s, |v| std::env::var(v))
 }
 
@@ -1358,6 +1573,7 @@ mod tests {
             &InstanceName(None),
             req,
             10000,
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 let digest1 = digest1.clone();
@@ -1464,6 +1680,7 @@ mod tests {
             &InstanceName(None),
             req,
             10, // kept small to simulate a large file download
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 let digest1 = digest1.clone();
@@ -1545,6 +1762,7 @@ mod tests {
             &InstanceName(None),
             req,
             100000,
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 let digest1 = digest1.clone();
@@ -1618,6 +1836,7 @@ mod tests {
             &InstanceName(None),
             req,
             10, // intentionally small value to keep data in the test blobs small
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 let digest1 = digest1.clone();
@@ -1673,6 +1892,7 @@ mod tests {
             &InstanceName(None),
             req,
             100000,
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 async move {
@@ -1711,6 +1931,7 @@ mod tests {
             &InstanceName(Some("instance".to_owned())),
             req,
             0,
+            CasCompressors::default(),
             |_req| async { panic!("not called") },
             |req| async move {
                 assert_eq!(req.resource_name, "instance/blobs/aa/0");
@@ -1780,6 +2001,7 @@ mod tests {
             &InstanceName(None),
             req,
             10000,
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 let digest1 = digest1.clone();
@@ -1862,6 +2084,7 @@ mod tests {
             &InstanceName(None),
             req,
             10, // kept small to simulate a large file upload
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 let digest1 = digest1.clone();
@@ -1935,6 +2158,7 @@ mod tests {
             &InstanceName(None),
             req,
             10, // kept small to simulate a large inlined upload
+            CasCompressors::default(),
             |req| {
                 let res = res.clone();
                 let digest1 = digest1.clone();
@@ -1995,6 +2219,7 @@ mod tests {
             &InstanceName(None), // TODO
             req,
             10,
+            CasCompressors::default(),
             |_req| async move {
                 panic!("This should not be called as there are no blobs to upload in batch");
             },
@@ -2055,6 +2280,7 @@ mod tests {
             &InstanceName(None),
             req,
             3,
+            CasCompressors::default(),
             |_req| async move {
                 panic!("Not called");
             },
@@ -2095,6 +2321,7 @@ mod tests {
             &InstanceName(None),
             req,
             0,
+            CasCompressors::default(),
             |_req| async move {
                 panic!("Not called");
             },
@@ -2140,6 +2367,7 @@ mod tests {
             &InstanceName(Some("instance".to_owned())),
             req,
             1,
+            CasCompressors::default(),
             |_req| async move {
                 panic!("Not called");
             },
@@ -2154,6 +2382,178 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_download_compressed() -> anyhow::Result<()> {
+        let small_data = vec![b'a'; 8];
+        let large_data = vec![b'b'; 77];
+
+        let digest1 = TDigest {
+            hash: "aa".to_owned(),
+            size_in_bytes: small_data.len() as i64,
+            ..Default::default()
+        };
+
+        let digest2 = TDigest {
+            hash: "xl".to_owned(),
+            size_in_bytes: large_data.len() as i64,
+            ..Default::default()
+        };
+
+        let req = DownloadRequest {
+            inlined_digests: Some(vec![digest1.clone(), digest2.clone()]),
+            ..Default::default()
+        };
+
+        let res = BatchReadBlobsResponse {
+            responses: vec![batch_read_blobs_response::Response {
+                digest: Some(tdigest_to(digest1.clone())),
+                data: compress(CasCompressor::Deflate, small_data.clone())?,
+                compressor: compressor_to_grpc(CasCompressor::Deflate) as i32,
+                ..Default::default()
+            }],
+        };
+
+        let compressed = compress(CasCompressor::Zstd, large_data.clone())?;
+        let (chunk1, chunk2) = compressed.split_at(compressed.len() / 2);
+        let read_responses = vec![
+            ReadResponse {
+                data: chunk1.to_vec(),
+            },
+            ReadResponse {
+                data: chunk2.to_vec(),
+            },
+        ];
+
+        let res = download_impl(
+            &InstanceName(None),
+            req,
+            50,
+            CasCompressors {
+                bytestream: CasCompressor::Zstd,
+                batch_update: CasCompressor::Identity,
+                batch_read: CasCompressor::Deflate,
+            },
+            |req| {
+                let res = res.clone();
+                async move {
+                    assert_eq!(
+                        req.acceptable_compressors,
+                        vec![compressor_to_grpc(CasCompressor::Deflate) as i32]
+                    );
+                    Ok(res)
+                }
+            },
+            |req| {
+                let read_responses = read_responses.clone();
+                async move {
+                    assert_eq!(req.resource_name, "compressed-blobs/zstd/xl/77");
+                    anyhow::Ok(Box::pin(futures::stream::iter(
+                        read_responses.into_iter().map(Ok),
+                    )))
+                }
+            },
+        )
+        .await?;
+
+        let blobs = res.inlined_blobs.unwrap();
+        assert_eq!(blobs[0].blob, small_data);
+        assert_eq!(blobs[1].blob, large_data);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_compressed() -> anyhow::Result<()> {
+        let small_data = vec![b'a'; 8];
+        let large_data = vec![b'b'; 77];
+
+        let digest1 = TDigest {
+            hash: "aa".to_owned(),
+            size_in_bytes: small_data.len() as i64,
+            ..Default::default()
+        };
+
+        let digest2 = TDigest {
+            hash: "xl".to_owned(),
+            size_in_bytes: large_data.len() as i64,
+            ..Default::default()
+        };
+
+        let req = UploadRequest {
+            inlined_blobs_with_digest: Some(vec![
+                InlinedBlobWithDigest {
+                    digest: digest1.clone(),
+                    blob: small_data.clone(),
+                    ..Default::default()
+                },
+                InlinedBlobWithDigest {
+                    digest: digest2.clone(),
+                    blob: large_data.clone(),
+                    ..Default::default()
+                },
+            ]),
+            ..Default::default()
+        };
+
+        upload_impl(
+            &InstanceName(None),
+            req,
+            50,
+            CasCompressors {
+                bytestream: CasCompressor::Zstd,
+                batch_update: CasCompressor::Deflate,
+                batch_read: CasCompressor::Identity,
+            },
+            |req| {
+                let small_data = small_data.clone();
+                async move {
+                    assert_eq!(req.requests.len(), 1);
+                    assert_eq!(
+                        req.requests[0].compressor,
+                        compressor_to_grpc(CasCompressor::Deflate) as i32
+                    );
+                    assert_eq!(
+                        decompress(
+                            CasCompressor::Deflate,
+                            req.requests[0].data.clone(),
+                            small_data.len() as i64
+                        )?,
+                        small_data
+                    );
+                    Ok(BatchUpdateBlobsResponse {
+                        responses: vec![batch_update_blobs_response::Response {
+                            digest: req.requests[0].digest.clone(),
+                            status: None,
+                        }],
+                    })
+                }
+            },
+            |write_reqs| {
+                let large_data = large_data.clone();
+                async move {
+                    assert!(
+                        write_reqs[0]
+                            .resource_name
+                            .ends_with("/compressed-blobs/zstd/xl/77")
+                    );
+                    let compressed = write_reqs
+                        .iter()
+                        .flat_map(|r| r.data.iter().copied())
+                        .collect::<Vec<_>>();
+                    assert_eq!(
+                        decompress(CasCompressor::Zstd, compressed, large_data.len() as i64)?,
+                        large_data
+                    );
+                    // The blob already existed on the server.
+                    anyhow::Ok(WriteResponse { committed_size: -1 })
+                }
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
     #[test]
     fn test_substitute_env_vars() {
         let getter = |s: &str| match s {
@@ -0,0 +1,255 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::io::Write;
+
+use anyhow::Context;
+use buck2_re_configuration::CasCompressor;
+use re_grpc_proto::build::bazel::remote::execution::v2::compressor;
+
+/// Zstd level used for uploads. Level 1 is what Bazel uses too: it is fast enough to not be a
+/// bottleneck and gets most of the size benefits for typical build artifacts.
+const ZSTD_LEVEL: i32 = 1;
+
+pub(crate) fn compressor_to_grpc(compressor: CasCompressor) -> compressor::Value {
+    match compressor {
+        CasCompressor::Identity => compressor::Value::Identity,
+        CasCompressor::Zstd => compressor::Value::Zstd,
+        CasCompressor::Deflate => compressor::Value::Deflate,
+    }
+}
+
+pub(crate) fn compressor_from_grpc(value: i32) -> anyhow::Result<CasCompressor> {
+    match compressor::Value::from_i32(value) {
+        Some(compressor::Value::Identity) => Ok(CasCompressor::Identity),
+        Some(compressor::Value::Zstd) => Ok(CasCompressor::Zstd),
+        Some(compressor::Value::Deflate) => Ok(CasCompressor::Deflate),
+        None => Err(anyhow::anyhow!("Unknown compressor: `{}`", value)),
+    }
+}
+
+/// The ByteStream resource name segment for blobs using this compressor, e.g.
+/// `compressed-blobs/zstd`.
+pub(crate) fn blobs_resource_segment(compressor: CasCompressor) -> &'static str {
+    match compressor {
+        CasCompressor::Identity => "blobs",
+        CasCompressor::Zstd => "compressed-blobs/zstd",
+        CasCompressor::Deflate => "compressed-blobs/deflate",
+    }
+}
+
+/// The compressors negotiated with the server for the two kinds of CAS transfers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CasCompressors {
+    /// Compressor for ByteStream reads and writes.
+    pub(crate) bytestream: CasCompressor,
+    /// Compressor for `BatchUpdateBlobs`.
+    pub(crate) batch_update: CasCompressor,
+    /// Compressor for `BatchReadBlobs`, which servers support for all of `supported_compressors`.
+    pub(crate) batch_read: CasCompressor,
+}
+
+impl CasCompressors {
+    /// Pick the compressors to use given what the user asked for and what the server supports.
+    pub(crate) fn negotiate(
+        preferred: CasCompressor,
+        supported_compressors: &[i32],
+        supported_batch_update_compressors: &[i32],
+    ) -> Self {
+        let pick = |supported: &[i32]| {
+            if supported.contains(&(compressor_to_grpc(preferred) as i32)) {
+                preferred
+            } else {
+                CasCompressor::Identity
+            }
+        };
+
+        Self {
+            bytestream: pick(supported_compressors),
+            batch_update: pick(supported_batch_update_compressors),
+            batch_read: pick(supported_compressors),
+        }
+    }
+}
+
+/// Compress a complete blob.
+pub(crate) fn compress(compressor: CasCompressor, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(compressor)?;
+    let mut out = encoder.write(&data)?;
+    out.extend(encoder.finish()?);
+    Ok(out)
+}
+
+/// Decompress a complete blob, checking it has the expected uncompressed size.
+pub(crate) fn decompress(
+    compressor: CasCompressor,
+    data: Vec<u8>,
+    expected_size: i64,
+) -> anyhow::Result<Vec<u8>> {
+    let mut decoder = Decoder::new(compressor)?;
+    let mut out = decoder.write(&data)?;
+    out.extend(decoder.finish()?);
+    check_size(out.len() as i64, expected_size)?;
+    Ok(out)
+}
+
+pub(crate) fn check_size(actual: i64, expected: i64) -> anyhow::Result<()> {
+    if actual != expected {
+        return Err(anyhow::anyhow!(
+            "Decompressed blob has size {} but {} was expected",
+            actual,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// A streaming compressor: feed it chunks of uncompressed data, get back chunks of compressed
+/// data.
+pub(crate) enum Encoder {
+    Identity,
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Deflate(flate2::write::DeflateEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    pub(crate) fn new(compressor: CasCompressor) -> anyhow::Result<Self> {
+        Ok(match compressor {
+            CasCompressor::Identity => Self::Identity,
+            CasCompressor::Zstd => Self::Zstd(
+                zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL)
+                    .context("Error creating zstd encoder")?,
+            ),
+            CasCompressor::Deflate => Self::Deflate(flate2::write::DeflateEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+        })
+    }
+
+    /// Compress a chunk, returning whatever compressed output is available so far.
+    pub(crate) fn write(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(data.to_vec()),
+            Self::Zstd(e) => {
+                e.write_all(data).context("Error compressing (zstd)")?;
+                Ok(std::mem::take(e.get_mut()))
+            }
+            Self::Deflate(e) => {
+                e.write_all(data).context("Error compressing (deflate)")?;
+                Ok(std::mem::take(e.get_mut()))
+            }
+        }
+    }
+
+    /// Flush the remaining compressed output.
+    pub(crate) fn finish(self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(Vec::new()),
+            Self::Zstd(e) => e.finish().context("Error compressing (zstd)"),
+            Self::Deflate(e) => e.finish().context("Error compressing (deflate)"),
+        }
+    }
+}
+
+/// A streaming decompressor: feed it chunks of compressed data, get back chunks of uncompressed
+/// data.
+pub(crate) enum Decoder {
+    Identity,
+    Zstd(zstd::stream::write::Decoder<'static, Vec<u8>>),
+    Deflate(flate2::write::DeflateDecoder<Vec<u8>>),
+}
+
+impl Decoder {
+    pub(crate) fn new(compressor: CasCompressor) -> anyhow::Result<Self> {
+        Ok(match compressor {
+            CasCompressor::Identity => Self::Identity,
+            CasCompressor::Zstd => Self::Zstd(
+                zstd::stream::write::Decoder::new(Vec::new())
+                    .context("Error creating zstd decoder")?,
+            ),
+            CasCompressor::Deflate => Self::Deflate(flate2::write::DeflateDecoder::new(Vec::new())),
+        })
+    }
+
+    /// Decompress a chunk, returning whatever uncompressed output is available so far.
+    pub(crate) fn write(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(data.to_vec()),
+            Self::Zstd(d) => {
+                d.write_all(data).context("Error decompressing (zstd)")?;
+                Ok(std::mem::take(d.get_mut()))
+            }
+            Self::Deflate(d) => {
+                d.write_all(data).context("Error decompressing (deflate)")?;
+                Ok(std::mem::take(d.get_mut()))
+            }
+        }
+    }
+
+    /// Flush the remaining uncompressed output.
+    pub(crate) fn finish(self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(Vec::new()),
+            Self::Zstd(mut d) => {
+                d.flush().context("Error decompressing (zstd)")?;
+                Ok(d.into_inner())
+            }
+            Self::Deflate(d) => d.finish().context("Error decompressing (deflate)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() -> anyhow::Result<()> {
+        let data = b"hello hello hello hello hello world".repeat(100);
+        for compressor in [
+            CasCompressor::Identity,
+            CasCompressor::Zstd,
+            CasCompressor::Deflate,
+        ] {
+            let compressed = compress(compressor, data.clone())?;
+            if compressor != CasCompressor::Identity {
+                assert!(compressed.len() < data.len());
+            }
+            assert_eq!(decompress(compressor, compressed, data.len() as i64)?, data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_size_mismatch() -> anyhow::Result<()> {
+        let compressed = compress(CasCompressor::Zstd, b"abc".to_vec())?;
+        assert!(decompress(CasCompressor::Zstd, compressed, 4).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let zstd = compressor::Value::Zstd as i32;
+        let deflate = compressor::Value::Deflate as i32;
+
+        assert_eq!(
+            CasCompressors::negotiate(CasCompressor::Zstd, &[zstd, deflate], &[deflate]),
+            CasCompressors {
+                bytestream: CasCompressor::Zstd,
+                batch_update: CasCompressor::Identity,
+                batch_read: CasCompressor::Zstd,
+            }
+        );
+        assert_eq!(
+            CasCompressors::negotiate(CasCompressor::Identity, &[zstd], &[zstd]),
+            CasCompressors::default()
+        );
+    }
+}
@@ -0,0 +1,730 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A cache-only backend speaking the plain HTTP protocol used by Bazel's `--remote_cache=http://`
+//! (and supported by e.g. bazel-remote, nginx with WebDAV, or S3-compatible storage).
+//!
+//! Action results are stored as serialized `ActionResult` protos under `/ac/<action hash>`, and
+//! blobs are stored under `/cas/<blob hash>`.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use buck2_http::retries::http_retry;
+use buck2_http::retries::AsHttpError;
+use buck2_http::retries::HttpError;
+use buck2_http::HttpClient;
+use buck2_http::HttpClientBuilder;
+use buck2_re_configuration::Buck2OssReConfiguration;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::stream::StreamExt;
+use futures::stream::TryStreamExt;
+use gazebo::prelude::*;
+use http::header::HeaderName;
+use http::header::HeaderValue;
+use http::Method;
+use http::Request;
+use http::StatusCode;
+use prost::Message;
+use re_grpc_proto::build::bazel::remote::execution::v2::ActionResult;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecutedActionMetadata;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputDirectory;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputFile;
+use sha1::Digest;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+use crate::client::convert_action_result;
+use crate::client::substitute_env_vars;
+use crate::client::tdigest_to;
+use crate::client::tstatus_ok;
+use crate::compression::check_size;
+use crate::error::*;
+use crate::request::*;
+use crate::response::*;
+
+/// Cache servers are often plain web servers, so don't open an unbounded number of connections.
+const CONCURRENT_REQUEST_LIMIT: usize = 64;
+
+fn retry_intervals() -> Vec<Duration> {
+    vec![2, 4, 8].into_iter().map(Duration::from_secs).collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+struct HttpCacheError(HttpError);
+
+impl AsHttpError for HttpCacheError {
+    fn as_http_error(&self) -> Option<&HttpError> {
+        Some(&self.0)
+    }
+}
+
+impl HttpCacheError {
+    fn status(&self) -> Option<StatusCode> {
+        match &self.0 {
+            HttpError::Client(buck2_http::HttpError::Status { status, .. }) => Some(*status),
+            _ => None,
+        }
+    }
+
+    fn is_not_found(&self) -> bool {
+        self.status() == Some(StatusCode::NOT_FOUND)
+    }
+
+    fn is_permission_denied(&self) -> bool {
+        matches!(
+            self.status(),
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+        )
+    }
+}
+
+/// The digest algorithms Buck2 can use with the HTTP cache. Keyed BLAKE3 isn't supported since
+/// we don't have the key here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DigestAlgorithm {
+    Sha1,
+    Sha256,
+    Blake3,
+}
+
+impl DigestAlgorithm {
+    fn parse(algorithm: Option<&str>) -> anyhow::Result<Self> {
+        match algorithm.map(|a| a.to_uppercase()).as_deref() {
+            None | Some("SHA256") => Ok(Self::Sha256),
+            Some("SHA1") => Ok(Self::Sha1),
+            Some("BLAKE3") => Ok(Self::Blake3),
+            Some(algorithm) => Err(anyhow::anyhow!(
+                "Digest algorithm `{}` is not supported with the HTTP cache",
+                algorithm
+            )),
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            Self::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            Self::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+}
+
+/// Computes digests incrementally, so that downloads can be verified as they are streamed.
+enum Hasher {
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(h) => h.update(data),
+            Self::Sha256(h) => h.update(data),
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Self::Sha1(h) => format!("{:x}", h.finalize()),
+            Self::Sha256(h) => format!("{:x}", h.finalize()),
+            Self::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+fn check_hash(actual: &str, digest: &TDigest) -> anyhow::Result<()> {
+    if actual != digest.hash {
+        return Err(anyhow::anyhow!(
+            "Downloaded blob has hash {} but {} was expected",
+            actual,
+            digest.hash
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) struct HttpCacheClient {
+    client: HttpClient,
+    /// Base URL of the cache, without a trailing slash.
+    address: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    digest_algorithm: DigestAlgorithm,
+}
+
+impl HttpCacheClient {
+    pub(crate) fn new(address: &str, opts: &Buck2OssReConfiguration) -> anyhow::Result<Self> {
+        let address = substitute_env_vars(address).context("Invalid address")?;
+
+        if opts.tls_ca_certs.is_some() {
+            return Err(anyhow::anyhow!(
+                "`tls_ca_certs` is not supported with the HTTP cache, system roots are used instead"
+            ));
+        }
+
+        let digest_algorithm = DigestAlgorithm::parse(opts.digest_algorithm.as_deref())?;
+
+        let mut builder = HttpClientBuilder::oss()?;
+        if let Some(tls_client_cert) = &opts.tls_client_cert {
+            let tls_client_cert =
+                substitute_env_vars(tls_client_cert).context("Invalid `tls_client_cert`")?;
+            builder
+                .with_client_auth_cert(&tls_client_cert)
+                .with_context(|| format!("Error loading `{}`", tls_client_cert))?;
+        }
+
+        let headers = opts
+            .http_headers
+            .iter()
+            .map(|h| {
+                let key = substitute_env_vars(&h.key)?;
+                let value = substitute_env_vars(&h.value)?;
+
+                let name = HeaderName::from_bytes(key.as_bytes())
+                    .with_context(|| format!("Invalid key in header: `{}: {}`", key, value))?;
+                let value = HeaderValue::from_str(&value)
+                    .with_context(|| format!("Invalid value in header: `{}: {}`", key, value))?;
+
+                anyhow::Ok((name, value))
+            })
+            .collect::<Result<_, _>>()
+            .context("Error converting headers")?;
+
+        Ok(Self {
+            client: builder.build(),
+            address: address.trim_end_matches('/').to_owned(),
+            headers,
+            digest_algorithm,
+        })
+    }
+
+    fn ac_url(&self, digest: &TDigest) -> String {
+        format!("{}/ac/{}", self.address, digest.hash)
+    }
+
+    fn cas_url(&self, digest: &TDigest) -> String {
+        format!("{}/cas/{}", self.address, digest.hash)
+    }
+
+    /// Send a request, retrying on transient errors. Non-success statuses are returned as errors.
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        body: Bytes,
+    ) -> Result<BoxStream<'_, hyper::Result<Bytes>>, HttpCacheError> {
+        http_retry(
+            || async {
+                let mut request = Request::builder().method(method.clone()).uri(url);
+                for (name, value) in &self.headers {
+                    request = request.header(name, value);
+                }
+                let request = request
+                    .body(body.clone())
+                    .map_err(|e| HttpCacheError(buck2_http::HttpError::from(e).into()))?;
+
+                let response = self
+                    .client
+                    .request(request)
+                    .await
+                    .map_err(|e| HttpCacheError(e.into()))?;
+                Ok(response.into_body())
+            },
+            retry_intervals(),
+        )
+        .await
+    }
+
+    /// Fetch a CAS blob, mapping a 404 to a `NOT_FOUND` error like the gRPC backend does.
+    async fn get_blob(
+        &self,
+        digest: &TDigest,
+    ) -> anyhow::Result<BoxStream<'_, hyper::Result<Bytes>>> {
+        let url = self.cas_url(digest);
+        match self.send(Method::GET, &url, Bytes::new()).await {
+            Ok(body) => Ok(body),
+            Err(e) if e.is_not_found() => Err(REClientError {
+                code: TCode::NOT_FOUND,
+                message: format!("Blob `{}` not found in HTTP cache", digest),
+            }
+            .into()),
+            Err(e) => Err(anyhow::Error::new(e).context(format!("Error fetching `{}`", url))),
+        }
+    }
+
+    async fn contains_blob(&self, digest: &TDigest) -> anyhow::Result<bool> {
+        if digest.size_in_bytes == 0 {
+            return Ok(true);
+        }
+
+        let url = self.cas_url(digest);
+        match self.send(Method::HEAD, &url, Bytes::new()).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.is_not_found() => Ok(false),
+            Err(e) => Err(anyhow::Error::new(e).context(format!("Error querying `{}`", url))),
+        }
+    }
+
+    async fn put_blob(&self, digest: &TDigest, data: Bytes) -> anyhow::Result<()> {
+        // Empty blobs are never fetched, see `download`.
+        if digest.size_in_bytes == 0 {
+            return Ok(());
+        }
+
+        let url = self.cas_url(digest);
+        self.send(Method::PUT, &url, data)
+            .await
+            .with_context(|| format!("Error uploading `{}`", url))?;
+        Ok(())
+    }
+
+    pub(crate) async fn upload_blob(&self, blob: Vec<u8>) -> anyhow::Result<TDigest> {
+        let mut hasher = self.digest_algorithm.hasher();
+        hasher.update(&blob);
+        let digest = TDigest {
+            hash: hasher.finalize(),
+            size_in_bytes: blob.len() as i64,
+            ..Default::default()
+        };
+        self.put_blob(&digest, blob.into()).await?;
+        Ok(digest)
+    }
+
+    pub(crate) async fn get_action_result(
+        &self,
+        request: ActionResultRequest,
+    ) -> anyhow::Result<ActionResultResponse> {
+        let url = self.ac_url(&request.digest);
+        let body = match self.send(Method::GET, &url, Bytes::new()).await {
+            Ok(body) => body,
+            Err(e) if e.is_not_found() => {
+                return Err(REClientError {
+                    code: TCode::NOT_FOUND,
+                    message: format!("Action result `{}` not found in HTTP cache", request.digest),
+                }
+                .into());
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(format!("Error fetching `{}`", url)));
+            }
+        };
+
+        let data = buck2_http::to_bytes(body)
+            .await
+            .with_context(|| format!("Error reading `{}`", url))?;
+        let action_result = ActionResult::decode(data)
+            .with_context(|| format!("Invalid action result at `{}`", url))?;
+
+        Ok(ActionResultResponse {
+            action_result: convert_action_result(action_result)?,
+            ttl: 0,
+        })
+    }
+
+    pub(crate) async fn write_action_result(
+        &self,
+        request: WriteActionResultRequest,
+    ) -> anyhow::Result<WriteActionResultResponse> {
+        let url = self.ac_url(&request.action_digest);
+        let data = action_result_to_grpc(request.action_result.clone()).encode_to_vec();
+
+        match self.send(Method::PUT, &url, data.into()).await {
+            Ok(_) => Ok(WriteActionResultResponse {
+                actual_action_result: request.action_result,
+                ttl_seconds: 0,
+            }),
+            Err(e) if e.is_permission_denied() => Err(REClientError {
+                code: TCode::PERMISSION_DENIED,
+                message: e.to_string(),
+            }
+            .into()),
+            Err(e) => Err(anyhow::Error::new(e).context(format!("Error uploading `{}`", url))),
+        }
+    }
+
+    pub(crate) async fn upload(&self, request: UploadRequest) -> anyhow::Result<UploadResponse> {
+        let upload_only_missing = request.upload_only_missing;
+        let mut uploads: Vec<BoxFuture<anyhow::Result<()>>> = Vec::new();
+
+        for blob in request.inlined_blobs_with_digest.unwrap_or_default() {
+            uploads.push(Box::pin(async move {
+                if upload_only_missing && self.contains_blob(&blob.digest).await? {
+                    return Ok(());
+                }
+                self.put_blob(&blob.digest, blob.blob.into()).await
+            }));
+        }
+
+        for file in request.files_with_digest.unwrap_or_default() {
+            uploads.push(Box::pin(async move {
+                if upload_only_missing && self.contains_blob(&file.digest).await? {
+                    return Ok(());
+                }
+                // Read lazily so that we only have as many files in memory as we have requests
+                // in flight.
+                let data = tokio::fs::read(&file.name)
+                    .await
+                    .with_context(|| format!("Opening `{}` for reading failed", file.name))?;
+                self.put_blob(&file.digest, data.into()).await
+            }));
+        }
+
+        futures::stream::iter(uploads)
+            .buffer_unordered(CONCURRENT_REQUEST_LIMIT)
+            .try_collect::<Vec<()>>()
+            .await?;
+
+        Ok(UploadResponse {})
+    }
+
+    pub(crate) async fn download(
+        &self,
+        request: DownloadRequest,
+    ) -> anyhow::Result<DownloadResponse> {
+        let inlined_blobs = futures::stream::iter(request.inlined_digests.unwrap_or_default())
+            .map(|digest| async move {
+                let blob = if digest.size_in_bytes == 0 {
+                    Vec::new()
+                } else {
+                    let body = self.get_blob(&digest).await?;
+                    buck2_http::to_bytes(body)
+                        .await
+                        .with_context(|| format!("Error reading digest `{}`", digest))?
+                        .to_vec()
+                };
+                check_size(blob.len() as i64, digest.size_in_bytes)?;
+                if !blob.is_empty() {
+                    let mut hasher = self.digest_algorithm.hasher();
+                    hasher.update(&blob);
+                    check_hash(&hasher.finalize(), &digest)?;
+                }
+                anyhow::Ok(InlinedDigestWithStatus {
+                    digest,
+                    status: tstatus_ok(),
+                    blob,
+                })
+            })
+            .buffered(CONCURRENT_REQUEST_LIMIT)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        futures::stream::iter(request.file_digests.unwrap_or_default())
+            .map(|req| async move {
+                self.download_file(&req).await.with_context(|| {
+                    format!(
+                        "Error downloading digest `{}` to `{}`",
+                        req.named_digest.digest, req.named_digest.name,
+                    )
+                })
+            })
+            .buffer_unordered(CONCURRENT_REQUEST_LIMIT)
+            .try_collect::<Vec<()>>()
+            .await?;
+
+        Ok(DownloadResponse {
+            inlined_blobs: Some(inlined_blobs),
+            directories: None,
+        })
+    }
+
+    async fn download_file(&self, req: &NamedDigestWithPermissions) -> anyhow::Result<()> {
+        let digest = &req.named_digest.digest;
+
+        let mut opts = OpenOptions::new();
+        opts.read(true).write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            if req.is_executable {
+                opts.mode(0o755);
+            } else {
+                opts.mode(0o644);
+            }
+        }
+
+        let mut file = opts
+            .open(&req.named_digest.name)
+            .await
+            .context("Error opening")?;
+
+        if digest.size_in_bytes > 0 {
+            let mut body = self.get_blob(digest).await?;
+            let mut received = 0;
+            let mut hasher = self.digest_algorithm.hasher();
+            while let Some(chunk) = body.try_next().await.context("Error reading response")? {
+                received += chunk.len() as i64;
+                hasher.update(&chunk);
+                file.write_all(&chunk)
+                    .await
+                    .with_context(|| format!("Error writing chunk of: {}", digest))?;
+            }
+            check_size(received, digest.size_in_bytes)?;
+            check_hash(&hasher.finalize(), digest)?;
+        }
+
+        file.flush().await.context("Error flushing")?;
+        Ok(())
+    }
+
+    pub(crate) async fn get_digests_ttl(
+        &self,
+        request: GetDigestsTtlRequest,
+    ) -> anyhow::Result<GetDigestsTtlResponse> {
+        let digests_with_ttl = futures::stream::iter(request.digests)
+            .map(|digest| async move {
+                // NOTE: HTTP caches don't tell us how long they'll keep blobs around, so use the
+                // same arbitrary TTL as the gRPC backend for blobs that are present.
+                let ttl = if self.contains_blob(&digest).await? {
+                    60
+                } else {
+                    0
+                };
+                anyhow::Ok(DigestWithTtl { digest, ttl })
+            })
+            .buffer_unordered(CONCURRENT_REQUEST_LIMIT)
+            .try_collect()
+            .await?;
+
+        Ok(GetDigestsTtlResponse { digests_with_ttl })
+    }
+}
+
+fn ttimestamp_to(ts: TTimestamp) -> Option<prost_types::Timestamp> {
+    Some(prost_types::Timestamp {
+        seconds: ts.seconds,
+        nanos: ts.nanos,
+    })
+}
+
+/// The inverse of `convert_action_result`, used to store action results in the cache.
+fn action_result_to_grpc(action_result: TActionResult2) -> ActionResult {
+    let metadata = action_result.execution_metadata;
+
+    ActionResult {
+        output_files: action_result
+            .output_files
+            .into_map(|output_file| OutputFile {
+                path: output_file.name,
+                digest: Some(tdigest_to(output_file.digest.digest)),
+                is_executable: output_file.executable,
+                ..Default::default()
+            }),
+        output_directories: action_result
+            .output_directories
+            .into_map(|output_directory| OutputDirectory {
+                path: output_directory.path,
+                tree_digest: Some(tdigest_to(output_directory.tree_digest)),
+                ..Default::default()
+            }),
+        exit_code: action_result.exit_code,
+        stdout_raw: action_result.stdout_raw.unwrap_or_default(),
+        stdout_digest: action_result.stdout_digest.map(tdigest_to),
+        stderr_raw: action_result.stderr_raw.unwrap_or_default(),
+        stderr_digest: action_result.stderr_digest.map(tdigest_to),
+        execution_metadata: Some(ExecutedActionMetadata {
+            worker: metadata.worker,
+            queued_timestamp: ttimestamp_to(metadata.queued_timestamp),
+            worker_start_timestamp: ttimestamp_to(metadata.worker_start_timestamp),
+            worker_completed_timestamp: ttimestamp_to(metadata.worker_completed_timestamp),
+            input_fetch_start_timestamp: ttimestamp_to(metadata.input_fetch_start_timestamp),
+            input_fetch_completed_timestamp: ttimestamp_to(
+                metadata.input_fetch_completed_timestamp,
+            ),
+            execution_start_timestamp: ttimestamp_to(metadata.execution_start_timestamp),
+            execution_completed_timestamp: ttimestamp_to(metadata.execution_completed_timestamp),
+            output_upload_start_timestamp: ttimestamp_to(metadata.output_upload_start_timestamp),
+            output_upload_completed_timestamp: ttimestamp_to(
+                metadata.output_upload_completed_timestamp,
+            ),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use httptest::matchers::*;
+    use httptest::responders::*;
+    use httptest::Expectation;
+    use httptest::Server;
+
+    use super::*;
+
+    /// SHA256 of `foo`.
+    const FOO: &str = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+    const FOO_PATH: &str = "/cas/2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+    /// SHA256 of `bar`.
+    const BAR: &str = "fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9";
+    const BAR_PATH: &str = "/cas/fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9";
+
+    fn digest(hash: &str, size_in_bytes: i64) -> TDigest {
+        TDigest {
+            hash: hash.to_owned(),
+            size_in_bytes,
+            ..Default::default()
+        }
+    }
+
+    fn client(server: &Server) -> anyhow::Result<HttpCacheClient> {
+        HttpCacheClient::new(&server.url_str("/"), &Buck2OssReConfiguration::default())
+    }
+
+    #[test]
+    fn test_action_result_round_trip() -> anyhow::Result<()> {
+        let action_result = TActionResult2 {
+            output_files: vec![TFile {
+                digest: DigestWithStatus {
+                    digest: digest("aa", 3),
+                    status: tstatus_ok(),
+                    _dot_dot_default: (),
+                },
+                name: "out/foo".to_owned(),
+                executable: true,
+                ..Default::default()
+            }],
+            exit_code: 1,
+            stdout_raw: Some(b"hello".to_vec()),
+            stderr_digest: Some(digest("bb", 10)),
+            ..Default::default()
+        };
+
+        let converted = convert_action_result(action_result_to_grpc(action_result))?;
+        assert_eq!(converted.output_files.len(), 1);
+        assert_eq!(converted.output_files[0].name, "out/foo");
+        assert_eq!(converted.output_files[0].digest.digest, digest("aa", 3));
+        assert!(converted.output_files[0].executable);
+        assert_eq!(converted.exit_code, 1);
+        assert_eq!(converted.stdout_raw, Some(b"hello".to_vec()));
+        assert_eq!(converted.stderr_digest, Some(digest("bb", 10)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_action_cache_miss() -> anyhow::Result<()> {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/ac/aa"))
+                .respond_with(status_code(404)),
+        );
+
+        let err = client(&server)?
+            .get_action_result(ActionResultRequest {
+                digest: digest("aa", 3),
+                ..Default::default()
+            })
+            .await
+            .err()
+            .context("Expected a cache miss")?;
+        assert_eq!(
+            err.downcast_ref::<REClientError>().map(|e| e.code.clone()),
+            Some(TCode::NOT_FOUND)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download() -> anyhow::Result<()> {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", FOO_PATH))
+                .respond_with(status_code(200).body("foo")),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", BAR_PATH))
+                .respond_with(status_code(200).body("bar")),
+        );
+
+        let work = tempfile::tempdir()?;
+        let path = work.path().join("path");
+        let path = path.to_str().context("tempdir is not utf8")?;
+
+        let res = client(&server)?
+            .download(DownloadRequest {
+                inlined_digests: Some(vec![digest(BAR, 3)]),
+                file_digests: Some(vec![NamedDigestWithPermissions {
+                    named_digest: NamedDigest {
+                        name: path.to_owned(),
+                        digest: digest(FOO, 3),
+                        ..Default::default()
+                    },
+                    is_executable: false,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            })
+            .await?;
+
+        assert_eq!(tokio::fs::read(&path).await?, b"foo");
+        let inlined_blobs = res.inlined_blobs.context("Expected inlined blobs")?;
+        assert_eq!(inlined_blobs.len(), 1);
+        assert_eq!(inlined_blobs[0].blob, b"bar");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_hash_mismatch() -> anyhow::Result<()> {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", FOO_PATH))
+                .respond_with(status_code(200).body("bar")),
+        );
+
+        let res = client(&server)?
+            .download(DownloadRequest {
+                inlined_digests: Some(vec![digest(FOO, 3)]),
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_blob_round_trip() -> anyhow::Result<()> {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(httptest::all_of![
+                request::method_path("PUT", FOO_PATH),
+                request::body("foo"),
+            ])
+            .respond_with(status_code(200)),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", FOO_PATH))
+                .respond_with(status_code(200).body("foo")),
+        );
+
+        let client = client(&server)?;
+        let uploaded = client.upload_blob(b"foo".to_vec()).await?;
+        assert_eq!(uploaded, digest(FOO, 3));
+
+        let res = client
+            .download(DownloadRequest {
+                inlined_digests: Some(vec![uploaded]),
+                ..Default::default()
+            })
+            .await?;
+        let inlined_blobs = res.inlined_blobs.context("Expected inlined blobs")?;
+        assert_eq!(inlined_blobs.len(), 1);
+        assert_eq!(inlined_blobs[0].blob, b"foo");
+
+        Ok(())
+    }
+}
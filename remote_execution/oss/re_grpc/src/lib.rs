@@ -8,9 +8,11 @@
  */
 
 mod client;
+mod compression;
 mod digest;
 mod error;
 mod grpc;
+mod http_cache;
 mod metadata;
 mod request;
 mod response;
@@ -25,6 +25,7 @@ use starlark_syntax::syntax::ast::Stmt;
 use starlark_syntax::syntax::module::AstModuleFields;
 use thiserror::Error;
 
+use crate::analysis::fix::LintFix;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::analysis::EvalSeverity;
@@ -56,54 +57,76 @@ impl LintWarning for Dubious {
     }
 }
 
+/// A dictionary key, which is equal to another if they always evaluate to the same key.
+#[derive(PartialEq, Eq, Hash)]
+pub(crate) enum Key<'a> {
+    Int(StarlarkInt),
+    Float(u64),
+    String(&'a str),
+    Identifier(&'a str),
+}
+
+pub(crate) fn to_key<'a>(x: &'a AstExpr) -> Option<(Key<'a>, Span)> {
+    match &**x {
+        Expr::Literal(x) => match x {
+            AstLiteral::Int(x) => Some((Key::Int(StarlarkInt::from(x.node.clone())), x.span)),
+            AstLiteral::Float(x) => {
+                let n = NumRef::from(x.node);
+                if let Some(i) = n.as_int() {
+                    // make an integer float always collide with other ints
+                    Some((Key::Int(StarlarkInt::from(i)), x.span))
+                } else {
+                    // use bits representation of float to be able to always compare them for equality
+                    // First normalise -0.0
+                    let v = if x.node == 0.0 { 0.0 } else { x.node };
+                    Some((Key::Float(v.to_bits()), x.span))
+                }
+            }
+            AstLiteral::String(x) => Some((Key::String(&x.node), x.span)),
+            AstLiteral::Ellipsis => None,
+        },
+        Expr::Identifier(x) => Some((Key::Identifier(&x.node.ident), x.span)),
+        _ => None,
+    }
+}
+
 // Go implementation of Starlark disallows duplicate top-level assignments,
 // it's likely that will become Starlark standard sooner or later, so check now.
 // The one place we allow it is to export something you grabbed with load.
 fn duplicate_dictionary_key(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
-    #[derive(PartialEq, Eq, Hash)]
-    enum Key<'a> {
-        Int(StarlarkInt),
-        Float(u64),
-        String(&'a str),
-        Identifier(&'a str),
-    }
-
-    fn to_key<'a>(x: &'a AstExpr) -> Option<(Key<'a>, Span)> {
-        match &**x {
-            Expr::Literal(x) => match x {
-                AstLiteral::Int(x) => Some((Key::Int(StarlarkInt::from(x.node.clone())), x.span)),
-                AstLiteral::Float(x) => {
-                    let n = NumRef::from(x.node);
-                    if let Some(i) = n.as_int() {
-                        // make an integer float always collide with other ints
-                        Some((Key::Int(StarlarkInt::from(i)), x.span))
-                    } else {
-                        // use bits representation of float to be able to always compare them for equality
-                        // First normalise -0.0
-                        let v = if x.node == 0.0 { 0.0 } else { x.node };
-                        Some((Key::Float(v.to_bits()), x.span))
-                    }
-                }
-                AstLiteral::String(x) => Some((Key::String(&x.node), x.span)),
-                AstLiteral::Ellipsis => None,
-            },
-            Expr::Identifier(x) => Some((Key::Identifier(&x.node.ident), x.span)),
-            _ => None,
-        }
+    fn has_call(x: &AstExpr) -> bool {
+        let mut res = matches!(&**x, Expr::Call(..));
+        x.visit_expr(|x| res = res || has_call(x));
+        res
     }
 
     fn expr<'a>(x: &'a AstExpr, codemap: &CodeMap, res: &mut Vec<LintT<Dubious>>) {
         match &**x {
             Expr::Dict(args) => {
                 let mut seen = HashMap::new();
-                for (key, _) in args {
+                for (i, (key, _)) in args.iter().enumerate() {
                     if let Some((key_id, pos)) = to_key(key) {
-                        if let Some(old) = seen.insert(key_id, pos) {
-                            res.push(LintT::new(
-                                codemap,
-                                old,
-                                Dubious::DuplicateKey(key.to_string(), codemap.file_span(pos)),
-                            ))
+                        if let Some((old, old_i)) = seen.insert(key_id, (pos, i)) {
+                            // The earlier entry is overridden, so it can go, unless evaluating
+                            // its value might have side effects. It is followed by another
+                            // entry, so removing up to that one removes its comma too.
+                            let (old_key, old_value) = &args[old_i];
+                            let fix = (!has_call(old_value)).then(|| {
+                                LintFix::new(
+                                    codemap,
+                                    Span::new(old_key.span.begin(), args[old_i + 1].0.span.begin()),
+                                    "",
+                                    format!("Remove the overridden entry for `{}`", key.node),
+                                )
+                            });
+                            res.push(
+                                LintT::new(
+                                    codemap,
+                                    old,
+                                    Dubious::DuplicateKey(key.to_string(), codemap.file_span(pos)),
+                                )
+                                .with_fix(fix),
+                            )
                         }
                     }
                 }
@@ -119,21 +142,46 @@ fn duplicate_dictionary_key(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
 }
 
 fn identifier_as_statement(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
-    fn stmt<'a>(x: &'a AstStmt, codemap: &CodeMap, res: &mut Vec<LintT<Dubious>>) {
+    fn is_identifier(x: &AstStmt) -> bool {
+        matches!(&**x, Stmt::Expression(x) if matches!(&**x, Expr::Identifier(..)))
+    }
+
+    // `removable` is whether the statement can be deleted without leaving an empty block.
+    fn stmt<'a>(x: &'a AstStmt, removable: bool, codemap: &CodeMap, res: &mut Vec<LintT<Dubious>>) {
         match &**x {
-            Stmt::Expression(x) => match &**x {
-                Expr::Identifier(x) => res.push(LintT::new(
-                    codemap,
-                    x.span,
-                    Dubious::IdentifierAsStatement(x.node.ident.clone()),
-                )),
+            Stmt::Expression(e) => match &**e {
+                Expr::Identifier(e) => {
+                    let fix = if removable {
+                        LintFix::remove_statement(
+                            codemap,
+                            x.span,
+                            format!("Remove the statement `{}`", e.ident),
+                        )
+                    } else {
+                        None
+                    };
+                    res.push(
+                        LintT::new(
+                            codemap,
+                            e.span,
+                            Dubious::IdentifierAsStatement(e.node.ident.clone()),
+                        )
+                        .with_fix(fix),
+                    )
+                }
                 _ => {}
             },
-            _ => x.visit_stmt(|x| stmt(x, codemap, res)),
+            Stmt::Statements(xs) => {
+                let removable = xs.iter().any(|x| !is_identifier(x));
+                for x in xs {
+                    stmt(x, removable, codemap, res);
+                }
+            }
+            _ => x.visit_stmt(|x| stmt(x, false, codemap, res)),
         }
     }
 
-    stmt(module.statement(), module.codemap(), res)
+    stmt(module.statement(), true, module.codemap(), res)
 }
 
 pub(crate) fn lint(module: &AstModule) -> Vec<LintT<Dubious>> {
@@ -148,6 +196,7 @@ mod tests {
     use starlark_syntax::slice_vec_ext::SliceExt;
 
     use super::*;
+    use crate::codemap::Pos;
     use crate::syntax::Dialect;
 
     fn module(x: &str) -> AstModule {
@@ -204,4 +253,63 @@ def foo():
         identifier_as_statement(&m, &mut res);
         assert_eq!(res.map(|x| x.problem.about()), &["no1", "no2"]);
     }
+
+    #[test]
+    fn test_lint_dubious_fixes() {
+        let m = module(
+            r#"
+x = {'a': 1, 'b': 2, 'a': 3, f(): 4, 'b': g()}
+y = {'c': g(), 'c': 1}
+x
+def foo():
+    y
+def bar():
+    y
+    return x
+"#,
+        );
+        let mut res = Vec::new();
+        duplicate_dictionary_key(&m, &mut res);
+        identifier_as_statement(&m, &mut res);
+        assert_eq!(
+            res.map(|x| (
+                x.problem.about().as_str(),
+                x.fix
+                    .as_ref()
+                    .map(|x| (x.description.as_str(), x.location.span))
+            )),
+            &[
+                (
+                    "\"a\"",
+                    Some((
+                        "Remove the overridden entry for `\"a\"`",
+                        Span::new(Pos::new(6), Pos::new(14))
+                    ))
+                ),
+                (
+                    "\"b\"",
+                    Some((
+                        "Remove the overridden entry for `\"b\"`",
+                        Span::new(Pos::new(14), Pos::new(22))
+                    ))
+                ),
+                ("\"c\"", None),
+                (
+                    "x",
+                    Some((
+                        "Remove the statement `x`",
+                        Span::new(Pos::new(71), Pos::new(73))
+                    ))
+                ),
+                ("y", None),
+                (
+                    "y",
+                    Some((
+                        "Remove the statement `y`",
+                        Span::new(Pos::new(101), Pos::new(107))
+                    ))
+                ),
+            ]
+        );
+    }
 }
@@ -0,0 +1,255 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Edits to the source code which fix the problems reported by lints.
+
+use starlark_syntax::lexer::lex_exactly_one_identifier;
+use starlark_syntax::syntax::ast::AssignP;
+use starlark_syntax::syntax::ast::AstNoPayload;
+use starlark_syntax::syntax::ast::AstParameter;
+use starlark_syntax::syntax::ast::AstStmt;
+use starlark_syntax::syntax::ast::ClauseP;
+use starlark_syntax::syntax::ast::Expr;
+use starlark_syntax::syntax::ast::ForP;
+use starlark_syntax::syntax::ast::LambdaP;
+use starlark_syntax::syntax::ast::Stmt;
+use starlark_syntax::syntax::uniplate::Visit;
+
+use crate::analysis::types::Lint;
+use crate::codemap::CodeMap;
+use crate::codemap::FileSpan;
+use crate::codemap::Pos;
+use crate::codemap::Span;
+
+/// An edit to the source code which fixes the problem reported by a [`Lint`].
+#[derive(Debug, Clone)]
+pub struct LintFix {
+    /// A short description of the edit, e.g. ``Remove unused `load` of `x` ``.
+    pub description: String,
+    /// The code to replace, which is in the same file as the lint.
+    pub location: FileSpan,
+    /// The text to replace it with, empty if the code is deleted.
+    pub replacement: String,
+}
+
+impl LintFix {
+    pub(crate) fn new(
+        codemap: &CodeMap,
+        span: Span,
+        replacement: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self {
+            description: description.into(),
+            location: codemap.file_span(span),
+            replacement: replacement.into(),
+        }
+    }
+
+    /// Delete a statement, along with its lines and any comment at the end of them.
+    /// Returns `None` if the statement shares a line with another, as deleting it would leave
+    /// a stray `;`.
+    pub(crate) fn remove_statement(
+        codemap: &CodeMap,
+        span: Span,
+        description: impl Into<String>,
+    ) -> Option<Self> {
+        let source = codemap.source();
+        let begin = span.begin().get() as usize;
+        let end = span.end().get() as usize;
+        let line_begin = source[..begin].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[end..]
+            .find('\n')
+            .map_or(source.len(), |i| end + i + 1);
+        let after = source[end..line_end].trim_start();
+        if !source[line_begin..begin].trim().is_empty()
+            || !(after.is_empty() || after.starts_with('#'))
+        {
+            return None;
+        }
+        Some(Self::new(
+            codemap,
+            Span::new(Pos::new(line_begin as u32), Pos::new(line_end as u32)),
+            "",
+            description,
+        ))
+    }
+
+    /// Rename a variable of a function to its name without the leading underscores, in `body`,
+    /// the body of the function. Returns `None` if the variable isn't bound in `body` or is a
+    /// parameter, or if the new name is not an identifier or is already used in `body`.
+    pub(crate) fn strip_underscore_prefix(
+        codemap: &CodeMap,
+        body: &AstStmt,
+        name: &str,
+    ) -> Option<Self> {
+        let new_name = name.trim_start_matches('_');
+        lex_exactly_one_identifier(new_name)?;
+
+        let mut spans = Vec::new();
+        let mut bound = false;
+        let mut renamable = true;
+        visit_names(Visit::Stmt(body), &mut |x, span, kind| {
+            if x == new_name {
+                renamable = false;
+            } else if x == name {
+                match kind {
+                    NameKind::Use => {}
+                    NameKind::Bind => bound = true,
+                    NameKind::Param => renamable = false,
+                }
+                spans.push(span);
+            }
+        });
+        if !renamable || !bound {
+            return None;
+        }
+
+        spans.sort_by_key(|x| x.begin());
+        let source = codemap.source();
+        let mut replacement = String::new();
+        let mut pos = body.span.begin().get() as usize;
+        for span in spans {
+            replacement.push_str(&source[pos..span.begin().get() as usize]);
+            replacement.push_str(new_name);
+            pos = span.end().get() as usize;
+        }
+        replacement.push_str(&source[pos..body.span.end().get() as usize]);
+        Some(Self::new(
+            codemap,
+            body.span,
+            replacement,
+            format!("Rename `{}` to `{}`", name, new_name),
+        ))
+    }
+}
+
+enum NameKind {
+    Use,
+    Bind,
+    Param,
+}
+
+/// Visit every occurrence of a name in a statement or expression, including in nested functions.
+fn visit_names<'a>(x: Visit<'a, AstNoPayload>, f: &mut dyn FnMut(&'a str, Span, NameKind)) {
+    fn params<'a>(xs: &'a [AstParameter], f: &mut dyn FnMut(&'a str, Span, NameKind)) {
+        for x in xs {
+            if let Some(x) = x.ident() {
+                f(&x.ident, x.span, NameKind::Param);
+            }
+        }
+    }
+
+    match x {
+        Visit::Stmt(x) => match &**x {
+            Stmt::Def(def) => {
+                f(&def.name.ident, def.name.span, NameKind::Bind);
+                params(&def.params, f);
+            }
+            Stmt::Assign(AssignP { lhs, .. })
+            | Stmt::AssignModify(lhs, ..)
+            | Stmt::For(ForP { var: lhs, .. }) => {
+                lhs.visit_lvalue(|x| f(&x.ident, x.span, NameKind::Bind));
+            }
+            _ => {}
+        },
+        Visit::Expr(x) => match &**x {
+            Expr::Identifier(x) => f(&x.ident, x.span, NameKind::Use),
+            Expr::Lambda(LambdaP { params: xs, .. }) => params(xs, f),
+            Expr::ListComprehension(_, for_, clauses)
+            | Expr::DictComprehension(_, for_, clauses) => {
+                for_.var
+                    .visit_lvalue(|x| f(&x.ident, x.span, NameKind::Bind));
+                for clause in clauses {
+                    if let ClauseP::For(clause) = clause {
+                        clause
+                            .var
+                            .visit_lvalue(|x| f(&x.ident, x.span, NameKind::Bind));
+                    }
+                }
+            }
+            _ => {}
+        },
+    }
+    x.visit_children(|x| visit_names(x, f));
+}
+
+/// Apply the fixes of the lints to the source code they were found in, returning `None` if none
+/// of them have a fix. Where fixes overlap only the first is applied, so the result may have more
+/// lints to fix.
+pub fn apply_lint_fixes(source: &str, lints: &[Lint]) -> Option<String> {
+    let mut fixes: Vec<&LintFix> = lints.iter().filter_map(|x| x.fix.as_ref()).collect();
+    if fixes.is_empty() {
+        return None;
+    }
+    fixes.sort_by_key(|x| (x.location.span.begin(), x.location.span.end()));
+
+    let mut res = String::with_capacity(source.len());
+    let mut pos = 0;
+    for fix in fixes {
+        let begin = fix.location.span.begin().get() as usize;
+        if begin < pos {
+            continue;
+        }
+        res.push_str(&source[pos..begin]);
+        res.push_str(&fix.replacement);
+        pos = fix.location.span.end().get() as usize;
+    }
+    res.push_str(&source[pos..]);
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AstModuleLint;
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    fn fix(program: &str) -> String {
+        let module = AstModule::parse("fix.bzl", program.to_owned(), &Dialect::Extended).unwrap();
+        apply_lint_fixes(program, &module.lint(None)).unwrap_or_else(|| program.to_owned())
+    }
+
+    #[test]
+    fn test_fix_unused_loads() {
+        assert_eq!(
+            "load(\"a.bzl\", \"used\")\nused()\n",
+            fix("load(\"a.bzl\", \"used\", \"unused\")\nused()\n")
+        );
+        assert_eq!(
+            "load(\"a.bzl\", \"used\")\nused()\n",
+            fix("load(\"a.bzl\", \"unused\", \"used\")\nused()\n")
+        );
+        assert_eq!(
+            "x = 1\n",
+            fix("load(\"a.bzl\", \"unused\", \"also_unused\")  # Comment\nx = 1\n")
+        );
+    }
+
+    #[test]
+    fn test_fix_expressions() {
+        assert_eq!(
+            r#"def f(x, **kwargs):
+    return dict(kwargs), {"a": x, "b": 2}, type(x) == type("")
+"#,
+            fix(r#"def f(x, **kwargs):
+    return dict(**kwargs), dict([("a", x), ("b", 2)]), type(x) == str
+"#)
+        );
+    }
+}
@@ -32,6 +32,7 @@ use starlark_syntax::syntax::ast::Stmt;
 use starlark_syntax::syntax::module::AstModuleFields;
 use thiserror::Error;
 
+use crate::analysis::fix::LintFix;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::analysis::EvalSeverity;
@@ -101,14 +102,28 @@ fn match_bad_type_equality(
             if (*op == BinOp::Equal || *op == BinOp::NotEqual) && is_type_call(lhs) =>
         {
             if let Some(replacement) = lookup_type(rhs, types) {
-                res.push(LintT::new(
+                let fix = LintFix::new(
                     codemap,
                     x.span,
-                    Incompatibility::IncompatibleTypeCheck(
-                        x.to_string(),
-                        format!("{}{}type({})", lhs.node, op, replacement),
+                    format!(
+                        "{}{}type({})",
+                        codemap.source_span(lhs.span),
+                        op,
+                        replacement
                     ),
-                ))
+                    format!("Compare with `type({})`", replacement),
+                );
+                res.push(
+                    LintT::new(
+                        codemap,
+                        x.span,
+                        Incompatibility::IncompatibleTypeCheck(
+                            x.to_string(),
+                            format!("{}{}type({})", lhs.node, op, replacement),
+                        ),
+                    )
+                    .with_fix(Some(fix)),
+                )
             }
         }
         _ => {}
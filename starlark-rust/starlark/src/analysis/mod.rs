@@ -19,6 +19,8 @@
 
 use std::collections::HashSet;
 
+pub use fix::apply_lint_fixes;
+pub use fix::LintFix;
 pub use lint_message::LintMessage;
 pub use types::EvalMessage;
 pub use types::EvalSeverity;
@@ -30,6 +32,7 @@ use crate::syntax::AstModule;
 
 mod dubious;
 pub mod find_call_name;
+mod fix;
 mod flow;
 mod incompatible;
 mod lint_message;
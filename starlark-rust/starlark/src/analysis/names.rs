@@ -44,8 +44,10 @@ use starlark_syntax::syntax::ast::ForP;
 use starlark_syntax::syntax::ast::LoadArgP;
 use starlark_syntax::syntax::ast::Stmt;
 use starlark_syntax::syntax::module::AstModuleFields;
+use starlark_syntax::syntax::top_level_stmts::top_level_stmts;
 use thiserror::Error;
 
+use crate::analysis::fix::LintFix;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::analysis::EvalSeverity;
@@ -555,7 +557,70 @@ pub(crate) fn lint(
         loop_depth: 0,
     };
     state.module(module);
-    state.warnings
+    let mut warnings = state.warnings;
+    fix_unused_loads(module, &mut warnings);
+    warnings
+}
+
+/// Attach fixes to the unused `load` warnings, which remove the symbols from the `load`,
+/// or the whole `load` if none of its symbols are used.
+fn fix_unused_loads(module: &AstModule, warnings: &mut [LintT<NameWarning>]) {
+    let codemap = module.codemap();
+    let unused: HashSet<Span> = warnings
+        .iter()
+        .filter(|x| matches!(x.problem, NameWarning::UnusedLoad(..)))
+        .map(|x| x.location.span)
+        .collect();
+    if unused.is_empty() {
+        return;
+    }
+
+    let mut fixes = HashMap::new();
+    for stmt in top_level_stmts(module.statement()) {
+        let Stmt::Load(load) = &**stmt else {
+            continue;
+        };
+        if load.args.iter().all(|arg| unused.contains(&arg.local.span)) {
+            let fix = LintFix::remove_statement(
+                codemap,
+                stmt.span,
+                format!("Remove unused `load` of `{}`", load.module.node),
+            );
+            for arg in &load.args {
+                fixes.insert(arg.local.span, fix.clone());
+            }
+            continue;
+        }
+        for (i, arg) in load.args.iter().enumerate() {
+            if !unused.contains(&arg.local.span) {
+                continue;
+            }
+            // Remove up to the next symbol, or if this is the last, from the end of the one
+            // before, which must exist as some symbol is used.
+            let span = match load.args.get(i + 1) {
+                Some(next) => Span::new(arg.span().begin(), next.span().begin()),
+                None => Span::new(
+                    load.args[i - 1].span().end(),
+                    arg.span_with_trailing_comma().end(),
+                ),
+            };
+            fixes.insert(
+                arg.local.span,
+                Some(LintFix::new(
+                    codemap,
+                    span,
+                    "",
+                    format!("Remove unused `load` of `{}`", arg.local.ident),
+                )),
+            );
+        }
+    }
+
+    for warning in warnings {
+        if let Some(fix) = fixes.remove(&warning.location.span) {
+            warning.fix = fix;
+        }
+    }
 }
 
 #[cfg(test)]
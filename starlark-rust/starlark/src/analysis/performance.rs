@@ -15,12 +15,17 @@
  * limitations under the License.
  */
 
+use std::collections::HashSet;
+
 use starlark_syntax::syntax::ast::Argument;
 use starlark_syntax::syntax::ast::AstExpr;
 use starlark_syntax::syntax::ast::Expr;
 use starlark_syntax::syntax::module::AstModuleFields;
 use thiserror::Error;
 
+use crate::analysis::dubious::to_key;
+use crate::analysis::dubious::Key;
+use crate::analysis::fix::LintFix;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::analysis::EvalSeverity;
@@ -32,6 +37,9 @@ pub(crate) enum Performance {
     #[error("Dict copy `{0}` is more efficient as `{1}`")]
     DictWithoutStarStar(String, String),
 
+    #[error("Dict construction `{0}` is more efficient as the literal `{1}`")]
+    DictFromListOfPairs(String, String),
+
     #[error(
         "`{0}` eagerly evaluates all items in the iterable, and allocates an array for the results. Prefer using a for-loop."
     )]
@@ -49,6 +57,7 @@ impl LintWarning for Performance {
     fn short_name(&self) -> &'static str {
         match self {
             Performance::DictWithoutStarStar(..) => "dict-without-star-star",
+            Performance::DictFromListOfPairs(..) => "dict-from-list-of-pairs",
             Performance::EagerAndInefficientBoolCheck(..) => "eager-and-inefficient-bool-check",
             Performance::InefficientBoolCheck(..) => "inefficient-bool-check",
         }
@@ -60,11 +69,97 @@ fn match_dict_copy(codemap: &CodeMap, x: &AstExpr, res: &mut Vec<LintT<Performan
     match &**x {
         Expr::Call(fun, args) if args.len() == 1 => match (&***fun, &*args[0]) {
             (Expr::Identifier(f), Argument::KwArgs(arg)) if f.node.ident == "dict" => {
-                res.push(LintT::new(
-                    codemap,
-                    x.span,
-                    Performance::DictWithoutStarStar(x.to_string(), format!("dict({})", arg.node)),
-                ))
+                let replacement = format!("dict({})", codemap.source_span(arg.span));
+                res.push(
+                    LintT::new(
+                        codemap,
+                        x.span,
+                        Performance::DictWithoutStarStar(
+                            x.to_string(),
+                            format!("dict({})", arg.node),
+                        ),
+                    )
+                    .with_fix(Some(LintFix::new(
+                        codemap,
+                        x.span,
+                        replacement,
+                        "Remove the `**`",
+                    ))),
+                )
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+fn match_dict_from_pairs(codemap: &CodeMap, x: &AstExpr, res: &mut Vec<LintT<Performance>>) {
+    // If we see `dict([(k, v), ...])` suggest `{k: v, ...}`
+    match &**x {
+        Expr::Call(fun, args) if args.len() == 1 => match (&***fun, &*args[0]) {
+            (Expr::Identifier(f), Argument::Positional(arg)) if f.node.ident == "dict" => {
+                let Expr::List(items) = &**arg else {
+                    return;
+                };
+                let mut keys = HashSet::new();
+                let Some(pairs) = items
+                    .iter()
+                    .map(|item| match &**item {
+                        // A literal with duplicate keys fails, where `dict` takes the last.
+                        Expr::Tuple(pair)
+                            if pair.len() == 2
+                                && keys.insert(codemap.source_span(pair[0].span)) =>
+                        {
+                            Some(format!(
+                                "{}: {}",
+                                codemap.source_span(pair[0].span),
+                                codemap.source_span(pair[1].span)
+                            ))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return;
+                };
+                if pairs.is_empty() {
+                    return;
+                }
+                // Only a literal is known to be a valid key that differs from the others: with
+                // other keys, `dict` might succeed where the literal fails. Literal keys that
+                // collide would make the literal fail, so there is nothing to suggest.
+                let mut literal_keys = HashSet::new();
+                let mut all_literal_keys = true;
+                for item in items {
+                    match &**item {
+                        Expr::Tuple(pair) => match to_key(&pair[0]) {
+                            Some((key @ (Key::Int(_) | Key::Float(_) | Key::String(_)), _)) => {
+                                if !literal_keys.insert(key) {
+                                    return;
+                                }
+                            }
+                            _ => all_literal_keys = false,
+                        },
+                        _ => all_literal_keys = false,
+                    }
+                }
+                let replacement = format!("{{{}}}", pairs.join(", "));
+                let fix = all_literal_keys.then(|| {
+                    LintFix::new(
+                        codemap,
+                        x.span,
+                        replacement.clone(),
+                        "Replace with a dict literal",
+                    )
+                });
+                res.push(
+                    LintT::new(
+                        codemap,
+                        x.span,
+                        Performance::DictFromListOfPairs(x.to_string(), replacement),
+                    )
+                    .with_fix(fix),
+                )
             }
             _ => {}
         },
@@ -114,6 +209,7 @@ fn match_inefficient_bool_check(codemap: &CodeMap, x: &AstExpr, res: &mut Vec<Li
 fn check_call_expr(module: &AstModule, res: &mut Vec<LintT<Performance>>) {
     fn check(codemap: &CodeMap, x: &AstExpr, res: &mut Vec<LintT<Performance>>) {
         match_dict_copy(codemap, x, res);
+        match_dict_from_pairs(codemap, x, res);
         match_inefficient_bool_check(codemap, x, res);
         x.visit_expr(|x| check(codemap, x, res));
     }
@@ -159,6 +255,36 @@ def foo(extra, **kwargs):
         );
     }
 
+    #[test]
+    fn test_lint_matches_dict_from_pairs() {
+        let mut res = Vec::new();
+        check_call_expr(
+            &module(
+                r#"
+def foo(k, v):
+    x = dict([(k, v), ("b", 1)])
+    y = dict([(k, v), (k, 1)])
+    z = dict([])
+    w = dict([("a", v), (1, 2)])
+    u = dict([('a', v), ("a", 2)])
+    return (x,y,z,w,u)
+"#,
+            ),
+            &mut res,
+        );
+        assert_eq!(
+            res.map(|x| x.to_string()),
+            &[
+                "bad.bzl:3:9-33: Dict construction `dict([(k, v), (\"b\", 1)])` is more efficient as the literal `{k: v, \"b\": 1}`",
+                "bad.bzl:6:9-33: Dict construction `dict([(\"a\", v), (1, 2)])` is more efficient as the literal `{\"a\": v, 1: 2}`",
+            ]
+        );
+        // `k` might not be hashable, or equal to `"b"`. `'a'` is the same key as `"a"`, so `u`
+        // has no literal to suggest.
+        assert!(res[0].fix.is_none());
+        assert_eq!("{\"a\": v, 1: 2}", res[1].fix.as_ref().unwrap().replacement);
+    }
+
     #[test]
     fn test_lint_matches_any_function() {
         let mut res = Vec::new();
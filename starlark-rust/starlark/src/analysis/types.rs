@@ -22,6 +22,7 @@ use std::path::Path;
 use dupe::Dupe;
 use serde::Serialize;
 
+use crate::analysis::fix::LintFix;
 use crate::codemap::CodeMap;
 use crate::codemap::FileSpan;
 use crate::codemap::ResolvedSpan;
@@ -39,6 +40,7 @@ pub(crate) struct LintT<T> {
     pub location: FileSpan,
     pub original: String,
     pub problem: T,
+    pub fix: Option<LintFix>,
 }
 
 /// A lint produced by `AstModule::lint`.
@@ -55,6 +57,8 @@ pub struct Lint {
     pub problem: String,
     /// The source code at [`location`](Lint::location).
     pub original: String,
+    /// An edit which fixes the problem, if one can be made automatically.
+    pub fix: Option<LintFix>,
 }
 
 impl Display for Lint {
@@ -76,9 +80,15 @@ impl<T: LintWarning> LintT<T> {
             original: location.file.source_span(span).to_owned(),
             location,
             problem,
+            fix: None,
         }
     }
 
+    pub(crate) fn with_fix(mut self, fix: Option<LintFix>) -> Self {
+        self.fix = fix;
+        self
+    }
+
     pub(crate) fn erase(self) -> Lint {
         Lint {
             location: self.location,
@@ -86,6 +96,7 @@ impl<T: LintWarning> LintT<T> {
             severity: self.problem.severity(),
             problem: self.problem.to_string(),
             original: self.original,
+            fix: self.fix,
         }
     }
 }
@@ -25,8 +25,10 @@ use starlark_syntax::syntax::ast::DefP;
 use starlark_syntax::syntax::ast::Expr;
 use starlark_syntax::syntax::ast::Stmt;
 use starlark_syntax::syntax::module::AstModuleFields;
+use starlark_syntax::syntax::uniplate::Visit;
 use thiserror::Error;
 
+use crate::analysis::fix::LintFix;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::analysis::EvalSeverity;
@@ -56,16 +58,17 @@ impl LintWarning for UnderscoreWarning {
 
 pub(crate) fn lint(module: &AstModule) -> Vec<LintT<UnderscoreWarning>> {
     let mut res = Vec::new();
-    inappropriate_underscore(module.codemap(), module.statement(), true, &mut res);
+    inappropriate_underscore(module.codemap(), module.statement(), None, &mut res);
     use_ignored(module.codemap(), module.statement(), &mut res);
     res
 }
 
-// There's no reason to make a def or lambda and give it an underscore name not at the top level
+// There's no reason to make a def or lambda and give it an underscore name not at the top level.
+// `scope` is the body of the function `x` is in, if it isn't at the top level.
 fn inappropriate_underscore(
     codemap: &CodeMap,
     x: &AstStmt,
-    top: bool,
+    scope: Option<&AstStmt>,
     res: &mut Vec<LintT<UnderscoreWarning>>,
 ) {
     // Is this value allowed as an assignment to a boring identifier - just tuple of vars and var
@@ -79,29 +82,30 @@ fn inappropriate_underscore(
         }
     }
 
+    let warning = |name: &str, span| {
+        LintT::new(
+            codemap,
+            span,
+            UnderscoreWarning::UnderscoreDefinition(name.to_owned()),
+        )
+        .with_fix(scope.and_then(|scope| LintFix::strip_underscore_prefix(codemap, scope, name)))
+    };
+
     match &**x {
         Stmt::Def(DefP { name, body, .. }) => {
-            if !top && name.ident.starts_with('_') {
-                res.push(LintT::new(
-                    codemap,
-                    name.span,
-                    UnderscoreWarning::UnderscoreDefinition(name.ident.clone()),
-                ))
+            if scope.is_some() && name.ident.starts_with('_') {
+                res.push(warning(&name.ident, name.span))
             }
-            inappropriate_underscore(codemap, body, false, res)
+            inappropriate_underscore(codemap, body, Some(body), res)
         }
-        Stmt::Assign(assign) if !top => {
+        Stmt::Assign(assign) if scope.is_some() => {
             if let AssignTarget::Identifier(name) = &assign.lhs.node {
                 if name.ident.starts_with('_') && !is_allowed(&assign.rhs) {
-                    res.push(LintT::new(
-                        codemap,
-                        name.span,
-                        UnderscoreWarning::UnderscoreDefinition(name.node.ident.clone()),
-                    ))
+                    res.push(warning(&name.ident, name.span))
                 }
             }
         }
-        _ => x.visit_stmt(|x| inappropriate_underscore(codemap, x, top, res)),
+        _ => x.visit_stmt(|x| inappropriate_underscore(codemap, x, scope, res)),
     }
 }
 
@@ -132,29 +136,54 @@ fn use_ignored(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<UnderscoreWar
         x.starts_with('_') && !(x.starts_with("__") && x.ends_with("__"))
     }
 
+    // `scope` is the body of the function the expression is in, if any.
     fn check_expr(
         codemap: &CodeMap,
         x: &AstExpr,
+        scope: Option<&AstStmt>,
         roots: &HashSet<&str>,
         res: &mut Vec<LintT<UnderscoreWarning>>,
     ) {
         match &**x {
             Expr::Identifier(x) => {
                 if is_ignored(x.ident.as_str()) && !roots.contains(x.ident.as_str()) {
-                    res.push(LintT::new(
-                        codemap,
-                        x.span,
-                        UnderscoreWarning::UsingIgnored(x.node.ident.clone()),
-                    ));
+                    res.push(
+                        LintT::new(
+                            codemap,
+                            x.span,
+                            UnderscoreWarning::UsingIgnored(x.node.ident.clone()),
+                        )
+                        .with_fix(scope.and_then(|scope| {
+                            LintFix::strip_underscore_prefix(codemap, scope, &x.ident)
+                        })),
+                    );
                 }
             }
-            _ => x.visit_expr(|x| check_expr(codemap, x, roots, res)),
+            _ => x.visit_expr(|x| check_expr(codemap, x, scope, roots, res)),
         }
     }
 
+    fn check_stmt(
+        codemap: &CodeMap,
+        x: &AstStmt,
+        scope: Option<&AstStmt>,
+        roots: &HashSet<&str>,
+        res: &mut Vec<LintT<UnderscoreWarning>>,
+    ) {
+        // The parameters of a function are evaluated outside of it, only its body is inside.
+        let inner = match &**x {
+            Stmt::Def(DefP { body, .. }) => Some(&**body),
+            _ => scope,
+        };
+        x.visit_children(|x| match x {
+            Visit::Stmt(x) => check_stmt(codemap, x, inner, roots, res),
+            Visit::Expr(x) => check_expr(codemap, x, scope, roots, res),
+        })
+    }
+
     let mut roots = HashSet::new();
     root_definitions(x, &mut roots);
-    x.visit_expr(|x| check_expr(codemap, x, &roots, res));
+    check_stmt(codemap, x, None, &roots, res);
 }
 
 #[cfg(test)]
@@ -190,7 +219,7 @@ def _ok():
 "#,
         );
         let mut res = Vec::new();
-        inappropriate_underscore(m.codemap(), m.statement(), true, &mut res);
+        inappropriate_underscore(m.codemap(), m.statement(), None, &mut res);
         let mut res = res.map(|x| x.problem.about());
         res.sort();
         assert_eq!(res, &["_no1", "_no2", "_no3"])
@@ -225,4 +254,58 @@ def bar():
         res.sort();
         assert_eq!(res, &["_no1", "_no2", "_no3"])
     }
+
+    #[test]
+    fn test_lint_underscore_fixes() {
+        let program = r#"
+def foo(_param, no3):
+    _no1 = [a for a in _param]
+    _no2 = 1 + _no1[0]
+    _no3 = no3
+    for _no4 in _no1:
+        print(_no4, _no2, _no3)
+"#;
+        let m = module(program);
+        let mut res = Vec::new();
+        inappropriate_underscore(m.codemap(), m.statement(), None, &mut res);
+        use_ignored(m.codemap(), m.statement(), &mut res);
+        let fixes = res.map(|x| {
+            (
+                x.problem.about().as_str(),
+                x.fix.as_ref().map(|x| x.description.as_str()),
+            )
+        });
+        assert_eq!(
+            fixes,
+            &[
+                ("_no1", Some("Rename `_no1` to `no1`")),
+                ("_no2", Some("Rename `_no2` to `no2`")),
+                ("_param", None),
+                ("_no1", Some("Rename `_no1` to `no1`")),
+                ("_no1", Some("Rename `_no1` to `no1`")),
+                ("_no4", Some("Rename `_no4` to `no4`")),
+                ("_no2", Some("Rename `_no2` to `no2`")),
+                ("_no3", None),
+            ]
+        );
+        let fix = res[5].fix.as_ref().unwrap();
+        let span = fix.location.span;
+        let fixed = format!(
+            "{}{}{}",
+            &program[..span.begin().get() as usize],
+            fix.replacement,
+            &program[span.end().get() as usize..]
+        );
+        assert_eq!(
+            r#"
+def foo(_param, no3):
+    _no1 = [a for a in _param]
+    _no2 = 1 + _no1[0]
+    _no3 = no3
+    for no4 in _no1:
+        print(no4, _no2, _no3)
+"#,
+            fixed
+        );
+    }
 }
@@ -1159,6 +1159,14 @@ impl<'f> ModuleScopeData<'f> {
         &self.bindings[id]
     }
 
+    /// All the bindings in the module, in the order they were created.
+    pub(crate) fn bindings(&self) -> impl Iterator<Item = (BindingId, &Binding<'f>)> {
+        self.bindings
+            .iter()
+            .enumerate()
+            .map(|(id, binding)| (BindingId(id), binding))
+    }
+
     fn mut_binding(&mut self, BindingId(id): BindingId) -> &mut Binding<'f> {
         &mut self.bindings[id]
     }
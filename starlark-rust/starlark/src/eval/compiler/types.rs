@@ -50,7 +50,7 @@ enum TypesError {
     ModuleVariableNotSet(String),
     #[error("Type payload not set (internal error)")]
     TypePayloadNotSet,
-    #[error("[] can only be applied to list function in type expression")]
+    #[error("[] can only be applied to list or set function in type expression")]
     TypeIndexOnNonList,
     #[error("[,] can only be applied to dict function in type expression")]
     TypeIndexOnNonDict,
@@ -168,7 +168,9 @@ impl<'v> Compiler<'v, '_, '_> {
             TypeExprUnpackP::Path(ident, rem) => self.eval_path_as_type(ident, &rem),
             TypeExprUnpackP::Index(a, i) => {
                 let a = self.eval_ident_in_type_expr(a)?;
-                if !a.ptr_eq(Constants::get().fn_list.0.to_value()) {
+                if !a.ptr_eq(Constants::get().fn_list.0.to_value())
+                    && !a.ptr_eq(Constants::get().fn_set.0.to_value())
+                {
                     return Err(EvalException::new_anyhow(
                         TypesError::TypeIndexOnNonList.into(),
                         expr.span,
@@ -31,6 +31,7 @@ pub(crate) mod internal;
 pub(crate) mod json;
 pub(crate) mod list;
 pub(crate) mod partial;
+pub(crate) mod set;
 pub(crate) mod string;
 pub(crate) mod structs;
 
@@ -60,6 +61,8 @@ pub enum LibraryExtension {
     RecordType,
     /// Definitions to support the `enum` type, the `enum()` constructor.
     EnumType,
+    /// Definitions to support the `set` type, the `set()` constructor.
+    SetType,
     /// A function `map(f, xs)` which applies `f` to each element of `xs` and returns the result.
     Map,
     /// A function `filter(f, xs)` which applies `f` to each element of `xs` and returns those for which `f` returns `True`.
@@ -97,7 +100,7 @@ impl LibraryExtension {
     pub(crate) fn all() -> &'static [Self] {
         use LibraryExtension::*;
         &[
-            StructType, RecordType, EnumType, Map, Filter, Partial, Debug, Print, Pprint,
+            StructType, RecordType, EnumType, SetType, Map, Filter, Partial, Debug, Print, Pprint,
             Breakpoint, Json, Typing, Internal, CallStack,
         ]
     }
@@ -109,6 +112,7 @@ impl LibraryExtension {
             StructType => structs::global(builder),
             RecordType => register_record(builder),
             EnumType => register_enum(builder),
+            SetType => set::register_set(builder),
             Map => extra::map(builder),
             Filter => extra::filter(builder),
             Partial => partial::partial(builder),
@@ -0,0 +1,483 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `set` constructor and the methods of the `set` type.
+
+use std::mem;
+
+use allocative::Allocative;
+use once_cell::sync::Lazy;
+use starlark_derive::starlark_module;
+use starlark_map::small_set::SmallSet;
+
+use crate as starlark;
+use crate::codemap::Span;
+use crate::codemap::Spanned;
+use crate::environment::GlobalsBuilder;
+use crate::environment::MethodsBuilder;
+use crate::typing::error::TypingOrInternalError;
+use crate::typing::function::TyCustomFunctionImpl;
+use crate::typing::Arg;
+use crate::typing::Param;
+use crate::typing::Ty;
+use crate::typing::TyFunction;
+use crate::typing::TypingOracleCtx;
+use crate::values::function::SpecialBuiltinFunction;
+use crate::values::none::NoneType;
+use crate::values::set::value::FrozenSet;
+use crate::values::set::Set;
+use crate::values::set::SetMut;
+use crate::values::set::SetRef;
+use crate::values::tuple::UnpackTuple;
+use crate::values::typing::StarlarkIter;
+use crate::values::Heap;
+use crate::values::Value;
+use crate::values::ValueOfUnchecked;
+
+/// Collect the values of an iterable into a set, failing if any of them are not hashable.
+fn set_from_iterable<'v>(xs: Value<'v>, heap: &'v Heap) -> starlark::Result<Set<'v>> {
+    if let Some(xs) = SetRef::from_value(xs) {
+        return Ok((*xs).clone());
+    }
+    let it = xs.iterate(heap)?;
+    let mut content = SmallSet::with_capacity(it.size_hint().0);
+    for x in it {
+        content.insert_hashed(x.get_hashed()?);
+    }
+    Ok(Set::new(content))
+}
+
+#[derive(Allocative, Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Debug)]
+struct SetType;
+
+impl TyCustomFunctionImpl for SetType {
+    fn has_type_attr(&self) -> bool {
+        true
+    }
+
+    fn validate_call(
+        &self,
+        span: Span,
+        args: &[Spanned<Arg>],
+        oracle: TypingOracleCtx,
+    ) -> Result<Ty, TypingOrInternalError> {
+        static SET: Lazy<TyFunction> = Lazy::new(|| {
+            TyFunction::new_with_type_attr(
+                vec![Param::pos_only(Ty::iter(Ty::any())).optional()],
+                Ty::any_set(),
+                Ty::any_set(),
+            )
+        });
+
+        oracle.validate_fn_call(span, &SET, args)?;
+
+        if let Some(arg) = args.first() {
+            // This is infallible after the check above.
+            if let Arg::Pos(arg_ty) = &arg.node {
+                // This is also infallible.
+                let item = oracle.iter_item(Spanned { span, node: arg_ty })?;
+                return Ok(Ty::set(item));
+            }
+        }
+
+        Ok(Ty::any_set())
+    }
+}
+
+#[starlark_module]
+pub(crate) fn register_set(globals: &mut GlobalsBuilder) {
+    /// [set](
+    /// https://bazel.build/rules/lib/core/set
+    /// ): construct a set.
+    ///
+    /// `set(x)` returns a new set containing the unique elements of the
+    /// iterable sequence x, in the order they were first seen.
+    ///
+    /// With no argument, `set()` returns a new empty set.
+    ///
+    /// `set` fails if any element is unhashable.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// len(set()) == 0
+    /// set([3, 1, 3, 2]) == set([1, 2, 3])
+    /// list(set([3, 1, 3, 2])) == [3, 1, 2]
+    /// set({"a": 1, "b": 2}) == set(["a", "b"])
+    /// # "#);
+    /// # starlark::assert::fail(r#"
+    /// set([[1]]) # error: not hashable
+    /// # "#, r#"not hashable"#);
+    /// ```
+    #[starlark(
+    as_type = FrozenSet,
+    speculative_exec_safe,
+    special_builtin_function = SpecialBuiltinFunction::Set,
+    ty_custom_function = SetType,
+    )]
+    fn set<'v>(
+        #[starlark(require = pos)] arg: Option<ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<Set<'v>> {
+        match arg {
+            Some(arg) => set_from_iterable(arg.get(), heap),
+            None => Ok(Set::default()),
+        }
+    }
+}
+
+#[starlark_module]
+pub(crate) fn set_methods(registry: &mut MethodsBuilder) {
+    /// [set.add](
+    /// https://bazel.build/rules/lib/core/set#add
+    /// ): add an element to a set.
+    ///
+    /// `S.add(x)` adds `x` to the set S, if it is not already present, and
+    /// returns `None`. It fails if `x` is unhashable, or if the set is frozen
+    /// or has active iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.add(3)
+    /// x.add(1)
+    /// x == set([1, 2, 3])
+    /// # "#);
+    /// ```
+    fn add<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos)] value: Value<'v>,
+    ) -> starlark::Result<NoneType> {
+        let value = value.get_hashed()?;
+        let mut this = SetMut::from_value(this)?;
+        this.insert_hashed(value);
+        Ok(NoneType)
+    }
+
+    /// [set.clear](
+    /// https://bazel.build/rules/lib/core/set#clear
+    /// ): clear a set.
+    ///
+    /// `S.clear()` removes all the elements of the set S and returns `None`.
+    /// It fails if the set is frozen or if there are active iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.clear()
+    /// x == set()
+    /// # "#);
+    /// ```
+    fn clear(this: Value) -> anyhow::Result<NoneType> {
+        let mut this = SetMut::from_value(this)?;
+        this.clear();
+        Ok(NoneType)
+    }
+
+    /// [set.difference](
+    /// https://bazel.build/rules/lib/core/set#difference
+    /// ): the elements of a set which are not in any of the others.
+    ///
+    /// `S.difference(*others)` returns a new set with the elements of S that
+    /// are not in any of the iterables `others`. `S - T` is the same as
+    /// `S.difference(T)`, but requires `T` to be a set.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// set([1, 2, 3]).difference([1], (3, 4)) == set([2])
+    /// set([1, 2, 3]) - set([1]) == set([2, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn difference<'v>(
+        this: SetRef<'v>,
+        #[starlark(args)] others: UnpackTuple<ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<Set<'v>> {
+        let mut res = (*this).clone();
+        for other in others.items {
+            res = res.difference(&set_from_iterable(other.get(), heap)?);
+        }
+        Ok(res)
+    }
+
+    /// [set.discard](
+    /// https://bazel.build/rules/lib/core/set#discard
+    /// ): remove an element from a set, if present.
+    ///
+    /// `S.discard(x)` removes `x` from the set S if it is present, and
+    /// returns `None`. Unlike `remove`, it does not fail if `x` is absent.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.discard(2)
+    /// x.discard(3)
+    /// x == set([1])
+    /// # "#);
+    /// ```
+    fn discard<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos)] value: Value<'v>,
+    ) -> starlark::Result<NoneType> {
+        let value = value.get_hashed()?;
+        let mut this = SetMut::from_value(this)?;
+        this.remove_hashed(value);
+        Ok(NoneType)
+    }
+
+    /// [set.intersection](
+    /// https://bazel.build/rules/lib/core/set#intersection
+    /// ): the elements of a set which are in all of the others.
+    ///
+    /// `S.intersection(*others)` returns a new set with the elements of S
+    /// that are in every one of the iterables `others`. `S & T` is the same
+    /// as `S.intersection(T)`, but requires `T` to be a set.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// set([1, 2, 3]).intersection([1, 2], (2, 3)) == set([2])
+    /// set([1, 2, 3]) & set([3, 4]) == set([3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn intersection<'v>(
+        this: SetRef<'v>,
+        #[starlark(args)] others: UnpackTuple<ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<Set<'v>> {
+        let mut res = (*this).clone();
+        for other in others.items {
+            res = res.intersection(&set_from_iterable(other.get(), heap)?);
+        }
+        Ok(res)
+    }
+
+    /// [set.isdisjoint](
+    /// https://bazel.build/rules/lib/core/set#isdisjoint
+    /// ): test whether a set has no elements in common with another.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// set([1, 2]).isdisjoint([3, 4])
+    /// not set([1, 2]).isdisjoint([2, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn isdisjoint<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos)] other: ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<bool> {
+        for x in other.get().iterate(heap)? {
+            if this.contains(x)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// [set.issubset](
+    /// https://bazel.build/rules/lib/core/set#issubset
+    /// ): test whether every element of a set is in another.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// set([1, 2]).issubset([1, 2, 3])
+    /// set([1, 2]).issubset(set([1, 2]))
+    /// not set([1, 4]).issubset([1, 2, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn issubset<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos)] other: ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<bool> {
+        Ok(this.is_subset(&set_from_iterable(other.get(), heap)?))
+    }
+
+    /// [set.issuperset](
+    /// https://bazel.build/rules/lib/core/set#issuperset
+    /// ): test whether a set contains every element of another.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// set([1, 2, 3]).issuperset([1, 2])
+    /// not set([1, 2]).issuperset([1, 4])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn issuperset<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos)] other: ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<bool> {
+        for x in other.get().iterate(heap)? {
+            if !this.contains(x)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// [set.pop](
+    /// https://bazel.build/rules/lib/core/set#pop
+    /// ): remove and return the first element of a set.
+    ///
+    /// `S.pop()` removes the first element of the set S, in iteration order,
+    /// and returns it. It fails if the set is empty, frozen, or has active
+    /// iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.pop() == 1 and x == set([2])
+    /// # "#);
+    /// # starlark::assert::fail(r#"
+    /// set().pop() # error: empty set
+    /// # "#, "empty set");
+    /// ```
+    fn pop<'v>(this: Value<'v>) -> anyhow::Result<Value<'v>> {
+        let mut this = SetMut::from_value(this)?;
+        match this.pop_first() {
+            Some(x) => Ok(x),
+            None => Err(anyhow::anyhow!("Cannot .pop() on an empty set")),
+        }
+    }
+
+    /// [set.remove](
+    /// https://bazel.build/rules/lib/core/set#remove
+    /// ): remove an element from a set.
+    ///
+    /// `S.remove(x)` removes `x` from the set S and returns `None`. It fails
+    /// if `x` is not in the set, is unhashable, or if the set is frozen or has
+    /// active iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.remove(2)
+    /// x == set([1])
+    /// # "#);
+    /// # starlark::assert::fail(r#"
+    /// set([1]).remove(2) # error: not found
+    /// # "#, "not found");
+    /// ```
+    fn remove<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos)] value: Value<'v>,
+    ) -> starlark::Result<NoneType> {
+        let hashed = value.get_hashed()?;
+        let mut me = SetMut::from_value(this)?;
+        if me.remove_hashed(hashed) {
+            Ok(NoneType)
+        } else {
+            mem::drop(me);
+            Err(anyhow::anyhow!(
+                "Value `{}` not found in set `{}`",
+                value.to_repr(),
+                this.to_repr()
+            )
+            .into())
+        }
+    }
+
+    /// [set.symmetric_difference](
+    /// https://bazel.build/rules/lib/core/set#symmetric_difference
+    /// ): the elements which are in exactly one of two sets.
+    ///
+    /// `S.symmetric_difference(x)` returns a new set with the elements that
+    /// are either in S or in the iterable x, but not in both. `S ^ T` is the
+    /// same as `S.symmetric_difference(T)`, but requires `T` to be a set.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// set([1, 2]).symmetric_difference([2, 3]) == set([1, 3])
+    /// set([1, 2]) ^ set([2, 3]) == set([1, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn symmetric_difference<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos)] other: ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<Set<'v>> {
+        Ok(this.symmetric_difference(&set_from_iterable(other.get(), heap)?))
+    }
+
+    /// [set.union](
+    /// https://bazel.build/rules/lib/core/set#union
+    /// ): the elements which are in a set or any of the others.
+    ///
+    /// `S.union(*others)` returns a new set with the elements of S followed
+    /// by those of each of the iterables `others`. `S | T` is the same as
+    /// `S.union(T)`, but requires `T` to be a set.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// set([1, 2]).union([2, 3], (4,)) == set([1, 2, 3, 4])
+    /// set([1, 2]) | set([3]) == set([1, 2, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn union<'v>(
+        this: SetRef<'v>,
+        #[starlark(args)] others: UnpackTuple<ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<Set<'v>> {
+        let mut res = (*this).clone();
+        for other in others.items {
+            for x in other.get().iterate(heap)? {
+                res.insert_hashed(x.get_hashed()?);
+            }
+        }
+        Ok(res)
+    }
+
+    /// [set.update](
+    /// https://bazel.build/rules/lib/core/set#update
+    /// ): add the elements of other iterables to a set.
+    ///
+    /// `S.update(*others)` adds the elements of each of the iterables
+    /// `others` to the set S and returns `None`. It fails if any element is
+    /// unhashable, or if the set is frozen or has active iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1])
+    /// x.update([2], (3, 1))
+    /// x == set([1, 2, 3])
+    /// # "#);
+    /// ```
+    fn update<'v>(
+        this: Value<'v>,
+        #[starlark(args)] others: UnpackTuple<ValueOfUnchecked<'v, StarlarkIter<Value<'v>>>>,
+        heap: &'v Heap,
+    ) -> starlark::Result<NoneType> {
+        let mut me = SetMut::from_value(this)?;
+        for other in others.items {
+            // `x.update(x)` is a no-op, and we can't iterate `x` while it is borrowed mutably.
+            if other.get().ptr_eq(this) {
+                continue;
+            }
+            for x in other.get().iterate(heap)? {
+                me.insert_hashed(x.get_hashed()?);
+            }
+        }
+        Ok(NoneType)
+    }
+}
@@ -562,8 +562,11 @@ impl<'a, 'v> GlobalTypesBuilder<'a, 'v> {
             TypeExprUnpackP::Path(first, rem) => self.path_ty(first, rem),
             TypeExprUnpackP::Index(a, i) => {
                 if let Some(a) = self.expr_ident(a)?.value {
-                    if !a.ptr_eq(Constants::get().fn_list.0.to_value()) {
-                        self.approximations.push(Approximation::new("Not list", x));
+                    if !a.ptr_eq(Constants::get().fn_list.0.to_value())
+                        && !a.ptr_eq(Constants::get().fn_set.0.to_value())
+                    {
+                        self.approximations
+                            .push(Approximation::new("Not list or set", x));
                         return Ok(Ty::any());
                     }
                     let i = self.from_type_expr_impl(i)?;
@@ -133,14 +133,26 @@ impl Param {
         }
     }
 
-    pub(crate) fn allows_pos(&self) -> bool {
+    /// The type of the parameter.
+    pub fn ty(&self) -> &Ty {
+        &self.ty
+    }
+
+    /// Whether the parameter has a default value or is otherwise optional.
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+
+    /// Whether the parameter can be passed by position.
+    pub fn allows_pos(&self) -> bool {
         match self.mode {
             ParamMode::PosOnly | ParamMode::PosOrName(_) | ParamMode::Args => true,
             ParamMode::NameOnly(_) | ParamMode::Kwargs => false,
         }
     }
 
-    pub(crate) fn allows_many(&self) -> bool {
+    /// Whether the parameter is `*args` or `**kwargs`.
+    pub fn allows_many(&self) -> bool {
         match self.mode {
             ParamMode::Args | ParamMode::Kwargs => true,
             _ => false,
@@ -280,6 +292,16 @@ impl TyFunction {
         }
     }
 
+    /// The parameters of the function.
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    /// The result type of the function.
+    pub fn result(&self) -> &Ty {
+        &self.result
+    }
+
     fn maybe_intern_params(params: Vec<Param>) -> SmallArcVec1OrStatic<Param> {
         if params.as_slice() == Self::any_params() {
             SmallArcVec1OrStatic::new_static(Self::any_params())
@@ -39,6 +39,7 @@ use crate::eval::compiler::scope::scope_resolver_globals::ScopeResolverGlobals;
 use crate::eval::compiler::scope::BindingId;
 use crate::eval::compiler::scope::BindingSource;
 use crate::eval::compiler::scope::ModuleScopes;
+use crate::eval::compiler::scope::Slot;
 use crate::syntax::AstModule;
 use crate::syntax::Dialect;
 use crate::typing::bindings::Bindings;
@@ -154,6 +155,16 @@ impl Display for TypeMap {
 }
 
 impl TypeMap {
+    /// The inferred types of all the bindings, with the name and the span where the
+    /// binding is defined: module-level variables, and parameters and variables of
+    /// top-level functions.
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, Span, &Ty)> {
+        self.bindings
+            .entries_sorted()
+            .into_iter()
+            .map(|(_binding_id, (name, span, ty))| (name.as_str(), *span, ty))
+    }
+
     #[cfg(test)]
     pub(crate) fn find_bindings_by_name<'a>(&'a self, name: &str) -> Vec<&'a Ty> {
         self.bindings
@@ -287,6 +298,18 @@ impl AstModuleTypecheck for AstModule {
             }
         }
 
+        // Module-level variables are not solved as bindings, their types come from
+        // filling the types of the top-level statements.
+        for (id, binding) in scope_data.bindings() {
+            if let (BindingSource::Source(span), Ok(Slot::Module(module_slot_id))) =
+                (&binding.source, binding.resolved_slot(&codemap))
+            {
+                if let Some(ty) = module_var_types.types.get(&module_slot_id) {
+                    typemap.insert(id, (binding.name.as_str().to_owned(), *span, ty.clone()));
+                }
+            }
+        }
+
         let typemap = TypeMap {
             bindings: typemap,
             codemap: codemap.dupe(),
@@ -51,6 +51,19 @@ impl<K: StarlarkTypeRepr, V: StarlarkTypeRepr> StarlarkTypeRepr for DictType<K,
     }
 }
 
+/// A set used just for display purposes.
+///
+/// `Set` does not take type parameters, so we need something for documentation generation.
+pub struct SetType<T: StarlarkTypeRepr> {
+    t: PhantomData<T>,
+}
+
+impl<T: StarlarkTypeRepr> StarlarkTypeRepr for SetType<T> {
+    fn starlark_type_repr() -> Ty {
+        Ty::set(T::starlark_type_repr())
+    }
+}
+
 impl<'v, T: StarlarkValue<'v> + ?Sized> StarlarkTypeRepr for T {
     fn starlark_type_repr() -> Ty {
         Self::get_type_starlark_repr()
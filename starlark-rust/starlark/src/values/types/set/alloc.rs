@@ -0,0 +1,92 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use starlark_map::small_set::SmallSet;
+
+use crate::typing::Ty;
+use crate::values::layout::value::ValueLike;
+use crate::values::set::value::FrozenSetData;
+use crate::values::set::Set;
+use crate::values::type_repr::SetType;
+use crate::values::type_repr::StarlarkTypeRepr;
+use crate::values::AllocFrozenValue;
+use crate::values::AllocValue;
+use crate::values::FrozenHeap;
+use crate::values::FrozenValue;
+use crate::values::Heap;
+use crate::values::Value;
+
+/// Utility to allocate a set from iterator.
+///
+/// Duplicate values are allowed, the first one wins.
+///
+/// # Panics
+///
+/// Panics if a value is not hashable.
+///
+/// # Example
+///
+/// ```
+/// use starlark::values::set::AllocSet;
+///
+/// # use starlark::values::{FrozenHeap, Heap};
+/// # fn alloc(heap: &Heap, frozen_heap: &FrozenHeap) {
+/// let s = heap.alloc(AllocSet(["a", "b", "c"]));
+/// let fs = frozen_heap.alloc(AllocSet(["a", "b", "c"]));
+/// # }
+/// ```
+pub struct AllocSet<S>(pub S);
+
+impl<S, T> StarlarkTypeRepr for AllocSet<S>
+where
+    S: IntoIterator<Item = T>,
+    T: StarlarkTypeRepr,
+{
+    fn starlark_type_repr() -> Ty {
+        SetType::<T>::starlark_type_repr()
+    }
+}
+
+impl<'v, S, T> AllocValue<'v> for AllocSet<S>
+where
+    S: IntoIterator<Item = T>,
+    T: AllocValue<'v>,
+{
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        let iter = self.0.into_iter();
+        let mut content = SmallSet::with_capacity(iter.size_hint().0);
+        for x in iter {
+            content.insert_hashed(x.alloc_value(heap).get_hashed().unwrap());
+        }
+        heap.alloc(Set::new(content))
+    }
+}
+
+impl<S, T> AllocFrozenValue for AllocSet<S>
+where
+    S: IntoIterator<Item = T>,
+    T: AllocFrozenValue,
+{
+    fn alloc_frozen_value(self, heap: &FrozenHeap) -> FrozenValue {
+        let iter = self.0.into_iter();
+        let mut content = SmallSet::with_capacity(iter.size_hint().0);
+        for x in iter {
+            content.insert_hashed(x.alloc_frozen_value(heap).get_hashed().unwrap());
+        }
+        heap.alloc(FrozenSetData::new(content))
+    }
+}
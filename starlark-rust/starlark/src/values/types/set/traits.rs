@@ -0,0 +1,67 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::hash::Hash;
+
+use starlark_map::small_set::SmallSet;
+
+use crate::typing::Ty;
+use crate::values::set::AllocSet;
+use crate::values::set::SetRef;
+use crate::values::type_repr::SetType;
+use crate::values::type_repr::StarlarkTypeRepr;
+use crate::values::AllocFrozenValue;
+use crate::values::AllocValue;
+use crate::values::FrozenHeap;
+use crate::values::FrozenValue;
+use crate::values::Heap;
+use crate::values::UnpackValue;
+use crate::values::Value;
+
+impl<'v, T: AllocValue<'v>> AllocValue<'v> for SmallSet<T> {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        AllocSet(self).alloc_value(heap)
+    }
+}
+
+impl<T: AllocFrozenValue> AllocFrozenValue for SmallSet<T> {
+    fn alloc_frozen_value(self, heap: &FrozenHeap) -> FrozenValue {
+        AllocSet(self).alloc_frozen_value(heap)
+    }
+}
+
+impl<T: StarlarkTypeRepr> StarlarkTypeRepr for SmallSet<T> {
+    fn starlark_type_repr() -> Ty {
+        SetType::<T>::starlark_type_repr()
+    }
+}
+
+impl<'v, T: UnpackValue<'v> + Hash + Eq> UnpackValue<'v> for SmallSet<T> {
+    fn expected() -> String {
+        format!("set of {}", T::expected())
+    }
+
+    fn unpack_value(value: Value<'v>) -> Option<Self> {
+        let set = SetRef::from_value(value)?;
+        let it = set.iter();
+        let mut r = SmallSet::with_capacity(it.len());
+        for x in it {
+            r.insert(T::unpack_value(x)?);
+        }
+        Some(r)
+    }
+}
@@ -0,0 +1,554 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::Ref;
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::mem;
+use std::ops::Deref;
+
+use allocative::Allocative;
+use display_container::fmt_container;
+use serde::Serialize;
+use starlark_derive::starlark_value;
+use starlark_derive::StarlarkDocs;
+use starlark_map::small_set::SmallSet;
+
+use crate as starlark;
+use crate::any::ProvidesStaticType;
+use crate::coerce::coerce;
+use crate::coerce::Coerce;
+use crate::collections::Hashed;
+use crate::environment::Methods;
+use crate::environment::MethodsStatic;
+use crate::typing::Ty;
+use crate::values::dict::refcell::unleak_borrow;
+use crate::values::error::ValueError;
+use crate::values::layout::avalue::alloc_static;
+use crate::values::layout::avalue::AValueImpl;
+use crate::values::layout::avalue::Simple;
+use crate::values::layout::heap::repr::AValueRepr;
+use crate::values::set::SetRef;
+use crate::values::type_repr::StarlarkTypeRepr;
+use crate::values::AllocFrozenValue;
+use crate::values::AllocValue;
+use crate::values::Freeze;
+use crate::values::Freezer;
+use crate::values::FrozenHeap;
+use crate::values::FrozenValue;
+use crate::values::Heap;
+use crate::values::StarlarkValue;
+use crate::values::Trace;
+use crate::values::Value;
+use crate::values::ValueLike;
+
+#[derive(
+    Clone,
+    Default,
+    Trace,
+    Debug,
+    ProvidesStaticType,
+    StarlarkDocs,
+    Allocative
+)]
+#[starlark_docs(builtin = "extension")]
+pub(crate) struct SetGen<T>(pub(crate) T);
+
+fn fmt_set<'a, 'v: 'a>(
+    f: &mut fmt::Formatter<'_>,
+    content: &'a SmallSet<Value<'v>>,
+) -> fmt::Result {
+    if content.is_empty() {
+        f.write_str("set()")
+    } else {
+        fmt_container(f, "set([", "])", content.iter())
+    }
+}
+
+impl<'v, T: SetLike<'v>> Display for SetGen<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_set(f, &self.0.content())
+    }
+}
+
+impl<'v> Display for Set<'v> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_set(f, &self.content)
+    }
+}
+
+/// Define the set type.
+#[derive(Clone, Default, Trace, Debug, ProvidesStaticType, Allocative)]
+#[repr(transparent)]
+pub struct Set<'v> {
+    /// The data stored by the set. The values must all be hashable.
+    content: SmallSet<Value<'v>>,
+}
+
+impl<'v> StarlarkTypeRepr for Set<'v> {
+    fn starlark_type_repr() -> Ty {
+        Ty::any_set()
+    }
+}
+
+#[derive(Clone, Default, Debug, ProvidesStaticType, Allocative)]
+#[repr(transparent)]
+pub(crate) struct FrozenSetData {
+    /// The data stored by the set. The values must all be hashable.
+    content: SmallSet<FrozenValue>,
+}
+
+/// Alias is used in `StarlarkDocs` derive.
+pub(crate) type FrozenSet = SetGen<FrozenSetData>;
+
+pub(crate) type MutableSet<'v> = SetGen<RefCell<Set<'v>>>;
+
+pub(crate) static VALUE_EMPTY_FROZEN_SET: AValueRepr<AValueImpl<Simple, SetGen<FrozenSetData>>> =
+    alloc_static(
+        Simple,
+        SetGen(FrozenSetData {
+            content: SmallSet::new(),
+        }),
+    );
+
+unsafe impl<'v> Coerce<Set<'v>> for FrozenSetData {}
+
+impl<'v> AllocValue<'v> for Set<'v> {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        heap.alloc_complex(SetGen(RefCell::new(self)))
+    }
+}
+
+impl StarlarkTypeRepr for FrozenSetData {
+    fn starlark_type_repr() -> Ty {
+        Ty::any_set()
+    }
+}
+
+impl AllocFrozenValue for FrozenSetData {
+    fn alloc_frozen_value(self, heap: &FrozenHeap) -> FrozenValue {
+        if self.content.is_empty() {
+            FrozenValue::new_repr(&VALUE_EMPTY_FROZEN_SET)
+        } else {
+            heap.alloc_simple(SetGen(self))
+        }
+    }
+}
+
+impl FrozenSetData {
+    pub(crate) fn new(content: SmallSet<FrozenValue>) -> FrozenSetData {
+        FrozenSetData { content }
+    }
+}
+
+impl<'v> Set<'v> {
+    /// The result of calling `type()` on sets.
+    pub const TYPE: &'static str = "set";
+
+    /// Create a set with the given content.
+    pub fn new(content: SmallSet<Value<'v>>) -> Self {
+        Self { content }
+    }
+
+    /// Number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Is the set empty?
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    /// Iterate through the values in the set, in insertion order.
+    pub fn iter<'a>(&'a self) -> impl ExactSizeIterator<Item = Value<'v>> + 'a {
+        self.content.iter().copied()
+    }
+
+    /// Iterate through the values in the set, but retaining their hashes.
+    pub fn iter_hashed<'a>(&'a self) -> impl ExactSizeIterator<Item = Hashed<Value<'v>>> + 'a
+    where
+        'v: 'a,
+    {
+        self.content.iter_hashed().map(|h| h.copied())
+    }
+
+    /// Does the set contain the value? Will be [`Err`] if the value is not hashable.
+    pub fn contains(&self, value: Value<'v>) -> crate::Result<bool> {
+        Ok(self.contains_hashed(value.get_hashed()?))
+    }
+
+    /// Does the set contain the prehashed value?
+    pub fn contains_hashed(&self, value: Hashed<Value<'v>>) -> bool {
+        contains(&self.content, value.as_ref())
+    }
+
+    /// Is every value in this set also in the other set?
+    pub fn is_subset(&self, other: &Set<'v>) -> bool {
+        is_subset(&self.content, &other.content)
+    }
+
+    /// Insert a value into the set. Returns `true` if it was not already present.
+    pub fn insert_hashed(&mut self, value: Hashed<Value<'v>>) -> bool {
+        self.content.insert_hashed(value)
+    }
+
+    /// Remove a value from the set. Returns `true` if it was present.
+    pub fn remove_hashed(&mut self, value: Hashed<Value<'v>>) -> bool {
+        self.content.remove_hashed(value.as_ref())
+    }
+
+    /// Remove and return the first value in the set.
+    pub fn pop_first(&mut self) -> Option<Value<'v>> {
+        let first = self.content.iter_hashed().next()?.copied();
+        self.content.remove_hashed(first.as_ref());
+        Some(first.into_key())
+    }
+
+    /// Remove all values from the set.
+    pub fn clear(&mut self) {
+        self.content.clear();
+    }
+
+    /// The values which are in either this set or the other, in the order they were first seen.
+    pub fn union(&self, other: &Set<'v>) -> Set<'v> {
+        Set::new(union(&self.content, &other.content))
+    }
+
+    /// The values in this set which are also in the other.
+    pub fn intersection(&self, other: &Set<'v>) -> Set<'v> {
+        Set::new(intersection(&self.content, &other.content))
+    }
+
+    /// The values in this set which are not in the other.
+    pub fn difference(&self, other: &Set<'v>) -> Set<'v> {
+        Set::new(difference(&self.content, &other.content))
+    }
+
+    /// The values which are in exactly one of this set and the other.
+    pub fn symmetric_difference(&self, other: &Set<'v>) -> Set<'v> {
+        Set::new(symmetric_difference(&self.content, &other.content))
+    }
+}
+
+fn contains<'v>(xs: &SmallSet<Value<'v>>, x: Hashed<&Value<'v>>) -> bool {
+    xs.get_index_of_hashed(x).is_some()
+}
+
+fn is_subset<'v>(xs: &SmallSet<Value<'v>>, ys: &SmallSet<Value<'v>>) -> bool {
+    xs.len() <= ys.len() && xs.iter_hashed().all(|x| contains(ys, x))
+}
+
+fn union<'v>(xs: &SmallSet<Value<'v>>, ys: &SmallSet<Value<'v>>) -> SmallSet<Value<'v>> {
+    let mut res = xs.clone();
+    for y in ys.iter_hashed() {
+        res.insert_hashed(y.copied());
+    }
+    res
+}
+
+fn intersection<'v>(xs: &SmallSet<Value<'v>>, ys: &SmallSet<Value<'v>>) -> SmallSet<Value<'v>> {
+    let mut res = SmallSet::new();
+    for x in xs.iter_hashed() {
+        if contains(ys, x) {
+            res.insert_hashed_unique_unchecked(x.copied());
+        }
+    }
+    res
+}
+
+fn difference<'v>(xs: &SmallSet<Value<'v>>, ys: &SmallSet<Value<'v>>) -> SmallSet<Value<'v>> {
+    let mut res = SmallSet::new();
+    for x in xs.iter_hashed() {
+        if !contains(ys, x) {
+            res.insert_hashed_unique_unchecked(x.copied());
+        }
+    }
+    res
+}
+
+fn symmetric_difference<'v>(
+    xs: &SmallSet<Value<'v>>,
+    ys: &SmallSet<Value<'v>>,
+) -> SmallSet<Value<'v>> {
+    let mut res = difference(xs, ys);
+    for y in ys.iter_hashed() {
+        if !contains(xs, y) {
+            res.insert_hashed_unique_unchecked(y.copied());
+        }
+    }
+    res
+}
+
+impl<'v> Freeze for SetGen<RefCell<Set<'v>>> {
+    type Frozen = SetGen<FrozenSetData>;
+    fn freeze(self, freezer: &Freezer) -> anyhow::Result<Self::Frozen> {
+        let content = self.0.into_inner().content.freeze(freezer)?;
+        Ok(SetGen(FrozenSetData { content }))
+    }
+}
+
+pub(crate) trait SetLike<'v>: Debug + Allocative {
+    type ContentRef<'a>: Deref<Target = SmallSet<Value<'v>>>
+    where
+        Self: 'a,
+        'v: 'a;
+    fn content<'a>(&'a self) -> Self::ContentRef<'a>;
+    // These functions are unsafe for the same reason
+    // `StarlarkValue` iterator functions are unsafe.
+    unsafe fn iter_start(&self);
+    unsafe fn content_unchecked(&self) -> &SmallSet<Value<'v>>;
+    unsafe fn iter_stop(&self);
+}
+
+impl<'v> SetLike<'v> for RefCell<Set<'v>> {
+    type ContentRef<'a> = Ref<'a, SmallSet<Value<'v>>> where Self: 'a, 'v: 'a;
+
+    fn content<'a>(&'a self) -> Ref<'a, SmallSet<Value<'v>>> {
+        Ref::map(self.borrow(), |x| &x.content)
+    }
+
+    #[inline]
+    unsafe fn iter_start(&self) {
+        mem::forget(self.borrow());
+    }
+
+    #[inline]
+    unsafe fn iter_stop(&self) {
+        unleak_borrow(self);
+    }
+
+    #[inline]
+    unsafe fn content_unchecked(&self) -> &SmallSet<Value<'v>> {
+        // SAFETY: this function contract is, caller must ensure that the value is borrowed.
+        &self.try_borrow_unguarded().ok().unwrap_unchecked().content
+    }
+}
+
+impl<'v> SetLike<'v> for FrozenSetData {
+    type ContentRef<'a> = &'a SmallSet<Value<'v>> where Self: 'a, 'v: 'a;
+
+    fn content<'a>(&'a self) -> &'a SmallSet<Value<'v>> {
+        coerce(&self.content)
+    }
+
+    unsafe fn iter_start(&self) {}
+
+    unsafe fn iter_stop(&self) {}
+
+    unsafe fn content_unchecked(&self) -> &SmallSet<Value<'v>> {
+        coerce(&self.content)
+    }
+}
+
+pub(crate) fn set_methods() -> Option<&'static Methods> {
+    static RES: MethodsStatic = MethodsStatic::new();
+    RES.methods(crate::stdlib::set::set_methods)
+}
+
+impl<'v, T: SetLike<'v> + 'v> SetGen<T>
+where
+    Self: ProvidesStaticType<'v>,
+{
+    fn binary_op(
+        &self,
+        op: &str,
+        rhs: Value<'v>,
+        heap: &'v Heap,
+        f: impl FnOnce(&SmallSet<Value<'v>>, &SmallSet<Value<'v>>) -> SmallSet<Value<'v>>,
+    ) -> crate::Result<Value<'v>> {
+        let rhs = SetRef::from_value(rhs)
+            .map_or_else(|| ValueError::unsupported_with(self, op, rhs), Ok)?;
+        Ok(heap.alloc(Set::new(f(&self.0.content(), &rhs.content))))
+    }
+}
+
+#[starlark_value(type = Set::TYPE)]
+impl<'v, T: SetLike<'v> + 'v> StarlarkValue<'v> for SetGen<T>
+where
+    Self: ProvidesStaticType<'v>,
+{
+    type Canonical = FrozenSet;
+
+    fn get_methods() -> Option<&'static Methods> {
+        set_methods()
+    }
+
+    fn collect_repr(&self, r: &mut String) {
+        let content = self.0.content();
+        if content.is_empty() {
+            r.push_str("set()");
+            return;
+        }
+        r.push_str("set([");
+        for (i, value) in content.iter().enumerate() {
+            if i != 0 {
+                r.push_str(", ");
+            }
+            value.collect_repr(r);
+        }
+        r.push_str("])");
+    }
+
+    fn collect_repr_cycle(&self, collector: &mut String) {
+        collector.push_str("set(...)");
+    }
+
+    fn to_bool(&self) -> bool {
+        !self.0.content().is_empty()
+    }
+
+    fn equals(&self, other: Value<'v>) -> crate::Result<bool> {
+        match SetRef::from_value(other) {
+            None => Ok(false),
+            Some(other) => {
+                let content = self.0.content();
+                Ok(content.len() == other.len() && is_subset(&content, &other.content))
+            }
+        }
+    }
+
+    fn length(&self) -> crate::Result<i32> {
+        Ok(self.0.content().len() as i32)
+    }
+
+    fn is_in(&self, other: Value<'v>) -> crate::Result<bool> {
+        Ok(contains(&self.0.content(), other.get_hashed()?.as_ref()))
+    }
+
+    unsafe fn iterate(&self, me: Value<'v>, _heap: &'v Heap) -> crate::Result<Value<'v>> {
+        self.0.iter_start();
+        Ok(me)
+    }
+
+    unsafe fn iter_size_hint(&self, index: usize) -> (usize, Option<usize>) {
+        debug_assert!(index <= self.0.content().len());
+        let rem = self.0.content().len() - index;
+        (rem, Some(rem))
+    }
+
+    unsafe fn iter_next(&self, index: usize, _heap: &'v Heap) -> Option<Value<'v>> {
+        self.0.content_unchecked().get_index(index).copied()
+    }
+
+    unsafe fn iter_stop(&self) {
+        self.0.iter_stop();
+    }
+
+    fn bit_or(&self, rhs: Value<'v>, heap: &'v Heap) -> crate::Result<Value<'v>> {
+        self.binary_op("|", rhs, heap, union)
+    }
+
+    fn bit_and(&self, rhs: Value<'v>, heap: &'v Heap) -> crate::Result<Value<'v>> {
+        self.binary_op("&", rhs, heap, intersection)
+    }
+
+    fn bit_xor(&self, rhs: Value<'v>, heap: &'v Heap) -> crate::Result<Value<'v>> {
+        self.binary_op("^", rhs, heap, symmetric_difference)
+    }
+
+    fn sub(&self, rhs: Value<'v>, heap: &'v Heap) -> crate::Result<Value<'v>> {
+        self.binary_op("-", rhs, heap, difference)
+    }
+
+    fn typechecker_ty(&self) -> Option<Ty> {
+        Some(Ty::any_set())
+    }
+
+    fn get_type_starlark_repr() -> Ty {
+        Ty::any_set()
+    }
+}
+
+impl<'v, T: SetLike<'v>> Serialize for SetGen<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.0.content().iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_repr() {
+        assert::eq("'set()'", "repr(set())");
+        assert::eq("'set([1, 2, 3])'", "repr(set([1, 2, 3, 2, 1]))");
+        assert::eq("'set([\"a\"])'", "str(set(['a']))");
+    }
+
+    #[test]
+    fn test_operators() {
+        assert::all_true(
+            r#"
+set([1, 2]) | set([2, 3]) == set([1, 2, 3])
+set([1, 2]) & set([2, 3]) == set([2])
+set([1, 2]) - set([2, 3]) == set([1])
+set([1, 2]) ^ set([2, 3]) == set([1, 3])
+set([1, 2]) == set([2, 1])
+set([1, 2]) != set([1])
+set([1]) != [1]
+2 in set([1, 2])
+3 not in set([1, 2])
+len(set([1, 1, 2])) == 2
+not set()
+list(set([3, 1, 2])) == [3, 1, 2]
+type(set()) == "set"
+"#,
+        );
+        assert::fail("set([1]) | [2]", "not supported");
+        assert::fail("set([[1]])", "not hashable");
+    }
+
+    #[test]
+    fn test_mutate_during_iteration() {
+        assert::fail(
+            r#"
+def f():
+    s = set([1, 2])
+    for x in s:
+        s.add(x + 10)
+f()
+"#,
+            "mutate an iterable",
+        );
+    }
+
+    #[test]
+    fn test_frozen() {
+        let mut a = assert::Assert::new();
+        a.module("m", "s = set([1, 2])");
+        a.is_true(
+            r#"
+load("m", "s")
+s == set([2, 1]) and 1 in s
+"#,
+        );
+        a.fail(
+            r#"
+load("m", "s")
+s.add(3)
+"#,
+            "Immutable",
+        );
+    }
+}
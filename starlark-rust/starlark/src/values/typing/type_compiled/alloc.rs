@@ -38,6 +38,8 @@ use crate::values::typing::type_compiled::matchers::IsListOf;
 use crate::values::typing::type_compiled::matchers::IsName;
 use crate::values::typing::type_compiled::matchers::IsNever;
 use crate::values::typing::type_compiled::matchers::IsNone;
+use crate::values::typing::type_compiled::matchers::IsSet;
+use crate::values::typing::type_compiled::matchers::IsSetOf;
 use crate::values::typing::type_compiled::matchers::IsStr;
 use crate::values::typing::type_compiled::matchers::IsType;
 use crate::values::typing::type_compiled::matchers::StarlarkTypeIdMatcher;
@@ -146,6 +148,7 @@ pub trait TypeMatcherAlloc: Sized {
             TyBasic::List(item) => self.list_of(item),
             TyBasic::Tuple(tuple) => tuple.matcher(self),
             TyBasic::Dict(k, v) => self.dict_of(k, v),
+            TyBasic::Set(item) => self.set_of(item),
             TyBasic::Iter(_item) => self.alloc(IsIterable),
             TyBasic::Callable => self.alloc(IsCallable),
             TyBasic::Type => self.alloc(IsType),
@@ -259,4 +262,33 @@ pub trait TypeMatcherAlloc: Sized {
             self.dict_of_matcher(k, v)
         }
     }
+
+    /// `set`.
+    fn set(self) -> Self::Result {
+        self.alloc(IsSet)
+    }
+
+    /// `set[Item]`.
+    fn set_of_matcher(self, item: impl TypeMatcher) -> Self::Result {
+        if item.is_wildcard() {
+            self.set()
+        } else {
+            self.alloc(IsSetOf(item))
+        }
+    }
+
+    /// `set[Item]`.
+    fn set_of(self, item: &Ty) -> Self::Result {
+        if item.is_any() {
+            self.set()
+        } else if let Some(item) = item.is_starlark_value() {
+            if item.is_str() {
+                self.set_of_matcher(IsStr)
+            } else {
+                self.set_of_matcher(StarlarkTypeIdMatcher::new(item))
+            }
+        } else {
+            self.set_of_matcher(TypeMatcherBoxAlloc.ty(item))
+        }
+    }
 }
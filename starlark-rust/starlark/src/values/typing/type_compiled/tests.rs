@@ -109,6 +109,8 @@ isinstance('test', int | str)
 isinstance(('test', None), (str, None))
 isinstance({"test": 1, "more": 2}, dict[str, int])
 isinstance({1: 1, 2: 2}, dict[int, int])
+isinstance(set(), set[int])
+isinstance(set([1, 2]), set[int])
 
 not isinstance(1, None)
 not isinstance((1, 1), str)
@@ -116,6 +118,8 @@ not isinstance('test', int | bool)
 not isinstance([1,2,None], list[int])
 not isinstance({"test": 1, 8: 2}, dict[str, int])
 not isinstance({"test": 1, "more": None}, dict[str, int])
+not isinstance(set([1, "x"]), set[int])
+not isinstance([1], set[int])
 
 isinstance(1, "")
 isinstance([1,2,"test"], list["_a"])
@@ -284,6 +288,20 @@ noop(uuu)(["mm"])
     );
 }
 
+#[test]
+fn test_set_syntax_fail_compile_time() {
+    assert::fail(
+        r#"
+def uuu(x: set[int]):
+    pass
+
+def www():
+    uuu(set(["mm"]))
+"#,
+        "Expected type `set[int]` but got `set[str]`",
+    );
+}
+
 #[test]
 fn test_bit_or() {
     let types = [
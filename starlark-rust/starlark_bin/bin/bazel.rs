@@ -46,6 +46,7 @@ use starlark::docs::Doc;
 use starlark::docs::DocItem;
 use starlark::docs::DocModule;
 use starlark::environment::FrozenModule;
+use starlark::environment::Globals;
 use starlark::environment::Module;
 use starlark::errors::EvalMessage;
 use starlark::eval::Evaluator;
@@ -61,6 +62,7 @@ use starlark_lsp::server::StringLiteralResult;
 use self::label::Label;
 use crate::eval::dialect;
 use crate::eval::globals;
+use crate::eval::workspace_files;
 use crate::eval::ContextMode;
 use crate::eval::EvalResult;
 
@@ -775,6 +777,29 @@ impl LspContext for BazelContext {
         DocModule::default()
     }
 
+    fn get_typecheck_globals(&self, _uri: &LspUrl) -> Globals {
+        globals()
+    }
+
+    fn get_workspace_files(
+        &self,
+        workspace_root: Option<&Path>,
+    ) -> anyhow::Result<Option<Vec<LspUrl>>> {
+        Ok(workspace_root.and_then(|root| {
+            workspace_files(root, |path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map_or(false, |name| Self::BUILD_FILE_NAMES.contains(&name))
+                    || path
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .map_or(false, |extension| {
+                            Self::LOADABLE_EXTENSIONS.contains(&extension)
+                        })
+            })
+        }))
+    }
+
     fn get_url_for_global_symbol(
         &self,
         _current_file: &LspUrl,
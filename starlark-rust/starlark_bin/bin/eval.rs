@@ -23,8 +23,10 @@ use std::iter;
 use std::path::Path;
 use std::path::PathBuf;
 
+use ignore::WalkBuilder;
 use itertools::Either;
 use lsp_types::Url;
+use starlark::analysis::apply_lint_fixes;
 use starlark::analysis::AstModuleLint;
 use starlark::docs::get_registered_starlark_docs;
 use starlark::docs::render_docs_as_code;
@@ -68,6 +70,8 @@ pub(crate) struct Context {
     pub(crate) module: Option<Module>,
     pub(crate) builtin_docs: HashMap<LspUrl, String>,
     pub(crate) builtin_symbols: HashMap<String, LspUrl>,
+    /// The extension of the Starlark files in the workspace, without the leading dot.
+    pub(crate) extension: String,
 }
 
 /// The outcome of evaluating (checking, parsing or running) given starlark code.
@@ -138,6 +142,7 @@ impl Context {
             module,
             builtin_docs,
             builtin_symbols,
+            extension: "bzl".to_owned(),
         })
     }
 
@@ -262,8 +267,49 @@ impl Context {
         )
     }
 
+    /// Apply the fixes the lints suggest to a file, returning whether it changed.
+    /// Files which don't parse, and fixes which would make them not parse, are left alone.
+    pub(crate) fn fix(&self, file: &Path) -> anyhow::Result<bool> {
+        let filename = file.to_string_lossy();
+        let parse = |content: &str| AstModule::parse(&filename, content.to_owned(), &dialect());
+        let original = fs::read_to_string(file)?;
+        let Ok(mut module) = parse(&original) else {
+            return Ok(false);
+        };
+        let globals = self.lint_globals();
+        let mut content = original.clone();
+        // Only one of any overlapping fixes is applied at a time, so lint again for the rest.
+        for _ in 0..10 {
+            let Some(fixed) = apply_lint_fixes(&content, &module.lint(globals.as_ref())) else {
+                break;
+            };
+            if fixed == content {
+                break;
+            }
+            let Ok(fixed_module) = parse(&fixed) else {
+                break;
+            };
+            module = fixed_module;
+            content = fixed;
+        }
+        if content == original {
+            return Ok(false);
+        }
+        fs::write(file, content)?;
+        Ok(true)
+    }
+
     fn check(&self, module: &AstModule) -> impl Iterator<Item = EvalMessage> {
-        let globals = if self.prelude.is_empty() {
+        let globals = self.lint_globals();
+        module
+            .lint(globals.as_ref())
+            .into_iter()
+            .map(EvalMessage::from)
+    }
+
+    /// The globals to check for undefined variables, if they are known.
+    fn lint_globals(&self) -> Option<HashSet<String>> {
+        if self.prelude.is_empty() {
             None
         } else {
             let mut globals = HashSet::new();
@@ -278,12 +324,7 @@ impl Context {
             }
 
             Some(globals)
-        };
-
-        module
-            .lint(globals.as_ref())
-            .into_iter()
-            .map(EvalMessage::from)
+        }
     }
 }
 
@@ -375,6 +416,40 @@ impl LspContext for Context {
     fn get_environment(&self, _uri: &LspUrl) -> DocModule {
         DocModule::default()
     }
+
+    fn get_typecheck_globals(&self, _uri: &LspUrl) -> Globals {
+        globals()
+    }
+
+    fn get_workspace_files(
+        &self,
+        workspace_root: Option<&Path>,
+    ) -> anyhow::Result<Option<Vec<LspUrl>>> {
+        Ok(workspace_root.and_then(|root| {
+            workspace_files(root, |path| {
+                path.extension()
+                    .map_or(false, |extension| extension == self.extension.as_str())
+            })
+        }))
+    }
+}
+
+/// List the files under `root` that `is_starlark` accepts, skipping hidden files and the files
+/// excluded by `.gitignore` or `.ignore`.
+///
+/// Returns `None` if part of the workspace can't be read, as it might hold files we would miss.
+pub(crate) fn workspace_files(
+    root: &Path,
+    is_starlark: impl Fn(&Path) -> bool,
+) -> Option<Vec<LspUrl>> {
+    let mut files = Vec::new();
+    for entry in WalkBuilder::new(root).build() {
+        let entry = entry.ok()?;
+        if entry.file_type().map_or(false, |t| t.is_file()) && is_starlark(entry.path()) {
+            files.push(LspUrl::File(entry.into_path()));
+        }
+    }
+    Some(files)
 }
 
 pub(crate) fn globals() -> Globals {
@@ -22,6 +22,7 @@
 use std::ffi::OsStr;
 use std::fmt;
 use std::fmt::Display;
+use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -43,6 +44,7 @@ use starlark::environment::Globals;
 use starlark::errors::EvalMessage;
 use starlark::errors::EvalSeverity;
 use starlark::read_line::ReadLine;
+use starlark::syntax::AstModule;
 use walkdir::WalkDir;
 
 use crate::eval::ContextMode;
@@ -62,6 +64,7 @@ struct Args {
             "check",
             "json",
             "docs",
+            "format",
             "evaluate",
             "files",
         ],
@@ -77,6 +80,7 @@ struct Args {
             "check",
             "json",
             "docs",
+            "format",
             "extension",
             "prelude",
             "evaluate",
@@ -92,6 +96,14 @@ struct Args {
     )]
     check: bool,
 
+    #[arg(
+        long = "fix",
+        help = "Apply the fixes suggested by the lints, then report the remaining lints.",
+        requires = "check",
+        conflicts_with_all = &["format", "evaluate"],
+    )]
+    fix: bool,
+
     #[arg(
         long = "json",
         help = "Show output as JSON lines.",
@@ -106,6 +118,13 @@ struct Args {
     )]
     docs: Option<ArgsDoc>,
 
+    #[arg(
+        long = "format",
+        help = "Format the files in place. With --check, only report the files which need formatting.",
+        conflicts_with_all = &["lsp", "dap", "json", "docs", "evaluate"],
+    )]
+    format: bool,
+
     #[arg(
         long = "extension",
         help = "File extension when searching directories."
@@ -241,6 +260,33 @@ fn interactive(ctx: &Context) -> anyhow::Result<()> {
     }
 }
 
+/// Format the files, rewriting those which change, or with `check` only reporting them.
+fn format_files(files: impl Iterator<Item = PathBuf>, check: bool) -> anyhow::Result<()> {
+    let mut unformatted = 0;
+    for file in files {
+        let content =
+            fs::read_to_string(&file).with_context(|| format!("reading `{}`", file.display()))?;
+        let formatted =
+            AstModule::parse(&file.to_string_lossy(), content.clone(), &eval::dialect())
+                .and_then(|ast| ast.format())
+                .map_err(starlark::Error::into_anyhow)?;
+        if formatted == content {
+            continue;
+        }
+        unformatted += 1;
+        if check {
+            println!("{} needs formatting", file.display());
+        } else {
+            fs::write(&file, formatted).with_context(|| format!("writing `{}`", file.display()))?;
+            println!("Formatted {}", file.display());
+        }
+    }
+    if check && unformatted > 0 {
+        return Err(anyhow::anyhow!("{} files need formatting", unformatted));
+    }
+    Ok(())
+}
+
 /// starlark-rust does not support panic.
 /// Terminate on panic even if compiled without `-Cpanic=abort`.
 fn terminate_on_panic() {
@@ -268,6 +314,10 @@ fn main() -> anyhow::Result<()> {
         let prelude = expand_dirs(ext, args.prelude).collect::<Vec<_>>();
         let print_non_none = !args.evaluate.is_empty() || is_interactive;
 
+        if args.format {
+            return format_files(expand_dirs(ext, args.files), args.check);
+        }
+
         // TODO: Remove this when extracting the Bazel binary to its own
         // repository, after the LspContext interface stabilizes.
         if args.bazel {
@@ -288,6 +338,7 @@ fn main() -> anyhow::Result<()> {
 
         if args.lsp {
             ctx.mode = ContextMode::Check;
+            ctx.extension = ext.to_owned();
             starlark_lsp::server::stdio_server(ctx)?;
         } else if let Some(docs) = args.docs {
             let mut builtin = get_registered_starlark_docs();
@@ -321,6 +372,9 @@ fn main() -> anyhow::Result<()> {
 
             for file in expand_dirs(ext, args.files.clone()) {
                 stats.increment_file();
+                if args.fix && ctx.fix(&file)? {
+                    eprintln!("Fixed {}", file.display());
+                }
                 drain(ctx.file(&file).messages, args.json, &mut stats)?;
             }
 
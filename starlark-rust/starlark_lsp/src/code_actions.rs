@@ -0,0 +1,95 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Code actions which apply the fixes the linter suggests.
+
+use std::collections::HashMap;
+
+use lsp_types::CodeAction;
+use lsp_types::CodeActionKind;
+use lsp_types::CodeActionOrCommand;
+use lsp_types::CodeActionParams;
+use lsp_types::CodeActionResponse;
+use lsp_types::NumberOrString;
+use lsp_types::Range;
+use lsp_types::TextEdit;
+use lsp_types::WorkspaceEdit;
+use starlark::analysis::AstModuleLint;
+
+use crate::server::Backend;
+use crate::server::LspContext;
+
+impl<T: LspContext> Backend<T> {
+    /// Offer the fixes for the lints within the requested range.
+    ///
+    /// Documents whose current contents don't parse get no actions, as the positions in the
+    /// last valid parse would be out of date.
+    pub(crate) fn get_code_actions(
+        &self,
+        params: CodeActionParams,
+    ) -> anyhow::Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri.clone().try_into()?;
+        if self.parse_errors.read().unwrap().contains(&uri) {
+            return Ok(None);
+        }
+        let Some(document) = self.get_ast(&uri) else {
+            return Ok(None);
+        };
+
+        let actions = document
+            .ast
+            .lint(None)
+            .into_iter()
+            .filter_map(|lint| {
+                let fix = lint.fix?;
+                let range = Range::from(lint.location.resolve_span());
+                if range.end < params.range.start || params.range.end < range.start {
+                    return None;
+                }
+                let diagnostics: Vec<_> = params
+                    .context
+                    .diagnostics
+                    .iter()
+                    .filter(|x| {
+                        x.range == range
+                            && x.code == Some(NumberOrString::String(lint.short_name.clone()))
+                    })
+                    .cloned()
+                    .collect();
+                let edit = TextEdit {
+                    range: Range::from(fix.location.resolve_span()),
+                    new_text: fix.replacement,
+                };
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: fix.description,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: (!diagnostics.is_empty()).then_some(diagnostics),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(
+                            params.text_document.uri.clone(),
+                            vec![edit],
+                        )])),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                }))
+            })
+            .collect();
+        Ok(Some(actions))
+    }
+}
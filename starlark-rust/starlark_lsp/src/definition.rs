@@ -15,6 +15,7 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
 use std::iter;
 
 use starlark::codemap::CodeMap;
@@ -22,7 +23,10 @@ use starlark::codemap::Pos;
 use starlark::codemap::ResolvedSpan;
 use starlark::codemap::Span;
 use starlark::codemap::Spanned;
+use starlark::environment::Globals;
 use starlark::syntax::AstModule;
+use starlark::typing::AstModuleTypecheck;
+use starlark::typing::TypeMap;
 use starlark_syntax::slice_vec_ext::SliceExt;
 use starlark_syntax::syntax::ast::ArgumentP;
 use starlark_syntax::syntax::ast::AssignTargetP;
@@ -231,6 +235,23 @@ impl LspModule {
         Self { ast }
     }
 
+    /// Run the typechecker over the module, and return the types it inferred for its bindings.
+    ///
+    /// Loaded modules are not typechecked, so the symbols loaded from them are `typing.Any`.
+    pub(crate) fn typecheck(&self, globals: &Globals) -> Option<TypeMap> {
+        // The typechecker consumes the module, so parse another copy of it.
+        let codemap = self.ast.codemap();
+        let ast = AstModule::parse(
+            codemap.filename(),
+            codemap.source().to_owned(),
+            self.ast.dialect(),
+        )
+        .ok()?;
+        let (_errors, type_map, _interface, _approximations) =
+            ast.typecheck(globals, &HashMap::new());
+        Some(type_map)
+    }
+
     /// Attempts to find the location where a symbol is defined in the module.
     ///
     /// `line` and `col` are zero based indexes of a location of the symbol to attempt to lookup.
@@ -0,0 +1,232 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The outline of a module, as used for `textDocument/documentSymbol` and `workspace/symbol`.
+
+use lsp_types::DocumentSymbol;
+use lsp_types::SymbolKind;
+use starlark::codemap::CodeMap;
+use starlark::codemap::Span;
+use starlark_syntax::syntax::ast::ArgumentP;
+use starlark_syntax::syntax::ast::AssignP;
+use starlark_syntax::syntax::ast::AstExprP;
+use starlark_syntax::syntax::ast::AstLiteral;
+use starlark_syntax::syntax::ast::AstPayload;
+use starlark_syntax::syntax::ast::AstStmtP;
+use starlark_syntax::syntax::ast::ExprP;
+use starlark_syntax::syntax::ast::StmtP;
+
+/// Find the symbols defined in a module: functions, variables, loads, and (in build files) the
+/// targets declared by calling a rule with a `name`. Symbols defined inside a function are
+/// returned as its children.
+pub(crate) fn get_document_symbols<P: AstPayload>(
+    codemap: &CodeMap,
+    ast: &AstStmtP<P>,
+) -> Vec<DocumentSymbol> {
+    let mut symbols = Vec::new();
+    walk(codemap, ast, &mut symbols);
+    symbols
+}
+
+fn walk<P: AstPayload>(codemap: &CodeMap, ast: &AstStmtP<P>, symbols: &mut Vec<DocumentSymbol>) {
+    match &ast.node {
+        StmtP::Assign(AssignP { lhs, ty: _, rhs }) => lhs.visit_lvalue(|x| {
+            symbols.push(make_document_symbol(
+                x.ident.clone(),
+                None,
+                match rhs.node {
+                    ExprP::Lambda(_) => SymbolKind::FUNCTION,
+                    _ => SymbolKind::VARIABLE,
+                },
+                codemap,
+                ast.span,
+                x.span,
+                None,
+            ));
+        }),
+        StmtP::Def(def) => {
+            let mut children = Vec::new();
+            walk(codemap, &def.body, &mut children);
+            symbols.push(make_document_symbol(
+                def.name.ident.clone(),
+                None,
+                SymbolKind::FUNCTION,
+                codemap,
+                ast.span,
+                def.name.span,
+                Some(children),
+            ));
+        }
+        StmtP::Load(load) => {
+            let children = load
+                .args
+                .iter()
+                .map(|arg| {
+                    make_document_symbol(
+                        arg.local.ident.clone(),
+                        None,
+                        SymbolKind::VARIABLE,
+                        codemap,
+                        arg.span(),
+                        arg.local.span,
+                        None,
+                    )
+                })
+                .collect();
+            symbols.push(make_document_symbol(
+                load.module.node.clone(),
+                None,
+                SymbolKind::MODULE,
+                codemap,
+                ast.span,
+                load.module.span,
+                Some(children),
+            ));
+        }
+        StmtP::Expression(expr) => {
+            if let Some(symbol) = target_symbol(codemap, expr, ast.span) {
+                symbols.push(symbol);
+            }
+        }
+        stmt => stmt.visit_stmt(|x| walk(codemap, x, symbols)),
+    }
+}
+
+/// A call like `cxx_library(name = "foo", ...)` declares the target `foo`.
+fn target_symbol<P: AstPayload>(
+    codemap: &CodeMap,
+    expr: &AstExprP<P>,
+    range: Span,
+) -> Option<DocumentSymbol> {
+    let ExprP::Call(function, args) = &expr.node else {
+        return None;
+    };
+    let rule = match &function.node {
+        ExprP::Identifier(identifier) => identifier.node.ident.clone(),
+        ExprP::Dot(_, attribute) => attribute.node.clone(),
+        _ => return None,
+    };
+    args.iter().find_map(|arg| match &arg.node {
+        ArgumentP::Named(arg_name, value) if arg_name.node == "name" => match &value.node {
+            ExprP::Literal(AstLiteral::String(name)) => Some(make_document_symbol(
+                name.node.clone(),
+                Some(rule.clone()),
+                SymbolKind::CONSTANT,
+                codemap,
+                range,
+                name.span,
+                None,
+            )),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+#[allow(deprecated)] // `DocumentSymbol::deprecated` is deprecated in favour of `tags`.
+fn make_document_symbol(
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    codemap: &CodeMap,
+    range: Span,
+    selection_range: Span,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: codemap.resolve_span(range).into(),
+        selection_range: codemap.resolve_span(selection_range).into(),
+        children,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark::syntax::AstModule;
+    use starlark::syntax::Dialect;
+    use starlark_syntax::syntax::module::AstModuleFields;
+
+    use super::*;
+
+    fn names(symbols: &[DocumentSymbol]) -> Vec<(String, SymbolKind)> {
+        symbols
+            .iter()
+            .map(|symbol| (symbol.name.clone(), symbol.kind))
+            .collect()
+    }
+
+    #[test]
+    fn document_symbols() {
+        let ast_module = AstModule::parse(
+            "t.star",
+            r#"load("foo.star", "exported_a", renamed = "exported_b")
+
+def method(param):
+    inner = 1
+    return param
+
+my_var = True
+my_lambda = lambda x: x
+
+if my_var:
+    conditional = 2
+
+cxx_library(
+    name = "my_library",
+    srcs = ["lib.cpp"],
+)
+native.genrule(name = "gen", out = "gen.txt")
+print("no name")
+"#
+            .to_owned(),
+            &Dialect::Extended,
+        )
+        .unwrap();
+
+        let symbols = get_document_symbols(ast_module.codemap(), ast_module.statement());
+        assert_eq!(
+            vec![
+                ("foo.star".to_owned(), SymbolKind::MODULE),
+                ("method".to_owned(), SymbolKind::FUNCTION),
+                ("my_var".to_owned(), SymbolKind::VARIABLE),
+                ("my_lambda".to_owned(), SymbolKind::FUNCTION),
+                ("conditional".to_owned(), SymbolKind::VARIABLE),
+                ("my_library".to_owned(), SymbolKind::CONSTANT),
+                ("gen".to_owned(), SymbolKind::CONSTANT),
+            ],
+            names(&symbols)
+        );
+        assert_eq!(
+            vec![
+                ("exported_a".to_owned(), SymbolKind::VARIABLE),
+                ("renamed".to_owned(), SymbolKind::VARIABLE),
+            ],
+            names(symbols[0].children.as_ref().unwrap())
+        );
+        assert_eq!(
+            vec![("inner".to_owned(), SymbolKind::VARIABLE)],
+            names(symbols[1].children.as_ref().unwrap())
+        );
+        assert_eq!(Some("cxx_library"), symbols[5].detail.as_deref());
+        assert_eq!(Some("genrule"), symbols[6].detail.as_deref());
+    }
+}
@@ -0,0 +1,57 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Formatting of whole documents, using the formatter in `starlark_syntax`.
+
+use lsp_types::DocumentFormattingParams;
+use lsp_types::Range;
+use lsp_types::TextEdit;
+use starlark_syntax::syntax::module::AstModuleFields;
+
+use crate::server::Backend;
+use crate::server::LspContext;
+
+impl<T: LspContext> Backend<T> {
+    /// Format a document, as a single edit replacing all of its text.
+    ///
+    /// Documents whose current contents don't parse are left alone, as the last valid parse
+    /// would not include the user's latest changes.
+    pub(crate) fn format_document(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> anyhow::Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri.try_into()?;
+        if self.parse_errors.read().unwrap().contains(&uri) {
+            return Ok(None);
+        }
+        let Some(document) = self.get_ast(&uri) else {
+            return Ok(None);
+        };
+        let codemap = document.ast.codemap();
+        let formatted = document
+            .ast
+            .format()
+            .map_err(starlark::Error::into_anyhow)?;
+        if formatted == codemap.source() {
+            return Ok(Some(Vec::new()));
+        }
+        Ok(Some(vec![TextEdit {
+            range: Range::from(codemap.resolve_span(codemap.full_span())),
+            new_text: formatted,
+        }]))
+    }
+}
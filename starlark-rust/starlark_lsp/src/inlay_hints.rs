@@ -0,0 +1,181 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Inlay hints showing the types that the typechecker inferred for variables and parameters
+//! which are not annotated.
+
+use std::collections::HashMap;
+
+use lsp_types::InlayHint;
+use lsp_types::InlayHintKind;
+use lsp_types::InlayHintLabel;
+use lsp_types::InlayHintParams;
+use lsp_types::Range;
+use starlark::codemap::CodeMap;
+use starlark::codemap::Span;
+use starlark::typing::Ty;
+use starlark::typing::TypeMap;
+use starlark_syntax::syntax::ast::AssignP;
+use starlark_syntax::syntax::ast::AstPayload;
+use starlark_syntax::syntax::ast::AstStmtP;
+use starlark_syntax::syntax::ast::ParameterP;
+use starlark_syntax::syntax::ast::StmtP;
+use starlark_syntax::syntax::module::AstModuleFields;
+
+use crate::server::Backend;
+use crate::server::LspContext;
+
+/// Find the type hints for the assignments and parameters in a module that have no type
+/// annotation.
+///
+/// Parameters always get a hint, even if it is just `typing.Any`, since that is exactly when an
+/// annotation would help the typechecker. Assignments whose type could not be inferred don't,
+/// as a hint of `typing.Any` on most lines of a module would be noise.
+pub(crate) fn get_type_hints<P: AstPayload>(
+    codemap: &CodeMap,
+    ast: &AstStmtP<P>,
+    type_map: &TypeMap,
+) -> Vec<InlayHint> {
+    fn hint(
+        codemap: &CodeMap,
+        types: &HashMap<Span, &Ty>,
+        span: Span,
+        include_any: bool,
+        hints: &mut Vec<InlayHint>,
+    ) {
+        if let Some(ty) = types.get(&span) {
+            if include_any || **ty != Ty::any() {
+                hints.push(InlayHint {
+                    position: Range::from(codemap.resolve_span(span)).end,
+                    label: InlayHintLabel::String(format!(": {}", ty)),
+                    kind: Some(InlayHintKind::TYPE),
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: None,
+                    padding_right: None,
+                    data: None,
+                });
+            }
+        }
+    }
+
+    fn walk<P: AstPayload>(
+        codemap: &CodeMap,
+        ast: &AstStmtP<P>,
+        types: &HashMap<Span, &Ty>,
+        hints: &mut Vec<InlayHint>,
+    ) {
+        match &ast.node {
+            StmtP::Assign(AssignP { lhs, ty: None, .. }) => {
+                lhs.visit_lvalue(|x| hint(codemap, types, x.span, false, hints))
+            }
+            StmtP::Def(def) => {
+                for param in &def.params {
+                    match &param.node {
+                        ParameterP::Normal(p, None)
+                        | ParameterP::WithDefaultValue(p, None, _)
+                        | ParameterP::Args(p, None)
+                        | ParameterP::KwArgs(p, None) => hint(codemap, types, p.span, true, hints),
+                        _ => {}
+                    }
+                }
+                walk(codemap, &def.body, types, hints);
+            }
+            stmt => stmt.visit_stmt(|x| walk(codemap, x, types, hints)),
+        }
+    }
+
+    // The bindings are keyed by the span of the identifier that defines them.
+    let types: HashMap<Span, &Ty> = type_map
+        .bindings()
+        .map(|(_name, span, ty)| (span, ty))
+        .collect();
+    let mut hints = Vec::new();
+    walk(codemap, ast, &types, &mut hints);
+    hints
+}
+
+impl<T: LspContext> Backend<T> {
+    pub(crate) fn get_inlay_hints(
+        &self,
+        params: InlayHintParams,
+    ) -> anyhow::Result<Option<Vec<InlayHint>>> {
+        let uri = params.text_document.uri.try_into()?;
+        let Some(document) = self.get_ast(&uri) else {
+            return Ok(None);
+        };
+        let Some(type_map) = document.typecheck(&self.context.get_typecheck_globals(&uri)) else {
+            return Ok(None);
+        };
+        Ok(Some(
+            get_type_hints(document.ast.codemap(), document.ast.statement(), &type_map)
+                .into_iter()
+                .filter(|hint| {
+                    params.range.start <= hint.position && hint.position <= params.range.end
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+    use starlark::environment::Globals;
+    use starlark::syntax::AstModule;
+    use starlark::syntax::Dialect;
+
+    use super::*;
+    use crate::definition::LspModule;
+
+    #[test]
+    fn type_hints() -> starlark::Result<()> {
+        let module = LspModule::new(AstModule::parse(
+            "t.star",
+            r#"def f(x, y: int, *args):
+    a = y
+    b: str = "b"
+    c = x
+    return a
+
+s = "s"
+n = 1
+"#
+            .to_owned(),
+            &Dialect::Extended,
+        )?);
+        let type_map = module.typecheck(&Globals::standard()).unwrap();
+
+        let hints = get_type_hints(module.ast.codemap(), module.ast.statement(), &type_map)
+            .into_iter()
+            .map(|hint| match hint.label {
+                InlayHintLabel::String(label) => (hint.position, label),
+                InlayHintLabel::LabelParts(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (Position::new(0, 7), ": typing.Any".to_owned()),
+                (Position::new(0, 22), ": tuple[typing.Any, ...]".to_owned()),
+                (Position::new(1, 5), ": int".to_owned()),
+                (Position::new(6, 1), ": str".to_owned()),
+            ],
+            hints
+        );
+        Ok(())
+    }
+}
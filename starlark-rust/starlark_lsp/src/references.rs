@@ -0,0 +1,562 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Find all the references to a symbol, both within a module and in the modules that load it.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use dupe::Dupe;
+use lsp_types::InitializeParams;
+use starlark::codemap::Pos;
+use starlark::codemap::ResolvedSpan;
+use starlark::codemap::Span;
+use starlark_syntax::codemap::ResolvedPos;
+use starlark_syntax::syntax::ast::Stmt;
+use starlark_syntax::syntax::module::AstModuleFields;
+use starlark_syntax::syntax::top_level_stmts::top_level_stmts;
+
+use crate::bind::scope;
+use crate::bind::Assigner;
+use crate::bind::Bind;
+use crate::bind::Scope;
+use crate::definition::LspModule;
+use crate::server::Backend;
+use crate::server::LspContext;
+use crate::server::LspUrl;
+
+/// What an identifier refers to, for the purposes of finding references to it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum ReferenceTarget {
+    /// A variable bound in this module. `binding` is where it is first bound in its scope.
+    Local {
+        name: String,
+        binding: Span,
+        top_level: bool,
+    },
+    /// A variable bound by a `load()` statement. `binding` is the local name in that statement,
+    /// and `name` is the name of the symbol in the module at `path`.
+    Loaded {
+        local: String,
+        binding: Span,
+        path: String,
+        name: String,
+    },
+    /// A name that is not bound anywhere in this module, e.g. a global builtin.
+    Global { name: String },
+}
+
+/// A place in a module where a variable is mentioned.
+#[derive(Debug, Clone, Copy, Dupe, Eq, PartialEq)]
+pub(crate) struct Reference {
+    pub(crate) span: Span,
+    /// Whether the variable is assigned to here, rather than read.
+    pub(crate) is_assignment: bool,
+}
+
+/// A symbol imported by a `load()` statement.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct LoadedSymbolReference {
+    /// The string naming the symbol in the loaded module, e.g. `"foo"` in `bar = "foo"`.
+    pub(crate) their: Span,
+    /// The local name that the symbol is bound to.
+    pub(crate) local: String,
+    /// Where `local` is bound. This is the same span as `their` if the symbol is not aliased.
+    pub(crate) binding: Span,
+}
+
+impl LoadedSymbolReference {
+    pub(crate) fn is_aliased(&self) -> bool {
+        self.their != self.binding
+    }
+}
+
+/// How a symbol is mentioned at an [`Occurrence`], which determines how it is renamed.
+#[derive(Debug, Clone, Copy, Dupe, Eq, PartialEq)]
+pub(crate) enum OccurrenceKind {
+    /// The symbol is assigned to, in the module that defines it.
+    Definition,
+    /// Any other use of the symbol by its name.
+    Identifier,
+    /// The contents of the string naming the symbol in a `load()` statement.
+    LoadedName,
+    /// A use of the symbol through a different name, given to it by a `load()` statement.
+    Alias,
+}
+
+/// A mention of a symbol somewhere in the workspace.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Occurrence {
+    pub(crate) uri: LspUrl,
+    pub(crate) span: ResolvedSpan,
+    pub(crate) kind: OccurrenceKind,
+}
+
+/// The mentions of a symbol found by [`Backend::find_occurrences`].
+pub(crate) struct Occurrences {
+    pub(crate) occurrences: Vec<Occurrence>,
+    /// Whether every file that could mention the symbol was searched. If not, renaming the
+    /// symbol would leave some of its uses behind.
+    pub(crate) complete: bool,
+}
+
+type Binding<'a> = (&'a Assigner, Span, bool);
+
+/// Call `f` for every mention of a variable in `scope` (and its inner scopes), along with the
+/// binding it resolves to, if any. The `bool` in the binding is whether it is at the top level.
+fn visit_references<'a>(
+    scope: &'a Scope,
+    outer: &HashMap<&'a str, Binding<'a>>,
+    top_level: bool,
+    f: &mut impl FnMut(&'a str, Reference, Option<Binding<'a>>),
+) {
+    let mut visible = outer.clone();
+    for (name, (assigner, span)) in &scope.bound {
+        visible.insert(name.as_str(), (assigner, *span, top_level));
+    }
+
+    for bind in &scope.inner {
+        match bind {
+            Bind::Set(_, x) => f(
+                x.ident.as_str(),
+                Reference {
+                    span: x.span,
+                    is_assignment: true,
+                },
+                visible.get(x.ident.as_str()).copied(),
+            ),
+            Bind::Get(x) => f(
+                x.node.ident.as_str(),
+                Reference {
+                    span: x.span,
+                    is_assignment: false,
+                },
+                visible.get(x.node.ident.as_str()).copied(),
+            ),
+            Bind::GetDotted(x) => f(
+                x.variable.node.ident.as_str(),
+                Reference {
+                    span: x.variable.span,
+                    is_assignment: false,
+                },
+                visible.get(x.variable.node.ident.as_str()).copied(),
+            ),
+            Bind::Scope(inner) => visit_references(inner, &visible, false, f),
+            Bind::Flow => {}
+        }
+    }
+}
+
+impl LspModule {
+    /// Find what the variable at the given position refers to, if there is a variable there.
+    ///
+    /// Unlike [`LspModule::find_definition_at_location`], this also works on the places where a
+    /// variable is assigned to.
+    pub(crate) fn find_reference_target_at_location(
+        &self,
+        line: u32,
+        col: u32,
+    ) -> Option<ReferenceTarget> {
+        let line_span = self.ast.codemap().line_span_opt(line as usize)?;
+        let current_pos: Pos = std::cmp::min(line_span.begin() + col, line_span.end());
+
+        let scope = scope(&self.ast);
+        let mut result = None;
+        visit_references(
+            &scope,
+            &HashMap::new(),
+            true,
+            &mut |name, reference, binding| {
+                if result.is_some() || !reference.span.contains(current_pos) {
+                    return;
+                }
+                result = Some(match binding {
+                    Some((Assigner::Load { path, name: their }, binding, _)) => {
+                        ReferenceTarget::Loaded {
+                            local: name.to_owned(),
+                            binding,
+                            path: path.node.clone(),
+                            name: their.node.clone(),
+                        }
+                    }
+                    Some((_, binding, top_level)) => ReferenceTarget::Local {
+                        name: name.to_owned(),
+                        binding,
+                        top_level,
+                    },
+                    None => ReferenceTarget::Global {
+                        name: name.to_owned(),
+                    },
+                });
+            },
+        );
+        result
+    }
+
+    /// Find all the mentions of `name` that resolve to the variable bound at `binding`, or that
+    /// do not resolve to any variable in this module if `binding` is `None`.
+    pub(crate) fn find_references(&self, name: &str, binding: Option<Span>) -> Vec<Reference> {
+        let scope = scope(&self.ast);
+        let mut result = Vec::new();
+        visit_references(&scope, &HashMap::new(), true, &mut |n, reference, b| {
+            if n == name && b.map(|(_, span, _)| span) == binding {
+                result.push(reference);
+            }
+        });
+        result
+    }
+
+    /// Where `name` is first assigned at the top level of this module, if it is defined here
+    /// (rather than loaded from elsewhere).
+    pub(crate) fn find_top_level_binding(&self, name: &str) -> Option<Span> {
+        match scope(&self.ast).bound.get(name) {
+            Some((Assigner::Load { .. }, _)) | None => None,
+            Some((_, span)) => Some(*span),
+        }
+    }
+
+    /// Find the `load()`s of the symbol `name`, from modules for which `is_module` returns
+    /// `true` given the (unresolved) path in the `load()` statement.
+    pub(crate) fn find_loads_of_symbol(
+        &self,
+        name: &str,
+        mut is_module: impl FnMut(&str) -> bool,
+    ) -> Vec<LoadedSymbolReference> {
+        let mut result = Vec::new();
+        for x in top_level_stmts(self.ast.statement()) {
+            if let Stmt::Load(load) = &**x {
+                if load.args.iter().any(|arg| arg.their.node == name)
+                    && is_module(&load.module.node)
+                {
+                    result.extend(load.args.iter().filter(|arg| arg.their.node == name).map(
+                        |arg| LoadedSymbolReference {
+                            their: arg.their.span,
+                            local: arg.local.ident.clone(),
+                            binding: arg.local.span,
+                        },
+                    ));
+                }
+            }
+        }
+        result
+    }
+}
+
+impl<T: LspContext> Backend<T> {
+    /// Find all the occurrences of the symbol that `target` refers to in `uri`.
+    ///
+    /// Symbols that can be loaded from other modules are also looked for in the modules that
+    /// load them: see [`Backend::find_exported_symbol_occurrences`].
+    pub(crate) fn find_occurrences(
+        &self,
+        uri: &LspUrl,
+        document: &LspModule,
+        target: ReferenceTarget,
+        initialize_params: &InitializeParams,
+    ) -> anyhow::Result<Occurrences> {
+        match target {
+            ReferenceTarget::Global { name } => Ok(Occurrences {
+                occurrences: Self::occurrences_in_document(
+                    uri,
+                    document,
+                    &name,
+                    None,
+                    OccurrenceKind::Identifier,
+                ),
+                complete: true,
+            }),
+            ReferenceTarget::Local {
+                name,
+                binding,
+                top_level: false,
+            } => Ok(Occurrences {
+                occurrences: Self::occurrences_in_document(
+                    uri,
+                    document,
+                    &name,
+                    Some(binding),
+                    OccurrenceKind::Definition,
+                ),
+                complete: true,
+            }),
+            ReferenceTarget::Local {
+                name,
+                top_level: true,
+                ..
+            } => self.find_exported_symbol_occurrences(uri, &name, initialize_params),
+            ReferenceTarget::Loaded { path, name, .. } => {
+                let workspace_root =
+                    Self::get_workspace_root(initialize_params.workspace_folders.as_ref(), uri);
+                let load_uri = self.resolve_load_path(&path, uri, workspace_root.as_deref())?;
+                self.find_exported_symbol_occurrences(&load_uri, &name, initialize_params)
+            }
+        }
+    }
+
+    /// Occurrences of a variable within a single document. Assignments are given the
+    /// `assignment_kind`, and all other mentions are [`OccurrenceKind::Identifier`].
+    fn occurrences_in_document(
+        uri: &LspUrl,
+        document: &LspModule,
+        name: &str,
+        binding: Option<Span>,
+        assignment_kind: OccurrenceKind,
+    ) -> Vec<Occurrence> {
+        document
+            .find_references(name, binding)
+            .into_iter()
+            .map(|reference| Occurrence {
+                uri: uri.clone(),
+                span: document.ast.codemap().resolve_span(reference.span),
+                kind: if reference.is_assignment {
+                    assignment_kind
+                } else {
+                    OccurrenceKind::Identifier
+                },
+            })
+            .collect()
+    }
+
+    /// Find the occurrences of the top-level symbol `name` defined in `definition_uri`, both in
+    /// that module and in the modules that load it.
+    ///
+    /// The modules searched are the open documents, the files of the workspace if the
+    /// [`LspContext`] can list them, and all the modules these load, directly or not. The search
+    /// is only complete if the workspace files were listed and could all be parsed, or if `name`
+    /// is private to its module.
+    fn find_exported_symbol_occurrences(
+        &self,
+        definition_uri: &LspUrl,
+        name: &str,
+        initialize_params: &InitializeParams,
+    ) -> anyhow::Result<Occurrences> {
+        let mut result = Vec::new();
+        let mut complete = true;
+
+        match self.get_ast_or_load_from_disk(definition_uri)? {
+            Some(module) => match module.find_top_level_binding(name) {
+                Some(binding) => result.extend(Self::occurrences_in_document(
+                    definition_uri,
+                    &module,
+                    name,
+                    Some(binding),
+                    OccurrenceKind::Definition,
+                )),
+                None => complete = false,
+            },
+            None => complete = false,
+        }
+
+        // Symbols starting with `_` can't be loaded by other modules.
+        if name.starts_with('_') {
+            return Ok(Occurrences {
+                occurrences: result,
+                complete,
+            });
+        }
+
+        let workspace_root =
+            Self::get_workspace_root(initialize_params.workspace_folders.as_ref(), definition_uri);
+        let workspace_files = self
+            .context
+            .get_workspace_files(workspace_root.as_deref())?;
+        complete &= workspace_files.is_some();
+        // The last valid parse of an open document that doesn't parse may be out of date.
+        complete &= self.parse_errors.read().unwrap().is_empty();
+
+        // The open documents and workspace files must all be searched for the search to be
+        // complete, but the modules they load might not exist.
+        let mut queue: Vec<(LspUrl, bool)> = self
+            .last_valid_parse
+            .read()
+            .unwrap()
+            .keys()
+            .chain(workspace_files.iter().flatten())
+            .map(|uri| (uri.clone(), true))
+            .collect();
+        let mut seen: HashSet<LspUrl> = queue.iter().map(|(uri, _)| uri.clone()).collect();
+
+        while let Some((uri, required)) = queue.pop() {
+            let document = match self.get_ast_or_load_from_disk(&uri) {
+                Ok(Some(document)) => document,
+                _ => {
+                    complete &= !required;
+                    continue;
+                }
+            };
+            let workspace_root =
+                Self::get_workspace_root(initialize_params.workspace_folders.as_ref(), &uri);
+            for load in document.ast.loads() {
+                if let Ok(load_uri) =
+                    self.resolve_load_path(load.module_id, &uri, workspace_root.as_deref())
+                {
+                    if seen.insert(load_uri.clone()) {
+                        queue.push((load_uri, false));
+                    }
+                }
+            }
+            if uri == *definition_uri {
+                continue;
+            }
+
+            let loads = document.find_loads_of_symbol(name, |path| {
+                self.resolve_load_path(path, &uri, workspace_root.as_deref())
+                    .map_or(false, |load_uri| &load_uri == definition_uri)
+            });
+            let codemap = document.ast.codemap();
+            for load in loads {
+                // Only the name inside the quotes refers to the symbol.
+                let their = codemap.resolve_span(load.their);
+                result.push(Occurrence {
+                    uri: uri.clone(),
+                    span: ResolvedSpan {
+                        begin: ResolvedPos {
+                            line: their.begin.line,
+                            column: their.begin.column + 1,
+                        },
+                        end: ResolvedPos {
+                            line: their.end.line,
+                            column: their.end.column.saturating_sub(1),
+                        },
+                    },
+                    kind: OccurrenceKind::LoadedName,
+                });
+
+                let kind = if load.is_aliased() {
+                    OccurrenceKind::Alias
+                } else {
+                    OccurrenceKind::Identifier
+                };
+                result.extend(
+                    document
+                        .find_references(&load.local, Some(load.binding))
+                        .into_iter()
+                        .filter(|reference| reference.span != load.their)
+                        .map(|reference| Occurrence {
+                            uri: uri.clone(),
+                            span: codemap.resolve_span(reference.span),
+                            kind,
+                        }),
+                );
+            }
+        }
+
+        Ok(Occurrences {
+            occurrences: result,
+            complete,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use textwrap::dedent;
+
+    use super::*;
+    use crate::definition::helpers::FixtureWithRanges;
+
+    fn references_at(
+        fixture: &FixtureWithRanges,
+        module: &LspModule,
+        identifier: &str,
+    ) -> Vec<ResolvedSpan> {
+        let target = module
+            .find_reference_target_at_location(
+                fixture.begin_line(identifier),
+                fixture.begin_column(identifier),
+            )
+            .unwrap();
+        let (name, binding) = match target {
+            ReferenceTarget::Local { name, binding, .. } => (name, Some(binding)),
+            ReferenceTarget::Loaded { local, binding, .. } => (local, Some(binding)),
+            ReferenceTarget::Global { name } => (name, None),
+        };
+        module
+            .find_references(&name, binding)
+            .into_iter()
+            .map(|reference| module.ast.codemap().resolve_span(reference.span))
+            .collect()
+    }
+
+    #[test]
+    fn finds_references_respecting_scopes() -> starlark::Result<()> {
+        let contents = dedent(
+            r#"
+            load("foo.star", <l1>"loaded"</l1>)
+            <x1>x</x1> = 1
+            def f(<x2>x</x2>):
+                return <x3>x</x3> + <x4>x</x4>.y + <l2>loaded</l2>
+            <x5>x</x5> += 1
+            <p1>print</p1>(<x6>x</x6>, <p2>print</p2>)
+            "#,
+        )
+        .trim()
+        .to_owned();
+        let parsed = FixtureWithRanges::from_fixture("foo.star", &contents)?;
+        let module = parsed.module()?;
+
+        let spans = |identifiers: &[&str]| -> Vec<ResolvedSpan> {
+            identifiers
+                .iter()
+                .map(|identifier| parsed.resolved_span(identifier))
+                .collect()
+        };
+
+        // `x += 1` both reads and assigns `x`.
+        assert_eq!(
+            spans(&["x1", "x5", "x5", "x6"]),
+            references_at(&parsed, &module, "x6")
+        );
+        assert_eq!(
+            spans(&["x1", "x5", "x5", "x6"]),
+            references_at(&parsed, &module, "x1")
+        );
+        assert_eq!(
+            spans(&["x2", "x3", "x4"]),
+            references_at(&parsed, &module, "x3")
+        );
+        assert_eq!(spans(&["l1", "l2"]), references_at(&parsed, &module, "l2"));
+        assert_eq!(spans(&["p1", "p2"]), references_at(&parsed, &module, "p2"));
+        Ok(())
+    }
+
+    #[test]
+    fn finds_loads_of_symbol() -> starlark::Result<()> {
+        let contents = dedent(
+            r#"
+            load("foo.star", "a", b = <b>"a"</b>)
+            load("bar.star", "a")
+            "#,
+        )
+        .trim()
+        .to_owned();
+        let parsed = FixtureWithRanges::from_fixture("baz.star", &contents)?;
+        let module = parsed.module()?;
+
+        let loads = module.find_loads_of_symbol("a", |path| path == "foo.star");
+        assert_eq!(2, loads.len());
+        assert!(!loads[0].is_aliased());
+        assert_eq!("a", loads[0].local);
+        assert!(loads[1].is_aliased());
+        assert_eq!("b", loads[1].local);
+        assert_eq!(
+            parsed.resolved_span("b"),
+            module.ast.codemap().resolve_span(loads[1].their)
+        );
+        Ok(())
+    }
+}
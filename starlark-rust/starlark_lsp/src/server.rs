@@ -42,9 +42,19 @@ use lsp_types::notification::DidCloseTextDocument;
 use lsp_types::notification::DidOpenTextDocument;
 use lsp_types::notification::LogMessage;
 use lsp_types::notification::PublishDiagnostics;
+use lsp_types::request::CodeActionRequest;
 use lsp_types::request::Completion;
+use lsp_types::request::DocumentSymbolRequest;
+use lsp_types::request::Formatting;
 use lsp_types::request::GotoDefinition;
 use lsp_types::request::HoverRequest;
+use lsp_types::request::InlayHintRequest;
+use lsp_types::request::References;
+use lsp_types::request::Rename;
+use lsp_types::request::SignatureHelpRequest;
+use lsp_types::request::WorkspaceSymbolRequest;
+use lsp_types::CodeActionParams;
+use lsp_types::CodeActionProviderCapability;
 use lsp_types::CompletionItem;
 use lsp_types::CompletionItemKind;
 use lsp_types::CompletionOptions;
@@ -55,6 +65,10 @@ use lsp_types::Diagnostic;
 use lsp_types::DidChangeTextDocumentParams;
 use lsp_types::DidCloseTextDocumentParams;
 use lsp_types::DidOpenTextDocumentParams;
+use lsp_types::DocumentFormattingParams;
+use lsp_types::DocumentSymbol;
+use lsp_types::DocumentSymbolParams;
+use lsp_types::DocumentSymbolResponse;
 use lsp_types::Documentation;
 use lsp_types::GotoDefinitionParams;
 use lsp_types::GotoDefinitionResponse;
@@ -63,7 +77,9 @@ use lsp_types::HoverContents;
 use lsp_types::HoverParams;
 use lsp_types::HoverProviderCapability;
 use lsp_types::InitializeParams;
+use lsp_types::InlayHintParams;
 use lsp_types::LanguageString;
+use lsp_types::Location;
 use lsp_types::LocationLink;
 use lsp_types::LogMessageParams;
 use lsp_types::MarkedString;
@@ -74,13 +90,21 @@ use lsp_types::OneOf;
 use lsp_types::Position;
 use lsp_types::PublishDiagnosticsParams;
 use lsp_types::Range;
+use lsp_types::ReferenceParams;
+use lsp_types::RenameParams;
 use lsp_types::ServerCapabilities;
+use lsp_types::SignatureHelpOptions;
+use lsp_types::SignatureHelpParams;
+use lsp_types::SymbolInformation;
 use lsp_types::TextDocumentSyncCapability;
 use lsp_types::TextDocumentSyncKind;
 use lsp_types::TextEdit;
 use lsp_types::Url;
 use lsp_types::WorkDoneProgressOptions;
+use lsp_types::WorkspaceEdit;
 use lsp_types::WorkspaceFolder;
+use lsp_types::WorkspaceSymbolParams;
+use lsp_types::WorkspaceSymbolResponse;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -93,6 +117,7 @@ use starlark::docs::markdown::render_doc_member;
 use starlark::docs::markdown::render_doc_param;
 use starlark::docs::DocMember;
 use starlark::docs::DocModule;
+use starlark::environment::Globals;
 use starlark::syntax::AstModule;
 use starlark_syntax::codemap::ResolvedPos;
 use starlark_syntax::syntax::ast::AstPayload;
@@ -105,8 +130,12 @@ use crate::definition::Definition;
 use crate::definition::DottedDefinition;
 use crate::definition::IdentifierDefinition;
 use crate::definition::LspModule;
+use crate::document_symbols::get_document_symbols;
 use crate::inspect::AstModuleInspect;
 use crate::inspect::AutocompleteType;
+use crate::references::OccurrenceKind;
+use crate::references::Occurrences;
+use crate::references::ReferenceTarget;
 use crate::symbols::find_symbols_at_location;
 
 /// The request to get the file contents for a starlark: URI
@@ -341,9 +370,29 @@ pub trait LspContext {
         Ok(result)
     }
 
+    /// List the Starlark files in the workspace, so that the references to a symbol are also found
+    /// in files that load it but are not open, or loaded by an open file.
+    ///
+    /// Returns `None` if the files can't be listed. Symbols that other files can load are then
+    /// not renamed, as some of their uses might be missed.
+    fn get_workspace_files(
+        &self,
+        workspace_root: Option<&Path>,
+    ) -> anyhow::Result<Option<Vec<LspUrl>>> {
+        let _unused = workspace_root;
+        Ok(None)
+    }
+
     /// Get the preloaded environment for a particular file.
     fn get_environment(&self, uri: &LspUrl) -> DocModule;
 
+    /// Get the globals that a particular file is typechecked against, to offer signature help
+    /// and inlay hints with the types the typechecker infers.
+    fn get_typecheck_globals(&self, uri: &LspUrl) -> Globals {
+        let _unused = uri;
+        Globals::standard()
+    }
+
     /// Get the LSPUrl for a global symbol if possible.
     ///
     /// The current file is provided in case different files have different global symbols
@@ -377,6 +426,23 @@ enum ResolveLoadError {
     WrongScheme(String, LspUrl),
 }
 
+/// Errors when renaming a symbol.
+#[derive(thiserror::Error, Debug)]
+enum RenameError {
+    /// The new name cannot be used as an identifier.
+    #[error("`{}` is not a valid identifier", .0)]
+    InvalidIdentifier(String),
+    /// The symbol is not defined in any file that we know about, e.g. it is a builtin.
+    #[error("`{}` is not defined in the workspace, so cannot be renamed", .0)]
+    UndefinedSymbol(String),
+    /// Not every file that could use the symbol could be searched.
+    #[error(
+        "`{}` may be used in files that could not be searched, so cannot be renamed safely",
+        .0
+    )]
+    IncompleteSearch(String),
+}
+
 /// Errors when loading contents of a starlark program.
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum LoadContentsError {
@@ -391,6 +457,25 @@ pub(crate) struct Backend<T: LspContext> {
     /// The `AstModule` from the last time that a file was opened / changed and parsed successfully.
     /// Entries are evicted when the file is closed.
     pub(crate) last_valid_parse: RwLock<HashMap<LspUrl, Arc<LspModule>>>,
+    /// The open files whose current contents do not parse, so `last_valid_parse` is out of date.
+    pub(crate) parse_errors: RwLock<HashSet<LspUrl>>,
+}
+
+/// The language keywords, which cannot be used as identifiers.
+const KEYWORDS: &[&str] = &[
+    // Actual keywords
+    "and", "else", "load", "break", "for", "not", "continue", "if", "or", "def", "in", "pass",
+    "elif", "return", "lambda", //
+    // Reserved words
+    "as", "import", "is", "class", "nonlocal", "del", "raise", "except", "try", "finally", "while",
+    "from", "with", "global", "yield",
+];
+
+fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
 }
 
 /// The logic implementations of stuff
@@ -428,11 +513,25 @@ impl<T: LspContext> Backend<T> {
                 ..Default::default()
             }),
             hover_provider: Some(HoverProviderCapability::Simple(true)),
+            references_provider: Some(OneOf::Left(true)),
+            rename_provider: Some(OneOf::Left(true)),
+            document_symbol_provider: Some(OneOf::Left(true)),
+            workspace_symbol_provider: Some(OneOf::Left(true)),
+            signature_help_provider: Some(SignatureHelpOptions {
+                trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
+                retrigger_characters: None,
+                work_done_progress_options: WorkDoneProgressOptions {
+                    work_done_progress: None,
+                },
+            }),
+            inlay_hint_provider: Some(OneOf::Left(true)),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
             ..ServerCapabilities::default()
         }
     }
 
-    fn get_ast(&self, uri: &LspUrl) -> Option<Arc<LspModule>> {
+    pub(crate) fn get_ast(&self, uri: &LspUrl) -> Option<Arc<LspModule>> {
         let last_valid_parse = self.last_valid_parse.read().unwrap();
         last_valid_parse.get(uri).duped()
     }
@@ -458,6 +557,9 @@ impl<T: LspContext> Backend<T> {
             let module = Arc::new(LspModule::new(ast));
             let mut last_valid_parse = self.last_valid_parse.write().unwrap();
             last_valid_parse.insert(uri.clone(), module);
+            self.parse_errors.write().unwrap().remove(&uri);
+        } else {
+            self.parse_errors.write().unwrap().insert(uri.clone());
         }
         self.publish_diagnostics(uri.try_into()?, eval_result.diagnostics, version);
         Ok(())
@@ -483,8 +585,10 @@ impl<T: LspContext> Backend<T> {
 
     fn did_close(&self, params: DidCloseTextDocumentParams) -> anyhow::Result<()> {
         {
+            let uri = params.text_document.uri.clone().try_into()?;
             let mut last_valid_parse = self.last_valid_parse.write().unwrap();
-            last_valid_parse.remove(&params.text_document.uri.clone().try_into()?);
+            last_valid_parse.remove(&uri);
+            self.parse_errors.write().unwrap().remove(&uri);
         }
         self.publish_diagnostics(params.text_document.uri, Vec::new(), None);
         Ok(())
@@ -526,6 +630,76 @@ impl<T: LspContext> Backend<T> {
         self.send_response(new_response(id, self.hover_info(params, initialize_params)));
     }
 
+    /// Offers the locations of all the references to the symbol at the current cursor, including
+    /// those in other open files that load it.
+    fn references(
+        &self,
+        id: RequestId,
+        params: ReferenceParams,
+        initialize_params: &InitializeParams,
+    ) {
+        self.send_response(new_response(
+            id,
+            self.find_references(params, initialize_params),
+        ));
+    }
+
+    /// Renames the symbol at the current cursor, including in other open files that load it.
+    fn rename(&self, id: RequestId, params: RenameParams, initialize_params: &InitializeParams) {
+        self.send_response(new_response(
+            id,
+            self.rename_edits(params, initialize_params),
+        ));
+    }
+
+    /// Offers an outline of the symbols defined in the current file.
+    fn document_symbols(&self, id: RequestId, params: DocumentSymbolParams) {
+        self.send_response(new_response(id, self.get_document_symbols(params)));
+    }
+
+    /// Offers the symbols defined in the open files and the workspace files that match a query.
+    fn workspace_symbols(
+        &self,
+        id: RequestId,
+        params: WorkspaceSymbolParams,
+        initialize_params: &InitializeParams,
+    ) {
+        self.send_response(new_response(
+            id,
+            self.get_workspace_symbols(params, initialize_params),
+        ));
+    }
+
+    /// Offers the signature of the function being called at the current cursor, with the
+    /// parameter being passed highlighted.
+    fn signature_help(
+        &self,
+        id: RequestId,
+        params: SignatureHelpParams,
+        initialize_params: &InitializeParams,
+    ) {
+        self.send_response(new_response(
+            id,
+            self.signature_help_info(params, initialize_params),
+        ));
+    }
+
+    /// Offers the types the typechecker inferred for variables and parameters that are not
+    /// annotated.
+    fn inlay_hints(&self, id: RequestId, params: InlayHintParams) {
+        self.send_response(new_response(id, self.get_inlay_hints(params)));
+    }
+
+    /// Offers fixes for the lints in the current selection.
+    fn code_actions(&self, id: RequestId, params: CodeActionParams) {
+        self.send_response(new_response(id, self.get_code_actions(params)));
+    }
+
+    /// Formats the current file.
+    fn formatting(&self, id: RequestId, params: DocumentFormattingParams) {
+        self.send_response(new_response(id, self.format_document(params)));
+    }
+
     /// Get the file contents of a starlark: URI.
     fn get_starlark_file_contents(&self, id: RequestId, params: StarlarkFileContentsParams) {
         let response: anyhow::Result<_> = match params.uri {
@@ -1005,17 +1179,8 @@ impl<T: LspContext> Backend<T> {
 
     /// Get completion items for each language keyword.
     pub(crate) fn get_keyword_completion_items() -> impl Iterator<Item = CompletionItem> {
-        [
-            // Actual keywords
-            "and", "else", "load", "break", "for", "not", "continue", "if", "or", "def", "in",
-            "pass", "elif", "return", "lambda", //
-            // Reserved words
-            "as", "import", "is", "class", "nonlocal", "del", "raise", "except", "try", "finally",
-            "while", "from", "with", "global", "yield",
-        ]
-        .into_iter()
-        .map(|keyword| CompletionItem {
-            label: keyword.to_owned(),
+        KEYWORDS.iter().map(|keyword| CompletionItem {
+            label: (*keyword).to_owned(),
             kind: Some(CompletionItemKind::KEYWORD),
             ..Default::default()
         })
@@ -1189,7 +1354,203 @@ impl<T: LspContext> Backend<T> {
         })
     }
 
-    fn get_workspace_root(
+    fn find_references(
+        &self,
+        params: ReferenceParams,
+        initialize_params: &InitializeParams,
+    ) -> anyhow::Result<Option<Vec<Location>>> {
+        let uri = params.text_document_position.text_document.uri.try_into()?;
+        let line = params.text_document_position.position.line;
+        let character = params.text_document_position.position.character;
+
+        let Some(document) = self.get_ast(&uri) else {
+            return Ok(None);
+        };
+        let Some(target) = document.find_reference_target_at_location(line, character) else {
+            return Ok(None);
+        };
+
+        let mut seen = HashSet::new();
+        let mut locations = Vec::new();
+        // Report what could be found, even if some files could not be searched.
+        let Occurrences { occurrences, .. } =
+            self.find_occurrences(&uri, &document, target, initialize_params)?;
+        for occurrence in occurrences {
+            if !params.context.include_declaration && occurrence.kind == OccurrenceKind::Definition
+            {
+                continue;
+            }
+            if seen.insert((occurrence.uri.clone(), occurrence.span)) {
+                locations.push(Location {
+                    uri: occurrence.uri.try_into()?,
+                    range: occurrence.span.into(),
+                });
+            }
+        }
+        Ok(Some(locations))
+    }
+
+    fn rename_edits(
+        &self,
+        params: RenameParams,
+        initialize_params: &InitializeParams,
+    ) -> anyhow::Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri.try_into()?;
+        let line = params.text_document_position.position.line;
+        let character = params.text_document_position.position.character;
+        let new_name = params.new_name;
+
+        if !is_valid_identifier(&new_name) {
+            return Err(RenameError::InvalidIdentifier(new_name).into());
+        }
+
+        let Some(document) = self.get_ast(&uri) else {
+            return Ok(None);
+        };
+        let target = match document.find_reference_target_at_location(line, character) {
+            None => return Ok(None),
+            Some(ReferenceTarget::Global { name }) => {
+                return Err(RenameError::UndefinedSymbol(name).into());
+            }
+            // The symbol was given a different name when it was loaded, so only rename that
+            // local name, rather than the symbol in the module it came from.
+            Some(ReferenceTarget::Loaded {
+                local,
+                binding,
+                name,
+                ..
+            }) if local != name => ReferenceTarget::Local {
+                name: local,
+                binding,
+                top_level: false,
+            },
+            Some(target) => target,
+        };
+        let name = match &target {
+            ReferenceTarget::Local { name, .. }
+            | ReferenceTarget::Loaded { name, .. }
+            | ReferenceTarget::Global { name } => name.clone(),
+        };
+
+        let Occurrences {
+            occurrences,
+            complete,
+        } = self.find_occurrences(&uri, &document, target, initialize_params)?;
+        if !complete {
+            return Err(RenameError::IncompleteSearch(name).into());
+        }
+
+        let mut seen = HashSet::new();
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for occurrence in occurrences {
+            // Aliases keep their own name; only the name in their `load()` changes.
+            if occurrence.kind == OccurrenceKind::Alias
+                || !seen.insert((occurrence.uri.clone(), occurrence.span))
+            {
+                continue;
+            }
+            changes
+                .entry(occurrence.uri.try_into()?)
+                .or_default()
+                .push(TextEdit::new(occurrence.span.into(), new_name.clone()));
+        }
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    fn get_document_symbols(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> anyhow::Result<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri.try_into()?;
+        Ok(self.get_ast(&uri).map(|document| {
+            DocumentSymbolResponse::Nested(get_document_symbols(
+                document.ast.codemap(),
+                document.ast.statement(),
+            ))
+        }))
+    }
+
+    fn get_workspace_symbols(
+        &self,
+        params: WorkspaceSymbolParams,
+        initialize_params: &InitializeParams,
+    ) -> anyhow::Result<Option<WorkspaceSymbolResponse>> {
+        fn flatten(
+            uri: &Url,
+            container_name: Option<&str>,
+            symbols: Vec<DocumentSymbol>,
+            query: &str,
+            result: &mut Vec<SymbolInformation>,
+        ) {
+            for symbol in symbols {
+                if symbol.name.to_lowercase().contains(query) {
+                    #[allow(deprecated)]
+                    result.push(SymbolInformation {
+                        name: symbol.name.clone(),
+                        kind: symbol.kind,
+                        tags: None,
+                        deprecated: None,
+                        location: Location::new(uri.clone(), symbol.selection_range),
+                        container_name: container_name.map(|name| name.to_owned()),
+                    });
+                }
+                if let Some(children) = symbol.children {
+                    flatten(uri, Some(&symbol.name), children, query, result);
+                }
+            }
+        }
+
+        let query = params.query.to_lowercase();
+        let mut result = Vec::new();
+
+        let mut uris: Vec<LspUrl> = self
+            .last_valid_parse
+            .read()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        let workspace_roots: Vec<Option<PathBuf>> = match &initialize_params.workspace_folders {
+            Some(folders) => folders
+                .iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .map(Some)
+                .collect(),
+            None => vec![None],
+        };
+        for workspace_root in workspace_roots {
+            if let Some(files) = self
+                .context
+                .get_workspace_files(workspace_root.as_deref())?
+            {
+                uris.extend(files);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for uri in uris {
+            if !seen.insert(uri.clone()) {
+                continue;
+            }
+            // Files that can't be parsed just don't contribute any symbols.
+            let Ok(Some(document)) = self.get_ast_or_load_from_disk(&uri) else {
+                continue;
+            };
+            let Ok(url) = Url::try_from(&uri) else {
+                continue;
+            };
+            flatten(
+                &url,
+                None,
+                get_document_symbols(document.ast.codemap(), document.ast.statement()),
+                &query,
+                &mut result,
+            );
+        }
+        Ok(Some(WorkspaceSymbolResponse::Flat(result)))
+    }
+
+    pub(crate) fn get_workspace_root(
         workspace_roots: Option<&Vec<WorkspaceFolder>>,
         target: &LspUrl,
     ) -> Option<PathBuf> {
@@ -1236,8 +1597,6 @@ impl<T: LspContext> Backend<T> {
         for msg in &self.connection.receiver {
             match msg {
                 Message::Request(req) => {
-                    // TODO(nmj): Also implement DocumentSymbols so that some logic can
-                    //            be handled client side.
                     if let Some(params) = as_request::<GotoDefinition>(&req) {
                         self.goto_definition(req.id, params, &initialize_params);
                     } else if let Some(params) = as_request::<StarlarkFileContentsRequest>(&req) {
@@ -1246,6 +1605,22 @@ impl<T: LspContext> Backend<T> {
                         self.completion(req.id, params, &initialize_params);
                     } else if let Some(params) = as_request::<HoverRequest>(&req) {
                         self.hover(req.id, params, &initialize_params);
+                    } else if let Some(params) = as_request::<References>(&req) {
+                        self.references(req.id, params, &initialize_params);
+                    } else if let Some(params) = as_request::<Rename>(&req) {
+                        self.rename(req.id, params, &initialize_params);
+                    } else if let Some(params) = as_request::<DocumentSymbolRequest>(&req) {
+                        self.document_symbols(req.id, params);
+                    } else if let Some(params) = as_request::<WorkspaceSymbolRequest>(&req) {
+                        self.workspace_symbols(req.id, params, &initialize_params);
+                    } else if let Some(params) = as_request::<SignatureHelpRequest>(&req) {
+                        self.signature_help(req.id, params, &initialize_params);
+                    } else if let Some(params) = as_request::<InlayHintRequest>(&req) {
+                        self.inlay_hints(req.id, params);
+                    } else if let Some(params) = as_request::<Formatting>(&req) {
+                        self.formatting(req.id, params);
+                    } else if let Some(params) = as_request::<CodeActionRequest>(&req) {
+                        self.code_actions(req.id, params);
                     } else if self.connection.handle_shutdown(&req)? {
                         return Ok(());
                     }
@@ -1309,6 +1684,7 @@ pub fn server_with_connection<T: LspContext>(
         connection,
         context,
         last_valid_parse: RwLock::default(),
+        parse_errors: RwLock::default(),
     }
     .main_loop(initialization_params)?;
 
@@ -1388,21 +1764,43 @@ where
 //            some paths. Revisit later.
 #[cfg(all(test, not(windows)))]
 mod tests {
+    use std::collections::HashMap;
     use std::path::Path;
     use std::path::PathBuf;
 
     use anyhow::Context;
     use lsp_server::Request;
     use lsp_server::RequestId;
+    use lsp_types::request::CodeActionRequest;
+    use lsp_types::request::Formatting;
     use lsp_types::request::GotoDefinition;
+    use lsp_types::request::References;
+    use lsp_types::request::Rename;
+    use lsp_types::request::SignatureHelpRequest;
+    use lsp_types::request::WorkspaceSymbolRequest;
+    use lsp_types::CodeActionOrCommand;
+    use lsp_types::CodeActionParams;
+    use lsp_types::CodeActionResponse;
+    use lsp_types::DocumentFormattingParams;
+    use lsp_types::FormattingOptions;
     use lsp_types::GotoDefinitionParams;
     use lsp_types::GotoDefinitionResponse;
+    use lsp_types::Location;
     use lsp_types::LocationLink;
     use lsp_types::Position;
     use lsp_types::Range;
+    use lsp_types::ReferenceContext;
+    use lsp_types::ReferenceParams;
+    use lsp_types::RenameParams;
+    use lsp_types::SignatureHelp;
+    use lsp_types::SignatureHelpParams;
     use lsp_types::TextDocumentIdentifier;
     use lsp_types::TextDocumentPositionParams;
+    use lsp_types::TextEdit;
     use lsp_types::Url;
+    use lsp_types::WorkspaceEdit;
+    use lsp_types::WorkspaceSymbolParams;
+    use lsp_types::WorkspaceSymbolResponse;
     use starlark::codemap::ResolvedSpan;
     use starlark::wasm::is_wasm;
     use textwrap::dedent;
@@ -2502,4 +2900,494 @@ mod tests {
         }
         Ok(())
     }
+
+    fn references_request(server: &mut TestServer, uri: Url, line: u32, character: u32) -> Request {
+        server.new_request::<References>(ReferenceParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position { line, character },
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        })
+    }
+
+    fn rename_request(
+        server: &mut TestServer,
+        uri: Url,
+        line: u32,
+        character: u32,
+        new_name: &str,
+    ) -> Request {
+        server.new_request::<Rename>(RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position { line, character },
+            },
+            new_name: new_name.to_owned(),
+            work_done_progress_params: Default::default(),
+        })
+    }
+
+    /// Three files: `bar.star` defines `baz`, `foo.star` loads it, and `qux.star` loads it
+    /// under a different name.
+    fn open_files_loading_baz(
+        server: &mut TestServer,
+    ) -> anyhow::Result<(
+        (Url, FixtureWithRanges),
+        (Url, FixtureWithRanges),
+        (Url, FixtureWithRanges),
+    )> {
+        let foo_uri = temp_file_uri("foo.star");
+        let bar_uri = temp_file_uri("bar.star");
+        let qux_uri = temp_file_uri("qux.star");
+
+        let foo_contents = dedent(
+            r#"
+            load("{load}", "<foo_load>baz</foo_load>")
+            <foo_call>baz</foo_call>()
+            "#,
+        )
+        .replace("{load}", bar_uri.path())
+        .trim()
+        .to_owned();
+        let bar_contents = dedent(
+            r#"
+            def <bar_def>baz</bar_def>():
+                pass
+            <bar_call>baz</bar_call>()
+            "#,
+        )
+        .trim()
+        .to_owned();
+        let qux_contents = dedent(
+            r#"
+            load("{load}", <qux_alias>renamed</qux_alias> = "<qux_load>baz</qux_load>")
+            <qux_call>renamed</qux_call>()
+            "#,
+        )
+        .replace("{load}", bar_uri.path())
+        .trim()
+        .to_owned();
+
+        let foo = FixtureWithRanges::from_fixture(foo_uri.path(), &foo_contents)?;
+        let bar = FixtureWithRanges::from_fixture(bar_uri.path(), &bar_contents)?;
+        let qux = FixtureWithRanges::from_fixture(qux_uri.path(), &qux_contents)?;
+
+        server.open_file(foo_uri.clone(), foo.program())?;
+        server.open_file(bar_uri.clone(), bar.program())?;
+        server.open_file(qux_uri.clone(), qux.program())?;
+
+        Ok(((foo_uri, foo), (bar_uri, bar), (qux_uri, qux)))
+    }
+
+    fn sorted_locations(mut locations: Vec<Location>) -> Vec<Location> {
+        locations.sort_by_key(|location| {
+            (
+                location.uri.to_string(),
+                location.range.start.line,
+                location.range.start.character,
+            )
+        });
+        locations
+    }
+
+    #[test]
+    fn finds_references_in_loading_files() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let ((foo_uri, foo), (bar_uri, bar), (qux_uri, qux)) = open_files_loading_baz(&mut server)?;
+
+        let location = |uri: &Url, fixture: &FixtureWithRanges, identifier: &str| -> Location {
+            Location::new(uri.clone(), fixture.resolved_span(identifier).into())
+        };
+        let expected = sorted_locations(vec![
+            location(&bar_uri, &bar, "bar_def"),
+            location(&bar_uri, &bar, "bar_call"),
+            location(&foo_uri, &foo, "foo_load"),
+            location(&foo_uri, &foo, "foo_call"),
+            location(&qux_uri, &qux, "qux_alias"),
+            location(&qux_uri, &qux, "qux_load"),
+            location(&qux_uri, &qux, "qux_call"),
+        ]);
+
+        for (uri, fixture, identifier) in [
+            (&foo_uri, &foo, "foo_call"),
+            (&bar_uri, &bar, "bar_def"),
+            (&qux_uri, &qux, "qux_call"),
+        ] {
+            let request = references_request(
+                &mut server,
+                uri.clone(),
+                fixture.begin_line(identifier),
+                fixture.begin_column(identifier),
+            );
+            let request_id = server.send_request(request)?;
+            let response = server.get_response::<Option<Vec<Location>>>(request_id)?;
+            assert_eq!(
+                expected,
+                sorted_locations(response.unwrap_or_default()),
+                "references from `{}`",
+                identifier
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn renames_symbol_in_loading_files() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let ((foo_uri, foo), (bar_uri, bar), (qux_uri, qux)) = open_files_loading_baz(&mut server)?;
+        server.set_workspace_files(Some(vec![
+            foo_uri.clone().try_into()?,
+            bar_uri.clone().try_into()?,
+            qux_uri.clone().try_into()?,
+        ]));
+
+        let request = rename_request(
+            &mut server,
+            bar_uri.clone(),
+            bar.begin_line("bar_def"),
+            bar.begin_column("bar_def"),
+            "new_baz",
+        );
+        let request_id = server.send_request(request)?;
+        let response = server.get_response::<Option<WorkspaceEdit>>(request_id)?;
+        let mut changes = response
+            .and_then(|edit| edit.changes)
+            .context("expected changes")?;
+        for edits in changes.values_mut() {
+            edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        }
+
+        let edit = |fixture: &FixtureWithRanges, identifier: &str| -> TextEdit {
+            TextEdit::new(
+                fixture.resolved_span(identifier).into(),
+                "new_baz".to_owned(),
+            )
+        };
+        let expected = HashMap::from([
+            (bar_uri, vec![edit(&bar, "bar_def"), edit(&bar, "bar_call")]),
+            (
+                foo_uri,
+                vec![edit(&foo, "foo_load"), edit(&foo, "foo_call")],
+            ),
+            // The alias keeps its name.
+            (qux_uri, vec![edit(&qux, "qux_load")]),
+        ]);
+        assert_eq!(expected, changes);
+        Ok(())
+    }
+
+    #[test]
+    fn finds_references_in_files_loaded_by_open_files() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let foo_uri = temp_file_uri("foo.star");
+        let bar_uri = temp_file_uri("bar.star");
+        let lib_uri = temp_file_uri("lib.star");
+
+        let foo_contents = dedent(
+            r#"
+            load("{load}", "run")
+            run()
+            "#,
+        )
+        .replace("{load}", lib_uri.path())
+        .trim()
+        .to_owned();
+        let bar_contents = dedent(
+            r#"
+            def <bar_def>baz</bar_def>():
+                pass
+            "#,
+        )
+        .trim()
+        .to_owned();
+        let lib_contents = dedent(
+            r#"
+            load("{load}", "<lib_load>baz</lib_load>")
+            def run():
+                <lib_call>baz</lib_call>()
+            "#,
+        )
+        .replace("{load}", bar_uri.path())
+        .trim()
+        .to_owned();
+
+        let bar = FixtureWithRanges::from_fixture(bar_uri.path(), &bar_contents)?;
+        let lib = FixtureWithRanges::from_fixture(lib_uri.path(), &lib_contents)?;
+
+        // `lib.star` is not open, but is reachable from `foo.star`.
+        server.open_file(foo_uri, foo_contents)?;
+        server.open_file(bar_uri.clone(), bar.program())?;
+        server.set_file_contents(PathBuf::from(lib_uri.path()), lib.program())?;
+
+        let request = references_request(
+            &mut server,
+            bar_uri.clone(),
+            bar.begin_line("bar_def"),
+            bar.begin_column("bar_def"),
+        );
+        let request_id = server.send_request(request)?;
+        let response = server.get_response::<Option<Vec<Location>>>(request_id)?;
+
+        let location = |uri: &Url, fixture: &FixtureWithRanges, identifier: &str| -> Location {
+            Location::new(uri.clone(), fixture.resolved_span(identifier).into())
+        };
+        let expected = sorted_locations(vec![
+            location(&bar_uri, &bar, "bar_def"),
+            location(&lib_uri, &lib, "lib_load"),
+            location(&lib_uri, &lib, "lib_call"),
+        ]);
+        assert_eq!(expected, sorted_locations(response.unwrap_or_default()));
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_rename_when_files_cannot_be_listed() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let ((foo_uri, foo), (bar_uri, bar), _) = open_files_loading_baz(&mut server)?;
+
+        // Without a listing of the workspace, files that are not open might load `baz`.
+        for (uri, fixture, identifier) in
+            [(&foo_uri, &foo, "foo_call"), (&bar_uri, &bar, "bar_def")]
+        {
+            let request = rename_request(
+                &mut server,
+                uri.clone(),
+                fixture.begin_line(identifier),
+                fixture.begin_column(identifier),
+                "new_baz",
+            );
+            let request_id = server.send_request(request)?;
+            assert!(
+                server
+                    .get_response::<Option<WorkspaceEdit>>(request_id)
+                    .is_err(),
+                "renaming from `{}` should fail",
+                identifier
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn finds_workspace_symbols_in_files_that_are_not_open() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let foo_uri = temp_file_uri("foo.star");
+        let bar_uri = temp_file_uri("bar.star");
+
+        server.open_file(foo_uri.clone(), "def find_open():\n    pass\n".to_owned())?;
+        server.set_file_contents(
+            PathBuf::from(bar_uri.path()),
+            "def find_closed():\n    pass\n".to_owned(),
+        )?;
+        server.set_workspace_files(Some(vec![bar_uri.clone().try_into()?]));
+
+        let request = server.new_request::<WorkspaceSymbolRequest>(WorkspaceSymbolParams {
+            query: "find".to_owned(),
+            ..Default::default()
+        });
+        let request_id = server.send_request(request)?;
+        let response = server.get_response::<Option<WorkspaceSymbolResponse>>(request_id)?;
+        let symbols = match response {
+            Some(WorkspaceSymbolResponse::Flat(symbols)) => symbols,
+            _ => panic!("expected flat workspace symbols"),
+        };
+
+        let mut found: Vec<(String, Url)> = symbols
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.location.uri))
+            .collect();
+        found.sort();
+        assert_eq!(
+            vec![
+                ("find_closed".to_owned(), bar_uri),
+                ("find_open".to_owned(), foo_uri),
+            ],
+            found
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_rename() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let ((foo_uri, foo), _, _) = open_files_loading_baz(&mut server)?;
+
+        for new_name in ["1baz", "baz-qux", "def", ""] {
+            let request = rename_request(
+                &mut server,
+                foo_uri.clone(),
+                foo.begin_line("foo_call"),
+                foo.begin_column("foo_call"),
+                new_name,
+            );
+            let request_id = server.send_request(request)?;
+            assert!(
+                server
+                    .get_response::<Option<WorkspaceEdit>>(request_id)
+                    .is_err(),
+                "`{}` should not be a valid name",
+                new_name
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn offers_signature_help_from_types_and_docs() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let uri = temp_file_uri("foo.star");
+        let contents = dedent(
+            r#"
+            def foo(a: int, b: str = "b"):
+                """Does foo."""
+                pass
+
+            foo(1, <cursor></cursor>)
+            "#,
+        )
+        .trim()
+        .to_owned();
+        let fixture = FixtureWithRanges::from_fixture(uri.path(), &contents)?;
+        server.open_file(uri.clone(), fixture.program())?;
+
+        let request = server.new_request::<SignatureHelpRequest>(SignatureHelpParams {
+            context: None,
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position {
+                    line: fixture.begin_line("cursor"),
+                    character: fixture.begin_column("cursor"),
+                },
+            },
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(request)?;
+        let response = server
+            .get_response::<Option<SignatureHelp>>(request_id)?
+            .context("expected signature help")?;
+
+        assert_eq!(Some(1), response.active_parameter);
+        assert_eq!("foo(a: int, b: str = ...)", response.signatures[0].label);
+        Ok(())
+    }
+
+    #[test]
+    fn offers_lint_fixes_as_code_actions() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let uri = temp_file_uri("foo.star");
+        server.open_file(
+            uri.clone(),
+            "load(\"bar.star\", \"used\", \"unused\")\nused()\n".to_owned(),
+        )?;
+
+        let request = server.new_request::<CodeActionRequest>(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: Range::new(Position::new(0, 0), Position::new(0, 100)),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(request)?;
+        let actions = server
+            .get_response::<Option<CodeActionResponse>>(request_id)?
+            .context("expected code actions")?;
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected one code action, got {:?}", actions);
+        };
+        assert_eq!("Remove unused `load` of `unused`", action.title);
+        let expected = HashMap::from([(
+            uri,
+            vec![TextEdit::new(
+                Range::new(Position::new(0, 23), Position::new(0, 33)),
+                String::new(),
+            )],
+        )]);
+        assert_eq!(
+            Some(&expected),
+            action.edit.as_ref().and_then(|x| x.changes.as_ref())
+        );
+        Ok(())
+    }
+
+    fn formatting_request(
+        server: &mut TestServer,
+        uri: Url,
+    ) -> anyhow::Result<Option<Vec<TextEdit>>> {
+        let request = server.new_request::<Formatting>(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri },
+            options: FormattingOptions {
+                tab_size: 4,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(request)?;
+        server.get_response::<Option<Vec<TextEdit>>>(request_id)
+    }
+
+    #[test]
+    fn formats_document() -> anyhow::Result<()> {
+        if is_wasm() {
+            return Ok(());
+        }
+
+        let mut server = TestServer::new()?;
+        let uri = temp_file_uri("foo.star");
+        server.open_file(uri.clone(), "x=[1,2]  # two\n".to_owned())?;
+
+        let edits = formatting_request(&mut server, uri.clone())?.context("expected edits")?;
+        assert_eq!(1, edits.len());
+        assert_eq!(Position::new(0, 0), edits[0].range.start);
+        assert_eq!("x = [1, 2]  # two\n", edits[0].new_text);
+
+        // Already formatted.
+        server.change_file(uri.clone(), "x = [1, 2]  # two\n".to_owned())?;
+        assert_eq!(
+            Some(Vec::new()),
+            formatting_request(&mut server, uri.clone())?
+        );
+
+        // The last valid parse doesn't have the current contents, so must not be formatted.
+        server.change_file(uri.clone(), "x = [1,\n".to_owned())?;
+        assert_eq!(None, formatting_request(&mut server, uri)?);
+        Ok(())
+    }
 }
@@ -0,0 +1,638 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Signature help for function calls, built from the function types that the typechecker
+//! infers and from the documentation of the function.
+
+use std::path::Path;
+
+use lsp_types::Documentation;
+use lsp_types::InitializeParams;
+use lsp_types::MarkupContent;
+use lsp_types::MarkupKind;
+use lsp_types::ParameterInformation;
+use lsp_types::ParameterLabel;
+use lsp_types::SignatureHelp;
+use lsp_types::SignatureHelpParams;
+use lsp_types::SignatureInformation;
+use starlark::codemap::CodeMap;
+use starlark::codemap::Pos;
+use starlark::codemap::ResolvedSpan;
+use starlark::codemap::Span;
+use starlark::docs::DocFunction;
+use starlark::docs::DocItem;
+use starlark::docs::DocMember;
+use starlark::docs::DocParam;
+use starlark::docs::DocString;
+use starlark::environment::Globals;
+use starlark::typing::Ty;
+use starlark::typing::TyFunction;
+use starlark_syntax::codemap::ResolvedPos;
+use starlark_syntax::syntax::ast::ArgumentP;
+use starlark_syntax::syntax::ast::AstArgumentP;
+use starlark_syntax::syntax::ast::AstPayload;
+use starlark_syntax::syntax::ast::AstStmtP;
+use starlark_syntax::syntax::ast::ExprP;
+use starlark_syntax::syntax::module::AstModuleFields;
+use starlark_syntax::syntax::uniplate::Visit;
+
+use crate::definition::Definition;
+use crate::definition::IdentifierDefinition;
+use crate::definition::LspModule;
+use crate::server::Backend;
+use crate::server::LspContext;
+use crate::server::LspUrl;
+use crate::symbols::find_symbols_at_location;
+
+/// The argument of a call that the cursor is on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ActiveArgument {
+    /// The n-th positional argument, counting from zero.
+    Positional(usize),
+    /// A named argument.
+    Named(String),
+}
+
+/// A call of a named function, where the cursor is within the parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CallAtLocation {
+    /// The name of the function being called.
+    pub(crate) function_name: String,
+    /// Where the name of the function being called is.
+    pub(crate) function_span: Span,
+    /// The argument that the cursor is on, if it can be matched to a parameter.
+    pub(crate) argument: Option<ActiveArgument>,
+}
+
+/// Find the innermost call of a named function which has the cursor within its parentheses.
+pub(crate) fn find_call_at_location<P: AstPayload>(
+    codemap: &CodeMap,
+    ast: &AstStmtP<P>,
+    pos: Pos,
+) -> Option<CallAtLocation> {
+    fn walk<P: AstPayload>(
+        codemap: &CodeMap,
+        node: Visit<P>,
+        pos: Pos,
+        result: &mut Option<CallAtLocation>,
+    ) {
+        match node {
+            Visit::Stmt(stmt) => {
+                if stmt.span.contains(pos) {
+                    stmt.visit_children(|x| walk(codemap, x, pos, result));
+                }
+            }
+            Visit::Expr(expr) => {
+                if !expr.span.contains(pos) {
+                    return;
+                }
+                if let ExprP::Call(function, args) = &expr.node {
+                    if let ExprP::Identifier(identifier) = &function.node {
+                        if function.span.end() < pos && pos < expr.span.end() {
+                            *result = Some(CallAtLocation {
+                                function_name: identifier.node.ident.clone(),
+                                function_span: function.span,
+                                argument: active_argument(codemap, args, pos),
+                            });
+                        }
+                    }
+                }
+                // Calls nested in the arguments are visited later, so take precedence.
+                expr.visit_expr(|x| walk(codemap, Visit::Expr(x), pos, result));
+            }
+        }
+    }
+
+    let mut result = None;
+    walk(codemap, Visit::Stmt(ast), pos, &mut result);
+    result
+}
+
+fn active_argument<P: AstPayload>(
+    codemap: &CodeMap,
+    args: &[AstArgumentP<P>],
+    pos: Pos,
+) -> Option<ActiveArgument> {
+    let mut positional = 0;
+    for arg in args {
+        // The cursor is between two arguments, e.g. just after a comma.
+        if pos < arg.span.begin() {
+            break;
+        }
+        // The cursor is still on an argument until it moves past the comma that follows it.
+        if pos <= arg.span.end()
+            || !codemap
+                .source_span(Span::new(arg.span.end(), pos))
+                .contains(',')
+        {
+            return match &arg.node {
+                ArgumentP::Positional(_) | ArgumentP::Args(_) => {
+                    Some(ActiveArgument::Positional(positional))
+                }
+                ArgumentP::Named(name, _) => Some(ActiveArgument::Named(name.node.clone())),
+                ArgumentP::KwArgs(_) => None,
+            };
+        }
+        if let ArgumentP::Positional(_) = &arg.node {
+            positional += 1;
+        }
+    }
+    Some(ActiveArgument::Positional(positional))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SignatureParamKind {
+    /// Can be passed by position, and usually by name.
+    Positional,
+    /// Can only be passed by name.
+    NamedOnly,
+    /// `*args`.
+    Args,
+    /// `**kwargs`.
+    Kwargs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SignatureParam {
+    /// How the parameter is shown in the signature, e.g. `x: int = ...`.
+    label: String,
+    /// The name the parameter is passed by, if it can be passed by name.
+    name: Option<String>,
+    kind: SignatureParamKind,
+    docs: Option<DocString>,
+}
+
+/// The signature of a function, as shown in signature help.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Signature {
+    name: String,
+    params: Vec<SignatureParam>,
+    result: Ty,
+    docs: Option<DocString>,
+}
+
+/// Render `prefix` and `ty`, unless the type is unknown.
+fn type_suffix(prefix: &str, ty: &Ty) -> String {
+    if ty == &Ty::any() {
+        String::new()
+    } else {
+        format!("{prefix}{ty}")
+    }
+}
+
+fn param_docs(param: &DocParam) -> Option<DocString> {
+    match param {
+        DocParam::Arg { docs, .. }
+        | DocParam::Args { docs, .. }
+        | DocParam::Kwargs { docs, .. } => docs.clone(),
+        DocParam::NoArgs | DocParam::OnlyPosBefore => None,
+    }
+}
+
+fn render_doc_string(docs: &DocString) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: match &docs.details {
+            Some(details) => format!("{}\n\n{}", docs.summary, details),
+            None => docs.summary.clone(),
+        },
+    })
+}
+
+impl Signature {
+    /// Build a signature from the type of a function, taking the docs for it and its
+    /// parameters from `docs`, if present.
+    pub(crate) fn from_type(name: &str, ty: &TyFunction, docs: Option<&DocFunction>) -> Self {
+        let params = ty
+            .params()
+            .iter()
+            .map(|param| {
+                let kind = match (param.allows_pos(), param.allows_many()) {
+                    (true, false) => SignatureParamKind::Positional,
+                    (false, false) => SignatureParamKind::NamedOnly,
+                    (true, true) => SignatureParamKind::Args,
+                    (false, true) => SignatureParamKind::Kwargs,
+                };
+                let mut label = format!("{}{}", param.name(), type_suffix(": ", param.ty()));
+                if param.is_optional() && !param.allows_many() {
+                    label.push_str(" = ...");
+                }
+                SignatureParam {
+                    label,
+                    name: (!param.allows_many()).then(|| param.name().to_owned()),
+                    kind,
+                    docs: docs
+                        .and_then(|docs| docs.find_param_with_name(param.name()))
+                        .and_then(param_docs),
+                }
+            })
+            .collect();
+        Self {
+            name: name.to_owned(),
+            params,
+            result: ty.result().clone(),
+            docs: docs.and_then(|docs| docs.docs.clone()),
+        }
+    }
+
+    /// Build a signature from the documentation of a function.
+    pub(crate) fn from_docs(name: &str, docs: &DocFunction) -> Self {
+        let mut named_only = false;
+        let mut params = Vec::new();
+        for param in &docs.params {
+            let (label, name, kind) = match param {
+                DocParam::Arg {
+                    name,
+                    typ,
+                    default_value,
+                    ..
+                } => {
+                    let mut label = format!("{}{}", name, type_suffix(": ", typ));
+                    if let Some(default_value) = default_value {
+                        label.push_str(" = ");
+                        label.push_str(default_value);
+                    }
+                    let kind = if named_only {
+                        SignatureParamKind::NamedOnly
+                    } else {
+                        SignatureParamKind::Positional
+                    };
+                    (label, Some(name.clone()), kind)
+                }
+                DocParam::Args { name, typ, .. } => {
+                    named_only = true;
+                    let name = if name.starts_with('*') {
+                        name.clone()
+                    } else {
+                        format!("*{name}")
+                    };
+                    let label = format!("{}{}", name, type_suffix(": ", typ));
+                    (label, None, SignatureParamKind::Args)
+                }
+                DocParam::Kwargs { name, typ, .. } => {
+                    let name = if name.starts_with("**") {
+                        name.clone()
+                    } else {
+                        format!("**{name}")
+                    };
+                    let label = format!("{}{}", name, type_suffix(": ", typ));
+                    (label, None, SignatureParamKind::Kwargs)
+                }
+                DocParam::NoArgs => {
+                    named_only = true;
+                    continue;
+                }
+                DocParam::OnlyPosBefore => continue,
+            };
+            params.push(SignatureParam {
+                label,
+                name,
+                kind,
+                docs: param_docs(param),
+            });
+        }
+        Self {
+            name: name.to_owned(),
+            params,
+            result: docs.ret.typ.clone(),
+            docs: docs.docs.clone(),
+        }
+    }
+
+    /// Which of the parameters an argument is passed to.
+    fn active_parameter(&self, argument: &ActiveArgument) -> Option<usize> {
+        let find_kind = |kind| self.params.iter().position(|p| p.kind == kind);
+        match argument {
+            ActiveArgument::Positional(n) => self
+                .params
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.kind == SignatureParamKind::Positional)
+                .nth(*n)
+                .map(|(i, _)| i)
+                .or_else(|| find_kind(SignatureParamKind::Args)),
+            ActiveArgument::Named(name) => self
+                .params
+                .iter()
+                .position(|p| p.name.as_ref() == Some(name))
+                .or_else(|| find_kind(SignatureParamKind::Kwargs)),
+        }
+    }
+
+    pub(crate) fn into_signature_help(self, argument: Option<&ActiveArgument>) -> SignatureHelp {
+        // Parameters are identified by their offsets in the label, which are in UTF-16 code
+        // units as for positions.
+        fn utf16_len(s: &str) -> u32 {
+            s.encode_utf16().count() as u32
+        }
+
+        let active_parameter = argument
+            .and_then(|argument| self.active_parameter(argument))
+            .map(|i| i as u32);
+
+        let mut label = format!("{}(", self.name);
+        let mut parameters = Vec::with_capacity(self.params.len());
+        for (i, param) in self.params.into_iter().enumerate() {
+            if i != 0 {
+                label.push_str(", ");
+            }
+            let begin = utf16_len(&label);
+            label.push_str(&param.label);
+            parameters.push(ParameterInformation {
+                label: ParameterLabel::LabelOffsets([begin, utf16_len(&label)]),
+                documentation: param.docs.as_ref().map(render_doc_string),
+            });
+        }
+        label.push(')');
+        label.push_str(&type_suffix(" -> ", &self.result));
+
+        SignatureHelp {
+            signatures: vec![SignatureInformation {
+                label,
+                documentation: self.docs.as_ref().map(render_doc_string),
+                parameters: Some(parameters),
+                active_parameter,
+            }],
+            active_signature: Some(0),
+            active_parameter,
+        }
+    }
+}
+
+impl LspModule {
+    /// Find the innermost call of a named function which has the cursor within its parentheses.
+    ///
+    /// `line` and `col` are zero based indexes of the cursor.
+    pub(crate) fn find_call_at_location(&self, line: u32, col: u32) -> Option<CallAtLocation> {
+        let line_span = self.ast.codemap().line_span_opt(line as usize)?;
+        let current_pos = std::cmp::min(line_span.begin() + col, line_span.end());
+        find_call_at_location(self.ast.codemap(), self.ast.statement(), current_pos)
+    }
+
+    /// The type that the typechecker inferred for the function defined at `definition`.
+    fn function_type_at(&self, definition: ResolvedSpan, globals: &Globals) -> Option<TyFunction> {
+        let type_map = self.typecheck(globals)?;
+        let codemap = self.ast.codemap();
+        type_map
+            .bindings()
+            .find(|(_, span, _)| codemap.resolve_span(*span) == definition)
+            .and_then(|(_, _, ty)| ty.as_function().cloned())
+    }
+}
+
+impl<T: LspContext> Backend<T> {
+    pub(crate) fn signature_help_info(
+        &self,
+        params: SignatureHelpParams,
+        initialize_params: &InitializeParams,
+    ) -> anyhow::Result<Option<SignatureHelp>> {
+        let uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .try_into()?;
+        let line = params.text_document_position_params.position.line;
+        let character = params.text_document_position_params.position.character;
+        let workspace_root =
+            Self::get_workspace_root(initialize_params.workspace_folders.as_ref(), &uri);
+
+        let Some(document) = self.get_ast(&uri) else {
+            return Ok(None);
+        };
+        let Some(call) = document.find_call_at_location(line, character) else {
+            return Ok(None);
+        };
+        let function = document.ast.codemap().resolve_span(call.function_span);
+        let signature = match document
+            .find_definition_at_location(function.begin.line as u32, function.begin.column as u32)
+        {
+            Definition::Identifier(definition) => self.signature_for_identifier_definition(
+                definition,
+                &call.function_name,
+                &document,
+                &uri,
+                workspace_root.as_deref(),
+            )?,
+            // Methods are not supported yet.
+            Definition::Dotted(_) => None,
+        };
+        Ok(signature.map(|signature| signature.into_signature_help(call.argument.as_ref())))
+    }
+
+    fn signature_for_identifier_definition(
+        &self,
+        definition: IdentifierDefinition,
+        function_name: &str,
+        document: &LspModule,
+        document_uri: &LspUrl,
+        workspace_root: Option<&Path>,
+    ) -> anyhow::Result<Option<Signature>> {
+        let as_function = |docs: Option<DocItem>| match docs {
+            Some(DocItem::Function(docs)) => Some(docs),
+            _ => None,
+        };
+        let signature = |ty: Option<TyFunction>, docs: Option<DocFunction>| match (ty, docs) {
+            (Some(ty), docs) => Some(Signature::from_type(function_name, &ty, docs.as_ref())),
+            (None, Some(docs)) => Some(Signature::from_docs(function_name, &docs)),
+            (None, None) => None,
+        };
+        let globals = self.context.get_typecheck_globals(document_uri);
+
+        Ok(match definition {
+            IdentifierDefinition::Location {
+                destination, name, ..
+            } => {
+                let docs = find_symbols_at_location(
+                    document.ast.codemap(),
+                    document.ast.statement(),
+                    ResolvedPos {
+                        line: destination.begin.line,
+                        column: destination.begin.column,
+                    },
+                )
+                .remove(&name)
+                .and_then(|symbol| as_function(symbol.doc));
+                signature(document.function_type_at(destination, &globals), docs)
+            }
+            IdentifierDefinition::LoadedLocation { path, name, .. } => {
+                let load_uri = self.resolve_load_path(&path, document_uri, workspace_root)?;
+                let Some(module) = self.get_ast_or_load_from_disk(&load_uri)? else {
+                    return Ok(None);
+                };
+                let Some(symbol) = module.find_exported_symbol(&name) else {
+                    return Ok(None);
+                };
+                let globals = self.context.get_typecheck_globals(&load_uri);
+                signature(
+                    module.function_type_at(symbol.span.resolve_span(), &globals),
+                    as_function(symbol.docs),
+                )
+            }
+            IdentifierDefinition::Unresolved { name, .. } => {
+                // Prefer the documentation of the environment, falling back to the globals the
+                // file is typechecked against.
+                let environment = self.context.get_environment(document_uri);
+                environment
+                    .members
+                    .into_iter()
+                    .chain(globals.documentation().members)
+                    .find_map(|(member_name, member)| match member {
+                        DocMember::Function(docs) if member_name == name => Some(docs),
+                        _ => None,
+                    })
+                    .and_then(|docs| signature(None, Some(docs)))
+            }
+            IdentifierDefinition::LoadPath { .. }
+            | IdentifierDefinition::StringLiteral { .. }
+            | IdentifierDefinition::NotFound => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark::docs::DocReturn;
+    use starlark::docs::DocStringKind;
+    use starlark::typing::Param;
+
+    use super::*;
+    use crate::definition::helpers::FixtureWithRanges;
+
+    fn call_at(fixture: &str, name: &str) -> anyhow::Result<Option<CallAtLocation>> {
+        let parsed = FixtureWithRanges::from_fixture("foo.star", fixture)?;
+        let module = parsed.module().map_err(|e| e.into_anyhow())?;
+        Ok(module.find_call_at_location(parsed.begin_line(name), parsed.begin_column(name)))
+    }
+
+    #[test]
+    fn finds_innermost_call() -> anyhow::Result<()> {
+        let fixture = r#"
+foo(1, <a></a>bar(<b></b>), x = <c></c>2)
+baz(1,<d></d>
+  y = 3)
+(<e></e>foo)()
+"#;
+        let call = call_at(fixture, "a")?.unwrap();
+        assert_eq!("foo", call.function_name);
+        assert_eq!(Some(ActiveArgument::Positional(1)), call.argument);
+
+        let call = call_at(fixture, "b")?.unwrap();
+        assert_eq!("bar", call.function_name);
+        assert_eq!(Some(ActiveArgument::Positional(0)), call.argument);
+
+        let call = call_at(fixture, "c")?.unwrap();
+        assert_eq!("foo", call.function_name);
+        assert_eq!(Some(ActiveArgument::Named("x".to_owned())), call.argument);
+
+        let call = call_at(fixture, "d")?.unwrap();
+        assert_eq!("baz", call.function_name);
+        assert_eq!(Some(ActiveArgument::Positional(1)), call.argument);
+
+        assert_eq!(None, call_at(fixture, "e")?);
+        Ok(())
+    }
+
+    #[test]
+    fn signature_from_type() {
+        let ty = TyFunction::new(
+            vec![
+                Param::pos_or_name("x", Ty::int()),
+                Param::pos_or_name("y", Ty::any()).optional(),
+                Param::args(Ty::any()),
+                Param::name_only("z", Ty::string()),
+                Param::kwargs(Ty::any()),
+            ],
+            Ty::none(),
+        );
+        let signature = Signature::from_type("f", &ty, None);
+
+        assert_eq!(
+            Some(0),
+            signature.active_parameter(&ActiveArgument::Positional(0))
+        );
+        assert_eq!(
+            Some(2),
+            signature.active_parameter(&ActiveArgument::Positional(5))
+        );
+        assert_eq!(
+            Some(3),
+            signature.active_parameter(&ActiveArgument::Named("z".to_owned()))
+        );
+        assert_eq!(
+            Some(4),
+            signature.active_parameter(&ActiveArgument::Named("w".to_owned()))
+        );
+
+        let help = signature.into_signature_help(Some(&ActiveArgument::Positional(1)));
+        assert_eq!(Some(1), help.active_parameter);
+        let information = &help.signatures[0];
+        assert_eq!(
+            "f(x: int, y = ..., *args, z: str, **kwargs) -> None",
+            information.label
+        );
+        assert_eq!(
+            Some(vec![
+                ParameterLabel::LabelOffsets([2, 8]),
+                ParameterLabel::LabelOffsets([10, 17]),
+                ParameterLabel::LabelOffsets([19, 24]),
+                ParameterLabel::LabelOffsets([26, 32]),
+                ParameterLabel::LabelOffsets([34, 42]),
+            ]),
+            information
+                .parameters
+                .as_ref()
+                .map(|params| params.iter().map(|p| p.label.clone()).collect::<Vec<_>>())
+        );
+    }
+
+    #[test]
+    fn signature_from_docs() {
+        let docs = DocFunction {
+            docs: DocString::from_docstring(DocStringKind::Starlark, "Does things."),
+            params: vec![
+                DocParam::Arg {
+                    name: "a".to_owned(),
+                    docs: DocString::from_docstring(DocStringKind::Starlark, "The a."),
+                    typ: Ty::string(),
+                    default_value: Some("\"x\"".to_owned()),
+                },
+                DocParam::NoArgs,
+                DocParam::Arg {
+                    name: "b".to_owned(),
+                    docs: None,
+                    typ: Ty::any(),
+                    default_value: None,
+                },
+            ],
+            ret: DocReturn {
+                docs: None,
+                typ: Ty::any(),
+            },
+            as_type: None,
+        };
+        let signature = Signature::from_docs("g", &docs);
+        assert_eq!(
+            None,
+            signature.active_parameter(&ActiveArgument::Positional(1))
+        );
+
+        let help = signature.into_signature_help(Some(&ActiveArgument::Named("b".to_owned())));
+        assert_eq!(Some(1), help.active_parameter);
+        assert_eq!("g(a: str = \"x\", b)", help.signatures[0].label);
+        assert!(help.signatures[0].documentation.is_some());
+    }
+}
@@ -130,6 +130,7 @@ pub(crate) enum TestServerError {
 struct TestServerContext {
     file_contents: Arc<RwLock<HashMap<PathBuf, String>>>,
     dirs: Arc<RwLock<HashSet<PathBuf>>>,
+    workspace_files: Arc<RwLock<Option<Vec<LspUrl>>>>,
     builtin_docs: Arc<HashMap<LspUrl, String>>,
     builtin_symbols: Arc<HashMap<String, LspUrl>>,
 }
@@ -290,6 +291,13 @@ impl LspContext for TestServerContext {
         Ok(self.builtin_symbols.get(symbol).cloned())
     }
 
+    fn get_workspace_files(
+        &self,
+        _workspace_root: Option<&Path>,
+    ) -> anyhow::Result<Option<Vec<LspUrl>>> {
+        Ok(self.workspace_files.read().unwrap().clone())
+    }
+
     fn get_environment(&self, _uri: &LspUrl) -> DocModule {
         DocModule {
             docs: None,
@@ -322,6 +330,7 @@ pub struct TestServer {
     recv_timeout: Duration,
     file_contents: Arc<RwLock<HashMap<PathBuf, String>>>,
     dirs: Arc<RwLock<HashSet<PathBuf>>>,
+    workspace_files: Arc<RwLock<Option<Vec<LspUrl>>>>,
     /// If it's been received, the response payload for initialization.
     initialize_response: Option<InitializeResult>,
     /// Documentation for built in symbols.
@@ -435,9 +444,11 @@ impl TestServer {
             .collect();
         let file_contents = Arc::new(RwLock::new(prelude_file_contents));
         let dirs = Arc::new(RwLock::new(HashSet::new()));
+        let workspace_files = Arc::new(RwLock::new(None));
         let ctx = TestServerContext {
             file_contents: file_contents.dupe(),
             dirs: dirs.dupe(),
+            workspace_files: workspace_files.dupe(),
             builtin_docs: builtin_docs.dupe(),
             builtin_symbols,
         };
@@ -458,6 +469,7 @@ impl TestServer {
             recv_timeout: Duration::from_secs(2),
             file_contents,
             dirs,
+            workspace_files,
             initialize_response: None,
             builtin_docs,
         };
@@ -671,6 +683,11 @@ impl TestServer {
         }
     }
 
+    /// Set the files that `get_workspace_files()` will return. By default, they can't be listed.
+    pub fn set_workspace_files(&self, files: Option<Vec<LspUrl>>) {
+        *self.workspace_files.write().unwrap() = files;
+    }
+
     /// Configure a path to be "a directory". This will return IsADirectory as an
     /// error from get_load_contents
     pub fn mkdir(&self, uri: Url) {
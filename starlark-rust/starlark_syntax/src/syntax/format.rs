@@ -0,0 +1,1242 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Formatting of Starlark source code in a standard style.
+//!
+//! The AST does not record comments, so they are taken from the lexer and placed back
+//! between the statements, list items and so on they were next to. String and number
+//! literals are copied from the source, so the quoting and escaping chosen by the author
+//! is kept.
+
+use dupe::Dupe;
+
+use crate::codemap::CodeMap;
+use crate::codemap::Pos;
+use crate::codemap::Span;
+use crate::dialect::Dialect;
+use crate::lexer::Lexer;
+use crate::lexer::Token;
+use crate::syntax::ast::Argument;
+use crate::syntax::ast::AssignP;
+use crate::syntax::ast::AssignTarget;
+use crate::syntax::ast::AstArgument;
+use crate::syntax::ast::AstAssignTarget;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstNoPayload;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::AstString;
+use crate::syntax::ast::BinOp;
+use crate::syntax::ast::Clause;
+use crate::syntax::ast::DefP;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::ForClause;
+use crate::syntax::ast::ForP;
+use crate::syntax::ast::LambdaP;
+use crate::syntax::ast::Load;
+use crate::syntax::ast::LoadArgP;
+use crate::syntax::ast::Parameter;
+use crate::syntax::ast::Stmt;
+
+/// Lines are split, where there is a bracketed list to split them at, to fit in this many columns.
+const MAX_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+// The precedence levels of expressions, lowest first, following the grammar.
+// An expression of a lower level than its context requires brackets.
+
+/// A tuple without brackets, only allowed at the top of a statement.
+const PREC_TUPLE: u8 = 0;
+/// `lambda` and `x if c else y`.
+const PREC_TEST: u8 = 1;
+const PREC_OR: u8 = 2;
+const PREC_AND: u8 = 3;
+const PREC_NOT: u8 = 4;
+/// Comparisons, which do not associate.
+const PREC_COMPARE: u8 = 5;
+const PREC_BIT_OR: u8 = 6;
+const PREC_BIT_XOR: u8 = 7;
+const PREC_BIT_AND: u8 = 8;
+const PREC_SHIFT: u8 = 9;
+const PREC_ARITH: u8 = 10;
+const PREC_PRODUCT: u8 = 11;
+/// Unary `-`, `+` and `~`.
+const PREC_UNARY: u8 = 12;
+/// Identifiers, literals, brackets, calls, indexing and `.`.
+const PREC_PRIMARY: u8 = 13;
+
+fn bin_op_prec(op: BinOp) -> u8 {
+    match op {
+        BinOp::Or => PREC_OR,
+        BinOp::And => PREC_AND,
+        BinOp::Equal
+        | BinOp::NotEqual
+        | BinOp::Less
+        | BinOp::Greater
+        | BinOp::LessOrEqual
+        | BinOp::GreaterOrEqual
+        | BinOp::In
+        | BinOp::NotIn => PREC_COMPARE,
+        BinOp::BitOr => PREC_BIT_OR,
+        BinOp::BitXor => PREC_BIT_XOR,
+        BinOp::BitAnd => PREC_BIT_AND,
+        BinOp::LeftShift | BinOp::RightShift => PREC_SHIFT,
+        BinOp::Add | BinOp::Subtract => PREC_ARITH,
+        BinOp::Multiply | BinOp::Percent | BinOp::Divide | BinOp::FloorDivide => PREC_PRODUCT,
+    }
+}
+
+fn expr_prec(x: &Expr) -> u8 {
+    match x {
+        Expr::Lambda(_) | Expr::If(_) => PREC_TEST,
+        Expr::Not(_) => PREC_NOT,
+        Expr::Op(_, op, _) => bin_op_prec(*op),
+        Expr::Minus(_) | Expr::Plus(_) | Expr::BitNot(_) => PREC_UNARY,
+        _ => PREC_PRIMARY,
+    }
+}
+
+/// Whether `s`, starting at column `col`, fits on the line. Only the first line of
+/// a multi-line string literal counts.
+fn fits(col: usize, s: &str) -> bool {
+    col + s.split('\n').next().unwrap_or_default().chars().count() <= MAX_WIDTH
+}
+
+/// The column after writing `s` starting at column `col`.
+fn end_column(col: usize, s: &str) -> usize {
+    match s.rfind('\n') {
+        Some(i) => s[i + 1..].chars().count(),
+        None => col + s.chars().count(),
+    }
+}
+
+fn push_indent(s: &mut String, indent: usize) {
+    for _ in 0..indent {
+        s.push_str(INDENT);
+    }
+}
+
+/// The first statement which isn't a list of statements.
+fn first_stmt(x: &AstStmt) -> &AstStmt {
+    match &x.node {
+        Stmt::Statements(xs) if !xs.is_empty() => first_stmt(&xs[0]),
+        _ => x,
+    }
+}
+
+#[derive(Clone)]
+struct Comment {
+    pos: Pos,
+    line: usize,
+    column: usize,
+    /// The comment is the only thing on its line, rather than following some code.
+    own_line: bool,
+    /// The text after the `#`.
+    text: String,
+}
+
+/// An item of a bracketed list, which is put on a line of its own when the list is split.
+trait ListItem {
+    fn span(&self) -> Span;
+
+    /// Format the item, see [`Formatter::layout`].
+    fn layout(&self, f: &mut Formatter, indent: usize, col: usize, flat: bool) -> Option<String>;
+}
+
+impl ListItem for AstExpr {
+    fn span(&self) -> Span {
+        self.span
+    }
+
+    fn layout(&self, f: &mut Formatter, indent: usize, col: usize, flat: bool) -> Option<String> {
+        f.child(self, indent, col, PREC_TEST, flat)
+    }
+}
+
+impl ListItem for (AstExpr, AstExpr) {
+    fn span(&self) -> Span {
+        self.0.span.merge(self.1.span)
+    }
+
+    fn layout(&self, f: &mut Formatter, indent: usize, col: usize, flat: bool) -> Option<String> {
+        let k = f.child(&self.0, indent, col, PREC_TEST, flat)?;
+        let v = f.child(&self.1, indent, end_column(col, &k) + 2, PREC_TEST, flat)?;
+        Some(format!("{}: {}", k, v))
+    }
+}
+
+impl ListItem for AstArgument {
+    fn span(&self) -> Span {
+        self.span
+    }
+
+    fn layout(&self, f: &mut Formatter, indent: usize, col: usize, flat: bool) -> Option<String> {
+        let prefix = match &self.node {
+            Argument::Positional(_) => String::new(),
+            Argument::Named(name, _) => format!("{} = ", name.node),
+            Argument::Args(_) => "*".to_owned(),
+            Argument::KwArgs(_) => "**".to_owned(),
+        };
+        let x = f.child(
+            self.node.expr(),
+            indent,
+            end_column(col, &prefix),
+            PREC_TEST,
+            flat,
+        )?;
+        Some(prefix + &x)
+    }
+}
+
+impl ListItem for AstParameter {
+    fn span(&self) -> Span {
+        self.span
+    }
+
+    fn layout(&self, f: &mut Formatter, indent: usize, col: usize, flat: bool) -> Option<String> {
+        let (prefix, name, ty, default) = match &self.node {
+            Parameter::Normal(name, ty) => ("", name, ty, None),
+            Parameter::WithDefaultValue(name, ty, default) => ("", name, ty, Some(default)),
+            Parameter::NoArgs => return Some("*".to_owned()),
+            Parameter::Args(name, ty) => ("*", name, ty, None),
+            Parameter::KwArgs(name, ty) => ("**", name, ty, None),
+        };
+        let mut s = format!("{}{}", prefix, name.node.ident);
+        if let Some(ty) = ty {
+            s.push_str(": ");
+            let col = end_column(col, &s);
+            s.push_str(&f.child(&ty.node.expr, indent, col, PREC_TEST, flat)?);
+        }
+        if let Some(default) = default {
+            s.push_str(" = ");
+            let col = end_column(col, &s);
+            s.push_str(&f.child(default, indent, col, PREC_TEST, flat)?);
+        }
+        Some(s)
+    }
+}
+
+/// A clause of a comprehension, where the first `for` is stored separately from the rest.
+enum ClauseRef<'a> {
+    For(&'a ForClause),
+    If(&'a AstExpr),
+}
+
+/// The arguments of `load`, which are split like any other list.
+enum LoadItem<'a> {
+    Module(&'a AstString),
+    Symbol(&'a LoadArgP<AstNoPayload>),
+}
+
+impl ListItem for LoadItem<'_> {
+    fn span(&self) -> Span {
+        match self {
+            LoadItem::Module(module) => module.span,
+            LoadItem::Symbol(symbol) => symbol.span(),
+        }
+    }
+
+    fn layout(
+        &self,
+        f: &mut Formatter,
+        _indent: usize,
+        _col: usize,
+        _flat: bool,
+    ) -> Option<String> {
+        Some(match self {
+            LoadItem::Module(module) => f.source(module.span).to_owned(),
+            LoadItem::Symbol(symbol) => {
+                let their = f.source(symbol.their.span);
+                if symbol.local.node.ident == symbol.their.node {
+                    their.to_owned()
+                } else {
+                    format!("{} = {}", symbol.local.node.ident, their)
+                }
+            }
+        })
+    }
+}
+
+struct Formatter<'a> {
+    codemap: &'a CodeMap,
+    /// All the comments in the file, in order.
+    comments: Vec<Comment>,
+    /// The first comment which has not been written yet.
+    next_comment: usize,
+    out: String,
+    /// The source line of the last statement or comment written, used to keep blank lines.
+    last_line: Option<usize>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(codemap: &'a CodeMap, dialect: &Dialect) -> Self {
+        let source = codemap.source();
+        let mut comments = Vec::new();
+        // The module has already been parsed, so lexing will succeed.
+        for (begin, token, _end) in Lexer::new(source, dialect, codemap.dupe()).flatten() {
+            if let Token::Comment(text) = token {
+                let pos = Pos::new(begin as u32);
+                let line = codemap.find_line(pos);
+                let before = &source[codemap.line_span(line).begin().get() as usize..begin];
+                comments.push(Comment {
+                    pos,
+                    line,
+                    column: before.chars().count(),
+                    own_line: before.trim().is_empty(),
+                    text: text.trim_end().to_owned(),
+                });
+            }
+        }
+        Formatter {
+            codemap,
+            comments,
+            next_comment: 0,
+            out: String::new(),
+            last_line: None,
+        }
+    }
+
+    fn source(&self, span: Span) -> &'a str {
+        self.codemap.source_span(span)
+    }
+
+    fn line(&self, pos: Pos) -> usize {
+        self.codemap.find_line(pos)
+    }
+
+    fn column(&self, pos: Pos) -> usize {
+        let line_begin = self.codemap.line_span(self.line(pos)).begin();
+        self.source(Span::new(line_begin, pos)).chars().count()
+    }
+
+    /// The position and first byte of the next token at or after `pos`,
+    /// skipping whitespace and comments. Only used between tokens.
+    fn next_token(&self, pos: Pos) -> Option<(Pos, u8)> {
+        let source = self.codemap.source().as_bytes();
+        let mut i = pos.get() as usize;
+        while i < source.len() {
+            match source[i] {
+                b' ' | b'\t' | b'\r' | b'\n' | b'\\' => i += 1,
+                b'#' => {
+                    while i < source.len() && source[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+                c => return Some((Pos::new(i as u32), c)),
+            }
+        }
+        None
+    }
+
+    /// The position of the first `byte` token at or after `pos`.
+    fn find_token(&self, mut pos: Pos, byte: u8) -> Pos {
+        while let Some((p, c)) = self.next_token(pos) {
+            if c == byte {
+                return p;
+            }
+            pos = p + 1;
+        }
+        pos
+    }
+
+    fn has_trailing_comma(&self, end: Pos) -> bool {
+        matches!(self.next_token(end), Some((_, b',')))
+    }
+
+    /// The span of a tuple doesn't include its brackets, so look at the source either side.
+    fn is_parenthesized(&self, span: Span) -> bool {
+        let before = self.codemap.source()[..span.begin().get() as usize]
+            .bytes()
+            .rev()
+            .find(|c| !c.is_ascii_whitespace());
+        before == Some(b'(') && matches!(self.next_token(span.end()), Some((_, b')')))
+    }
+
+    fn has_comment_in(&self, span: Span) -> bool {
+        let i = self.comments.partition_point(|c| c.pos < span.begin());
+        self.comments.get(i).map_or(false, |c| c.pos < span.end())
+    }
+
+    /// Take the comments before `pos` which have not been written yet.
+    fn take_comments_before(&mut self, pos: Pos) -> Vec<Comment> {
+        let start = self.next_comment;
+        while self
+            .comments
+            .get(self.next_comment)
+            .map_or(false, |c| c.pos < pos)
+        {
+            self.next_comment += 1;
+        }
+        self.comments[start..self.next_comment].to_vec()
+    }
+
+    /// Write blank lines for those between the last thing written and `line` in the source.
+    fn blank_lines(&mut self, line: usize, max_blank: usize) {
+        if let Some(last) = self.last_line {
+            for _ in 0..line.saturating_sub(last + 1).min(max_blank) {
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn comment_line(&mut self, c: &Comment, indent: usize, max_blank: usize) {
+        self.blank_lines(c.line, max_blank);
+        push_indent(&mut self.out, indent);
+        self.out.push('#');
+        self.out.push_str(&c.text);
+        self.out.push('\n');
+        self.last_line = Some(c.line);
+    }
+
+    fn comment_lines(&mut self, pos: Pos, indent: usize, max_blank: usize) {
+        for c in self.take_comments_before(pos) {
+            self.comment_line(&c, indent, max_blank);
+        }
+    }
+
+    /// Finish the current line. Comments before `end` which could not be kept in place,
+    /// and those on the same source line as `end`, go at the end of it,
+    /// so long as they are before `limit`.
+    fn end_line(&mut self, end: Pos, limit: Pos, indent: usize) {
+        let line = self.line(end);
+        let mut last_line = line;
+        let mut first = true;
+        while let Some(c) = self.comments.get(self.next_comment) {
+            if c.pos >= limit || (c.pos >= end && (c.line != line || c.own_line)) {
+                break;
+            }
+            if first {
+                self.out.push_str("  #");
+            } else {
+                self.out.push('\n');
+                push_indent(&mut self.out, indent);
+                self.out.push('#');
+            }
+            self.out.push_str(&c.text);
+            last_line = last_line.max(c.line);
+            first = false;
+            self.next_comment += 1;
+        }
+        self.out.push('\n');
+        self.last_line = Some(last_line);
+    }
+
+    /// Finish the line of a compound statement, the `:` of which is the first one after `pos`.
+    fn header_end(&mut self, pos: Pos, body: &AstStmt, indent: usize) {
+        let colon = self.find_token(pos, b':');
+        self.out.push(':');
+        self.end_line(colon + 1, first_stmt(body).span.begin(), indent);
+    }
+
+    /// Write the body of a compound statement.
+    fn block(&mut self, body: &AstStmt, indent: usize) {
+        self.last_line = None;
+        self.stmt(body, indent);
+        // Comments after the last statement belong to the block if they are indented as far.
+        let column = self.column(first_stmt(body).span.begin());
+        let next = self
+            .next_token(body.span.end())
+            .map_or(self.codemap.full_span().end(), |(pos, _)| pos);
+        while let Some(c) = self.comments.get(self.next_comment) {
+            if c.pos >= next || !c.own_line || c.column < column {
+                break;
+            }
+            let c = c.clone();
+            self.next_comment += 1;
+            self.comment_line(&c, indent, 1);
+        }
+    }
+
+    fn stmt(&mut self, x: &AstStmt, indent: usize) {
+        if let Stmt::Statements(xs) = &x.node {
+            for x in xs {
+                self.stmt(x, indent);
+            }
+            return;
+        }
+
+        let max_blank = if indent == 0 { 2 } else { 1 };
+        self.comment_lines(x.span.begin(), indent, max_blank);
+        self.blank_lines(self.line(x.span.begin()), max_blank);
+        push_indent(&mut self.out, indent);
+        let col = indent * INDENT.len();
+        match &x.node {
+            Stmt::If(cond, then_block) => self.if_stmt("if", cond, then_block, None, indent),
+            Stmt::IfElse(cond, then_else) => {
+                let (then_block, else_block) = &**then_else;
+                self.if_stmt("if", cond, then_block, Some(else_block), indent)
+            }
+            Stmt::For(ForP { var, over, body }) => {
+                let mut s = "for ".to_owned();
+                s.push_str(
+                    &self
+                        .target(var, indent, col + 4, PREC_TUPLE, false)
+                        .unwrap_or_default(),
+                );
+                s.push_str(" in ");
+                let over_col = end_column(col, &s);
+                s.push_str(&self.expr(over, indent, over_col, PREC_TEST));
+                self.out.push_str(&s);
+                self.header_end(over.span.end(), body, indent);
+                self.block(body, indent + 1);
+            }
+            Stmt::Def(def) => self.def(def, indent),
+            _ => {
+                let s = self.simple_stmt(x, indent);
+                self.out.push_str(&s);
+                self.end_line(x.span.end(), self.codemap.full_span().end(), indent);
+            }
+        }
+    }
+
+    fn if_stmt(
+        &mut self,
+        keyword: &str,
+        cond: &AstExpr,
+        then_block: &AstStmt,
+        else_block: Option<&AstStmt>,
+        indent: usize,
+    ) {
+        let col = indent * INDENT.len() + keyword.len() + 1;
+        let cond_str = self.expr(cond, indent, col, PREC_TEST);
+        self.out.push_str(keyword);
+        self.out.push(' ');
+        self.out.push_str(&cond_str);
+        self.header_end(cond.span.end(), then_block, indent);
+        self.block(then_block, indent + 1);
+
+        let Some(else_block) = else_block else {
+            return;
+        };
+        let else_pos = self
+            .next_token(then_block.span.end())
+            .map_or(else_block.span.begin(), |(pos, _)| pos);
+        self.comment_lines(else_pos, indent, 1);
+        push_indent(&mut self.out, indent);
+        let is_elif = self.codemap.source()[else_pos.get() as usize..].starts_with("elif");
+        match &else_block.node {
+            Stmt::If(cond, then_block) if is_elif => {
+                self.if_stmt("elif", cond, then_block, None, indent)
+            }
+            Stmt::IfElse(cond, then_else) if is_elif => {
+                let (then_block, else_block) = &**then_else;
+                self.if_stmt("elif", cond, then_block, Some(else_block), indent)
+            }
+            _ => {
+                self.out.push_str("else");
+                self.header_end(else_pos, else_block, indent);
+                self.block(else_block, indent + 1);
+            }
+        }
+    }
+
+    fn def(&mut self, def: &DefP<AstNoPayload>, indent: usize) {
+        let DefP {
+            name,
+            params,
+            return_type,
+            body,
+            payload: _,
+        } = def;
+        let mut s = format!("def {}", name.node.ident);
+        let col = end_column(indent * INDENT.len(), &s);
+        let params_end = params.last().map_or(name.span.end(), |x| x.span.end());
+        let close = self.find_token(params_end, b')');
+        let return_type_str = match return_type {
+            Some(x) => {
+                let x = self.layout(&x.node.expr, indent, col, PREC_TEST, true);
+                format!(" -> {}:", x.unwrap_or_default())
+            }
+            None => ":".to_owned(),
+        };
+        // Pretend the parameters start further right, so they are split if the return type doesn't fit.
+        let params_str = self
+            .list(
+                ("(", ")"),
+                params,
+                Span::new(name.span.end(), close + 1),
+                indent,
+                col + return_type_str.chars().count(),
+                false,
+            )
+            .unwrap_or_default();
+        s.push_str(&params_str);
+        if let Some(return_type) = return_type {
+            s.push_str(" -> ");
+            let col = end_column(indent * INDENT.len(), &s);
+            s.push_str(&self.expr(&return_type.node.expr, indent, col, PREC_TEST));
+        }
+        self.out.push_str(&s);
+        let header_end = return_type.as_ref().map_or(close + 1, |x| x.span.end());
+        self.header_end(header_end, body, indent);
+        self.block(body, indent + 1);
+    }
+
+    fn simple_stmt(&mut self, x: &AstStmt, indent: usize) -> String {
+        let col = indent * INDENT.len();
+        match &x.node {
+            Stmt::Break => "break".to_owned(),
+            Stmt::Continue => "continue".to_owned(),
+            Stmt::Pass => "pass".to_owned(),
+            Stmt::Return(None) => "return".to_owned(),
+            Stmt::Return(Some(e)) => {
+                format!("return {}", self.expr(e, indent, col + 7, PREC_TUPLE))
+            }
+            Stmt::Expression(e) => self.expr(e, indent, col, PREC_TEST),
+            Stmt::Assign(AssignP { lhs, ty, rhs }) => {
+                let mut s = self
+                    .target(lhs, indent, col, PREC_TUPLE, false)
+                    .unwrap_or_default();
+                if let Some(ty) = ty {
+                    s.push_str(": ");
+                    let col = end_column(col, &s);
+                    s.push_str(&self.expr(&ty.node.expr, indent, col, PREC_TEST));
+                }
+                s.push_str(" = ");
+                let col = end_column(col, &s);
+                s.push_str(&self.expr(rhs, indent, col, PREC_TUPLE));
+                s
+            }
+            Stmt::AssignModify(lhs, op, rhs) => {
+                let mut s = self
+                    .target(lhs, indent, col, PREC_TUPLE, false)
+                    .unwrap_or_default();
+                s.push_str(&op.to_string());
+                let col = end_column(col, &s);
+                s.push_str(&self.expr(rhs, indent, col, PREC_TUPLE));
+                s
+            }
+            Stmt::Load(load) => self.load(load, x.span, indent),
+            Stmt::Statements(_) | Stmt::If(..) | Stmt::IfElse(..) | Stmt::For(_) | Stmt::Def(_) => {
+                unreachable!("compound statements are formatted by `stmt`")
+            }
+        }
+    }
+
+    fn load(&mut self, load: &Load, span: Span, indent: usize) -> String {
+        let items: Vec<LoadItem> = std::iter::once(LoadItem::Module(&load.module))
+            .chain(load.args.iter().map(LoadItem::Symbol))
+            .collect();
+        let col = indent * INDENT.len() + "load".len();
+        let args = self
+            .list(
+                ("(", ")"),
+                &items,
+                Span::new(load.module.span.begin(), span.end()),
+                indent,
+                col,
+                false,
+            )
+            .unwrap_or_default();
+        format!("load{}", args)
+    }
+
+    /// Format an expression, on one line if it fits, otherwise split over several.
+    fn expr(&mut self, x: &AstExpr, indent: usize, col: usize, prec: u8) -> String {
+        if let Some(s) = self.layout(x, indent, col, prec, true) {
+            if fits(col, &s) {
+                return s;
+            }
+        }
+        // Layout which is not flat always succeeds.
+        self.layout(x, indent, col, prec, false).unwrap_or_default()
+    }
+
+    /// Format a subexpression, on one line if `flat`.
+    fn child(
+        &mut self,
+        x: &AstExpr,
+        indent: usize,
+        col: usize,
+        prec: u8,
+        flat: bool,
+    ) -> Option<String> {
+        if flat {
+            self.layout(x, indent, col, prec, true)
+        } else {
+            Some(self.expr(x, indent, col, prec))
+        }
+    }
+
+    /// Format an expression in a context of precedence `prec`, which starts at column `col`
+    /// of a line indented by `indent`.
+    ///
+    /// With `flat`, the expression is formatted on one line, or `None` returned if it contains
+    /// a list which must be split, because it has comments or a trailing comma. Otherwise the
+    /// subexpressions are split over lines where they don't fit, and the result is not `None`.
+    fn layout(
+        &mut self,
+        x: &AstExpr,
+        indent: usize,
+        col: usize,
+        prec: u8,
+        flat: bool,
+    ) -> Option<String> {
+        if let Expr::Tuple(xs) = &x.node {
+            return self.tuple(x.span, xs, indent, col, prec, flat);
+        }
+        let parens = expr_prec(&x.node) < prec;
+        let col = if parens { col + 1 } else { col };
+        let s = match &x.node {
+            Expr::Tuple(_) => unreachable!("handled above"),
+            Expr::Dot(e, name) => {
+                format!(
+                    "{}.{}",
+                    self.child(e, indent, col, PREC_PRIMARY, flat)?,
+                    name.node
+                )
+            }
+            Expr::Call(f, args) => {
+                let mut s = self.child(f, indent, col, PREC_PRIMARY, flat)?;
+                let col = end_column(col, &s);
+                let region = Span::new(f.span.end(), x.span.end());
+                s.push_str(&self.list(("(", ")"), args, region, indent, col, flat)?);
+                s
+            }
+            Expr::Index(e_i) => {
+                let (e, i) = &**e_i;
+                let mut s = self.child(e, indent, col, PREC_PRIMARY, flat)?;
+                s.push('[');
+                let col = end_column(col, &s);
+                s.push_str(&self.child(i, indent, col, PREC_TEST, flat)?);
+                s.push(']');
+                s
+            }
+            Expr::Index2(e_i0_i1) => {
+                let (e, i0, i1) = &**e_i0_i1;
+                let mut s = self.child(e, indent, col, PREC_PRIMARY, flat)?;
+                s.push('[');
+                let col0 = end_column(col, &s);
+                s.push_str(&self.child(i0, indent, col0, PREC_TEST, flat)?);
+                s.push_str(", ");
+                let col1 = end_column(col, &s);
+                s.push_str(&self.child(i1, indent, col1, PREC_TEST, flat)?);
+                s.push(']');
+                s
+            }
+            Expr::Slice(e, i0, i1, i2) => {
+                let mut s = self.child(e, indent, col, PREC_PRIMARY, flat)?;
+                s.push('[');
+                for (i, x) in [i0, i1, i2].into_iter().enumerate() {
+                    if i == 1 || (i == 2 && x.is_some()) {
+                        s.push(':');
+                    }
+                    if let Some(x) = x {
+                        let col = end_column(col, &s);
+                        s.push_str(&self.child(x, indent, col, PREC_TEST, flat)?);
+                    }
+                }
+                s.push(']');
+                s
+            }
+            Expr::Identifier(ident) => ident.node.ident.clone(),
+            Expr::Literal(_) | Expr::FString(_) => self.source(x.span).to_owned(),
+            Expr::Lambda(LambdaP { params, body, .. }) => {
+                let mut s = "lambda".to_owned();
+                for (i, param) in params.iter().enumerate() {
+                    s.push_str(if i == 0 { " " } else { ", " });
+                    let col = end_column(col, &s);
+                    s.push_str(&param.layout(self, indent, col, flat)?);
+                }
+                s.push_str(": ");
+                let col = end_column(col, &s);
+                s.push_str(&self.child(body, indent, col, PREC_TEST, flat)?);
+                s
+            }
+            Expr::Not(e) => format!("not {}", self.child(e, indent, col + 4, PREC_NOT, flat)?),
+            Expr::Minus(e) => format!("-{}", self.child(e, indent, col + 1, PREC_UNARY, flat)?),
+            Expr::Plus(e) => format!("+{}", self.child(e, indent, col + 1, PREC_UNARY, flat)?),
+            Expr::BitNot(e) => format!("~{}", self.child(e, indent, col + 1, PREC_UNARY, flat)?),
+            Expr::Op(l, op, r) => {
+                let op_prec = bin_op_prec(*op);
+                // Comparisons don't associate, the others associate to the left.
+                let (l_prec, r_prec) = if op_prec == PREC_COMPARE {
+                    (PREC_BIT_OR, PREC_BIT_OR)
+                } else {
+                    (op_prec, op_prec + 1)
+                };
+                let mut s = self.child(l, indent, col, l_prec, flat)?;
+                s.push_str(&op.to_string());
+                let col = end_column(col, &s);
+                s.push_str(&self.child(r, indent, col, r_prec, flat)?);
+                s
+            }
+            Expr::If(c_t_e) => {
+                let (c, t, e) = &**c_t_e;
+                let mut s = self.child(t, indent, col, PREC_OR, flat)?;
+                s.push_str(" if ");
+                let c_col = end_column(col, &s);
+                s.push_str(&self.child(c, indent, c_col, PREC_OR, flat)?);
+                s.push_str(" else ");
+                let e_col = end_column(col, &s);
+                s.push_str(&self.child(e, indent, e_col, PREC_TEST, flat)?);
+                s
+            }
+            Expr::List(xs) => self.list(("[", "]"), xs, x.span, indent, col, flat)?,
+            Expr::Dict(xs) => self.list(("{", "}"), xs, x.span, indent, col, flat)?,
+            Expr::ListComprehension(..) | Expr::DictComprehension(..) => {
+                self.comprehension(x, indent, col, flat)?
+            }
+        };
+        Some(if parens { format!("({})", s) } else { s })
+    }
+
+    fn tuple(
+        &mut self,
+        span: Span,
+        xs: &[AstExpr],
+        indent: usize,
+        col: usize,
+        prec: u8,
+        flat: bool,
+    ) -> Option<String> {
+        if xs.is_empty() {
+            return Some("()".to_owned());
+        }
+        if prec == PREC_TUPLE && !self.is_parenthesized(span) {
+            // Without brackets the tuple can't be split, but its items can.
+            let mut s = String::new();
+            for (i, x) in xs.iter().enumerate() {
+                if i != 0 {
+                    s.push_str(", ");
+                }
+                let col = end_column(col, &s);
+                s.push_str(&self.child(x, indent, col, PREC_TEST, flat)?);
+            }
+            if xs.len() == 1 {
+                s.push(',');
+            }
+            return Some(s);
+        }
+        if let [x] = xs {
+            return Some(format!(
+                "({},)",
+                self.child(x, indent, col + 1, PREC_TEST, flat)?
+            ));
+        }
+        let close = self.find_token(span.end(), b')');
+        self.list(
+            ("(", ")"),
+            xs,
+            Span::new(span.begin(), close + 1),
+            indent,
+            col,
+            flat,
+        )
+    }
+
+    /// Format the target of an assignment. These are never split, but the expressions in
+    /// them can be.
+    fn target(
+        &mut self,
+        x: &AstAssignTarget,
+        indent: usize,
+        col: usize,
+        prec: u8,
+        flat: bool,
+    ) -> Option<String> {
+        Some(match &x.node {
+            AssignTarget::Tuple(xs) => {
+                let bare = prec == PREC_TUPLE && !self.is_parenthesized(x.span);
+                let mut s = if bare { String::new() } else { "(".to_owned() };
+                for (i, x) in xs.iter().enumerate() {
+                    if i != 0 {
+                        s.push_str(", ");
+                    }
+                    let col = end_column(col, &s);
+                    s.push_str(&self.target(x, indent, col, PREC_TEST, flat)?);
+                }
+                if xs.len() == 1 {
+                    s.push(',');
+                }
+                if !bare {
+                    s.push(')');
+                }
+                s
+            }
+            AssignTarget::Index(e_i) => {
+                let (e, i) = &**e_i;
+                let mut s = self.child(e, indent, col, PREC_PRIMARY, flat)?;
+                s.push('[');
+                let col = end_column(col, &s);
+                s.push_str(&self.child(i, indent, col, PREC_TEST, flat)?);
+                s.push(']');
+                s
+            }
+            AssignTarget::Dot(e, name) => {
+                format!(
+                    "{}.{}",
+                    self.child(e, indent, col, PREC_PRIMARY, flat)?,
+                    name.node
+                )
+            }
+            AssignTarget::Identifier(ident) => ident.node.ident.clone(),
+        })
+    }
+
+    /// Format a bracketed list, on one line if it fits, otherwise with one item per line.
+    /// Lists with comments in `region`, or a trailing comma, are always split.
+    fn list<T: ListItem>(
+        &mut self,
+        brackets: (&str, &str),
+        items: &[T],
+        region: Span,
+        indent: usize,
+        col: usize,
+        flat: bool,
+    ) -> Option<String> {
+        let (open, close) = brackets;
+        let must_split = self.has_comment_in(region)
+            || items
+                .last()
+                .map_or(false, |x| self.has_trailing_comma(x.span().end()));
+        if !must_split {
+            if let Some(s) = self.list_flat(brackets, items, indent, col) {
+                if flat || fits(col, &s) {
+                    return Some(s);
+                }
+            }
+        }
+        if flat {
+            return None;
+        }
+
+        let inner = indent + 1;
+        let mut s = open.to_owned();
+        s.push('\n');
+        let mut last_line = None;
+        for (i, x) in items.iter().enumerate() {
+            let span = x.span();
+            self.list_comments(&mut s, span.begin(), inner, &mut last_line);
+            let line = self.line(span.begin());
+            if last_line.map_or(false, |last| line > last + 1) {
+                s.push('\n');
+            }
+            push_indent(&mut s, inner);
+            s.push_str(&x.layout(self, inner, inner * INDENT.len(), false)?);
+            s.push(',');
+            let end_line = self.line(span.end());
+            let next = items.get(i + 1).map_or(region.end(), |x| x.span().begin());
+            if let Some(c) = self.comments.get(self.next_comment) {
+                if c.pos < next && !c.own_line && c.line == end_line {
+                    s.push_str("  #");
+                    s.push_str(&c.text);
+                    self.next_comment += 1;
+                }
+            }
+            s.push('\n');
+            last_line = Some(end_line);
+        }
+        self.list_comments(&mut s, region.end(), inner, &mut last_line);
+        push_indent(&mut s, indent);
+        s.push_str(close);
+        Some(s)
+    }
+
+    fn list_flat<T: ListItem>(
+        &mut self,
+        (open, close): (&str, &str),
+        items: &[T],
+        indent: usize,
+        col: usize,
+    ) -> Option<String> {
+        let mut s = open.to_owned();
+        for (i, x) in items.iter().enumerate() {
+            if i != 0 {
+                s.push_str(", ");
+            }
+            let col = end_column(col, &s);
+            s.push_str(&x.layout(self, indent, col, true)?);
+        }
+        s.push_str(close);
+        Some(s)
+    }
+
+    /// Write the comments before `pos` on lines of their own within a split list.
+    fn list_comments(
+        &mut self,
+        s: &mut String,
+        pos: Pos,
+        indent: usize,
+        last_line: &mut Option<usize>,
+    ) {
+        for c in self.take_comments_before(pos) {
+            if last_line.map_or(false, |last| c.line > last + 1) {
+                s.push('\n');
+            }
+            push_indent(s, indent);
+            s.push('#');
+            s.push_str(&c.text);
+            s.push('\n');
+            *last_line = Some(c.line);
+        }
+    }
+
+    /// Format a comprehension, which when split has the value and each clause on a line.
+    fn comprehension(
+        &mut self,
+        x: &AstExpr,
+        indent: usize,
+        col: usize,
+        flat: bool,
+    ) -> Option<String> {
+        if !flat {
+            if let Some(s) = self.comprehension(x, indent, col, true) {
+                if fits(col, &s) {
+                    return Some(s);
+                }
+            }
+        } else if self.has_comment_in(x.span) {
+            return None;
+        }
+
+        let ((open, close), for_clause, clauses) = match &x.node {
+            Expr::ListComprehension(_, for_clause, clauses) => (("[", "]"), for_clause, clauses),
+            Expr::DictComprehension(_, for_clause, clauses) => (("{", "}"), for_clause, clauses),
+            _ => unreachable!("not a comprehension"),
+        };
+        let (indent_inner, sep) = if flat {
+            (indent, " ")
+        } else {
+            (indent + 1, "\n")
+        };
+        let mut last_line = None;
+        let mut s = open.to_owned();
+        let mut part_begin = |f: &mut Self, s: &mut String, pos: Pos| -> usize {
+            if flat {
+                end_column(col, s)
+            } else {
+                f.list_comments(s, pos, indent_inner, &mut last_line);
+                push_indent(s, indent_inner);
+                indent_inner * INDENT.len()
+            }
+        };
+
+        if !flat {
+            s.push('\n');
+        }
+        match &x.node {
+            Expr::ListComprehension(e, ..) => {
+                let col = part_begin(self, &mut s, e.span.begin());
+                s.push_str(&self.child(e, indent_inner, col, PREC_TEST, flat)?);
+            }
+            Expr::DictComprehension(k_v, ..) => {
+                let (k, v) = &**k_v;
+                let col = part_begin(self, &mut s, k.span.begin());
+                let k = self.child(k, indent_inner, col, PREC_TEST, flat)?;
+                let v = self.child(v, indent_inner, end_column(col, &k) + 2, PREC_TEST, flat)?;
+                s.push_str(&format!("{}: {}", k, v));
+            }
+            _ => unreachable!("not a comprehension"),
+        }
+        let clauses =
+            std::iter::once(ClauseRef::For(for_clause)).chain(clauses.iter().map(|c| match c {
+                Clause::For(f) => ClauseRef::For(f),
+                Clause::If(e) => ClauseRef::If(e),
+            }));
+        for clause in clauses {
+            s.push_str(sep);
+            let part = match clause {
+                ClauseRef::For(f) => {
+                    let col = part_begin(self, &mut s, f.var.span.begin());
+                    self.for_clause(f, indent_inner, col, flat)?
+                }
+                ClauseRef::If(e) => {
+                    let col = part_begin(self, &mut s, e.span.begin());
+                    format!(
+                        "if {}",
+                        self.child(e, indent_inner, col + 3, PREC_OR, flat)?
+                    )
+                }
+            };
+            s.push_str(&part);
+        }
+        if !flat {
+            s.push('\n');
+            self.list_comments(&mut s, x.span.end(), indent_inner, &mut last_line);
+            push_indent(&mut s, indent);
+        }
+        s.push_str(close);
+        Some(s)
+    }
+
+    fn for_clause(
+        &mut self,
+        f: &ForClause,
+        indent: usize,
+        col: usize,
+        flat: bool,
+    ) -> Option<String> {
+        let mut s = "for ".to_owned();
+        s.push_str(&self.target(&f.var, indent, col + 4, PREC_TUPLE, flat)?);
+        s.push_str(" in ");
+        let col = end_column(col, &s);
+        s.push_str(&self.child(&f.over, indent, col, PREC_OR, flat)?);
+        Some(s)
+    }
+}
+
+/// Format a parsed module, see [`AstModule::format`](crate::syntax::AstModule::format).
+pub(crate) fn format_module(codemap: &CodeMap, statement: &AstStmt, dialect: &Dialect) -> String {
+    let mut f = Formatter::new(codemap, dialect);
+    f.stmt(statement, 0);
+    f.comment_lines(codemap.full_span().end(), 0, 2);
+    f.out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dialect::Dialect;
+    use crate::syntax::AstModule;
+
+    fn format(program: &str) -> String {
+        let dialect = Dialect {
+            enable_f_strings: true,
+            ..Dialect::Extended
+        };
+        let res = AstModule::parse("x.star", program.to_owned(), &dialect)
+            .unwrap()
+            .format()
+            .unwrap();
+        let again = AstModule::parse("x.star", res.clone(), &dialect)
+            .unwrap()
+            .format()
+            .unwrap();
+        assert_eq!(res, again, "Formatting is not idempotent");
+        res
+    }
+
+    #[test]
+    fn test_format_spacing_and_brackets() {
+        assert_eq!(
+            r#"x = 1 + 2 * 3
+y = (1 + 2) * 3
+z = a - (b - c)
+w = not (a and b)
+f(a, b = 1, *c, **d)
+g = lambda x, y = 1: x if y else -x
+"#,
+            format(
+                r#"x=1+2*3
+y=(1+2)*3
+z=a-(b-c)
+w=not(a and b)
+f(a,b=1,*c,**d)
+g=lambda x,y=1:(x if y else(-x))
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let formatted = r#"# Leading comment
+
+load("a.bzl", "b")  # Trailing
+
+def f(x):
+    # Inside
+    return x  # Return
+
+    # End of f
+
+# Before y
+y = [
+    1,  # One
+    # Two
+    2,
+]
+"#;
+        assert_eq!(
+            formatted,
+            format(
+                r#"# Leading comment
+
+load("a.bzl","b")  # Trailing
+
+def f( x ):
+  # Inside
+  return x  # Return
+
+  # End of f
+
+# Before y
+y = [1,  # One
+  # Two
+  2]
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_split() {
+        assert_eq!("x = [\n    1,\n    2,\n]\n", format("x = [1, 2,]\n"));
+        assert_eq!(
+            r#"cc_library(
+    name = "a_very_long_target_name_for_testing",
+    srcs = ["first_source_file.cpp", "second_source_file.cpp"],
+)
+"#,
+            format(
+                r#"cc_library(name = "a_very_long_target_name_for_testing", srcs = ["first_source_file.cpp", "second_source_file.cpp"])"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_statements() {
+        assert_eq!(
+            r#"def f(x: int, y: str = "a") -> list[int]:
+    for k, v in d.items():
+        if k:
+            pass
+        elif v:
+            continue
+        else:
+            break
+    x = 1,
+    a, b = b, a
+    t = (1, 2)
+    return [x]
+"#,
+            format(
+                r#"def f(x:int,y:str="a")->list[int]:
+  for k,v in d.items():
+    if k:
+      pass
+    elif v: continue
+    else:
+      break
+  x = 1,
+  a, b = b, a
+  t = (1, 2)
+  return [x]
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_literals() {
+        assert_eq!(
+            "x = f\"{a}\" + r'\\d' + 0x10 + '''\n  y'''\n",
+            format("x=f\"{a}\"+r'\\d'+0x10+'''\n  y'''\n")
+        );
+    }
+}
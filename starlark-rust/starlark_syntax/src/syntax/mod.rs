@@ -25,6 +25,7 @@ pub use crate::dialect::DialectTypes;
 
 pub mod ast;
 pub mod def;
+mod format;
 #[cfg(test)]
 mod grammar_tests;
 pub mod grammar_util;
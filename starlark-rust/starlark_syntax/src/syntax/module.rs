@@ -40,6 +40,7 @@ use crate::syntax::ast::ExprP;
 use crate::syntax::ast::IdentP;
 use crate::syntax::ast::LoadArgP;
 use crate::syntax::ast::Stmt;
+use crate::syntax::format::format_module;
 use crate::syntax::grammar::StarlarkParser;
 use crate::syntax::state::ParserState;
 use crate::syntax::AstLoad;
@@ -211,6 +212,30 @@ impl AstModule {
         }
     }
 
+    /// Format the module as source code in a standard style, keeping its comments.
+    ///
+    /// Blocks are indented by four spaces, and bracketed lists are split one item per line
+    /// if they don't fit in 100 columns, contain comments, or end with a trailing comma.
+    /// String and number literals are written exactly as they appear in the source.
+    ///
+    /// ```
+    /// use starlark_syntax::syntax::AstModule;
+    /// use starlark_syntax::syntax::Dialect;
+    ///
+    /// let ast = AstModule::parse("x.star", "x=[1,2]  # two\n".to_owned(), &Dialect::Standard).unwrap();
+    /// assert_eq!(ast.format().unwrap(), "x = [1, 2]  # two\n");
+    /// ```
+    pub fn format(&self) -> crate::Result<String> {
+        let res = format_module(&self.codemap, &self.statement, &self.dialect);
+        // Check the result, so a bug in the formatter can't break the file being formatted.
+        if let Err(e) = AstModule::parse(self.codemap.filename(), res.clone(), &self.dialect) {
+            return Err(crate::Error::new(crate::ErrorKind::Internal(
+                anyhow::anyhow!("Formatting produced invalid code: {}", e),
+            )));
+        }
+        Ok(res)
+    }
+
     /// Return the file names of all the `load` statements in the module.
     /// If the [`Dialect`] had [`enable_load`](Dialect::enable_load) set to [`false`] this will be an empty list.
     pub fn loads(&self) -> Vec<AstLoad> {
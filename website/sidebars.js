@@ -127,6 +127,10 @@ const manualSidebar = [
           'users/advanced/deferred_materialization',
           'users/advanced/restarter',
           'users/advanced/in_memory_cache',
+          'users/advanced/local_action_cache',
+          'users/advanced/action_cache_verification',
+          'users/advanced/check_determinism',
+          'users/advanced/persistent_dice_graph',
           isInternal() ? 'users/advanced/offline_build_archives' : [],
           isInternal() ? 'users/advanced/vpnless' : [],
         ],